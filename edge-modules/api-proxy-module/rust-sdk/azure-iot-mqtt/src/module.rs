@@ -215,7 +215,7 @@ impl futures_core::Stream for Client {
 				State::WaitingForSubscriptions { reset_session, acked } =>
 					if *reset_session {
 						match std::pin::Pin::new(&mut this.inner).poll_next(cx) {
-							std::task::Poll::Ready(Some(Ok(mqtt3::Event::NewConnection { .. }))) | std::task::Poll::Ready(Some(Ok(mqtt3::Event::Disconnected(_)))) => (),
+							std::task::Poll::Ready(Some(Ok(mqtt3::Event::NewConnection { .. }))) | std::task::Poll::Ready(Some(Ok(mqtt3::Event::Disconnected(_)))) | std::task::Poll::Ready(Some(Ok(mqtt3::Event::ConnectionAttemptFailed { .. }))) => (),
 
 							std::task::Poll::Ready(Some(Ok(mqtt3::Event::Publication(publication)))) => match InternalMessage::parse(publication) {
 								Ok(InternalMessage::DirectMethod { name, payload, request_id }) =>
@@ -281,7 +281,7 @@ impl futures_core::Stream for Client {
 						// Don't expect any subscription updates at this point
                         std::task::Poll::Ready(Some(Ok(mqtt3::Event::SubscriptionUpdates(_)))) => unreachable!(),
 
-                        std::task::Poll::Ready(Some(Ok(mqtt3::Event::Disconnected(_)))) => continue,
+                        std::task::Poll::Ready(Some(Ok(mqtt3::Event::Disconnected(_)))) | std::task::Poll::Ready(Some(Ok(mqtt3::Event::ConnectionAttemptFailed { .. }))) => continue,
 
 						std::task::Poll::Ready(Some(Err(err))) => return std::task::Poll::Ready(Some(Err(err))),
 
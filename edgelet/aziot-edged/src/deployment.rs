@@ -0,0 +1,545 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use sha2::Digest;
+
+use edgelet_core::{
+    DeploymentStatus, Drift, Module, ModuleDeploymentStatus, ModuleRuntime, ModuleRuntimeState,
+    ModuleStatus,
+};
+use edgelet_settings::deployment::{DesiredStatus, Manifest};
+use edgelet_settings::watchdog::ModuleSupervision;
+use edgelet_settings::{DockerConfig, RuntimeSettings};
+
+use crate::error::Error as EdgedError;
+use crate::supervisor::Supervisor;
+
+type ModuleManifest = edgelet_settings::deployment::Module<DockerConfig>;
+
+/// Label of containers created from the local deployment manifest. Only these
+/// are removed when they are no longer in the manifest.
+const DEPLOYMENT_LABEL_KEY: &str = "net.azure-devices.edge.deployment";
+const DEPLOYMENT_LABEL_VALUE: &str = "local";
+
+/// Label with the digest of the configuration a module was created from. The runtime can't
+/// report the options a container was created with, so changes to them are detected from it.
+const CONFIG_DIGEST_LABEL_KEY: &str = "net.azure-devices.edge.deployment-config";
+
+/// Reconciles the modules in the runtime with the local deployment manifest,
+/// and records what differed and what was done about it in `status`.
+pub(crate) async fn reconcile(
+    settings: &edgelet_settings::docker::Settings,
+    local_deployment: &edgelet_settings::deployment::Settings,
+    runtime: &edgelet_docker::DockerModuleRuntime,
+    supervisor: &mut Supervisor,
+    status: &futures_util::lock::Mutex<DeploymentStatus>,
+) -> Result<(), EdgedError> {
+    log::info!("Reconciling local deployment manifest");
+
+    let result = reconcile_modules(settings, local_deployment, runtime, supervisor).await;
+
+    // Failures of single modules are reported in their status, only failures to
+    // read the manifest or talk to the runtime fail the reconcile.
+    let (new_status, result) = match result {
+        Ok(modules) => {
+            let failed = modules
+                .iter()
+                .filter(|module| module.error().is_some())
+                .count();
+
+            if failed == 0 {
+                log::info!("Local deployment is up to date");
+            } else {
+                log::warn!(
+                    "Failed to reconcile {} module(s) of the local deployment",
+                    failed
+                );
+            }
+
+            (DeploymentStatus::new(modules), Ok(()))
+        }
+        Err(err) => (DeploymentStatus::failed(err.to_string()), Err(err)),
+    };
+
+    *status.lock().await = new_status;
+
+    result
+}
+
+async fn reconcile_modules(
+    settings: &edgelet_settings::docker::Settings,
+    local_deployment: &edgelet_settings::deployment::Settings,
+    runtime: &edgelet_docker::DockerModuleRuntime,
+    supervisor: &mut Supervisor,
+) -> Result<Vec<ModuleDeploymentStatus>, EdgedError> {
+    let manifest = read_manifest(local_deployment.manifest())?;
+
+    let mut existing: std::collections::BTreeMap<_, _> = runtime
+        .list_with_details()
+        .await
+        .map_err(|err| EdgedError::from_err("Failed to list modules", err))?
+        .into_iter()
+        .map(|(module, state)| (module.name().to_string(), (module, state)))
+        .collect();
+
+    let mut statuses = Vec::new();
+
+    // Remove modules that are not part of the deployment first, so that they don't hold on to
+    // resources that the modules of the deployment need. Modules which were not created from
+    // the local deployment are left alone.
+    for (name, (module, state)) in &existing {
+        if manifest.modules.contains_key(name) {
+            continue;
+        }
+
+        if !is_deployed(module) {
+            log::debug!(
+                "Ignoring module {} which is not part of the local deployment",
+                name
+            );
+            continue;
+        }
+
+        let mut status = ModuleDeploymentStatus::new(name.clone(), None, Some(*state.status()));
+        status.set_drift(Drift::Unexpected);
+        log::info!("Removing module {}: {}", name, Drift::Unexpected);
+
+        if let Err(err) = runtime.remove(name).await {
+            log::warn!("Failed to remove module {}: {}", name, err);
+            status.set_error(err.to_string());
+        }
+
+        statuses.push(status);
+    }
+
+    for (name, module) in manifest.modules_in_startup_order() {
+        let current = existing.remove(name);
+
+        let status = reconcile_module(settings, runtime, supervisor, name, module, current).await;
+        statuses.push(status);
+    }
+
+    Ok(statuses)
+}
+
+/// Whether the module was created from the local deployment manifest.
+fn is_deployed(module: &edgelet_docker::DockerModule) -> bool {
+    module
+        .config()
+        .create_options()
+        .labels()
+        .and_then(|labels| labels.get(DEPLOYMENT_LABEL_KEY))
+        .map_or(false, |value| value == DEPLOYMENT_LABEL_VALUE)
+}
+
+fn read_manifest(path: &std::path::Path) -> Result<Manifest<DockerConfig>, EdgedError> {
+    let manifest = std::fs::read(path).map_err(|err| {
+        EdgedError::from_err(
+            format!("Failed to read deployment manifest {}", path.display()),
+            err,
+        )
+    })?;
+
+    serde_json::from_slice(&manifest).map_err(|err| {
+        EdgedError::from_err(
+            format!("Failed to parse deployment manifest {}", path.display()),
+            err,
+        )
+    })
+}
+
+async fn reconcile_module(
+    settings: &edgelet_settings::docker::Settings,
+    runtime: &edgelet_docker::DockerModuleRuntime,
+    supervisor: &mut Supervisor,
+    name: &str,
+    module: &ModuleManifest,
+    current: Option<(edgelet_docker::DockerModule, ModuleRuntimeState)>,
+) -> ModuleDeploymentStatus {
+    let mut status = ModuleDeploymentStatus::new(
+        name.to_string(),
+        Some(module.status()),
+        current.as_ref().map(|(_, state)| *state.status()),
+    );
+
+    let action = action(
+        supervisor,
+        name,
+        module,
+        current
+            .as_ref()
+            .map(|(current, state)| (current.config(), state)),
+        tokio::time::Instant::now(),
+    );
+
+    if let Some(action) = action {
+        let drift = action.drift();
+        log::info!("Reconciling module {}: {}", name, drift);
+        status.set_drift(drift);
+
+        let result = match action {
+            Action::Create => {
+                supervisor.forget(name);
+
+                create_module(settings, runtime, name, module).await
+            }
+            Action::Recreate(_) => {
+                // The new container does not inherit the restart history of the old one.
+                supervisor.forget(name);
+
+                match runtime.remove(name).await {
+                    Ok(()) => create_module(settings, runtime, name, module).await,
+                    Err(err) => Err(EdgedError::from_err("Failed to remove module", err)),
+                }
+            }
+            Action::UpdateStatus(current) => {
+                update_status(settings, runtime, name, module, current).await
+            }
+        };
+
+        if let Err(err) = result {
+            log::warn!("Failed to reconcile module {}: {}", name, err);
+            status.set_error(err.to_string());
+        }
+    }
+
+    status
+}
+
+/// What is done to a module of the manifest which differs from the runtime.
+#[derive(Clone, Copy, Debug)]
+enum Action {
+    Create,
+    Recreate(Drift),
+    UpdateStatus(ModuleStatus),
+}
+
+impl Action {
+    fn drift(self) -> Drift {
+        match self {
+            Action::Create => Drift::Missing,
+            Action::Recreate(drift) => drift,
+            Action::UpdateStatus(_) => Drift::StatusChanged,
+        }
+    }
+}
+
+/// Decides what to do about a module of the manifest, given the config and state of its
+/// container if it exists.
+fn action(
+    supervisor: &mut Supervisor,
+    name: &str,
+    module: &ModuleManifest,
+    current: Option<(&DockerConfig, &ModuleRuntimeState)>,
+    now: tokio::time::Instant,
+) -> Option<Action> {
+    let (config, state) = match current {
+        Some(current) => current,
+        None => return Some(Action::Create),
+    };
+
+    let digest = config
+        .create_options()
+        .labels()
+        .and_then(|labels| labels.get(CONFIG_DIGEST_LABEL_KEY));
+
+    if config.image() != module.config().image() {
+        Some(Action::Recreate(Drift::ImageChanged))
+    } else if digest != Some(&config_digest(module)) {
+        Some(Action::Recreate(Drift::ConfigChanged))
+    } else if needs_action(module, state)
+        || supervisor
+            .decide(name, &supervision(module), state, now)
+            .is_some()
+    {
+        Some(Action::UpdateStatus(*state.status()))
+    } else {
+        None
+    }
+}
+
+/// Whether the module needs to be stopped or recreated to match the manifest.
+///
+/// Modules which should be running but exited are started by the supervisor instead,
+/// so that they back off between restarts and stop being restarted when they crash-loop.
+fn needs_action(module: &ModuleManifest, state: &ModuleRuntimeState) -> bool {
+    match (module.status(), state.status()) {
+        (_, ModuleStatus::Dead | ModuleStatus::Unknown)
+        | (DesiredStatus::Stopped, ModuleStatus::Running) => true,
+
+        (DesiredStatus::Running, _)
+        | (DesiredStatus::Stopped, ModuleStatus::Stopped | ModuleStatus::Failed) => false,
+    }
+}
+
+/// How the supervisor restarts a module of the manifest after it exits. The manifest has no
+/// settings for health checks, so unhealthy modules are not restarted.
+fn supervision(module: &ModuleManifest) -> ModuleSupervision {
+    ModuleSupervision {
+        status: module.status(),
+        restart_policy: module.restart_policy(),
+        restart_unhealthy: false,
+        ..ModuleSupervision::default()
+    }
+}
+
+/// Digest of everything in the manifest that the container of a module is created from.
+/// Status, restart policy and startup order are left out, since they don't need the
+/// container to be recreated.
+fn config_digest(module: &ModuleManifest) -> String {
+    let config = module.config();
+
+    let json = serde_json::json!({
+        "image": config.image(),
+        "createOptions": config.create_options(),
+        "digest": config.digest(),
+        "env": module.env(),
+    })
+    .to_string();
+
+    let digest = sha2::Sha256::digest(json.as_bytes());
+
+    base64::encode(digest)
+}
+
+/// Config of the container of a module, labelled as created from the local deployment.
+///
+/// Elevated permissions are a device setting, and can't be granted by the manifest.
+fn docker_config(
+    module: &ModuleManifest,
+    allow_elevated_docker_permissions: bool,
+) -> Result<DockerConfig, EdgedError> {
+    let config = module.config();
+
+    let mut labels = config
+        .create_options()
+        .labels()
+        .cloned()
+        .unwrap_or_default();
+    labels.insert(
+        DEPLOYMENT_LABEL_KEY.to_string(),
+        DEPLOYMENT_LABEL_VALUE.to_string(),
+    );
+    labels.insert(CONFIG_DIGEST_LABEL_KEY.to_string(), config_digest(module));
+    let create_options = config.create_options().clone().with_labels(labels);
+
+    DockerConfig::new(
+        config.image().to_string(),
+        create_options,
+        config.digest().map(ToString::to_string),
+        config.auth().cloned(),
+        allow_elevated_docker_permissions,
+    )
+    .map_err(|err| EdgedError::from_err("Invalid module in deployment manifest", err))
+}
+
+async fn update_status(
+    settings: &edgelet_settings::docker::Settings,
+    runtime: &edgelet_docker::DockerModuleRuntime,
+    name: &str,
+    module: &ModuleManifest,
+    current: ModuleStatus,
+) -> Result<(), EdgedError> {
+    match current {
+        ModuleStatus::Dead | ModuleStatus::Unknown => {
+            runtime
+                .remove(name)
+                .await
+                .map_err(|err| EdgedError::from_err("Failed to remove module", err))?;
+
+            create_module(settings, runtime, name, module).await
+        }
+
+        ModuleStatus::Running => runtime
+            .stop(name, Some(std::time::Duration::from_secs(30)))
+            .await
+            .map_err(|err| EdgedError::from_err("Failed to stop module", err)),
+
+        ModuleStatus::Stopped | ModuleStatus::Failed => runtime
+            .start(name)
+            .await
+            .map_err(|err| EdgedError::from_err("Failed to start module", err)),
+    }
+}
+
+async fn create_module(
+    settings: &edgelet_settings::docker::Settings,
+    runtime: &edgelet_docker::DockerModuleRuntime,
+    name: &str,
+    module: &ModuleManifest,
+) -> Result<(), EdgedError> {
+    let spec = module
+        .to_spec(name)
+        .map_err(|err| EdgedError::from_err("Invalid module in deployment manifest", err))?;
+
+    let config = docker_config(module, settings.allow_elevated_docker_permissions())?;
+    let spec = spec.with_config(config);
+
+    if let edgelet_settings::module::ImagePullPolicy::OnCreate = spec.image_pull_policy() {
+        edgelet_core::ModuleRegistry::pull(runtime.registry(), spec.config())
+            .await
+            .map_err(|err| EdgedError::from_err("Failed to pull module image", err))?;
+    }
+
+    runtime
+        .create(spec)
+        .await
+        .map_err(|err| EdgedError::from_err("Failed to create module", err))?;
+
+    log::info!("Created module {}", name);
+
+    if module.status() == DesiredStatus::Running {
+        runtime
+            .start(name)
+            .await
+            .map_err(|err| EdgedError::from_err("Failed to start module", err))?;
+
+        log::info!("Started module {}", name);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{Drift, ModuleRuntimeState, ModuleStatus};
+    use edgelet_settings::deployment::DesiredStatus;
+    use test_case::test_case;
+
+    use super::{Action, ModuleManifest};
+    use crate::supervisor::Supervisor;
+
+    fn sensor() -> serde_json::Value {
+        serde_json::json!({
+            "type": "docker",
+            "status": "running",
+            "restartPolicy": "always",
+            "config": {
+                "image": "sensor:1.0",
+                "createOptions": {
+                    "Hostname": "sensor",
+                    "Labels": { "app": "sensor" }
+                }
+            },
+            "env": { "interval": "10" }
+        })
+    }
+
+    fn with(mut module: serde_json::Value, pointer: &str, value: &str) -> serde_json::Value {
+        *module.pointer_mut(pointer).unwrap() = value.into();
+        module
+    }
+
+    fn manifest(module: serde_json::Value) -> ModuleManifest {
+        serde_json::from_value(module).unwrap()
+    }
+
+    fn state(status: ModuleStatus) -> ModuleRuntimeState {
+        ModuleRuntimeState::default().with_status(status)
+    }
+
+    #[test_case(DesiredStatus::Running, ModuleStatus::Running, false; "when running")]
+    #[test_case(DesiredStatus::Running, ModuleStatus::Stopped, false; "when stopped and desired running")]
+    #[test_case(DesiredStatus::Running, ModuleStatus::Failed, false; "when failed and desired running")]
+    #[test_case(DesiredStatus::Running, ModuleStatus::Dead, true; "when dead and desired running")]
+    #[test_case(DesiredStatus::Running, ModuleStatus::Unknown, true; "when unknown and desired running")]
+    #[test_case(DesiredStatus::Stopped, ModuleStatus::Running, true; "when running and desired stopped")]
+    #[test_case(DesiredStatus::Stopped, ModuleStatus::Stopped, false; "when stopped")]
+    #[test_case(DesiredStatus::Stopped, ModuleStatus::Failed, false; "when failed and desired stopped")]
+    #[test_case(DesiredStatus::Stopped, ModuleStatus::Dead, true; "when dead and desired stopped")]
+    #[test_case(DesiredStatus::Stopped, ModuleStatus::Unknown, true; "when unknown and desired stopped")]
+    fn needs_action(desired: DesiredStatus, status: ModuleStatus, expected: bool) {
+        let module = with(sensor(), "/status", &desired.to_string());
+
+        assert_eq!(
+            expected,
+            super::needs_action(&manifest(module), &state(status))
+        );
+    }
+
+    #[test_case(sensor(), None, Some(Drift::Missing); "when module is missing")]
+    #[test_case(sensor(), Some((sensor(), ModuleStatus::Running)), None; "when module is up to date")]
+    #[test_case(with(sensor(), "/config/image", "sensor:2.0"), Some((sensor(), ModuleStatus::Running)), Some(Drift::ImageChanged); "when image changed")]
+    #[test_case(with(sensor(), "/config/createOptions/Hostname", "meter"), Some((sensor(), ModuleStatus::Running)), Some(Drift::ConfigChanged); "when create options changed")]
+    #[test_case(with(sensor(), "/config/createOptions/Labels/app", "meter"), Some((sensor(), ModuleStatus::Running)), Some(Drift::ConfigChanged); "when labels changed")]
+    #[test_case(with(sensor(), "/env/interval", "20"), Some((sensor(), ModuleStatus::Running)), Some(Drift::ConfigChanged); "when env changed")]
+    #[test_case(with(sensor(), "/restartPolicy", "on-failure"), Some((sensor(), ModuleStatus::Running)), None; "when restart policy changed")]
+    #[test_case(with(sensor(), "/status", "stopped"), Some((sensor(), ModuleStatus::Running)), Some(Drift::StatusChanged); "when running and desired stopped")]
+    #[test_case(with(sensor(), "/status", "stopped"), Some((sensor(), ModuleStatus::Failed)), None; "when failed and desired stopped")]
+    #[test_case(sensor(), Some((sensor(), ModuleStatus::Failed)), Some(Drift::StatusChanged); "when failed")]
+    #[test_case(sensor(), Some((sensor(), ModuleStatus::Stopped)), Some(Drift::StatusChanged); "when stopped and always restarted")]
+    #[test_case(with(sensor(), "/restartPolicy", "on-failure"), Some((sensor(), ModuleStatus::Stopped)), None; "when stopped and restarted on failure")]
+    #[test_case(with(sensor(), "/restartPolicy", "never"), Some((sensor(), ModuleStatus::Failed)), None; "when failed and never restarted")]
+    #[test_case(sensor(), Some((sensor(), ModuleStatus::Dead)), Some(Drift::StatusChanged); "when dead")]
+    #[test_case(sensor(), Some((sensor(), ModuleStatus::Unknown)), Some(Drift::StatusChanged); "when unknown")]
+    fn action(
+        desired: serde_json::Value,
+        current: Option<(serde_json::Value, ModuleStatus)>,
+        expected: Option<Drift>,
+    ) {
+        let current = current.map(|(deployed, status)| {
+            let config = super::docker_config(&manifest(deployed), false).unwrap();
+            (config, state(status))
+        });
+
+        let action = super::action(
+            &mut Supervisor::default(),
+            "sensor",
+            &manifest(desired),
+            current.as_ref().map(|(config, state)| (config, state)),
+            tokio::time::Instant::now(),
+        );
+
+        assert_eq!(expected, action.map(Action::drift));
+    }
+
+    #[test]
+    fn action_recreates_module_without_config_digest() {
+        let module = manifest(sensor());
+
+        // Created before config digests were recorded, or not created from the manifest.
+        let config = module.config().clone();
+
+        let action = super::action(
+            &mut Supervisor::default(),
+            "sensor",
+            &module,
+            Some((&config, &state(ModuleStatus::Running))),
+            tokio::time::Instant::now(),
+        );
+
+        assert_eq!(Some(Drift::ConfigChanged), action.map(Action::drift));
+    }
+
+    #[test]
+    fn action_backs_off_between_restarts() {
+        let module = manifest(sensor());
+        let config = super::docker_config(&module, false).unwrap();
+        let mut supervisor = Supervisor::default();
+        let start = tokio::time::Instant::now();
+
+        // The default supervision backs off for 10 and then 20 seconds.
+        for &(secs, status, expected) in &[
+            (0, ModuleStatus::Failed, Some(Drift::StatusChanged)),
+            (5, ModuleStatus::Failed, None),
+            (10, ModuleStatus::Failed, Some(Drift::StatusChanged)),
+            (15, ModuleStatus::Running, None),
+            (20, ModuleStatus::Failed, None),
+            (30, ModuleStatus::Failed, Some(Drift::StatusChanged)),
+        ] {
+            let action = super::action(
+                &mut supervisor,
+                "sensor",
+                &module,
+                Some((&config, &state(status))),
+                start + std::time::Duration::from_secs(secs),
+            );
+
+            assert_eq!(
+                expected,
+                action.map(Action::drift),
+                "check after {} seconds",
+                secs
+            );
+        }
+    }
+}
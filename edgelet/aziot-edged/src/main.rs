@@ -3,9 +3,11 @@
 #![deny(rust_2018_idioms)]
 #![warn(clippy::all, clippy::pedantic)]
 
+mod deployment;
 mod error;
 mod management;
 mod provision;
+mod supervisor;
 mod watchdog;
 mod workload_manager;
 
@@ -126,11 +128,25 @@ async fn run() -> Result<(), EdgedError> {
     // appropriate hostname.
     let settings = settings.agent_upstream_resolve(&device_info.gateway_host);
 
+    // With a local deployment manifest, aziot-edged runs the modules itself instead of Edge Agent.
+    // The status of the last reconcile is served by the management API.
+    let deployment_status = settings.local_deployment().map(|local_deployment| {
+        log::info!(
+            "Using local deployment manifest {}",
+            local_deployment.manifest().display()
+        );
+
+        std::sync::Arc::new(futures_util::lock::Mutex::new(
+            edgelet_core::DeploymentStatus::default(),
+        ))
+    });
+
     // Start management and workload sockets.
     let management_shutdown = management::start(
         &settings,
         runtime.clone(),
         shutdown_tx.clone(),
+        deployment_status.clone(),
         tasks.clone(),
     )
     .await?;
@@ -146,6 +162,7 @@ async fn run() -> Result<(), EdgedError> {
         &device_info,
         runtime,
         &identity_client,
+        deployment_status,
         shutdown_rx,
     )
     .await?;
@@ -10,6 +10,9 @@ pub(crate) async fn start<M>(
     settings: &impl edgelet_settings::RuntimeSettings,
     runtime: M,
     sender: tokio::sync::mpsc::UnboundedSender<edgelet_core::ShutdownReason>,
+    deployment_status: Option<
+        std::sync::Arc<futures_util::lock::Mutex<edgelet_core::DeploymentStatus>>,
+    >,
     tasks: std::sync::Arc<std::sync::atomic::AtomicUsize>,
 ) -> Result<tokio::sync::oneshot::Sender<()>, EdgedError>
 where
@@ -25,6 +28,7 @@ where
         settings.endpoints().aziot_identityd_url(),
         runtime,
         sender,
+        deployment_status,
     )
     .map_err(|err| EdgedError::from_err("Invalid Identity Service URL", err))?;
 
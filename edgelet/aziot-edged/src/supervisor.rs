@@ -0,0 +1,398 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use edgelet_core::{ModuleHealth, ModuleRuntime, ModuleRuntimeState, ModuleStatus};
+use edgelet_settings::watchdog::{DesiredStatus, ModuleSupervision, RestartPolicy};
+
+use crate::error::Error as EdgedError;
+
+/// Keeps the modules listed in the watchdog settings running, independently of Edge Agent.
+///
+/// Edge Agent remains responsible for creating modules; the supervisor only starts and restarts
+/// modules that already exist.
+#[derive(Default)]
+pub(crate) struct Supervisor {
+    modules: std::collections::BTreeMap<String, SupervisedModule>,
+}
+
+/// Restart history of a single supervised module.
+#[derive(Default)]
+struct SupervisedModule {
+    /// Number of consecutive restarts, i.e. restarts after which the module did not stay up
+    /// for its stable uptime.
+    restarts: u32,
+    last_restart: Option<tokio::time::Instant>,
+    next_restart: Option<tokio::time::Instant>,
+    crash_looping: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Action {
+    Start,
+    Restart,
+}
+
+/// What the supervisor does about a module during a single check.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Step {
+    /// The module does not need to be started or restarted.
+    Idle,
+    /// The module needs to be started or restarted, but its backoff has not elapsed yet.
+    Backoff,
+    /// The module has just exceeded its crash-loop threshold and is no longer restarted.
+    CrashLoopDetected,
+    /// The module is crash-looping and is left alone until it runs again.
+    CrashLooping,
+    Act(Action),
+}
+
+impl Supervisor {
+    pub(crate) async fn check(
+        &mut self,
+        settings: &edgelet_settings::watchdog::Settings,
+        runtime: &edgelet_docker::DockerModuleRuntime,
+    ) -> Result<(), EdgedError> {
+        let mut failed = 0;
+
+        for (name, supervision) in settings.modules() {
+            if let Err(err) = self.check_module(name, supervision, runtime).await {
+                log::warn!("Failed to supervise module {}: {}", name, err);
+                failed += 1;
+            }
+        }
+
+        if failed == 0 {
+            Ok(())
+        } else {
+            Err(EdgedError::new(format!(
+                "Failed to supervise {} module(s)",
+                failed
+            )))
+        }
+    }
+
+    /// Decides whether the module needs to be started or restarted now, taking its restart
+    /// history into account.
+    ///
+    /// The local deployment uses this too, so that a module which is both deployed and
+    /// supervised shares a single restart history.
+    pub(crate) fn decide(
+        &mut self,
+        name: &str,
+        supervision: &ModuleSupervision,
+        state: &ModuleRuntimeState,
+        now: tokio::time::Instant,
+    ) -> Option<Action> {
+        let module = self.modules.entry(name.to_string()).or_default();
+
+        match module.step(supervision, *state.status(), state.health(), now) {
+            Step::Idle => None,
+
+            Step::Backoff => {
+                log::info!(
+                    "Module {} is {}, restart is delayed by backoff",
+                    name,
+                    state.status()
+                );
+
+                None
+            }
+
+            Step::CrashLoopDetected => {
+                log::error!(
+                    "Module {} was restarted {} times in a row and is crash-looping",
+                    name,
+                    module.restarts
+                );
+
+                None
+            }
+
+            Step::CrashLooping => {
+                log::warn!(
+                    "Module {} is crash-looping and will not be restarted until it runs again",
+                    name
+                );
+
+                None
+            }
+
+            Step::Act(action) => Some(action),
+        }
+    }
+
+    /// Forgets the restart history of a module, for example because its container was recreated.
+    pub(crate) fn forget(&mut self, name: &str) {
+        self.modules.remove(name);
+    }
+
+    async fn check_module(
+        &mut self,
+        name: &str,
+        supervision: &ModuleSupervision,
+        runtime: &edgelet_docker::DockerModuleRuntime,
+    ) -> Result<(), EdgedError> {
+        let state = if let Ok((_, state)) = runtime.get(name).await {
+            state
+        } else {
+            log::info!("Supervised module {} does not exist yet", name);
+
+            return Ok(());
+        };
+
+        match self.decide(name, supervision, &state, tokio::time::Instant::now()) {
+            Some(Action::Start) => {
+                log::info!("Module {} is {}, starting it now...", name, state.status());
+
+                runtime
+                    .start(name)
+                    .await
+                    .map_err(|err| EdgedError::from_err("Failed to start module", err))?;
+
+                log::info!("Started module {}", name);
+            }
+
+            Some(Action::Restart) => {
+                log::info!("Module {} is unhealthy, restarting it now...", name);
+
+                runtime
+                    .restart(name)
+                    .await
+                    .map_err(|err| EdgedError::from_err("Failed to restart module", err))?;
+
+                log::info!("Restarted module {}", name);
+            }
+
+            None => (),
+        }
+
+        Ok(())
+    }
+}
+
+impl SupervisedModule {
+    /// Decides what to do about the module at `now`, and records a restart when one is due.
+    ///
+    /// Restarts are counted when they are attempted, so a module that fails to start also backs off.
+    fn step(
+        &mut self,
+        supervision: &ModuleSupervision,
+        status: ModuleStatus,
+        health: Option<ModuleHealth>,
+        now: tokio::time::Instant,
+    ) -> Step {
+        let action = match action(supervision, status, health) {
+            Some(action) => action,
+            None => {
+                // A crash-looping module that runs again was started by someone else, and a module
+                // that stayed up for its stable uptime has recovered, so either starts a new series
+                // of restarts. Merely seeing the module running is not enough, since it may crash
+                // right after every check.
+                let stable = self.last_restart.map_or(true, |last_restart| {
+                    now.saturating_duration_since(last_restart) >= supervision.stable_uptime()
+                });
+                if status == ModuleStatus::Running && (self.crash_looping || stable) {
+                    *self = SupervisedModule::default();
+                }
+
+                return Step::Idle;
+            }
+        };
+
+        if self.crash_looping {
+            return Step::CrashLooping;
+        }
+
+        if self
+            .next_restart
+            .map_or(false, |next_restart| now < next_restart)
+        {
+            return Step::Backoff;
+        }
+
+        if self.restarts >= supervision.crash_loop_threshold() {
+            self.crash_looping = true;
+
+            return Step::CrashLoopDetected;
+        }
+
+        self.last_restart = Some(now);
+        self.next_restart = Some(now + supervision.backoff(self.restarts));
+        self.restarts += 1;
+
+        Step::Act(action)
+    }
+}
+
+fn action(
+    supervision: &ModuleSupervision,
+    status: ModuleStatus,
+    health: Option<ModuleHealth>,
+) -> Option<Action> {
+    // Modules which should not be running were stopped on purpose.
+    if supervision.status() != DesiredStatus::Running {
+        return None;
+    }
+
+    match (supervision.restart_policy(), status) {
+        // Dead modules can't be started again, and must be recreated by whoever created them.
+        (RestartPolicy::Never, _)
+        | (RestartPolicy::OnFailure, ModuleStatus::Stopped)
+        | (_, ModuleStatus::Dead | ModuleStatus::Unknown) => None,
+
+        (_, ModuleStatus::Running) => {
+            if supervision.restart_unhealthy() && health == Some(ModuleHealth::Unhealthy) {
+                Some(Action::Restart)
+            } else {
+                None
+            }
+        }
+
+        (RestartPolicy::Always, ModuleStatus::Stopped) | (_, ModuleStatus::Failed) => {
+            Some(Action::Start)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{ModuleHealth, ModuleStatus};
+    use edgelet_settings::watchdog::{DesiredStatus, ModuleSupervision, RestartPolicy};
+    use test_case::test_case;
+
+    use super::{Action, Step, SupervisedModule};
+
+    #[test_case(DesiredStatus::Running, RestartPolicy::Always, ModuleStatus::Running, None, None; "when running")]
+    #[test_case(DesiredStatus::Running, RestartPolicy::Always, ModuleStatus::Running, Some(ModuleHealth::Healthy), None; "when healthy")]
+    #[test_case(DesiredStatus::Running, RestartPolicy::Always, ModuleStatus::Running, Some(ModuleHealth::Unhealthy), Some(Action::Restart); "when unhealthy")]
+    #[test_case(DesiredStatus::Running, RestartPolicy::Always, ModuleStatus::Stopped, None, Some(Action::Start); "when stopped and always restarted")]
+    #[test_case(DesiredStatus::Running, RestartPolicy::Always, ModuleStatus::Failed, None, Some(Action::Start); "when failed and always restarted")]
+    #[test_case(DesiredStatus::Running, RestartPolicy::Always, ModuleStatus::Dead, None, None; "when dead")]
+    #[test_case(DesiredStatus::Running, RestartPolicy::Always, ModuleStatus::Unknown, None, None; "when unknown")]
+    #[test_case(DesiredStatus::Running, RestartPolicy::OnFailure, ModuleStatus::Stopped, None, None; "when stopped and restarted on failure")]
+    #[test_case(DesiredStatus::Running, RestartPolicy::OnFailure, ModuleStatus::Failed, None, Some(Action::Start); "when failed and restarted on failure")]
+    #[test_case(DesiredStatus::Running, RestartPolicy::OnFailure, ModuleStatus::Running, Some(ModuleHealth::Unhealthy), Some(Action::Restart); "when unhealthy and restarted on failure")]
+    #[test_case(DesiredStatus::Running, RestartPolicy::Never, ModuleStatus::Failed, None, None; "when failed and never restarted")]
+    #[test_case(DesiredStatus::Running, RestartPolicy::Never, ModuleStatus::Running, Some(ModuleHealth::Unhealthy), None; "when unhealthy and never restarted")]
+    #[test_case(DesiredStatus::Stopped, RestartPolicy::Always, ModuleStatus::Failed, None, None; "when failed and desired stopped")]
+    #[test_case(DesiredStatus::Stopped, RestartPolicy::Always, ModuleStatus::Running, Some(ModuleHealth::Unhealthy), None; "when unhealthy and desired stopped")]
+    fn action(
+        desired_status: DesiredStatus,
+        restart_policy: RestartPolicy,
+        status: ModuleStatus,
+        health: Option<ModuleHealth>,
+        expected: Option<Action>,
+    ) {
+        let supervision = ModuleSupervision {
+            status: desired_status,
+            restart_policy,
+            ..ModuleSupervision::default()
+        };
+
+        assert_eq!(expected, super::action(&supervision, status, health));
+    }
+
+    #[test]
+    fn action_ignores_unhealthy_when_disabled() {
+        let supervision = ModuleSupervision {
+            restart_unhealthy: false,
+            ..ModuleSupervision::default()
+        };
+
+        assert_eq!(
+            None,
+            super::action(
+                &supervision,
+                ModuleStatus::Running,
+                Some(ModuleHealth::Unhealthy)
+            )
+        );
+    }
+
+    // Each check is the number of seconds since the first one, the status and health of the module,
+    // and the expected step. The default supervision backs off for 10, 20, 40, 80 and 160 seconds,
+    // detects a crash loop after 5 restarts and resets after 600 seconds of uptime.
+    #[test_case(
+        &[
+            (0, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (5, ModuleStatus::Failed, None, Step::Backoff),
+            (10, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (29, ModuleStatus::Failed, None, Step::Backoff),
+            (30, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+        ],
+        3;
+        "when restarts back off"
+    )]
+    #[test_case(
+        &[
+            (0, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (60, ModuleStatus::Running, None, Step::Idle),
+            (120, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (180, ModuleStatus::Running, None, Step::Idle),
+            (240, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (300, ModuleStatus::Running, None, Step::Idle),
+            (360, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (420, ModuleStatus::Running, None, Step::Idle),
+            (480, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (540, ModuleStatus::Running, None, Step::Idle),
+            (600, ModuleStatus::Failed, None, Step::Backoff),
+            (660, ModuleStatus::Failed, None, Step::CrashLoopDetected),
+            (720, ModuleStatus::Failed, None, Step::CrashLooping),
+        ],
+        5;
+        "when module crashes shortly after every check"
+    )]
+    #[test_case(
+        &[
+            (0, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (60, ModuleStatus::Running, None, Step::Idle),
+            (600, ModuleStatus::Running, None, Step::Idle),
+            (601, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (610, ModuleStatus::Failed, None, Step::Backoff),
+        ],
+        1;
+        "when module stays up for its stable uptime"
+    )]
+    #[test_case(
+        &[
+            (0, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (10, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (30, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (70, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (150, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+            (310, ModuleStatus::Failed, None, Step::CrashLoopDetected),
+            (400, ModuleStatus::Failed, None, Step::CrashLooping),
+            (460, ModuleStatus::Running, None, Step::Idle),
+            (470, ModuleStatus::Failed, None, Step::Act(Action::Start)),
+        ],
+        1;
+        "when crash-looping module runs again"
+    )]
+    #[test_case(
+        &[
+            (0, ModuleStatus::Running, Some(ModuleHealth::Unhealthy), Step::Act(Action::Restart)),
+            (5, ModuleStatus::Running, Some(ModuleHealth::Unhealthy), Step::Backoff),
+            (10, ModuleStatus::Running, Some(ModuleHealth::Healthy), Step::Idle),
+            (20, ModuleStatus::Running, Some(ModuleHealth::Unhealthy), Step::Act(Action::Restart)),
+        ],
+        2;
+        "when module is unhealthy"
+    )]
+    fn step(checks: &[(u64, ModuleStatus, Option<ModuleHealth>, Step)], restarts: u32) {
+        let supervision = ModuleSupervision::default();
+        let mut module = SupervisedModule::default();
+        let start = tokio::time::Instant::now();
+
+        for &(secs, status, health, expected) in checks {
+            let now = start + std::time::Duration::from_secs(secs);
+
+            assert_eq!(
+                expected,
+                module.step(&supervision, status, health, now),
+                "check after {} seconds",
+                secs
+            );
+        }
+
+        assert_eq!(restarts, module.restarts);
+    }
+}
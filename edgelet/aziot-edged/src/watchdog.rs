@@ -10,13 +10,17 @@ pub(crate) async fn run_until_shutdown(
     device_info: &aziot_identity_common::AzureIoTSpec,
     runtime: edgelet_docker::DockerModuleRuntime,
     identity_client: &aziot_identity_client_async::Client,
+    deployment_status: Option<
+        std::sync::Arc<futures_util::lock::Mutex<edgelet_core::DeploymentStatus>>,
+    >,
     mut shutdown_rx: tokio::sync::mpsc::UnboundedReceiver<edgelet_core::ShutdownReason>,
 ) -> Result<edgelet_core::ShutdownReason, EdgedError> {
-    // Run the watchdog every 60 seconds while waiting for any running task to send a
+    // Run the watchdog periodically while waiting for any running task to send a
     // shutdown signal.
-    let watchdog_period = std::time::Duration::from_secs(60);
+    let watchdog_period = settings.watchdog().period();
     let watchdog_retries = settings.watchdog().max_retries();
     let mut watchdog_errors = 0;
+    let mut supervisor = crate::supervisor::Supervisor::default();
 
     let mut watchdog_timer = tokio::time::interval(watchdog_period);
     watchdog_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
@@ -24,7 +28,10 @@ pub(crate) async fn run_until_shutdown(
     let shutdown_loop = shutdown_rx.recv();
     futures_util::pin_mut!(shutdown_loop);
 
-    log::info!("Starting watchdog with 60 second period...");
+    log::info!(
+        "Starting watchdog with {} second period...",
+        watchdog_period.as_secs()
+    );
 
     loop {
         let watchdog_next = watchdog_timer.tick();
@@ -32,8 +39,27 @@ pub(crate) async fn run_until_shutdown(
 
         match futures_util::future::select(watchdog_next, shutdown_loop).await {
             futures_util::future::Either::Left((_, shutdown)) => {
-                if let Err(err) = watchdog(&settings, device_info, &runtime, identity_client).await
-                {
+                let result = match (settings.local_deployment(), &deployment_status) {
+                    (Some(local_deployment), Some(status)) => {
+                        crate::deployment::reconcile(
+                            &settings,
+                            local_deployment,
+                            &runtime,
+                            &mut supervisor,
+                            status,
+                        )
+                        .await
+                    }
+                    _ => watchdog(&settings, device_info, &runtime, identity_client).await,
+                };
+
+                // Supervised modules failing to start don't count as watchdog errors, since
+                // the runtime itself is still managed.
+                if let Err(err) = supervisor.check(settings.watchdog(), &runtime).await {
+                    log::warn!("Error in module supervision: {}", err);
+                }
+
+                if let Err(err) = result {
                     log::warn!("Error in watchdog: {}", err);
 
                     watchdog_errors += 1;
@@ -46,6 +46,8 @@ pub struct InlineResponse200State {
     /// The time when this container last exited.
     #[serde(rename = "FinishedAt", skip_serializing_if = "Option::is_none")]
     finished_at: Option<String>,
+    #[serde(rename = "Health", skip_serializing_if = "Option::is_none")]
+    health: Option<crate::models::InlineResponse200StateHealth>,
 }
 
 impl InlineResponse200State {
@@ -63,6 +65,7 @@ impl InlineResponse200State {
             error: None,
             started_at: None,
             finished_at: None,
+            health: None,
         }
     }
 
@@ -252,4 +255,21 @@ impl InlineResponse200State {
     pub fn reset_finished_at(&mut self) {
         self.finished_at = None;
     }
+
+    pub fn set_health(&mut self, health: crate::models::InlineResponse200StateHealth) {
+        self.health = Some(health);
+    }
+
+    pub fn with_health(mut self, health: crate::models::InlineResponse200StateHealth) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    pub fn health(&self) -> Option<&crate::models::InlineResponse200StateHealth> {
+        self.health.as_ref()
+    }
+
+    pub fn reset_health(&mut self) {
+        self.health = None;
+    }
 }
@@ -144,6 +144,8 @@ mod inline_response_200_9;
 pub use self::inline_response_200_9::InlineResponse2009;
 mod inline_response_200_state;
 pub use self::inline_response_200_state::InlineResponse200State;
+mod inline_response_200_state_health;
+pub use self::inline_response_200_state_health::InlineResponse200StateHealth;
 mod inline_response_201;
 pub use self::inline_response_201::InlineResponse201;
 mod inline_response_201_1;
@@ -0,0 +1,146 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use edgelet_settings::deployment::DesiredStatus;
+
+use crate::module::ModuleStatus;
+
+/// Result of the last reconcile of a local deployment manifest.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_reconciled: Option<DateTime<Utc>>,
+
+    /// Set when the manifest could not be read or the runtime could not list modules,
+    /// in which case no module was reconciled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    modules: Vec<ModuleDeploymentStatus>,
+}
+
+impl DeploymentStatus {
+    pub fn new(modules: Vec<ModuleDeploymentStatus>) -> Self {
+        DeploymentStatus {
+            last_reconciled: Some(Utc::now()),
+            error: None,
+            modules,
+        }
+    }
+
+    pub fn failed(error: String) -> Self {
+        DeploymentStatus {
+            last_reconciled: Some(Utc::now()),
+            error: Some(error),
+            modules: Vec::new(),
+        }
+    }
+
+    pub fn last_reconciled(&self) -> Option<DateTime<Utc>> {
+        self.last_reconciled
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn modules(&self) -> &[ModuleDeploymentStatus] {
+        &self.modules
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleDeploymentStatus {
+    name: String,
+
+    /// `None` for modules that are not part of the manifest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    desired_status: Option<DesiredStatus>,
+
+    /// `None` if the module did not exist in the runtime.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runtime_status: Option<ModuleStatus>,
+
+    /// How the module differed from the manifest before it was reconciled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drift: Option<Drift>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl ModuleDeploymentStatus {
+    pub fn new(
+        name: String,
+        desired_status: Option<DesiredStatus>,
+        runtime_status: Option<ModuleStatus>,
+    ) -> Self {
+        ModuleDeploymentStatus {
+            name,
+            desired_status,
+            runtime_status,
+            drift: None,
+            error: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn desired_status(&self) -> Option<DesiredStatus> {
+        self.desired_status
+    }
+
+    pub fn runtime_status(&self) -> Option<ModuleStatus> {
+        self.runtime_status
+    }
+
+    pub fn drift(&self) -> Option<Drift> {
+        self.drift
+    }
+
+    pub fn set_drift(&mut self, drift: Drift) {
+        self.drift = Some(drift);
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Drift {
+    /// The module is in the manifest but does not exist in the runtime.
+    Missing,
+    /// The module exists in the runtime but is not in the manifest.
+    Unexpected,
+    /// The module runs a different image than the manifest specifies.
+    ImageChanged,
+    /// The module was created from a different configuration than the manifest specifies,
+    /// such as different create options or environment variables.
+    ConfigChanged,
+    /// The module is not in the status the manifest specifies.
+    StatusChanged,
+}
+
+impl std::fmt::Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Drift::Missing => f.write_str("module is missing"),
+            Drift::Unexpected => f.write_str("module is not in the deployment manifest"),
+            Drift::ImageChanged => f.write_str("module image differs from the manifest"),
+            Drift::ConfigChanged => f.write_str("module configuration differs from the manifest"),
+            Drift::StatusChanged => f.write_str("module status differs from the manifest"),
+        }
+    }
+}
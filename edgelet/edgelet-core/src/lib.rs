@@ -20,6 +20,7 @@ mod authentication;
 mod authorization;
 mod certificate_properties;
 pub mod crypto;
+pub mod deployment;
 pub mod error;
 mod identity;
 mod logs;
@@ -35,13 +36,14 @@ pub use crypto::{
     Certificate, CreateCertificate, GetDeviceIdentityCertificate, GetIssuerAlias, KeyBytes,
     PrivateKey,
 };
+pub use deployment::{DeploymentStatus, Drift, ModuleDeploymentStatus};
 pub use error::{Error, ErrorKind};
 pub use identity::{AuthType, Identity, IdentityManager, IdentityOperation, IdentitySpec};
 //pub use logs::{Chunked, LogChunk, LogDecode};
 pub use module::{
-    DiskInfo, LogOptions, LogTail, MakeModuleRuntime, Module, ModuleOperation, ModuleRegistry,
-    ModuleRuntime, ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleStatus, ProvisioningInfo,
-    RegistryOperation, RuntimeOperation, SystemInfo, SystemResources,
+    DiskInfo, LogOptions, LogTail, MakeModuleRuntime, Module, ModuleHealth, ModuleOperation,
+    ModuleRegistry, ModuleRuntime, ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleStatus,
+    ProvisioningInfo, RegistryOperation, RuntimeOperation, SystemInfo, SystemResources,
 };
 pub use parse_since::parse_since;
 pub use virtualization::is_virtualized_env;
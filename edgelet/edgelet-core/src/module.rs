@@ -27,6 +27,15 @@ pub enum ModuleStatus {
     Dead,
 }
 
+/// Result of the health check of a module, for modules that have one.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleHealth {
+    Starting,
+    Healthy,
+    Unhealthy,
+}
+
 pub enum ModuleAction {
     Start(String, tokio::sync::oneshot::Sender<()>),
     Stop(String),
@@ -62,6 +71,8 @@ pub struct ModuleRuntimeState {
     finished_at: Option<DateTime<Utc>>,
     image_id: Option<String>,
     pid: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    health: Option<ModuleHealth>,
 }
 
 impl Default for ModuleRuntimeState {
@@ -74,6 +85,7 @@ impl Default for ModuleRuntimeState {
             finished_at: None,
             image_id: None,
             pid: None,
+            health: None,
         }
     }
 }
@@ -141,6 +153,15 @@ impl ModuleRuntimeState {
         self.pid = pid;
         self
     }
+
+    pub fn health(&self) -> Option<ModuleHealth> {
+        self.health
+    }
+
+    pub fn with_health(mut self, health: Option<ModuleHealth>) -> Self {
+        self.health = health;
+        self
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
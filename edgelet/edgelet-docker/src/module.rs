@@ -6,7 +6,7 @@ use chrono::prelude::*;
 
 use docker::apis::{DockerApi, DockerApiClient};
 use docker::models::InlineResponse200State;
-use edgelet_core::{Module, ModuleOperation, ModuleRuntimeState, ModuleStatus};
+use edgelet_core::{Module, ModuleHealth, ModuleOperation, ModuleRuntimeState, ModuleStatus};
 use edgelet_settings::DockerConfig;
 
 use edgelet_utils::ensure_not_empty_with_context;
@@ -50,6 +50,19 @@ fn status_from_exit_code(exit_code: Option<i64>) -> Option<ModuleStatus> {
     })
 }
 
+fn health_from_state(state: &InlineResponse200State) -> Option<ModuleHealth> {
+    // Containers without a health check report no health, or a health of "none".
+    state
+        .health()
+        .and_then(|health| health.status())
+        .and_then(|status| match status {
+            "starting" => Some(ModuleHealth::Starting),
+            "healthy" => Some(ModuleHealth::Healthy),
+            "unhealthy" => Some(ModuleHealth::Unhealthy),
+            _ => None,
+        })
+}
+
 pub fn runtime_state(
     id: Option<&str>,
     response_state: Option<&InlineResponse200State>,
@@ -83,6 +96,7 @@ pub fn runtime_state(
             )
             .with_image_id(id.map(ToOwned::to_owned))
             .with_pid(state.pid())
+            .with_health(health_from_state(state))
     })
 }
 
@@ -0,0 +1,3 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+pub(super) mod status;
@@ -0,0 +1,104 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+pub(crate) struct Route<M>
+where
+    M: edgelet_core::ModuleRuntime + Send + Sync,
+{
+    deployment: Option<std::sync::Arc<futures_util::lock::Mutex<edgelet_core::DeploymentStatus>>>,
+    phantom: std::marker::PhantomData<M>,
+}
+
+const PATH: &str = "/deployment/status";
+
+#[async_trait::async_trait]
+impl<M> http_common::server::Route for Route<M>
+where
+    M: edgelet_core::ModuleRuntime + Send + Sync,
+{
+    type ApiVersion = edgelet_http::ApiVersion;
+    fn api_version() -> &'static dyn http_common::DynRangeBounds<Self::ApiVersion> {
+        &((edgelet_http::ApiVersion::V2020_10_10)..)
+    }
+
+    type Service = crate::Service<M>;
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(std::borrow::Cow<'_, str>, std::borrow::Cow<'_, str>)],
+        _extensions: &http::Extensions,
+    ) -> Option<Self> {
+        if path != PATH {
+            return None;
+        }
+
+        Some(Route {
+            deployment: service.deployment.clone(),
+            phantom: std::marker::PhantomData,
+        })
+    }
+
+    type DeleteBody = serde::de::IgnoredAny;
+
+    async fn get(self) -> http_common::server::RouteResponse {
+        let deployment = self
+            .deployment
+            .ok_or_else(|| edgelet_http::error::not_found("local deployment is not configured"))?;
+        let deployment = deployment.lock().await;
+
+        Ok(http_common::server::response::json(
+            hyper::StatusCode::OK,
+            &*deployment,
+        ))
+    }
+
+    type PostBody = serde::de::IgnoredAny;
+
+    type PutBody = serde::de::IgnoredAny;
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_test_utils::{test_route_err, test_route_ok};
+
+    #[test]
+    fn parse_uri() {
+        // Valid URI
+        test_route_ok!(super::PATH);
+
+        // Extra character at beginning of URI
+        test_route_err!(&format!("a{}", super::PATH));
+
+        // Extra character at end of URI
+        test_route_err!(&format!("{}a", super::PATH));
+    }
+
+    #[tokio::test]
+    async fn not_configured() {
+        let route = test_route_ok!(super::PATH);
+
+        let response = route.get().await.unwrap_err();
+        assert_eq!(hyper::StatusCode::NOT_FOUND, response.status_code);
+    }
+
+    #[tokio::test]
+    async fn get_status() {
+        let mut module = edgelet_core::ModuleDeploymentStatus::new(
+            "testModule".to_string(),
+            Some(edgelet_settings::deployment::DesiredStatus::Running),
+            None,
+        );
+        module.set_drift(edgelet_core::Drift::Missing);
+        let status = edgelet_core::DeploymentStatus::new(vec![module]);
+
+        let mut route = test_route_ok!(super::PATH);
+        route.deployment = Some(std::sync::Arc::new(futures_util::lock::Mutex::new(
+            status.clone(),
+        )));
+
+        let response = route.get().await.unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let response: edgelet_core::DeploymentStatus = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(status, response);
+    }
+}
@@ -1,5 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+mod deployment;
 mod device_actions;
 mod identity;
 mod module;
@@ -19,6 +20,7 @@ where
     identity: std::sync::Arc<futures_util::lock::Mutex<IdentityClient>>,
     runtime: std::sync::Arc<futures_util::lock::Mutex<M>>,
     reprovision: tokio::sync::mpsc::UnboundedSender<edgelet_core::ShutdownReason>,
+    deployment: Option<std::sync::Arc<futures_util::lock::Mutex<edgelet_core::DeploymentStatus>>>,
 }
 
 impl<M> Service<M>
@@ -30,6 +32,9 @@ where
         identity_socket: &url::Url,
         runtime: M,
         reprovision: tokio::sync::mpsc::UnboundedSender<edgelet_core::ShutdownReason>,
+        deployment: Option<
+            std::sync::Arc<futures_util::lock::Mutex<edgelet_core::DeploymentStatus>>,
+        >,
     ) -> Result<Self, http_common::ConnectorError> {
         let connector = http_common::Connector::new(identity_socket)?;
 
@@ -46,6 +51,7 @@ where
             identity,
             runtime,
             reprovision,
+            deployment,
         })
     }
 
@@ -67,6 +73,7 @@ where
             identity,
             runtime,
             reprovision: reprovision_tx,
+            deployment: None,
         }
     }
 
@@ -92,6 +99,7 @@ where
                 identity,
                 runtime,
                 reprovision: reprovision_tx,
+                deployment: None,
             },
             reprovision_rx,
         )
@@ -121,5 +129,7 @@ http_common::make_service! {
         system_info::support_bundle::Route<M>,
 
         device_actions::reprovision::Route<M>,
+
+        deployment::status::Route<M>,
     ],
 }
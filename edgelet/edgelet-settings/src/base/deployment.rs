@@ -0,0 +1,187 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+/// Settings for running modules from a local deployment manifest instead of through Edge Agent.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Settings {
+    /// Path to the deployment manifest. It is read again on every reconcile,
+    /// so changes to the file are applied without restarting aziot-edged.
+    pub manifest: std::path::PathBuf,
+}
+
+impl Settings {
+    pub fn manifest(&self) -> &std::path::Path {
+        &self.manifest
+    }
+}
+
+/// A local deployment manifest, describing every module that should run on the device.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Manifest<ModuleConfig> {
+    #[serde(default)]
+    pub modules: std::collections::BTreeMap<String, Module<ModuleConfig>>,
+}
+
+impl<ModuleConfig> Manifest<ModuleConfig> {
+    /// Returns the modules in the order they should be started, by startup order and then by name.
+    pub fn modules_in_startup_order(&self) -> Vec<(&str, &Module<ModuleConfig>)> {
+        let mut modules: Vec<_> = self
+            .modules
+            .iter()
+            .map(|(name, module)| (name.as_str(), module))
+            .collect();
+        modules.sort_by_key(|(name, module)| (module.startup_order, *name));
+
+        modules
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Module<ModuleConfig> {
+    r#type: String,
+
+    #[serde(default)]
+    status: DesiredStatus,
+
+    #[serde(default, rename = "restartPolicy")]
+    restart_policy: RestartPolicy,
+
+    #[serde(default = "default_startup_order", rename = "startupOrder")]
+    startup_order: u32,
+
+    #[serde(default, rename = "imagePullPolicy")]
+    image_pull_policy: super::module::ImagePullPolicy,
+
+    config: ModuleConfig,
+
+    #[serde(default)]
+    env: std::collections::BTreeMap<String, String>,
+}
+
+fn default_startup_order() -> u32 {
+    // Modules without a startup order are started last, same as in Edge Agent deployments.
+    u32::MAX
+}
+
+impl<ModuleConfig> Module<ModuleConfig> {
+    pub fn status(&self) -> DesiredStatus {
+        self.status
+    }
+
+    pub fn restart_policy(&self) -> RestartPolicy {
+        self.restart_policy
+    }
+
+    pub fn startup_order(&self) -> u32 {
+        self.startup_order
+    }
+
+    pub fn config(&self) -> &ModuleConfig {
+        &self.config
+    }
+
+    pub fn env(&self) -> &std::collections::BTreeMap<String, String> {
+        &self.env
+    }
+
+    /// Creates the spec the module runtime creates this module from.
+    pub fn to_spec(&self, name: &str) -> Result<super::module::Settings<ModuleConfig>, String>
+    where
+        ModuleConfig: Clone,
+    {
+        super::module::Settings::new(
+            name.to_string(),
+            self.r#type.clone(),
+            self.config.clone(),
+            self.env.clone(),
+            self.image_pull_policy,
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DesiredStatus {
+    Running,
+    Stopped,
+}
+
+impl Default for DesiredStatus {
+    fn default() -> Self {
+        DesiredStatus::Running
+    }
+}
+
+impl std::fmt::Display for DesiredStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DesiredStatus::Running => f.write_str("running"),
+            DesiredStatus::Stopped => f.write_str("stopped"),
+        }
+    }
+}
+
+/// Whether a module that should be running is started again after its container exited.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum RestartPolicy {
+    #[serde(rename = "never")]
+    Never,
+    #[serde(rename = "on-failure")]
+    OnFailure,
+    #[serde(rename = "always")]
+    Always,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::Always
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DesiredStatus, Manifest, RestartPolicy};
+
+    #[test]
+    fn manifest_defaults_and_order() {
+        let manifest: Manifest<serde_json::Value> = serde_json::from_value(serde_json::json!({
+            "modules": {
+                "late": {
+                    "type": "docker",
+                    "config": { "image": "late:1.0" }
+                },
+                "second": {
+                    "type": "docker",
+                    "status": "stopped",
+                    "restartPolicy": "on-failure",
+                    "startupOrder": 1,
+                    "config": { "image": "second:1.0" },
+                    "env": { "key": "value" }
+                },
+                "first": {
+                    "type": "docker",
+                    "restartPolicy": "never",
+                    "startupOrder": 0,
+                    "config": { "image": "first:1.0" }
+                }
+            }
+        }))
+        .unwrap();
+
+        let modules = manifest.modules_in_startup_order();
+        let names: Vec<_> = modules.iter().map(|(name, _)| *name).collect();
+        assert_eq!(vec!["first", "second", "late"], names);
+
+        let (_, late) = modules[2];
+        assert_eq!(DesiredStatus::Running, late.status());
+        assert_eq!(RestartPolicy::Always, late.restart_policy());
+        assert_eq!(u32::MAX, late.startup_order());
+
+        let (_, second) = modules[1];
+        assert_eq!(DesiredStatus::Stopped, second.status());
+        assert_eq!(RestartPolicy::OnFailure, second.restart_policy());
+
+        let spec = second.to_spec("second").unwrap();
+        assert_eq!("second", spec.name());
+        assert_eq!(Some("value"), spec.env().get("key").map(String::as_str));
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 pub mod aziot;
+pub mod deployment;
 pub mod module;
 pub mod uri;
 pub mod watchdog;
@@ -28,6 +29,8 @@ pub trait RuntimeSettings {
 
     fn watchdog(&self) -> &watchdog::Settings;
 
+    fn local_deployment(&self) -> Option<&deployment::Settings>;
+
     fn endpoints(&self) -> &aziot::Endpoints;
 
     fn allow_elevated_docker_permissions(&self) -> bool;
@@ -62,6 +65,10 @@ pub struct Settings<ModuleConfig> {
     #[serde(default)]
     pub watchdog: watchdog::Settings,
 
+    /// Run the modules of a local deployment manifest instead of Edge Agent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_deployment: Option<deployment::Settings>,
+
     /// Map of service names to endpoint URIs.
     ///
     /// Only configurable in debug builds for the sake of tests.
@@ -126,6 +133,10 @@ impl<T: Clone> RuntimeSettings for Settings<T> {
         &self.watchdog
     }
 
+    fn local_deployment(&self) -> Option<&deployment::Settings> {
+        self.local_deployment.as_ref()
+    }
+
     fn endpoints(&self) -> &aziot::Endpoints {
         &self.endpoints
     }
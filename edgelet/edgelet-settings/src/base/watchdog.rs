@@ -2,16 +2,143 @@
 
 use std::convert::TryInto;
 
+pub use super::deployment::{DesiredStatus, RestartPolicy};
+
+/// The watchdog period used when none is configured.
+pub const DEFAULT_PERIOD: std::time::Duration = std::time::Duration::from_secs(60);
+
 #[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
 pub struct Settings {
     #[serde(default)]
     pub max_retries: MaxRetries,
+
+    /// How often the watchdog checks modules, in seconds. Defaults to 60 seconds, which is also used for 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period_secs: Option<u64>,
+
+    /// Modules that the watchdog keeps running in addition to Edge Agent, by module name.
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub modules: std::collections::BTreeMap<String, ModuleSupervision>,
 }
 
 impl Settings {
     pub fn max_retries(&self) -> MaxRetries {
         self.max_retries
     }
+
+    pub fn period(&self) -> std::time::Duration {
+        self.period_secs
+            .filter(|&period_secs| period_secs > 0)
+            .map_or(DEFAULT_PERIOD, std::time::Duration::from_secs)
+    }
+
+    pub fn modules(&self) -> &std::collections::BTreeMap<String, ModuleSupervision> {
+        &self.modules
+    }
+}
+
+/// How the watchdog supervises a module.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ModuleSupervision {
+    /// Modules are only supervised while their desired status is running, so a module
+    /// which was stopped on purpose is left alone.
+    #[serde(default)]
+    pub status: DesiredStatus,
+
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+
+    /// Delay between the first and the second restart, in seconds.
+    /// Every further consecutive restart doubles the delay.
+    #[serde(default = "default_initial_backoff_secs")]
+    pub initial_backoff_secs: u64,
+
+    /// Upper bound of the delay between restarts, in seconds.
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+
+    /// Number of consecutive restarts after which the module is considered to be crash-looping
+    /// and is no longer restarted, until it is seen running again.
+    #[serde(default = "default_crash_loop_threshold")]
+    pub crash_loop_threshold: u32,
+
+    /// How long the module has to keep running after a restart, in seconds, before its
+    /// restarts are no longer considered consecutive.
+    #[serde(default = "default_stable_uptime_secs")]
+    pub stable_uptime_secs: u64,
+
+    /// Whether to restart the module when its Docker health check reports it as unhealthy.
+    #[serde(default = "default_restart_unhealthy")]
+    pub restart_unhealthy: bool,
+}
+
+fn default_initial_backoff_secs() -> u64 {
+    10
+}
+
+fn default_max_backoff_secs() -> u64 {
+    300
+}
+
+fn default_crash_loop_threshold() -> u32 {
+    5
+}
+
+fn default_stable_uptime_secs() -> u64 {
+    600
+}
+
+fn default_restart_unhealthy() -> bool {
+    true
+}
+
+impl Default for ModuleSupervision {
+    fn default() -> Self {
+        ModuleSupervision {
+            status: DesiredStatus::default(),
+            restart_policy: RestartPolicy::default(),
+            initial_backoff_secs: default_initial_backoff_secs(),
+            max_backoff_secs: default_max_backoff_secs(),
+            crash_loop_threshold: default_crash_loop_threshold(),
+            stable_uptime_secs: default_stable_uptime_secs(),
+            restart_unhealthy: default_restart_unhealthy(),
+        }
+    }
+}
+
+impl ModuleSupervision {
+    pub fn status(&self) -> DesiredStatus {
+        self.status
+    }
+
+    pub fn restart_policy(&self) -> RestartPolicy {
+        self.restart_policy
+    }
+
+    pub fn crash_loop_threshold(&self) -> u32 {
+        self.crash_loop_threshold
+    }
+
+    pub fn stable_uptime(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.stable_uptime_secs)
+    }
+
+    pub fn restart_unhealthy(&self) -> bool {
+        self.restart_unhealthy
+    }
+
+    /// Returns how long to wait before restarting the module, after it has already been
+    /// restarted `restarts` times in a row.
+    pub fn backoff(&self, restarts: u32) -> std::time::Duration {
+        let backoff = 2_u64
+            .checked_pow(restarts)
+            .and_then(|factor| self.initial_backoff_secs.checked_mul(factor))
+            .map_or(self.max_backoff_secs, |backoff| {
+                backoff.min(self.max_backoff_secs)
+            });
+
+        std::time::Duration::from_secs(backoff)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -141,4 +268,58 @@ mod tests {
         assert!(max_retries == 10);
         assert!(max_retries < 11);
     }
+
+    #[test]
+    fn supervision_defaults() {
+        let settings: super::Settings = serde_json::from_value(serde_json::json!({
+            "period_secs": 15,
+            "modules": {
+                "edgeHub": {},
+                "sensor": {
+                    "restart_policy": "on-failure",
+                    "stable_uptime_secs": 120,
+                    "restart_unhealthy": false
+                },
+                "maintenance": { "status": "stopped" }
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(std::time::Duration::from_secs(15), settings.period());
+
+        let edge_hub = &settings.modules()["edgeHub"];
+        assert_eq!(&super::ModuleSupervision::default(), edge_hub);
+        assert_eq!(super::DesiredStatus::Running, edge_hub.status());
+        assert_eq!(super::RestartPolicy::Always, edge_hub.restart_policy());
+        assert_eq!(
+            std::time::Duration::from_secs(600),
+            edge_hub.stable_uptime()
+        );
+
+        let sensor = &settings.modules()["sensor"];
+        assert_eq!(super::RestartPolicy::OnFailure, sensor.restart_policy());
+        assert_eq!(std::time::Duration::from_secs(120), sensor.stable_uptime());
+        assert!(!sensor.restart_unhealthy());
+
+        let maintenance = &settings.modules()["maintenance"];
+        assert_eq!(super::DesiredStatus::Stopped, maintenance.status());
+
+        let settings = super::Settings::default();
+        assert_eq!(super::DEFAULT_PERIOD, settings.period());
+        assert!(settings.modules().is_empty());
+    }
+
+    #[test]
+    fn supervision_backoff() {
+        let supervision = super::ModuleSupervision::default();
+
+        assert_eq!(std::time::Duration::from_secs(10), supervision.backoff(0));
+        assert_eq!(std::time::Duration::from_secs(20), supervision.backoff(1));
+        assert_eq!(std::time::Duration::from_secs(160), supervision.backoff(4));
+        assert_eq!(std::time::Duration::from_secs(300), supervision.backoff(5));
+        assert_eq!(
+            std::time::Duration::from_secs(300),
+            supervision.backoff(100)
+        );
+    }
 }
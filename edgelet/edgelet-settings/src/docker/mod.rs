@@ -102,6 +102,10 @@ impl crate::RuntimeSettings for Settings {
         self.base.watchdog()
     }
 
+    fn local_deployment(&self) -> Option<&crate::deployment::Settings> {
+        self.base.local_deployment()
+    }
+
     fn endpoints(&self) -> &crate::aziot::Endpoints {
         self.base.endpoints()
     }
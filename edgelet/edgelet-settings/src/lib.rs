@@ -13,7 +13,7 @@ pub mod base;
 
 pub use base::module::Settings as ModuleSpec;
 pub use base::RuntimeSettings;
-pub use base::{aziot, module, uri, watchdog};
+pub use base::{aziot, deployment, module, uri, watchdog};
 
 #[cfg(feature = "settings-docker")]
 pub mod docker;
@@ -62,6 +62,10 @@ impl edgelet_settings::RuntimeSettings for Settings {
         unimplemented!()
     }
 
+    fn local_deployment(&self) -> Option<&edgelet_settings::deployment::Settings> {
+        unimplemented!()
+    }
+
     fn endpoints(&self) -> &edgelet_settings::aziot::Endpoints {
         unimplemented!()
     }
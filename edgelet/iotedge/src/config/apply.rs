@@ -186,6 +186,7 @@ fn execute_inner(
         connect,
         listen,
         watchdog,
+        local_deployment,
         edge_ca,
         moby_runtime,
     } = toml::from_slice(&config).map_err(|err| format!("could not parse config file: {}", err))?;
@@ -418,6 +419,8 @@ fn execute_inner(
 
             watchdog,
 
+            local_deployment,
+
             endpoints: Default::default(),
         },
 
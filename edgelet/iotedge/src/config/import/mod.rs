@@ -509,9 +509,12 @@ fn execute_inner(
                         edgelet_settings::watchdog::MaxRetries::Num(num)
                     }
                 },
+                ..Default::default()
             }
         },
 
+        local_deployment: None,
+
         edge_ca,
 
         moby_runtime: {
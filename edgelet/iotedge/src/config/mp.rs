@@ -83,6 +83,8 @@ To reconfigure IoT Edge, run:
 
         watchdog: Default::default(),
 
+        local_deployment: None,
+
         edge_ca: None,
 
         moby_runtime: Default::default(),
@@ -46,6 +46,9 @@ pub struct Config {
     #[serde(default)]
     pub watchdog: edgelet_settings::watchdog::Settings,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_deployment: Option<edgelet_settings::deployment::Settings>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub edge_ca: Option<EdgeCa>,
 
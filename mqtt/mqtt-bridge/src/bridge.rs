@@ -6,11 +6,12 @@ use mqtt3::ShutdownError;
 use tracing::{debug, error, info, info_span};
 use tracing_futures::Instrument;
 
-use mqtt_util::Credentials;
+use mqtt_util::{Credentials, Failover};
 
 use crate::{
     client::{ClientError, MqttClientConfig},
-    config_update::BridgeDiff,
+    config_update::{self, BridgeDiff},
+    loop_guard::LoopGuard,
     persist::{PersistError, PublicationStore, RingBuffer, StreamWakeableState, WakingMemoryStore},
     pump::{Builder, Pump, PumpError, PumpHandle, PumpMessage},
     settings::{ConnectionSettings, MemorySettings, RingBufferSettings},
@@ -81,36 +82,23 @@ impl Bridge<WakingMemoryStore> {
         settings: &ConnectionSettings,
         memory_settings: MemorySettings,
     ) -> Result<Self, BridgeError> {
-        debug!("creating bridge {}...", settings.name());
+        let builder = Builder::<WakingMemoryStore>::default()
+            .with_store(move |_| Ok(PublicationStore::new_memory(&memory_settings)));
 
-        let (local_pump, remote_pump) = Builder::<WakingMemoryStore>::default()
-            .with_local(|pump| {
-                pump.with_config(MqttClientConfig::new(
-                    system_address,
-                    settings.keep_alive(),
-                    settings.clean_session(),
-                    Credentials::Anonymous(format!("{}/{}/$bridge", device_id, settings.name())),
-                ))
-                .with_rules(settings.forwards());
-            })
-            .with_remote(|pump| {
-                pump.with_config(MqttClientConfig::new(
-                    settings.address(),
-                    settings.keep_alive(),
-                    settings.clean_session(),
-                    settings.credentials().clone(),
-                ))
-                .with_rules(settings.subscriptions());
-            })
-            .with_store(move |_| Ok(PublicationStore::new_memory(&memory_settings)))
-            .build()?;
+        Self::build(system_address, device_id, settings, builder)
+    }
 
-        debug!("created bridge {}...", settings.name());
+    pub fn new_remote(
+        system_address: &str,
+        device_id: &str,
+        settings: &ConnectionSettings,
+        memory_settings: MemorySettings,
+    ) -> Result<Self, BridgeError> {
+        let builder = Builder::<WakingMemoryStore>::default()
+            .for_remote(settings.name())
+            .with_store(move |_| Ok(PublicationStore::new_memory(&memory_settings)));
 
-        Ok(Bridge {
-            local_pump,
-            remote_pump,
-        })
+        Self::build(system_address, device_id, settings, builder)
     }
 }
 
@@ -121,10 +109,78 @@ impl Bridge<RingBuffer> {
         settings: &ConnectionSettings,
         ring_buffer_settings: RingBufferSettings,
     ) -> Result<Self, BridgeError> {
-        debug!("creating bridge {}...", settings.name());
         let bridge_name = String::from(settings.name());
+        let builder = Builder::<RingBuffer>::default().with_store(move |suffix| {
+            PublicationStore::new_ring_buffer(&ring_buffer_settings, &bridge_name, suffix)
+        });
+
+        Self::build(system_address, device_id, settings, builder)
+    }
+
+    pub fn new_remote(
+        system_address: &str,
+        device_id: &str,
+        settings: &ConnectionSettings,
+        ring_buffer_settings: RingBufferSettings,
+    ) -> Result<Self, BridgeError> {
+        let bridge_name = String::from(settings.name());
+        let builder = Builder::<RingBuffer>::default()
+            .for_remote(settings.name())
+            .with_store(move |suffix| {
+                PublicationStore::new_ring_buffer(&ring_buffer_settings, &bridge_name, suffix)
+            });
+
+        Self::build(system_address, device_id, settings, builder)
+    }
+}
+
+fn remote_config(settings: &ConnectionSettings) -> MqttClientConfig {
+    let config = MqttClientConfig::new(
+        settings.address(),
+        settings.keep_alive(),
+        settings.clean_session(),
+        settings.credentials().clone(),
+    );
+
+    let failover = settings.failover();
+    if failover.addresses().is_empty() {
+        config
+    } else {
+        config.with_failover(Failover::new(
+            settings.address(),
+            failover.addresses().to_vec(),
+            failover.max_attempts(),
+            failover.fail_back_interval(),
+        ))
+    }
+}
 
-        let (local_pump, remote_pump) = Builder::<RingBuffer>::default()
+impl<S> Bridge<S>
+where
+    S: StreamWakeableState + Send,
+{
+    fn build(
+        system_address: &str,
+        device_id: &str,
+        settings: &ConnectionSettings,
+        mut builder: Builder<S>,
+    ) -> Result<Self, BridgeError> {
+        debug!("creating bridge {}...", settings.name());
+
+        config_update::warn_overlapping_rules(
+            settings.name(),
+            &settings.forwards(),
+            &settings.subscriptions(),
+        );
+
+        if let Some(loop_prevention) = settings.loop_prevention() {
+            builder = builder.with_loop_guard(LoopGuard::new(
+                format!("{}/{}", device_id, settings.name()),
+                loop_prevention.max_hops(),
+            ));
+        }
+
+        let (local_pump, remote_pump) = builder
             .with_local(|pump| {
                 pump.with_config(MqttClientConfig::new(
                     system_address,
@@ -135,16 +191,9 @@ impl Bridge<RingBuffer> {
                 .with_rules(settings.forwards());
             })
             .with_remote(|pump| {
-                pump.with_config(MqttClientConfig::new(
-                    settings.address(),
-                    settings.keep_alive(),
-                    settings.clean_session(),
-                    settings.credentials().clone(),
-                ))
-                .with_rules(settings.subscriptions());
-            })
-            .with_store(move |suffix| {
-                PublicationStore::new_ring_buffer(&ring_buffer_settings, &bridge_name, suffix)
+                pump.with_config(remote_config(settings))
+                    .with_rules(settings.subscriptions())
+                    .with_egress(settings.egress().clone());
             })
             .build()?;
 
@@ -155,12 +204,7 @@ impl Bridge<RingBuffer> {
             remote_pump,
         })
     }
-}
 
-impl<S> Bridge<S>
-where
-    S: StreamWakeableState + Send,
-{
     pub async fn run(self) -> Result<(), BridgeError> {
         info!("starting bridge...");
 
@@ -173,7 +217,7 @@ where
         // Send initial state as disconnected
         local_pump_handle
             .send(PumpMessage::Event(
-                LocalUpstreamPumpEvent::ConnectivityUpdate(ConnectivityState::Disconnected),
+                LocalUpstreamPumpEvent::ConnectivityUpdate(ConnectivityState::Disconnected, None),
             ))
             .await?;
 
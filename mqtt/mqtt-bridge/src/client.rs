@@ -13,7 +13,9 @@ use mqtt3::{
     proto::{self, Publication, SubscribeTo},
     Client, Event, ShutdownError, UpdateSubscriptionError,
 };
-use mqtt_util::{ClientIoSource, Credentials, SasTokenSource, TcpConnection, TrustBundleSource};
+use mqtt_util::{
+    ClientIoSource, Credentials, Failover, SasTokenSource, TcpConnection, TrustBundleSource,
+};
 
 const DEFAULT_MAX_RECONNECT: Duration = Duration::from_secs(60);
 // TODO: get QOS from topic settings
@@ -26,6 +28,7 @@ pub struct MqttClientConfig {
     keep_alive: Duration,
     clean_session: bool,
     credentials: Credentials,
+    failover: Option<Failover>,
 }
 
 impl MqttClientConfig {
@@ -40,8 +43,19 @@ impl MqttClientConfig {
             keep_alive,
             clean_session,
             credentials,
+            failover: None,
         }
     }
+
+    /// Sets secondary endpoints client fails over to when `addr` is unavailable.
+    pub fn with_failover(mut self, failover: Failover) -> Self {
+        self.failover = Some(failover);
+        self
+    }
+
+    pub fn failover(&self) -> Option<&Failover> {
+        self.failover.as_ref()
+    }
 }
 
 /// This is a wrapper over mqtt3 client
@@ -56,7 +70,10 @@ where
 {
     pub fn tcp(config: MqttClientConfig, event_handler: H) -> Result<Self, ClientError> {
         let token_source = Self::token_source(&config.credentials);
-        let tcp_connection = TcpConnection::new(config.addr, token_source, None);
+        let tcp_connection = Self::with_failover(
+            TcpConnection::new(config.addr, token_source, None),
+            config.failover,
+        );
         let io_source = ClientIoSource::Tcp(tcp_connection);
 
         Self::new(
@@ -72,7 +89,10 @@ where
         let trust_bundle = Some(TrustBundleSource::new(config.credentials.clone()));
 
         let token_source = Self::token_source(&config.credentials);
-        let tcp_connection = TcpConnection::new(config.addr, token_source, trust_bundle);
+        let tcp_connection = Self::with_failover(
+            TcpConnection::new(config.addr, token_source, trust_bundle),
+            config.failover,
+        );
         let io_source = ClientIoSource::Tls(tcp_connection);
 
         Self::new(
@@ -154,6 +174,16 @@ where
         Ok(())
     }
 
+    fn with_failover(
+        tcp_connection: TcpConnection<SasTokenSource>,
+        failover: Option<Failover>,
+    ) -> TcpConnection<SasTokenSource> {
+        match failover {
+            Some(failover) => tcp_connection.with_failover(failover),
+            None => tcp_connection,
+        }
+    }
+
     fn token_source(connection_credentials: &Credentials) -> Option<SasTokenSource> {
         match connection_credentials {
             Credentials::Provider(_) | Credentials::PlainText(_) => {
@@ -1,9 +1,13 @@
 use std::collections::HashMap;
 
 use serde::Deserialize;
-use tracing::debug;
+use tracing::{debug, warn};
 
-use crate::{bridge::BridgeHandle, controller::Error, settings::Direction, settings::TopicRule};
+use crate::{
+    bridge::BridgeHandle,
+    controller::Error,
+    settings::{ConnectionSettings, Direction, TopicRule},
+};
 
 /// Keeps the current subscriptions and forwards and calculates the diff with an `BridgeUpdate`
 /// It is used to send a diff to a `BridgeHandle` and update itself with latest configuration
@@ -35,8 +39,11 @@ impl ConfigUpdater {
     }
 
     fn diff(&self, bridge_update: BridgeUpdate) -> BridgeDiff {
+        let endpoint = bridge_update.endpoint().to_owned();
         let (forwards, subscriptions) = bridge_update.into_parts();
 
+        warn_overlapping_rules(&endpoint, &forwards, &subscriptions);
+
         let local_diff = diff_topic_rules(forwards, &self.current_forwards);
 
         let remote_diff = diff_topic_rules(subscriptions, &self.current_subscriptions);
@@ -55,6 +62,64 @@ impl ConfigUpdater {
     }
 }
 
+/// Logs a warning for each pair of forward and subscription rules which can
+/// make publications loop between brokers.
+pub(crate) fn warn_overlapping_rules(
+    name: &str,
+    forwards: &[TopicRule],
+    subscriptions: &[TopicRule],
+) {
+    for (forward, subscription) in overlapping_rules(forwards, subscriptions) {
+        warn!(
+            "bridge {} rules {:?} and {:?} overlap, publications may loop between brokers",
+            name, forward, subscription
+        );
+    }
+}
+
+/// Finds pairs of forward and subscription rules where one rule subscribes
+/// to topics the other rule publishes to, either on local or remote broker.
+fn overlapping_rules<'a>(
+    forwards: &'a [TopicRule],
+    subscriptions: &'a [TopicRule],
+) -> Vec<(&'a TopicRule, &'a TopicRule)> {
+    let mut overlaps = vec![];
+
+    for forward in forwards {
+        for subscription in subscriptions {
+            let local = filters_overlap(&forward.subscribe_to(), &publish_to(subscription));
+            let remote = filters_overlap(&subscription.subscribe_to(), &publish_to(forward));
+            if local || remote {
+                overlaps.push((forward, subscription));
+            }
+        }
+    }
+
+    overlaps
+}
+
+fn publish_to(rule: &TopicRule) -> String {
+    format!("{}{}", rule.out_prefix().unwrap_or_default(), rule.topic())
+}
+
+/// Checks whether there is a topic matched by both topic filters.
+fn filters_overlap(left: &str, right: &str) -> bool {
+    let mut left = left.split('/');
+    let mut right = right.split('/');
+
+    loop {
+        match (left.next(), right.next()) {
+            (Some("#"), _) | (_, Some("#")) | (None, None) => return true,
+            (Some(l), Some(r)) => {
+                if l != "+" && r != "+" && l != r {
+                    return false;
+                }
+            }
+            (None, Some(_)) | (Some(_), None) => return false,
+        }
+    }
+}
+
 fn diff_topic_rules(updated: Vec<TopicRule>, current: &HashMap<String, TopicRule>) -> PumpDiff {
     let mut added = vec![];
     let mut removed = vec![];
@@ -162,6 +227,74 @@ impl BridgeUpdate {
     }
 }
 
+/// A complete list of remote bridges the controller should run.
+///
+/// Remote bridges which are not in the list are stopped, new ones are
+/// started and bridges with changed settings are restarted.
+#[derive(Debug, Deserialize)]
+pub struct RemotesUpdate(Vec<ConnectionSettings>);
+
+impl RemotesUpdate {
+    pub fn new(remotes: Vec<ConnectionSettings>) -> Self {
+        Self(remotes)
+    }
+
+    /// Returns names of requested remote bridges.
+    pub fn names(&self) -> Vec<&str> {
+        self.0.iter().map(ConnectionSettings::name).collect()
+    }
+
+    /// Compares requested remote bridges with currently running ones.
+    pub fn diff(self, current: &HashMap<String, ConnectionSettings>) -> RemotesDiff {
+        let mut diff = RemotesDiff::default();
+
+        let updated = self
+            .0
+            .into_iter()
+            .map(|remote| (remote.name().to_owned(), remote))
+            .collect::<HashMap<_, _>>();
+
+        for name in current.keys() {
+            if !updated.contains_key(name) {
+                diff.removed.push(name.clone());
+            }
+        }
+
+        for (name, remote) in updated {
+            match current.get(&name) {
+                None => diff.added.push(remote),
+                Some(curr) if *curr != remote => diff.changed.push(remote),
+                Some(_) => {}
+            }
+        }
+
+        diff
+    }
+
+    pub fn into_inner(self) -> Vec<ConnectionSettings> {
+        self.0
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RemotesDiff {
+    added: Vec<ConnectionSettings>,
+    removed: Vec<String>,
+    changed: Vec<ConnectionSettings>,
+}
+
+impl RemotesDiff {
+    pub fn into_parts(
+        self,
+    ) -> (
+        Vec<ConnectionSettings>,
+        Vec<String>,
+        Vec<ConnectionSettings>,
+    ) {
+        (self.added, self.removed, self.changed)
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct BridgeDiff {
     local_pump_diff: PumpDiff,
@@ -221,6 +354,14 @@ impl PumpDiff {
 #[cfg(test)]
 #[allow(clippy::bool_assert_comparison)]
 mod tests {
+    use std::time::Duration;
+
+    use matches::assert_matches;
+
+    use mqtt_util::Credentials;
+
+    use crate::settings::StorageSettings;
+
     use super::*;
 
     #[test]
@@ -976,4 +1117,126 @@ mod tests {
         assert_eq!(subscriptions, vec![sub_rule]);
         assert_eq!(forwards, vec![forward_rule]);
     }
+
+    fn remote(name: &str, address: &str) -> ConnectionSettings {
+        ConnectionSettings::new(
+            name,
+            address,
+            Credentials::Anonymous(name.into()),
+            vec![],
+            Duration::from_secs(60),
+            false,
+        )
+    }
+
+    #[test]
+    fn remotes_diff_detects_added_removed_and_changed_bridges() {
+        let current = vec![
+            remote("r1", "remote1:8883"),
+            remote("r2", "remote2:8883"),
+            remote("r3", "remote3:8883"),
+        ]
+        .into_iter()
+        .map(|remote| (remote.name().to_owned(), remote))
+        .collect::<HashMap<_, _>>();
+
+        let update = RemotesUpdate::new(vec![
+            remote("r1", "remote1:8883"),
+            remote("r2", "other:8883"),
+            remote("r4", "remote4:8883"),
+        ]);
+
+        let (added, removed, changed) = update.diff(&current).into_parts();
+        assert_eq!(added, vec![remote("r4", "remote4:8883")]);
+        assert_eq!(removed, vec!["r3".to_owned()]);
+        assert_eq!(changed, vec![remote("r2", "other:8883")]);
+    }
+
+    #[test]
+    fn remotes_diff_with_same_bridges_is_empty() {
+        let current = vec![remote("r1", "remote1:8883")]
+            .into_iter()
+            .map(|remote| (remote.name().to_owned(), remote))
+            .collect::<HashMap<_, _>>();
+
+        let update = RemotesUpdate::new(vec![remote("r1", "remote1:8883")]);
+
+        assert_eq!(update.diff(&current), RemotesDiff::default());
+    }
+
+    #[test]
+    fn remotes_update_deserializes() {
+        let update: RemotesUpdate = serde_json::from_str(
+            r#"[{
+                "name": "floor2",
+                "address": "floor2:8883",
+                "client_id": "edge1",
+                "username": "user",
+                "password": "pass",
+                "subscriptions": [{ "direction": "in", "topic": "temp/#" }],
+                "keep_alive": "30s",
+                "clean_session": false,
+                "storage": { "type": "memory", "max_size": 1024 }
+            }]"#,
+        )
+        .unwrap();
+
+        let remotes = update.into_inner();
+        assert_eq!(remotes.len(), 1);
+        assert_eq!(remotes[0].name(), "floor2");
+        assert_eq!(remotes[0].keep_alive(), Duration::from_secs(30));
+        assert_matches!(remotes[0].storage(), Some(StorageSettings::Memory(_)));
+    }
+
+    #[test]
+    fn filters_overlap_with_wildcards() {
+        assert!(filters_overlap("a/b", "a/b"));
+        assert!(filters_overlap("a/+", "a/b"));
+        assert!(filters_overlap("a/#", "a"));
+        assert!(filters_overlap("#", "a/b/c"));
+        assert!(filters_overlap("+/b/#", "a/+/c"));
+
+        assert!(!filters_overlap("a/b", "a/c"));
+        assert!(!filters_overlap("a/+", "a/b/c"));
+        assert!(!filters_overlap("a/b", "a"));
+    }
+
+    #[test]
+    fn overlapping_rules_detects_loops_on_both_brokers() {
+        let forwards = vec![
+            TopicRule::new("temp/#", None, None),
+            TopicRule::new("#", Some("local/".into()), Some("remote/".into())),
+        ];
+        let subscriptions = vec![
+            TopicRule::new("temp/#", None, None),
+            TopicRule::new("cmd/#", None, Some("local/".into())),
+            TopicRule::new("status/#", None, None),
+        ];
+
+        let overlaps = overlapping_rules(&forwards, &subscriptions);
+
+        assert_eq!(
+            overlaps,
+            vec![
+                (&forwards[0], &subscriptions[0]),
+                (&forwards[1], &subscriptions[1]),
+            ]
+        );
+    }
+
+    #[test]
+    fn prefixed_rules_do_not_overlap() {
+        let forwards = vec![TopicRule::new(
+            "temp/#",
+            Some("local/".into()),
+            Some("remote/".into()),
+        )];
+        let subscriptions = vec![TopicRule::new(
+            "temp/#",
+            Some("remote/in/".into()),
+            Some("local/in/".into()),
+        )];
+
+        assert!(overlapping_rules(&forwards, &subscriptions).is_empty());
+    }
 }
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     pin::Pin,
     task::{Context, Poll},
 };
@@ -34,6 +34,7 @@ pub(crate) struct Bridges {
     bridge_handles: HashMap<String, BridgeHandle>,
     config_updaters: HashMap<String, ConfigUpdater>,
     bridges: FuturesUnordered<BridgeFuture>,
+    running: HashSet<String>,
 }
 
 impl Bridges {
@@ -52,11 +53,17 @@ impl Bridges {
         self.config_updaters.insert(name.clone(), config_updater);
 
         // start bridge
+        self.running.insert(name.clone());
         let upstream_bridge = bridge.run().instrument(info_span!("bridge", name = %name));
         let task = tokio::spawn(upstream_bridge).map(|res| (name, res));
         self.bridges.push(Box::pin(task));
     }
 
+    /// Returns whether a bridge with given name has not exited yet.
+    pub(crate) fn is_running(&self, name: &str) -> bool {
+        self.running.contains(name)
+    }
+
     pub(crate) async fn send_update(&mut self, update: BridgeUpdate) {
         let endpoint = update.endpoint().to_owned();
         if let Some(config) = self.config_updaters.get_mut(&endpoint) {
@@ -119,6 +126,7 @@ impl Stream for Bridges {
         if let Poll::Ready(Some((name, _))) = &poll {
             self.bridge_handles.remove(name);
             self.config_updaters.remove(name);
+            self.running.remove(name);
         }
 
         poll
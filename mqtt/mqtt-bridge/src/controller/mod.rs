@@ -1,8 +1,10 @@
 mod bridges;
+mod remotes;
 
 use std::collections::HashMap;
 
 use bridges::Bridges;
+use remotes::{RemoteAction, Remotes};
 
 use async_trait::async_trait;
 use futures_util::{
@@ -18,9 +20,9 @@ use mqtt_broker::sidecar::{Sidecar, SidecarShutdownHandle, SidecarShutdownHandle
 
 use crate::{
     bridge::{Bridge, BridgeError},
-    config_update::{BridgeControllerUpdate, BridgeUpdate},
+    config_update::{BridgeControllerUpdate, BridgeUpdate, RemotesUpdate},
     persist::{RingBuffer, WakingMemoryStore},
-    settings::{BridgeSettings, StorageSettings},
+    settings::{BridgeSettings, ConnectionSettings, StorageSettings},
 };
 
 const UPSTREAM: &str = "$upstream";
@@ -31,10 +33,16 @@ const UPSTREAM: &str = "$upstream";
 /// Controller handles monitors settings updates and starts a new `Bridge` or
 /// stops running `Bridge` if the number of bridges changes. In addition it
 /// prepares changes in forwarding rules and applies them to `Bridge` if required.
+///
+/// Besides upstream bridge controller runs remote bridges configured at
+/// runtime with `RemotesUpdate`: new bridges are started, missing ones are
+/// stopped and bridges with changed settings are restarted. Remote bridges
+/// listed in `BridgeSettings` are not started by the controller.
 pub struct BridgeController {
     system_address: String,
     device_id: String,
     settings: BridgeSettings,
+    remotes: Remotes,
     handle: BridgeControllerHandle,
     messages: Fuse<UnboundedReceiverStream<BridgeControllerMessage>>,
 }
@@ -48,6 +56,7 @@ impl BridgeController {
             system_address,
             device_id,
             settings,
+            remotes: Remotes::default(),
             handle,
             messages: UnboundedReceiverStream::new(updates_receiver).fuse(),
         }
@@ -57,43 +66,85 @@ impl BridgeController {
         self.handle.clone()
     }
 
-    async fn start_bridge(&self, bridges: &mut Bridges) {
+    async fn start_upstream(&self, bridges: &mut Bridges) {
         if let Some(upstream_settings) = self.settings.upstream() {
-            let storage_settings = self.settings.storage();
-            match storage_settings {
-                StorageSettings::Memory(memory_settings) => {
-                    match Bridge::<WakingMemoryStore>::new_upstream(
+            self.start_bridge(bridges, upstream_settings).await;
+        } else {
+            info!("no upstream settings detected");
+        }
+    }
+
+    async fn start_bridge(&self, bridges: &mut Bridges, settings: &ConnectionSettings) {
+        let storage_settings = settings
+            .storage()
+            .unwrap_or_else(|| self.settings.storage());
+        let upstream = settings.name() == UPSTREAM;
+
+        match storage_settings {
+            StorageSettings::Memory(memory_settings) => {
+                let bridge = if upstream {
+                    Bridge::<WakingMemoryStore>::new_upstream(
                         &self.system_address,
                         &self.device_id,
-                        upstream_settings,
+                        settings,
                         memory_settings.clone(),
-                    ) {
-                        Ok(bridge) => {
-                            bridges.start_bridge(bridge, upstream_settings).await;
-                        }
-                        Err(e) => {
-                            error!(err = %e, "failed to create {} bridge", UPSTREAM);
-                        }
+                    )
+                } else {
+                    Bridge::<WakingMemoryStore>::new_remote(
+                        &self.system_address,
+                        &self.device_id,
+                        settings,
+                        memory_settings.clone(),
+                    )
+                };
+
+                match bridge {
+                    Ok(bridge) => {
+                        bridges.start_bridge(bridge, settings).await;
+                    }
+                    Err(e) => {
+                        error!(err = %e, "failed to create {} bridge", settings.name());
                     }
                 }
-                StorageSettings::RingBuffer(ring_buffer_settings) => {
-                    match Bridge::<RingBuffer>::new_upstream(
+            }
+            StorageSettings::RingBuffer(ring_buffer_settings) => {
+                let bridge = if upstream {
+                    Bridge::<RingBuffer>::new_upstream(
+                        &self.system_address,
+                        &self.device_id,
+                        settings,
+                        ring_buffer_settings.clone(),
+                    )
+                } else {
+                    Bridge::<RingBuffer>::new_remote(
                         &self.system_address,
                         &self.device_id,
-                        upstream_settings,
+                        settings,
                         ring_buffer_settings.clone(),
-                    ) {
-                        Ok(bridge) => {
-                            bridges.start_bridge(bridge, upstream_settings).await;
-                        }
-                        Err(e) => {
-                            error!(err = %e, "failed to create {} bridge", UPSTREAM);
-                        }
+                    )
+                };
+
+                match bridge {
+                    Ok(bridge) => {
+                        bridges.start_bridge(bridge, settings).await;
+                    }
+                    Err(e) => {
+                        error!(err = %e, "failed to create {} bridge", settings.name());
                     }
                 }
             }
-        } else {
-            info!("no upstream settings detected");
+        }
+    }
+
+    async fn process_remotes_update(&mut self, update: RemotesUpdate, bridges: &mut Bridges) {
+        debug!("received remotes update: {:?}", update.names());
+
+        let actions = self.remotes.update(update, |name| bridges.is_running(name));
+        for action in actions {
+            match action {
+                RemoteAction::Start(remote) => self.start_bridge(bridges, &remote).await,
+                RemoteAction::Shutdown(name) => bridges.shutdown_bridge(&name).await,
+            }
         }
     }
 }
@@ -110,7 +161,7 @@ impl Sidecar for BridgeController {
 
         let mut bridges = Bridges::default();
 
-        self.start_bridge(&mut bridges).await;
+        self.start_upstream(&mut bridges).await;
 
         loop {
             let wait_bridge_or_pending = if bridges.is_terminated() {
@@ -125,6 +176,9 @@ impl Sidecar for BridgeController {
                 Either::Left((BridgeControllerMessage::BridgeControllerUpdate(update), _)) => {
                     process_update(update, &mut bridges).await;
                 }
+                Either::Left((BridgeControllerMessage::RemotesUpdate(update), _)) => {
+                    self.process_remotes_update(update, &mut bridges).await;
+                }
                 Either::Left((BridgeControllerMessage::Shutdown, _)) => {
                     info!("bridge controller shutdown requested");
                     bridges.shutdown_all().await;
@@ -141,10 +195,14 @@ impl Sidecar for BridgeController {
                         Err(e) => error!(error = %e, "bridge {} panicked ", name),
                     };
 
-                    // always restart upstream bridge
+                    // always restart upstream bridge and remote bridges which are
+                    // still configured
                     if name == UPSTREAM {
                         info!("restarting bridge...");
-                        self.start_bridge(&mut bridges).await;
+                        self.start_upstream(&mut bridges).await;
+                    } else if let Some(remote) = self.remotes.get(&name) {
+                        info!("restarting bridge {}...", name);
+                        self.start_bridge(&mut bridges, remote).await;
                     }
                 }
                 Either::Right((None, _)) => {
@@ -185,6 +243,10 @@ impl BridgeControllerHandle {
         self.send_message(BridgeControllerMessage::BridgeControllerUpdate(update))
     }
 
+    pub fn send_remotes_update(&mut self, update: RemotesUpdate) -> Result<(), Error> {
+        self.send_message(BridgeControllerMessage::RemotesUpdate(update))
+    }
+
     pub fn shutdown(mut self) {
         if let Err(e) = self.send_message(BridgeControllerMessage::Shutdown) {
             error!(error = %e, "unable to request shutdown for bridge controller");
@@ -208,9 +270,12 @@ impl BridgeControllerHandle {
 #[derive(Debug)]
 pub enum BridgeControllerMessage {
     BridgeControllerUpdate(BridgeControllerUpdate),
+    // Replace the set of remote bridges
+    RemotesUpdate(RemotesUpdate),
     // Shutdown all bridges
     Shutdown,
-    // Shutdown a bridge by name. $upstream bridge will be recreated if it is shutdown
+    // Shutdown a bridge by name. $upstream bridge and configured remote bridges
+    // will be recreated if they are shutdown
     ShutdownBridge(String),
 }
 
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use tracing::{info, warn};
+
+use crate::{config_update::RemotesUpdate, settings::ConnectionSettings};
+
+use super::UPSTREAM;
+
+/// An action `BridgeController` takes on running bridges to apply
+/// a `RemotesUpdate`.
+#[derive(Debug, PartialEq)]
+pub(crate) enum RemoteAction {
+    /// Start a new bridge with given settings.
+    Start(ConnectionSettings),
+
+    /// Shutdown a running bridge. It is restarted with the latest settings
+    /// when it exits if it is still configured.
+    Shutdown(String),
+}
+
+/// Keeps settings of remote bridges configured at runtime and calculates
+/// actions to apply a `RemotesUpdate` to running bridges.
+#[derive(Debug, Default)]
+pub(crate) struct Remotes {
+    remotes: HashMap<String, ConnectionSettings>,
+    upstream_warned: bool,
+}
+
+impl Remotes {
+    /// Returns settings of configured remote bridge.
+    pub(crate) fn get(&self, name: &str) -> Option<&ConnectionSettings> {
+        self.remotes.get(name)
+    }
+
+    /// Applies an update and returns actions required to run the updated
+    /// set of bridges.
+    ///
+    /// `is_running` reports whether a bridge with given name has not exited yet.
+    pub(crate) fn update(
+        &mut self,
+        update: RemotesUpdate,
+        is_running: impl Fn(&str) -> bool,
+    ) -> Vec<RemoteAction> {
+        let (upstream, remotes): (Vec<_>, Vec<_>) = update
+            .into_inner()
+            .into_iter()
+            .partition(|remote| remote.name() == UPSTREAM);
+
+        if !upstream.is_empty() && !self.upstream_warned {
+            warn!(
+                "{} bridge cannot be configured as a remote bridge",
+                UPSTREAM
+            );
+            self.upstream_warned = true;
+        }
+
+        let (added, removed, changed) =
+            RemotesUpdate::new(remotes).diff(&self.remotes).into_parts();
+
+        let mut actions = Vec::new();
+
+        for name in removed {
+            info!("removing bridge {}", name);
+            self.remotes.remove(&name);
+            actions.push(RemoteAction::Shutdown(name));
+        }
+
+        // running bridge is restarted with new settings when it exits
+        for remote in changed {
+            let name = remote.name().to_owned();
+            info!("reconfiguring bridge {}", name);
+
+            if is_running(&name) {
+                actions.push(RemoteAction::Shutdown(name.clone()));
+            } else {
+                actions.push(RemoteAction::Start(remote.clone()));
+            }
+            self.remotes.insert(name, remote);
+        }
+
+        for remote in added {
+            let name = remote.name().to_owned();
+            info!("adding bridge {}", name);
+
+            // bridge with the same name may still be shutting down,
+            // in this case it is restarted with new settings when it exits
+            if !is_running(&name) {
+                actions.push(RemoteAction::Start(remote.clone()));
+            }
+            self.remotes.insert(name, remote);
+        }
+
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use mqtt_util::Credentials;
+
+    use crate::{config_update::RemotesUpdate, settings::ConnectionSettings};
+
+    use super::{RemoteAction, Remotes};
+
+    fn remote(name: &str, address: &str) -> ConnectionSettings {
+        ConnectionSettings::new(
+            name,
+            address,
+            Credentials::Anonymous(name.into()),
+            vec![],
+            Duration::from_secs(60),
+            false,
+        )
+    }
+
+    fn not_running(_: &str) -> bool {
+        false
+    }
+
+    fn running(_: &str) -> bool {
+        true
+    }
+
+    #[test]
+    fn it_starts_added_bridges() {
+        let mut remotes = Remotes::default();
+
+        let update = RemotesUpdate::new(vec![remote("r1", "remote1:8883")]);
+        let actions = remotes.update(update, not_running);
+
+        assert_eq!(
+            actions,
+            vec![RemoteAction::Start(remote("r1", "remote1:8883"))]
+        );
+        assert_eq!(remotes.get("r1"), Some(&remote("r1", "remote1:8883")));
+    }
+
+    #[test]
+    fn it_does_not_start_added_bridge_still_running() {
+        let mut remotes = Remotes::default();
+
+        let update = RemotesUpdate::new(vec![remote("r1", "remote1:8883")]);
+        let actions = remotes.update(update, running);
+
+        assert_eq!(actions, vec![]);
+        assert_eq!(remotes.get("r1"), Some(&remote("r1", "remote1:8883")));
+    }
+
+    #[test]
+    fn it_shuts_down_removed_bridges() {
+        let mut remotes = Remotes::default();
+        remotes.update(
+            RemotesUpdate::new(vec![remote("r1", "remote1:8883")]),
+            not_running,
+        );
+
+        let actions = remotes.update(RemotesUpdate::new(vec![]), running);
+
+        assert_eq!(actions, vec![RemoteAction::Shutdown("r1".into())]);
+        assert_eq!(remotes.get("r1"), None);
+    }
+
+    #[test]
+    fn it_restarts_changed_running_bridge() {
+        let mut remotes = Remotes::default();
+        remotes.update(
+            RemotesUpdate::new(vec![remote("r1", "remote1:8883")]),
+            not_running,
+        );
+
+        let update = RemotesUpdate::new(vec![remote("r1", "other:8883")]);
+        let actions = remotes.update(update, running);
+
+        // bridge is restarted with new settings when it exits
+        assert_eq!(actions, vec![RemoteAction::Shutdown("r1".into())]);
+        assert_eq!(remotes.get("r1"), Some(&remote("r1", "other:8883")));
+    }
+
+    #[test]
+    fn it_starts_changed_bridge_not_running() {
+        let mut remotes = Remotes::default();
+        remotes.update(
+            RemotesUpdate::new(vec![remote("r1", "remote1:8883")]),
+            not_running,
+        );
+
+        let update = RemotesUpdate::new(vec![remote("r1", "other:8883")]);
+        let actions = remotes.update(update, not_running);
+
+        assert_eq!(
+            actions,
+            vec![RemoteAction::Start(remote("r1", "other:8883"))]
+        );
+    }
+
+    #[test]
+    fn it_keeps_unchanged_bridges() {
+        let mut remotes = Remotes::default();
+        remotes.update(
+            RemotesUpdate::new(vec![remote("r1", "remote1:8883")]),
+            not_running,
+        );
+
+        let update = RemotesUpdate::new(vec![remote("r1", "remote1:8883")]);
+        let actions = remotes.update(update, running);
+
+        assert_eq!(actions, vec![]);
+    }
+
+    #[test]
+    fn it_ignores_upstream_bridge() {
+        let mut remotes = Remotes::default();
+
+        let update = RemotesUpdate::new(vec![remote("$upstream", "upstream:8883")]);
+        let actions = remotes.update(update, not_running);
+        assert_eq!(actions, vec![]);
+        assert!(remotes.upstream_warned);
+
+        let update = RemotesUpdate::new(vec![
+            remote("$upstream", "upstream:8883"),
+            remote("r1", "remote1:8883"),
+        ]);
+        let actions = remotes.update(update, not_running);
+        assert_eq!(
+            actions,
+            vec![RemoteAction::Start(remote("r1", "remote1:8883"))]
+        );
+        assert_eq!(remotes.get("$upstream"), None);
+    }
+}
@@ -14,6 +14,7 @@ mod bridge;
 pub mod client;
 mod config_update;
 pub mod controller;
+mod loop_guard;
 mod messages;
 mod persist;
 pub mod pump;
@@ -21,7 +22,7 @@ pub mod settings;
 pub mod upstream;
 
 pub use crate::{
-    config_update::BridgeControllerUpdate,
+    config_update::{BridgeControllerUpdate, RemotesUpdate},
     controller::{BridgeController, BridgeControllerHandle, Error},
     persist::FlushOptions,
     settings::BridgeSettings,
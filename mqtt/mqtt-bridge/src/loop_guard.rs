@@ -0,0 +1,209 @@
+//! Loop prevention for publications forwarded by bridges.
+//!
+//! When loop prevention is enabled a bridge wraps the payload of every
+//! publication forwarded by a bridge-only topic rule into an envelope which
+//! contains a list of bridges the publication has passed through.
+//! Publications that already passed through the bridge or exceeded a hop
+//! limit are dropped.
+//!
+//! Subscribers receive the wrapped payload, so the envelope is restricted to
+//! topics exchanged between bridges. Publications of other topic rules are
+//! forwarded unchanged.
+//!
+//! Envelope format:
+//! ```text
+//! | magic (2) | version (1) | hops (1) | origins count (1) | origins ... | payload |
+//! ```
+//! where each origin is a big-endian `u16` length followed by UTF-8 bytes.
+
+use std::convert::TryFrom;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tracing::debug;
+
+const MAGIC: [u8; 2] = [0xe5, 0xb7];
+const VERSION: u8 = 1;
+
+/// Tags forwarded publications with a bridge origin and drops looped ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopGuard {
+    origin: String,
+    max_hops: u8,
+}
+
+impl LoopGuard {
+    pub fn new(origin: impl Into<String>, max_hops: u8) -> Self {
+        Self {
+            origin: origin.into(),
+            max_hops,
+        }
+    }
+
+    /// Returns a payload to forward or `None` if publication must be dropped.
+    pub fn process(&self, payload: &Bytes) -> Option<Bytes> {
+        let (mut envelope, payload) = Envelope::decode(payload);
+
+        if envelope.origins.contains(&self.origin) {
+            debug!("publication already passed through {}", self.origin);
+            return None;
+        }
+
+        if envelope.hops >= self.max_hops {
+            debug!("publication exceeded hop limit of {}", self.max_hops);
+            return None;
+        }
+
+        envelope.hops += 1;
+        envelope.origins.push(self.origin.clone());
+
+        envelope.encode(&payload)
+    }
+}
+
+/// Forwarding metadata attached to a publication payload.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Envelope {
+    hops: u8,
+    origins: Vec<String>,
+}
+
+impl Envelope {
+    pub fn hops(&self) -> u8 {
+        self.hops
+    }
+
+    pub fn origins(&self) -> &[String] {
+        &self.origins
+    }
+
+    /// Splits a payload into an envelope and original payload. Payloads
+    /// without a valid envelope are returned as is with an empty envelope.
+    pub fn decode(payload: &Bytes) -> (Self, Bytes) {
+        Self::try_decode(payload.clone()).unwrap_or_else(|| (Self::default(), payload.clone()))
+    }
+
+    fn try_decode(mut buf: Bytes) -> Option<(Self, Bytes)> {
+        if buf.len() < 5 || buf[..2] != MAGIC || buf[2] != VERSION {
+            return None;
+        }
+        buf.advance(3);
+
+        let hops = buf.get_u8();
+        let count = buf.get_u8();
+
+        let mut origins = Vec::with_capacity(count.into());
+        for _ in 0..count {
+            if buf.remaining() < 2 {
+                return None;
+            }
+            let len = buf.get_u16().into();
+            if buf.remaining() < len {
+                return None;
+            }
+            let origin = String::from_utf8(buf.split_to(len).to_vec()).ok()?;
+            origins.push(origin);
+        }
+
+        Some((Self { hops, origins }, buf))
+    }
+
+    /// Wraps a payload into the envelope. Returns `None` if envelope
+    /// cannot be encoded.
+    pub fn encode(&self, payload: &[u8]) -> Option<Bytes> {
+        let count = u8::try_from(self.origins.len()).ok()?;
+
+        let mut buf = BytesMut::new();
+        buf.put_slice(&MAGIC);
+        buf.put_u8(VERSION);
+        buf.put_u8(self.hops);
+        buf.put_u8(count);
+        for origin in &self.origins {
+            buf.put_u16(u16::try_from(origin.len()).ok()?);
+            buf.put_slice(origin.as_bytes());
+        }
+        buf.put_slice(payload);
+
+        Some(buf.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{Envelope, LoopGuard};
+
+    #[test]
+    fn plain_payload_is_wrapped() {
+        let guard = LoopGuard::new("edge1/$upstream", 3);
+
+        let wrapped = guard.process(&Bytes::from("hello")).unwrap();
+        let (envelope, payload) = Envelope::decode(&wrapped);
+
+        assert_eq!(envelope.hops(), 1);
+        assert_eq!(envelope.origins(), &["edge1/$upstream".to_owned()]);
+        assert_eq!(payload, Bytes::from("hello"));
+    }
+
+    #[test]
+    fn publication_from_the_same_bridge_is_dropped() {
+        let guard = LoopGuard::new("edge1/$upstream", 3);
+
+        let wrapped = guard.process(&Bytes::from("hello")).unwrap();
+
+        assert_eq!(guard.process(&wrapped), None);
+    }
+
+    #[test]
+    fn publication_passes_through_other_bridges_until_hop_limit() {
+        let edge1 = LoopGuard::new("edge1/$upstream", 2);
+        let edge2 = LoopGuard::new("edge2/$upstream", 2);
+        let edge3 = LoopGuard::new("edge3/$upstream", 2);
+
+        let wrapped = edge1.process(&Bytes::from("hello")).unwrap();
+        let wrapped = edge2.process(&wrapped).unwrap();
+        let (envelope, payload) = Envelope::decode(&wrapped);
+
+        assert_eq!(envelope.hops(), 2);
+        assert_eq!(
+            envelope.origins(),
+            &["edge1/$upstream".to_owned(), "edge2/$upstream".to_owned()]
+        );
+        assert_eq!(payload, Bytes::from("hello"));
+
+        assert_eq!(edge3.process(&wrapped), None);
+    }
+
+    #[test]
+    fn publication_looped_through_several_bridges_is_dropped() {
+        let edge1 = LoopGuard::new("edge1/$upstream", 4);
+        let edge2 = LoopGuard::new("edge2/$upstream", 4);
+        let edge3 = LoopGuard::new("edge3/$upstream", 4);
+
+        let wrapped = edge1.process(&Bytes::from("hello")).unwrap();
+        let wrapped = edge2.process(&wrapped).unwrap();
+        let wrapped = edge3.process(&wrapped).unwrap();
+
+        assert_eq!(edge1.process(&wrapped), None);
+    }
+
+    #[test]
+    fn same_payload_published_again_is_forwarded() {
+        let guard = LoopGuard::new("edge1/$upstream", 3);
+
+        let first = guard.process(&Bytes::from("hello")).unwrap();
+        let second = guard.process(&Bytes::from("hello")).unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn truncated_envelope_is_treated_as_plain_payload() {
+        let payload = Bytes::from_static(&[0xe5, 0xb7, 1, 0, 1, 0, 10, b'a']);
+
+        let (envelope, decoded) = Envelope::decode(&payload);
+
+        assert_eq!(envelope, Envelope::default());
+        assert_eq!(decoded, payload);
+    }
+}
@@ -18,9 +18,10 @@ use crate::client::UpdateSubscriptionHandle;
 use crate::{
     bridge::BridgeError,
     client::{Handled, MqttEventHandler},
+    loop_guard::LoopGuard,
     persist::{PersistError, PublicationStore, RingBufferError, StreamWakeableState},
     pump::TopicMapperUpdates,
-    settings::TopicRule,
+    settings::{Priority, TopicRule},
 };
 
 #[derive(Default, Clone, Debug)]
@@ -52,11 +53,20 @@ impl TryFrom<TopicRule> for TopicMapper {
 }
 
 /// Handle events from client and saves them with the forward topic
+///
+/// Publications matched by a high priority rule are saved to a priority
+/// store if one is set.
+///
+/// If a loop guard is set, publications of bridge-only rules which already
+/// passed through the bridge are dropped and others are tagged with the
+/// bridge origin. Publications of other rules are forwarded unchanged.
 pub struct StoreMqttEventHandler<S> {
     topic_mappers: HashMap<String, TopicMapper>,
     topic_mappers_updates: TopicMapperUpdates,
     store: PublicationStore<S>,
+    priority_store: Option<PublicationStore<S>>,
     retry_sub_send: Option<UnboundedSender<SubscribeTo>>,
+    loop_guard: Option<LoopGuard>,
 }
 
 impl<S> StoreMqttEventHandler<S> {
@@ -65,7 +75,9 @@ impl<S> StoreMqttEventHandler<S> {
             topic_mappers: HashMap::new(),
             topic_mappers_updates,
             store,
+            priority_store: None,
             retry_sub_send: None,
+            loop_guard: None,
         }
     }
 
@@ -73,7 +85,15 @@ impl<S> StoreMqttEventHandler<S> {
         self.retry_sub_send = Some(sender);
     }
 
-    fn transform(&self, topic_name: &str) -> Option<String> {
+    pub fn set_priority_store(&mut self, store: PublicationStore<S>) {
+        self.priority_store = Some(store);
+    }
+
+    pub fn set_loop_guard(&mut self, loop_guard: LoopGuard) {
+        self.loop_guard = Some(loop_guard);
+    }
+
+    fn transform(&self, topic_name: &str) -> Option<(String, &TopicRule)> {
         self.topic_mappers.values().find_map(|mapper| {
             if mapper.topic_filter.matches(topic_name) {
                 mapper
@@ -100,7 +120,7 @@ impl<S> StoreMqttEventHandler<S> {
                             );
                             None
                         } else {
-                            Some(transformed_topic)
+                            Some((transformed_topic, &mapper.topic_settings))
                         }
                     })
             } else {
@@ -149,16 +169,35 @@ where
             Event::Publication(publication) => {
                 let forward_publication =
                     self.transform(&publication.topic_name)
-                        .map(|topic_name| Publication {
-                            topic_name,
-                            qos: publication.qos,
-                            retain: publication.retain,
-                            payload: publication.payload.clone(),
+                        .map(|(topic_name, rule)| {
+                            let publication = Publication {
+                                topic_name,
+                                qos: publication.qos,
+                                retain: publication.retain,
+                                payload: publication.payload.clone(),
+                            };
+                            (publication, rule.priority(), rule.bridge_only())
                         });
 
-                if let Some(publication) = forward_publication {
+                if let Some((mut forward_publication, priority, bridge_only)) = forward_publication
+                {
+                    if let Some(loop_guard) = self.loop_guard.as_ref().filter(|_| bridge_only) {
+                        match loop_guard.process(&forward_publication.payload) {
+                            Some(payload) => forward_publication.payload = payload,
+                            None => {
+                                debug!("dropping looped publication on {}", publication.topic_name);
+                                return Ok(Handled::Fully);
+                            }
+                        }
+                    }
+
+                    let store = match (&self.priority_store, priority) {
+                        (Some(priority_store), Priority::High) => priority_store,
+                        _ => &self.store,
+                    };
+
                     debug!("saving message to store");
-                    return match self.store.push(&publication) {
+                    return match store.push(&forward_publication) {
                         Ok(_) => Ok(Handled::Fully),
                         Err(
                             err
@@ -197,7 +236,9 @@ where
 
                 return Ok(Handled::Fully);
             }
-            Event::NewConnection { reset_session: _ } | Event::Disconnected(_) => {}
+            Event::NewConnection { reset_session: _ }
+            | Event::Disconnected(_)
+            | Event::ConnectionAttemptFailed { .. } => {}
         }
 
         Ok(Handled::Skipped(event))
@@ -237,6 +278,7 @@ pub async fn retry_subscriptions<S: Stream<Item = SubscribeTo> + Unpin>(
 mod tests {
     use std::{
         collections::HashMap,
+        convert::TryInto,
         fmt::Debug,
         num::{NonZeroU64, NonZeroUsize},
         path::PathBuf,
@@ -258,13 +300,14 @@ mod tests {
 
     use crate::{
         client::MqttEventHandler,
+        loop_guard::LoopGuard,
         persist::{
             FlushOptions, PublicationStore, RingBuffer, StreamWakeableState, WakingMemoryStore,
         },
         pump::TopicMapperUpdates,
         settings::{
-            BridgeSettings, ConnectionSettings, Direction, MemorySettings, RingBufferSettings,
-            StorageSettings, TopicRule,
+            BridgeSettings, ConnectionSettings, Direction, MemorySettings, Priority,
+            RingBufferSettings, StorageSettings, TopicRule,
         },
     };
 
@@ -966,6 +1009,166 @@ mod tests {
         assert_empty(handler.store.loader(BATCH_SIZE)).await;
     }
 
+    #[tokio::test]
+    async fn message_handler_drops_looped_publication() {
+        let loop_guard = LoopGuard::new("edge1/$upstream", 4);
+
+        // local -> remote
+        let rule = TopicRule::new("floor/#", Some("local/".into()), Some("remote/".into()))
+            .with_bridge_only(true);
+        let topics_updates = TopicMapperUpdates::new(HashMap::new());
+        topics_updates.insert("local/floor/#", rule.try_into().unwrap());
+        let mut local =
+            StoreMqttEventHandler::new(MemoryPublicationStore::default(), topics_updates);
+        local.set_loop_guard(loop_guard.clone());
+
+        // remote -> local
+        let rule = TopicRule::new("floor/#", Some("remote/".into()), Some("local/".into()))
+            .with_bridge_only(true);
+        let topics_updates = TopicMapperUpdates::new(HashMap::new());
+        topics_updates.insert("remote/floor/#", rule.try_into().unwrap());
+        let mut remote =
+            StoreMqttEventHandler::new(MemoryPublicationStore::default(), topics_updates);
+        remote.set_loop_guard(loop_guard);
+
+        subscribe(&mut local, "local/floor/#").await;
+        subscribe(&mut remote, "remote/floor/#").await;
+
+        let publication = |topic_name: &str, payload: Bytes| ReceivedPublication {
+            topic_name: topic_name.to_string(),
+            qos: QoS::AtLeastOnce,
+            retain: false,
+            payload,
+            dup: false,
+        };
+
+        // client publishes the same payload twice, both are forwarded
+        for _ in 0..2 {
+            local
+                .handle(Event::Publication(publication(
+                    "local/floor/1",
+                    Bytes::from("hello"),
+                )))
+                .await
+                .unwrap();
+        }
+
+        let mut loader = local.store.loader(BATCH_SIZE);
+        let (key, first) = loader.try_next().await.unwrap().unwrap();
+        local.store.remove(key).unwrap();
+        let (_, second) = loader.try_next().await.unwrap().unwrap();
+        assert_eq!(first.topic_name, "remote/floor/1");
+        assert_ne!(first.payload, Bytes::from("hello"));
+        assert_eq!(first, second);
+
+        // the forwarded publication received back from remote broker is dropped
+        remote
+            .handle(Event::Publication(publication(
+                "remote/floor/1",
+                first.payload,
+            )))
+            .await
+            .unwrap();
+        assert_empty(remote.store.loader(BATCH_SIZE)).await;
+
+        // other publications from remote broker are forwarded
+        remote
+            .handle(Event::Publication(publication(
+                "remote/floor/1",
+                Bytes::from("hello"),
+            )))
+            .await
+            .unwrap();
+
+        let mut loader = remote.store.loader(BATCH_SIZE);
+        let (_, extracted) = loader.try_next().await.unwrap().unwrap();
+        assert_eq!(extracted.topic_name, "local/floor/1");
+    }
+
+    #[tokio::test]
+    async fn message_handler_forwards_payload_unchanged_for_rule_not_bridge_only() {
+        let rule = TopicRule::new("floor/#", Some("local/".into()), Some("remote/".into()));
+        let topics_updates = TopicMapperUpdates::new(HashMap::new());
+        topics_updates.insert("local/floor/#", rule.try_into().unwrap());
+        let mut handler =
+            StoreMqttEventHandler::new(MemoryPublicationStore::default(), topics_updates);
+        handler.set_loop_guard(LoopGuard::new("edge1/$upstream", 4));
+
+        subscribe(&mut handler, "local/floor/#").await;
+
+        let publication = ReceivedPublication {
+            topic_name: "local/floor/1".into(),
+            qos: QoS::AtLeastOnce,
+            retain: false,
+            payload: Bytes::from("hello"),
+            dup: false,
+        };
+        handler
+            .handle(Event::Publication(publication))
+            .await
+            .unwrap();
+
+        let mut loader = handler.store.loader(BATCH_SIZE);
+        let (_, extracted) = loader.try_next().await.unwrap().unwrap();
+        assert_eq!(extracted.topic_name, "remote/floor/1");
+        assert_eq!(extracted.payload, Bytes::from("hello"));
+    }
+
+    #[tokio::test]
+    async fn message_handler_saves_message_of_added_priority_rule_to_priority_store() {
+        let topics_updates = TopicMapperUpdates::new(HashMap::new());
+
+        let mut handler =
+            StoreMqttEventHandler::new(MemoryPublicationStore::default(), topics_updates.clone());
+        handler.set_priority_store(MemoryPublicationStore::default());
+
+        // high priority rule is added after the handler was created
+        let rule =
+            TopicRule::new("alerts/#", None, Some("remote/".into())).with_priority(Priority::High);
+        topics_updates.insert("alerts/#", rule.try_into().unwrap());
+
+        handler
+            .handle(Event::SubscriptionUpdates(vec![
+                SubscriptionUpdateEvent::Subscribe(SubscribeTo {
+                    topic_filter: "alerts/#".to_string(),
+                    qos: QoS::AtLeastOnce,
+                }),
+            ]))
+            .await
+            .unwrap();
+
+        let pub1 = ReceivedPublication {
+            topic_name: "alerts/fire".to_string(),
+            qos: QoS::AtLeastOnce,
+            retain: false,
+            payload: Bytes::from("hello"),
+            dup: false,
+        };
+        handler.handle(Event::Publication(pub1)).await.unwrap();
+
+        let priority_store = handler.priority_store.as_ref().unwrap();
+        let mut loader = priority_store.loader(BATCH_SIZE);
+        let (_, extracted) = loader.try_next().await.unwrap().unwrap();
+        assert_eq!(extracted.topic_name, "remote/alerts/fire");
+
+        assert_empty(handler.store.loader(BATCH_SIZE)).await;
+    }
+
+    async fn subscribe<S>(handler: &mut StoreMqttEventHandler<S>, topic_filter: &str)
+    where
+        S: StreamWakeableState + Send,
+    {
+        handler
+            .handle(Event::SubscriptionUpdates(vec![
+                SubscriptionUpdateEvent::Subscribe(SubscribeTo {
+                    topic_filter: topic_filter.to_string(),
+                    qos: QoS::AtLeastOnce,
+                }),
+            ]))
+            .await
+            .unwrap();
+    }
+
     async fn assert_empty<S: Stream<Item = T> + Unpin, T: Debug>(mut stream: S) {
         time::timeout(Duration::from_millis(500), stream.next())
             .await
@@ -6,13 +6,14 @@ use tokio_stream::wrappers::UnboundedReceiverStream;
 use crate::{
     bridge::BridgeError,
     client::{MqttClient, MqttClientConfig, MqttClientExt},
+    loop_guard::LoopGuard,
     messages::{self, StoreMqttEventHandler, TopicMapper},
     persist::{PersistResult, PublicationStore, StreamWakeableState},
-    settings::TopicRule,
+    settings::{EgressSettings, Priority, TopicRule},
     upstream::{
         ConnectivityMqttEventHandler, LocalRpcMqttEventHandler, LocalUpstreamMqttEventHandler,
         LocalUpstreamPumpEventHandler, RemoteRpcMqttEventHandler, RemoteUpstreamMqttEventHandler,
-        RemoteUpstreamPumpEventHandler, RpcSubscriptions,
+        RemoteUpstreamPumpEventHandler, RpcSubscriptions, CONNECTIVITY_TOPIC,
     },
 };
 
@@ -35,10 +36,15 @@ type BoxedStorageCreatedFn<S> = Box<StoreCreateFn<S>>;
 /// Remote pump connects to a remote broker, subscribes to topics to receive
 /// messages from remote broker and put it in the store of the local pump.
 /// Also reads messages from a remote store and publishes them to local broker.
+///
+/// Pumps of a remote bridge other than upstream do not handle RPC commands
+/// and report connectivity on a topic with a bridge name.
 pub struct Builder<S> {
     local: PumpBuilder,
     remote: PumpBuilder,
     store: Option<BoxedStorageCreatedFn<S>>,
+    remote_name: Option<String>,
+    loop_guard: Option<LoopGuard>,
 }
 
 impl<S> Default for Builder<S> {
@@ -47,6 +53,8 @@ impl<S> Default for Builder<S> {
             local: PumpBuilder::default(),
             remote: PumpBuilder::default(),
             store: None,
+            remote_name: None,
+            loop_guard: None,
         }
     }
 }
@@ -73,6 +81,12 @@ where
         self
     }
 
+    /// Creates pumps for a remote bridge with given name instead of upstream.
+    pub fn for_remote(mut self, name: impl Into<String>) -> Self {
+        self.remote_name = Some(name.into());
+        self
+    }
+
     /// Setups a factory to create publication store.
     pub fn with_store<F, S1>(self, store: F) -> Builder<S1>
     where
@@ -82,9 +96,17 @@ where
             local: self.local,
             remote: self.remote,
             store: Some(Box::new(store)),
+            remote_name: self.remote_name,
+            loop_guard: self.loop_guard,
         }
     }
 
+    /// Drops looped publications and tags forwarded ones in both pumps.
+    pub fn with_loop_guard(mut self, loop_guard: LoopGuard) -> Self {
+        self.loop_guard = Some(loop_guard);
+        self
+    }
+
     /// Creates a pair of local and remote pump.
     pub fn build(&mut self) -> Result<PumpPair<S>, BridgeError> {
         let store = self.store.as_ref().ok_or(BridgeError::UnsetStorage)?;
@@ -92,6 +114,16 @@ where
         let remote_store = (store)("remote")?;
         let local_store = (store)("local")?;
 
+        // high priority forwards are stored separately so that they are not
+        // held back by normal priority publications outside of transmission windows.
+        // The store is only created if configured rules have high priority, high
+        // priority rules added with a bridge update use the regular store instead.
+        let remote_priority_store = if has_priority_rules(&self.local.rules) {
+            Some((store)("remote-priority")?)
+        } else {
+            None
+        };
+
         let (remote_messages_send, remote_messages_recv) = mpsc::channel(100);
         let (local_messages_send, local_messages_recv) = mpsc::channel(100);
 
@@ -99,9 +131,20 @@ where
         let topic_filters = make_topics(&self.local.rules)?;
         let local_topic_mappers_updates = TopicMapperUpdates::new(topic_filters);
 
-        let rpc = LocalRpcMqttEventHandler::new(PumpHandle::new(remote_messages_send.clone()));
-        let messages =
+        let remote_pump_handle = PumpHandle::new(remote_messages_send.clone());
+        let rpc = if self.remote_name.is_some() {
+            LocalRpcMqttEventHandler::without_commands(remote_pump_handle)
+        } else {
+            LocalRpcMqttEventHandler::new(remote_pump_handle)
+        };
+        let mut messages =
             StoreMqttEventHandler::new(remote_store.clone(), local_topic_mappers_updates.clone());
+        if let Some(remote_priority_store) = &remote_priority_store {
+            messages.set_priority_store(remote_priority_store.clone());
+        }
+        if let Some(loop_guard) = &self.loop_guard {
+            messages.set_loop_guard(loop_guard.clone());
+        }
 
         let handler = LocalUpstreamMqttEventHandler::new(messages, rpc);
 
@@ -114,7 +157,10 @@ where
             .update_subscription_handle()
             .map_err(BridgeError::UpdateSubscriptionHandle)?;
 
-        let handler = LocalUpstreamPumpEventHandler::new(local_pub_handle);
+        let mut handler = LocalUpstreamPumpEventHandler::new(local_pub_handle);
+        if let Some(name) = &self.remote_name {
+            handler = handler.with_connectivity_topic(format!("{}/{}", CONNECTIVITY_TOPIC, name));
+        }
         let pump_handle = PumpHandle::new(local_messages_send.clone());
         let messages = MessagesProcessor::new(
             handler,
@@ -128,6 +174,8 @@ where
             local_messages_send.clone(),
             client,
             local_store.clone(),
+            None,
+            &self.local.egress,
             messages,
         )?;
 
@@ -142,11 +190,20 @@ where
         let mut messages =
             StoreMqttEventHandler::new(local_store, remote_topic_mappers_updates.clone());
         messages.set_retry_sub_sender(retry_send);
+        if let Some(loop_guard) = &self.loop_guard {
+            messages.set_loop_guard(loop_guard.clone());
+        }
 
-        let connectivity = ConnectivityMqttEventHandler::new(PumpHandle::new(local_messages_send));
+        let config = self.remote.client.take().expect("remote client config");
+
+        let mut connectivity =
+            ConnectivityMqttEventHandler::new(PumpHandle::new(local_messages_send));
+        if let Some(failover) = config.failover() {
+            connectivity.set_failover(failover.clone());
+        }
+        let connectivity_status = connectivity.status();
         let handler = RemoteUpstreamMqttEventHandler::new(messages, rpc, connectivity);
 
-        let config = self.remote.client.take().expect("remote client config");
         let client = MqttClient::tls(config, handler).map_err(BridgeError::ValidationError)?;
         let remote_pub_handle = client
             .publish_handle()
@@ -170,6 +227,7 @@ where
             remote_pub_handle,
             local_pump.handle(),
             rpc_subscriptions,
+            connectivity_status,
         );
         let pump_handle = PumpHandle::new(remote_messages_send.clone());
 
@@ -184,7 +242,14 @@ where
             remote_topic_mappers_updates,
         );
 
-        let remote_pump = Pump::new(remote_messages_send, client, remote_store, messages)?;
+        let remote_pump = Pump::new(
+            remote_messages_send,
+            client,
+            remote_store,
+            remote_priority_store,
+            &self.remote.egress,
+            messages,
+        )?;
 
         Ok((local_pump, remote_pump))
     }
@@ -195,6 +260,7 @@ where
 pub struct PumpBuilder {
     client: Option<MqttClientConfig>,
     rules: Vec<TopicRule>,
+    egress: EgressSettings,
 }
 
 impl PumpBuilder {
@@ -209,6 +275,16 @@ impl PumpBuilder {
         self.client = Some(config);
         self
     }
+
+    /// Applies bandwidth and transmission window settings to pump egress.
+    pub fn with_egress(&mut self, egress: EgressSettings) -> &mut Self {
+        self.egress = egress;
+        self
+    }
+}
+
+fn has_priority_rules(rules: &[TopicRule]) -> bool {
+    rules.iter().any(|rule| rule.priority() == Priority::High)
 }
 
 fn make_topics(rules: &[TopicRule]) -> Result<HashMap<String, TopicMapper>, BridgeError> {
@@ -1,8 +1,8 @@
-use std::num::NonZeroUsize;
+use std::{num::NonZeroUsize, sync::Arc};
 
 use futures_util::{
-    pin_mut,
-    stream::{StreamExt, TryStreamExt},
+    future, pin_mut,
+    stream::{self, Stream, StreamExt, TryStreamExt},
 };
 use lazy_static::lazy_static;
 use mockall_double::double;
@@ -11,7 +11,12 @@ use tracing::{debug, error, info};
 
 #[double]
 use crate::client::PublishHandle;
-use crate::persist::{Key, PublicationStore, StreamWakeableState};
+use crate::{
+    persist::{Key, PublicationStore, StreamWakeableState},
+    settings::EgressSettings,
+};
+
+use super::shaping::{Lane, Shaper};
 
 use mqtt3::proto::Publication;
 
@@ -26,9 +31,14 @@ lazy_static! {
 /// It loads messages from the local store and publishes them as MQTT messages
 /// to the broker. After acknowledgement is received from the broker it
 /// deletes publication from the store.
+///
+/// Publications from an optional priority store are sent alongside the
+/// regular ones, bypassing transmission windows of egress settings.
 pub(crate) struct Egress<S> {
     publish_handle: PublishHandle,
     store: PublicationStore<S>,
+    priority_store: Option<PublicationStore<S>>,
+    shaper: Shaper,
     shutdown_send: Option<oneshot::Sender<()>>,
     shutdown_recv: oneshot::Receiver<()>,
 }
@@ -38,12 +48,19 @@ where
     S: StreamWakeableState,
 {
     /// Creates a new instance of egress.
-    pub(crate) fn new(publish_handle: PublishHandle, store: PublicationStore<S>) -> Egress<S> {
+    pub(crate) fn new(
+        publish_handle: PublishHandle,
+        store: PublicationStore<S>,
+        priority_store: Option<PublicationStore<S>>,
+        settings: &EgressSettings,
+    ) -> Egress<S> {
         let (shutdown_send, shutdown_recv) = oneshot::channel();
 
         Self {
             publish_handle,
             store,
+            priority_store,
+            shaper: Shaper::new(settings),
             shutdown_send: Some(shutdown_send),
             shutdown_recv,
         }
@@ -59,24 +76,19 @@ where
         let Egress {
             publish_handle,
             store,
+            priority_store,
+            shaper,
             mut shutdown_recv,
             ..
         } = self;
 
         info!("starting egress publication processing...");
 
-        // Take the stream of loaded messages and convert to a stream of futures
-        // which publish. Then convert to buffered stream so that we can have
-        // multiple in-flight and also limit number of publications.
-        let publications = store
-            .loader(*BATCH_SIZE)
-            .map_err(EgressError::LoadPublication)
-            .try_filter_map(|(key, publication)| {
-                let publish_handle = publish_handle.clone();
-                async move { Ok(Some(try_publish(key, publication, publish_handle))) }
-            })
-            .try_buffered(MAX_IN_FLIGHT)
-            .fuse();
+        let shaper = Arc::new(shaper);
+        let normal = publish_from(store, publish_handle.clone(), shaper.clone(), Lane::Normal);
+        let priority =
+            priority_store.map(|store| publish_from(store, publish_handle, shaper, Lane::Priority));
+        let publications = stream::select(normal, stream::iter(priority).flatten()).fuse();
 
         pin_mut!(publications);
 
@@ -86,9 +98,8 @@ where
                     debug!("received shutdown signal for egress messages");
                     break;
                 }
-                maybe_key = publications.select_next_some() => {
-                    let key = maybe_key?;
-                    store.remove(key).map_err(|e| EgressError::RemovePublication(key, e))?;
+                processed = publications.select_next_some() => {
+                    processed?;
                 }
             }
         }
@@ -98,6 +109,40 @@ where
     }
 }
 
+/// Takes the stream of loaded messages and converts it to a stream of futures
+/// which publish. Then converts to buffered stream so that we can have
+/// multiple in-flight and also limit number of publications. Publications are
+/// removed from the store in order once acknowledged.
+fn publish_from<S>(
+    store: PublicationStore<S>,
+    publish_handle: PublishHandle,
+    shaper: Arc<Shaper>,
+    lane: Lane,
+) -> impl Stream<Item = Result<(), EgressError>>
+where
+    S: StreamWakeableState,
+{
+    store
+        .loader(*BATCH_SIZE)
+        .map_err(EgressError::LoadPublication)
+        .and_then(move |(key, publication)| {
+            let publish_handle = publish_handle.clone();
+            let shaper = shaper.clone();
+            async move {
+                shaper.ready(lane, &publication).await;
+                Ok(try_publish(key, publication, publish_handle))
+            }
+        })
+        .try_buffered(MAX_IN_FLIGHT)
+        .and_then(move |key| {
+            future::ready(
+                store
+                    .remove(key)
+                    .map_err(|e| EgressError::RemovePublication(key, e)),
+            )
+        })
+}
+
 async fn try_publish(
     key: Key,
     publication: Publication,
@@ -2,6 +2,7 @@ mod builder;
 mod egress;
 mod ingress;
 mod messages;
+mod shaping;
 
 use std::{collections::HashMap, error::Error as StdError, fmt::Debug, future::Future, sync::Arc};
 
@@ -26,6 +27,7 @@ use crate::{
     config_update::PumpDiff,
     messages::TopicMapper,
     persist::{PublicationStore, StreamWakeableState},
+    settings::EgressSettings,
 };
 
 #[cfg(test)]
@@ -79,6 +81,8 @@ where
         messages_send: mpsc::Sender<PumpMessage<M::Message>>,
         client: MqttClient<H>,
         store: PublicationStore<S>,
+        priority_store: Option<PublicationStore<S>>,
+        egress_settings: &EgressSettings,
         messages: MessagesProcessor<M>,
     ) -> Result<Self, BridgeError> {
         let client_shutdown = client.shutdown_handle()?;
@@ -86,7 +90,7 @@ where
             .publish_handle()
             .map_err(BridgeError::PublishHandle)?;
 
-        let egress = Egress::new(publish_handle, store);
+        let egress = Egress::new(publish_handle, store, priority_store, egress_settings);
         let ingress = Ingress::new(client, client_shutdown);
 
         Ok(Self {
@@ -0,0 +1,188 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use tokio::time::{self, Instant};
+use tracing::debug;
+
+use mqtt3::proto::Publication;
+
+use crate::settings::{EgressSettings, TimeOfDay, TransmissionWindow};
+
+/// Identifies which store publication was loaded from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Lane {
+    /// Normal priority publications, sent only when a transmission window is open.
+    Normal,
+
+    /// High priority publications, sent regardless of transmission windows.
+    Priority,
+}
+
+/// Controls the rate and time at which egress sends publications.
+///
+/// Both lanes share the same bandwidth limit, only normal lane is subject
+/// to transmission windows.
+pub(crate) struct Shaper {
+    throttle: Option<Throttle>,
+    windows: Vec<TransmissionWindow>,
+}
+
+impl Shaper {
+    pub(crate) fn new(settings: &EgressSettings) -> Self {
+        Self {
+            throttle: settings
+                .max_bytes_per_sec()
+                .map(|rate| Throttle::new(rate.get())),
+            windows: settings.windows().to_vec(),
+        }
+    }
+
+    /// Waits until publication is allowed to be sent.
+    pub(crate) async fn ready(&self, lane: Lane, publication: &Publication) {
+        if lane == Lane::Normal {
+            self.wait_window().await;
+        }
+
+        if let Some(throttle) = &self.throttle {
+            let size = publication.topic_name.len() + publication.payload.len();
+            throttle.acquire(size).await;
+        }
+    }
+
+    async fn wait_window(&self) {
+        loop {
+            let delay = match opens_in(&self.windows, now()) {
+                Some(delay) if delay > Duration::default() => delay,
+                _ => return,
+            };
+
+            debug!("egress transmission window is closed, waiting {:?}", delay);
+            time::sleep(delay).await;
+        }
+    }
+}
+
+/// Returns the time left until any of the windows opens.
+fn opens_in(windows: &[TransmissionWindow], time: TimeOfDay) -> Option<Duration> {
+    windows.iter().map(|window| window.opens_in(time)).min()
+}
+
+fn now() -> TimeOfDay {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    TimeOfDay::from_unix_secs(secs)
+}
+
+/// Limits the number of bytes sent per second.
+///
+/// Each acquired chunk reserves the time slot it takes to transmit at the
+/// configured rate, callers wait until their slot starts.
+struct Throttle {
+    bytes_per_sec: u64,
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            next_slot: Mutex::new(None),
+        }
+    }
+
+    async fn acquire(&self, bytes: usize) {
+        let delay = self.reserve(bytes, Instant::now());
+        if delay > Duration::default() {
+            time::sleep(delay).await;
+        }
+    }
+
+    fn reserve(&self, bytes: usize, now: Instant) -> Duration {
+        let mut next_slot = self.next_slot.lock();
+
+        let start = next_slot.map_or(now, |slot| slot.max(now));
+        #[allow(clippy::cast_precision_loss)]
+        let transmit = Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+        *next_slot = Some(start + transmit);
+
+        start - now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::settings::{TimeOfDay, TransmissionWindow};
+
+    use super::{opens_in, Throttle};
+
+    fn time(s: &str) -> TimeOfDay {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn window_contains_time() {
+        let window = TransmissionWindow::new(time("01:00"), time("05:30"));
+
+        assert!(window.contains(time("01:00")));
+        assert!(window.contains(time("05:29")));
+        assert!(!window.contains(time("05:30")));
+        assert!(!window.contains(time("00:59")));
+    }
+
+    #[test]
+    fn window_wraps_around_midnight() {
+        let window = TransmissionWindow::new(time("22:00"), time("06:00"));
+
+        assert!(window.contains(time("23:15")));
+        assert!(window.contains(time("00:00")));
+        assert!(!window.contains(time("12:00")));
+        assert_eq!(window.opens_in(time("21:00")), Duration::from_secs(60 * 60));
+        assert_eq!(
+            window.opens_in(time("06:00")),
+            Duration::from_secs(16 * 60 * 60)
+        );
+    }
+
+    #[test]
+    fn closest_window_opens_first() {
+        let windows = vec![
+            TransmissionWindow::new(time("22:00"), time("23:00")),
+            TransmissionWindow::new(time("12:00"), time("13:00")),
+        ];
+
+        assert_eq!(
+            opens_in(&windows, time("11:30")),
+            Some(Duration::from_secs(30 * 60))
+        );
+        assert_eq!(opens_in(&windows, time("12:30")), Some(Duration::default()));
+        assert_eq!(opens_in(&[], time("12:30")), None);
+    }
+
+    #[test]
+    fn time_of_day_rejects_invalid_values() {
+        assert!("24:00".parse::<TimeOfDay>().is_err());
+        assert!("12:60".parse::<TimeOfDay>().is_err());
+        assert!("noon".parse::<TimeOfDay>().is_err());
+        assert_eq!(time("07:05").to_string(), "07:05");
+    }
+
+    #[test]
+    fn throttle_reserves_consecutive_slots() {
+        let throttle = Throttle::new(100);
+        let now = Instant::now();
+
+        assert_eq!(throttle.reserve(50, now), Duration::default());
+        assert_eq!(throttle.reserve(100, now), Duration::from_millis(500));
+        assert_eq!(throttle.reserve(10, now), Duration::from_millis(1500));
+
+        // idle time does not accumulate burst credit
+        let later = now + Duration::from_secs(10);
+        assert_eq!(throttle.reserve(10, later), Duration::default());
+    }
+}
@@ -1,6 +1,8 @@
 use std::{
-    num::{NonZeroU64, NonZeroUsize},
+    fmt::{Display, Formatter, Result as FmtResult},
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
     path::PathBuf,
+    str::FromStr,
     time::Duration,
     vec::Vec,
 };
@@ -12,6 +14,9 @@ use mqtt_util::{CredentialProviderSettings, Credentials};
 use crate::persist::FlushOptions;
 
 const DEFAULT_UPSTREAM_PORT: &str = "8883";
+const DEFAULT_FAILOVER_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_FAIL_BACK_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_HOPS: u8 = 4;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct BridgeSettings {
@@ -78,6 +83,10 @@ impl<'de> serde::Deserialize<'de> for BridgeSettings {
             credentials: Credentials::Provider(nested_bridge),
             clean_session: upstream.clean_session,
             keep_alive: upstream.keep_alive,
+            egress: upstream.egress,
+            failover: upstream.failover,
+            loop_prevention: upstream.loop_prevention,
+            storage: None,
         });
 
         Ok(BridgeSettings {
@@ -100,6 +109,18 @@ pub struct ConnectionSettings {
     #[serde(with = "humantime_serde")]
     keep_alive: Duration,
     clean_session: bool,
+
+    #[serde(default)]
+    egress: EgressSettings,
+
+    #[serde(default)]
+    failover: FailoverSettings,
+
+    #[serde(default)]
+    loop_prevention: Option<LoopPreventionSettings>,
+
+    #[serde(default)]
+    storage: Option<StorageSettings>,
 }
 
 impl ConnectionSettings {
@@ -118,9 +139,33 @@ impl ConnectionSettings {
             subscriptions,
             keep_alive,
             clean_session,
+            egress: EgressSettings::default(),
+            failover: FailoverSettings::default(),
+            loop_prevention: None,
+            storage: None,
         }
     }
 
+    pub fn with_loop_prevention(mut self, loop_prevention: LoopPreventionSettings) -> Self {
+        self.loop_prevention = Some(loop_prevention);
+        self
+    }
+
+    pub fn with_storage(mut self, storage: StorageSettings) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn with_failover(mut self, failover: FailoverSettings) -> Self {
+        self.failover = failover;
+        self
+    }
+
+    pub fn with_egress(mut self, egress: EgressSettings) -> Self {
+        self.egress = egress;
+        self
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -160,6 +205,257 @@ impl ConnectionSettings {
     pub fn clean_session(&self) -> bool {
         self.clean_session
     }
+
+    pub fn egress(&self) -> &EgressSettings {
+        &self.egress
+    }
+
+    pub fn failover(&self) -> &FailoverSettings {
+        &self.failover
+    }
+
+    pub fn loop_prevention(&self) -> Option<&LoopPreventionSettings> {
+        self.loop_prevention.as_ref()
+    }
+
+    pub fn storage(&self) -> Option<&StorageSettings> {
+        self.storage.as_ref()
+    }
+}
+
+/// Secondary endpoints the bridge switches to when the primary `address`
+/// is unavailable.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FailoverSettings {
+    /// Secondary endpoint addresses in order of preference.
+    #[serde(default)]
+    addresses: Vec<String>,
+
+    /// Number of consecutive failed connection attempts before switching
+    /// to the next endpoint.
+    #[serde(default = "default_failover_max_attempts")]
+    max_attempts: NonZeroU32,
+
+    /// How often the primary endpoint is probed while connected to a
+    /// secondary one. Fail back is disabled if not set.
+    #[serde(
+        default = "default_fail_back_interval",
+        with = "humantime_serde::option"
+    )]
+    fail_back_interval: Option<Duration>,
+}
+
+impl FailoverSettings {
+    pub fn new(
+        addresses: Vec<String>,
+        max_attempts: NonZeroU32,
+        fail_back_interval: Option<Duration>,
+    ) -> Self {
+        Self {
+            addresses,
+            max_attempts,
+            fail_back_interval,
+        }
+    }
+
+    pub fn addresses(&self) -> &[String] {
+        &self.addresses
+    }
+
+    pub fn max_attempts(&self) -> NonZeroU32 {
+        self.max_attempts
+    }
+
+    pub fn fail_back_interval(&self) -> Option<Duration> {
+        self.fail_back_interval
+    }
+}
+
+impl Default for FailoverSettings {
+    fn default() -> Self {
+        Self {
+            addresses: Vec::new(),
+            max_attempts: default_failover_max_attempts(),
+            fail_back_interval: default_fail_back_interval(),
+        }
+    }
+}
+
+fn default_failover_max_attempts() -> NonZeroU32 {
+    NonZeroU32::new(DEFAULT_FAILOVER_MAX_ATTEMPTS).expect("non-zero default")
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn default_fail_back_interval() -> Option<Duration> {
+    Some(DEFAULT_FAIL_BACK_INTERVAL)
+}
+
+/// Tags forwarded publications with the bridge origin to drop publications
+/// looping between brokers with bidirectional rules.
+///
+/// Only publications of topic rules marked with `bridgeOnly` are tagged, their
+/// payloads are wrapped into an envelope, so these topics must not be consumed
+/// by clients other than bridges. Publications of other rules are forwarded
+/// unchanged and are not checked for loops.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LoopPreventionSettings {
+    /// Maximum number of bridges a publication can pass through.
+    #[serde(default = "default_max_hops")]
+    max_hops: u8,
+}
+
+impl LoopPreventionSettings {
+    pub fn new(max_hops: u8) -> Self {
+        Self { max_hops }
+    }
+
+    pub fn max_hops(&self) -> u8 {
+        self.max_hops
+    }
+}
+
+impl Default for LoopPreventionSettings {
+    fn default() -> Self {
+        Self {
+            max_hops: default_max_hops(),
+        }
+    }
+}
+
+fn default_max_hops() -> u8 {
+    DEFAULT_MAX_HOPS
+}
+
+/// Traffic shaping applied to publications the bridge sends to the remote broker.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct EgressSettings {
+    /// Maximum number of bytes (topic name and payload) sent per second.
+    #[serde(default, deserialize_with = "deserialize_option_nonzerou64")]
+    max_bytes_per_sec: Option<NonZeroU64>,
+
+    /// Time-of-day windows (UTC) when normal priority publications are sent.
+    /// Publications are sent at any time if no windows are configured.
+    #[serde(default)]
+    windows: Vec<TransmissionWindow>,
+}
+
+impl EgressSettings {
+    pub fn new(max_bytes_per_sec: Option<NonZeroU64>, windows: Vec<TransmissionWindow>) -> Self {
+        Self {
+            max_bytes_per_sec,
+            windows,
+        }
+    }
+
+    pub fn max_bytes_per_sec(&self) -> Option<NonZeroU64> {
+        self.max_bytes_per_sec
+    }
+
+    pub fn windows(&self) -> &[TransmissionWindow] {
+        &self.windows
+    }
+}
+
+/// A daily transmission window in UTC. A window where `end` precedes `start`
+/// wraps around midnight, a window where they are equal spans the whole day.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct TransmissionWindow {
+    start: TimeOfDay,
+    end: TimeOfDay,
+}
+
+impl TransmissionWindow {
+    pub fn new(start: TimeOfDay, end: TimeOfDay) -> Self {
+        Self { start, end }
+    }
+
+    pub fn start(&self) -> TimeOfDay {
+        self.start
+    }
+
+    pub fn end(&self) -> TimeOfDay {
+        self.end
+    }
+
+    /// Checks whether a given time of day falls into the window.
+    pub fn contains(&self, time: TimeOfDay) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else if self.start > self.end {
+            self.start <= time || time < self.end
+        } else {
+            true
+        }
+    }
+
+    /// Returns time remaining until the window opens, zero if it is open.
+    pub fn opens_in(&self, time: TimeOfDay) -> Duration {
+        if self.contains(time) {
+            Duration::default()
+        } else {
+            time.until(self.start)
+        }
+    }
+}
+
+/// Time of day with a minute precision, parsed from `HH:MM` string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(u32);
+
+const SECS_PER_DAY: u32 = 24 * 60 * 60;
+
+impl TimeOfDay {
+    pub fn new(hours: u32, minutes: u32) -> Option<Self> {
+        if hours < 24 && minutes < 60 {
+            Some(Self(hours * 60 * 60 + minutes * 60))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the time of day for a number of seconds since UNIX epoch.
+    pub fn from_unix_secs(secs: u64) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self((secs % u64::from(SECS_PER_DAY)) as u32)
+    }
+
+    /// Returns time left until the next occurrence of `other`.
+    pub fn until(self, other: TimeOfDay) -> Duration {
+        let secs = (other.0 + SECS_PER_DAY - self.0) % SECS_PER_DAY;
+        Duration::from_secs(secs.into())
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid time of day {}, expected HH:MM", s);
+
+        let mut parts = s.splitn(2, ':');
+        let hours = parts.next().and_then(|hours| hours.parse().ok());
+        let minutes = parts.next().and_then(|minutes| minutes.parse().ok());
+
+        match (hours, minutes) {
+            (Some(hours), Some(minutes)) => Self::new(hours, minutes).ok_or_else(error),
+            _ => Err(error()),
+        }
+    }
+}
+
+impl std::convert::TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{:02}:{:02}", self.0 / 3600, self.0 % 3600 / 60)
+    }
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Deserialize)]
@@ -171,6 +467,14 @@ pub struct TopicRule {
 
     #[serde(rename = "inPrefix")]
     in_prefix: Option<String>,
+
+    #[serde(default)]
+    priority: Priority,
+
+    /// Publications are consumed only by bridges on both sides, so loop
+    /// prevention may wrap their payloads into an envelope.
+    #[serde(default, rename = "bridgeOnly")]
+    bridge_only: bool,
 }
 
 impl TopicRule {
@@ -183,9 +487,21 @@ impl TopicRule {
             topic: topic.into(),
             out_prefix,
             in_prefix,
+            priority: Priority::default(),
+            bridge_only: false,
         }
     }
 
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_bridge_only(mut self, bridge_only: bool) -> Self {
+        self.bridge_only = bridge_only;
+        self
+    }
+
     pub fn topic(&self) -> &str {
         &self.topic
     }
@@ -198,6 +514,14 @@ impl TopicRule {
         self.in_prefix.as_deref().filter(|s| !s.is_empty())
     }
 
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    pub fn bridge_only(&self) -> bool {
+        self.bridge_only
+    }
+
     pub fn subscribe_to(&self) -> String {
         match &self.in_prefix {
             Some(local) => {
@@ -208,6 +532,22 @@ impl TopicRule {
     }
 }
 
+/// Priority of publications forwarded by a topic rule.
+///
+/// High priority publications are exempt from egress transmission windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Normal,
+    High,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Normal
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(tag = "direction")]
 pub enum Direction {
@@ -227,6 +567,15 @@ struct UpstreamSettings {
     keep_alive: Duration,
     clean_session: bool,
     subscriptions: Vec<Direction>,
+
+    #[serde(default)]
+    egress: EgressSettings,
+
+    #[serde(default)]
+    failover: FailoverSettings,
+
+    #[serde(default)]
+    loop_prevention: Option<LoopPreventionSettings>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -310,6 +659,13 @@ where
     })
 }
 
+fn deserialize_option_nonzerou64<'de, D>(deserializer: D) -> Result<Option<NonZeroU64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_nonzerou64(deserializer).map(Some)
+}
+
 fn deserialize_nonzerouusize<'de, D>(deserializer: D) -> Result<NonZeroUsize, D::Error>
 where
     D: Deserializer<'de>,
@@ -335,3 +691,65 @@ where
         ))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Priority, TopicRule};
+
+    #[test]
+    fn it_deserializes_normal_priority_by_default() {
+        let rule: TopicRule = serde_json::from_str(r#"{ "topic": "temp/#" }"#).unwrap();
+
+        assert_eq!(rule.priority(), Priority::Normal);
+        assert_eq!(rule, TopicRule::new("temp/#", None, None));
+    }
+
+    #[test]
+    fn it_deserializes_priority() {
+        let rule: TopicRule =
+            serde_json::from_str(r#"{ "topic": "alerts/#", "priority": "high" }"#).unwrap();
+        assert_eq!(rule.priority(), Priority::High);
+
+        let rule: TopicRule =
+            serde_json::from_str(r#"{ "topic": "temp/#", "priority": "normal" }"#).unwrap();
+        assert_eq!(rule.priority(), Priority::Normal);
+    }
+
+    #[test]
+    fn it_deserializes_priority_in_direction() {
+        let direction: Direction = serde_json::from_str(
+            r#"{
+                "direction": "out",
+                "topic": "alerts/#",
+                "outPrefix": "remote/",
+                "priority": "high"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            direction,
+            Direction::Out(
+                TopicRule::new("alerts/#", None, Some("remote/".into()))
+                    .with_priority(Priority::High)
+            )
+        );
+    }
+
+    #[test]
+    fn it_deserializes_bridge_only_rule() {
+        let rule: TopicRule = serde_json::from_str(r#"{ "topic": "temp/#" }"#).unwrap();
+        assert!(!rule.bridge_only());
+
+        let rule: TopicRule =
+            serde_json::from_str(r#"{ "topic": "temp/#", "bridgeOnly": true }"#).unwrap();
+        assert!(rule.bridge_only());
+    }
+
+    #[test]
+    fn it_rejects_unknown_priority() {
+        let rule = serde_json::from_str::<TopicRule>(r#"{ "topic": "temp/#", "priority": "low" }"#);
+
+        assert!(rule.is_err());
+    }
+}
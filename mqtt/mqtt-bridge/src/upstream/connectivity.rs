@@ -1,10 +1,15 @@
-use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
+use parking_lot::Mutex;
 use serde::Serialize;
 use tracing::{debug, info};
 
 use mqtt3::Event;
+use mqtt_util::Failover;
 
 use crate::{
     client::{Handled, MqttEventHandler},
@@ -28,19 +33,63 @@ impl Display for ConnectivityState {
     }
 }
 
+/// Shared view of the latest connectivity state of a client.
+#[derive(Clone, Debug)]
+pub struct ConnectivityStatus(Arc<Mutex<ConnectivityState>>);
+
+impl ConnectivityStatus {
+    pub fn new(state: ConnectivityState) -> Self {
+        Self(Arc::new(Mutex::new(state)))
+    }
+
+    pub fn get(&self) -> ConnectivityState {
+        *self.0.lock()
+    }
+
+    fn set(&self, state: ConnectivityState) {
+        *self.0.lock() = state;
+    }
+}
+
+impl Default for ConnectivityStatus {
+    fn default() -> Self {
+        Self::new(ConnectivityState::Disconnected)
+    }
+}
+
 /// Handles connection and disconnection events and sends a notification when status changes
+///
+/// When the client fails over between several endpoints, the notification
+/// contains the endpoint the client is connected to.
 pub struct ConnectivityMqttEventHandler {
     state: ConnectivityState,
+    status: ConnectivityStatus,
     sender: PumpHandle<LocalUpstreamPumpEvent>,
+    failover: Option<Failover>,
 }
 
 impl ConnectivityMqttEventHandler {
     pub fn new(sender: PumpHandle<LocalUpstreamPumpEvent>) -> Self {
         ConnectivityMqttEventHandler {
             state: ConnectivityState::Disconnected,
+            status: ConnectivityStatus::default(),
             sender,
+            failover: None,
         }
     }
+
+    /// Returns a shared view of connectivity state maintained by the handler.
+    pub fn status(&self) -> ConnectivityStatus {
+        self.status.clone()
+    }
+
+    pub fn set_failover(&mut self, failover: Failover) {
+        self.failover = Some(failover);
+    }
+
+    fn endpoint(&self) -> Option<String> {
+        self.failover.as_ref().map(Failover::current)
+    }
 }
 
 #[async_trait]
@@ -54,9 +103,11 @@ impl MqttEventHandler for ConnectivityMqttEventHandler {
                 match self.state {
                     ConnectivityState::Connected => {
                         self.state = ConnectivityState::Disconnected;
+                        self.status.set(self.state);
 
                         let event = LocalUpstreamPumpEvent::ConnectivityUpdate(
                             ConnectivityState::Disconnected,
+                            self.endpoint(),
                         );
                         let msg = PumpMessage::Event(event);
                         self.sender.send(msg).await?;
@@ -72,15 +123,24 @@ impl MqttEventHandler for ConnectivityMqttEventHandler {
             }
 
             Event::NewConnection { reset_session: _ } => {
+                if let Some(failover) = &self.failover {
+                    failover.connected();
+                    if !failover.is_primary() {
+                        info!("connected to secondary endpoint {}", failover.current());
+                    }
+                }
+
                 match self.state {
                     ConnectivityState::Connected => {
                         debug!("already connected");
                     }
                     ConnectivityState::Disconnected => {
                         self.state = ConnectivityState::Connected;
+                        self.status.set(self.state);
 
                         let event = LocalUpstreamPumpEvent::ConnectivityUpdate(
                             ConnectivityState::Connected,
+                            self.endpoint(),
                         );
                         let msg = PumpMessage::Event(event);
                         self.sender.send(msg).await?;
@@ -105,6 +165,8 @@ pub struct ConnectivityError(#[from] PumpError);
 #[cfg(test)]
 #[allow(clippy::semicolon_if_nothing_returned)]
 mod tests {
+    use std::num::NonZeroU32;
+
     use futures_util::FutureExt;
     use matches::assert_matches;
     use mqtt3::{proto::QoS, proto::SubscribeTo, ConnectionError, Event, SubscriptionUpdateEvent};
@@ -130,10 +192,12 @@ mod tests {
         assert_eq!(
             msg,
             PumpMessage::Event(LocalUpstreamPumpEvent::ConnectivityUpdate(
-                ConnectivityState::Connected
+                ConnectivityState::Connected,
+                None
             ))
         );
         assert_eq!(ch.state, ConnectivityState::Connected);
+        assert_eq!(ch.status().get(), ConnectivityState::Connected);
         assert_eq!(res, Handled::Fully);
     }
 
@@ -161,7 +225,8 @@ mod tests {
         assert_eq!(
             msg,
             PumpMessage::Event(LocalUpstreamPumpEvent::ConnectivityUpdate(
-                ConnectivityState::Disconnected
+                ConnectivityState::Disconnected,
+                None
             ))
         );
         assert_eq!(ch.state, ConnectivityState::Disconnected);
@@ -229,6 +294,34 @@ mod tests {
         assert_matches!(res, Handled::Skipped(_));
     }
 
+    #[tokio::test]
+    async fn sends_connected_state_with_endpoint() {
+        let (handle, mut connectivity_receiver) = pump::channel();
+
+        let mut ch = ConnectivityMqttEventHandler::new(handle);
+        ch.set_failover(Failover::new(
+            "primary:8883",
+            vec!["secondary:8883".into()],
+            NonZeroU32::new(1).unwrap(),
+            None,
+        ));
+
+        ch.handle(Event::NewConnection {
+            reset_session: true,
+        })
+        .await
+        .unwrap();
+
+        let msg = connectivity_receiver.recv().await.unwrap();
+        assert_eq!(
+            msg,
+            PumpMessage::Event(LocalUpstreamPumpEvent::ConnectivityUpdate(
+                ConnectivityState::Connected,
+                Some("primary:8883".into())
+            ))
+        );
+    }
+
     #[tokio::test]
     async fn default_disconnected_state() {
         let (handle, _) = pump::channel();
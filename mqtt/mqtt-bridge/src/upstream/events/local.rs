@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use bson::doc;
+use bson::{doc, Document};
 use bytes::Bytes;
 use mockall_double::double;
 use mqtt3::{proto::Publication, proto::QoS};
@@ -14,13 +14,14 @@ use crate::{
     upstream::{CommandId, ConnectivityState},
 };
 
-const CONNECTIVITY_TOPIC: &str = "$internal/connectivity";
+pub(crate) const CONNECTIVITY_TOPIC: &str = "$internal/connectivity";
 
 /// Pump control event for a local upstream bridge pump.
 #[derive(Debug, PartialEq)]
 pub enum LocalUpstreamPumpEvent {
-    /// Connectivity update event.
-    ConnectivityUpdate(ConnectivityState),
+    /// Connectivity update event with an address of the remote endpoint
+    /// when it is known.
+    ConnectivityUpdate(ConnectivityState, Option<String>),
 
     /// RPC command acknowledgement event.
     RpcAck(CommandId),
@@ -28,6 +29,9 @@ pub enum LocalUpstreamPumpEvent {
     /// RPC command negative acknowledgement event.
     RpcNack(CommandId, String),
 
+    /// RPC command acknowledgement event with a result of command execution.
+    RpcReply(CommandId, Document),
+
     /// Forward incoming upstream publication event.
     Publication(Publication),
 }
@@ -37,18 +41,31 @@ pub enum LocalUpstreamPumpEvent {
 /// It handles following events:
 /// * connectivity update - emitted when the connection to remote broker changed
 ///   (connected/disconnected). It should publish corresponding MQTT message to the
-///   local broker.
+///   local broker. The message contains an endpoint address the bridge is connected
+///   to if the bridge fails over between several endpoints.
 /// * RPC command acknowledgement - emitted when the RPC command executed with
 ///   success result.
 /// * RPC command negative acknowledgement - emitted when the RPC command failed
 ///   to execute.
+/// * RPC command reply - emitted when the RPC command executed with success
+///   result which should be returned with acknowledgement.
 pub struct LocalUpstreamPumpEventHandler {
     publish_handle: PublishHandle,
+    connectivity_topic: String,
 }
 
 impl LocalUpstreamPumpEventHandler {
     pub fn new(publish_handle: PublishHandle) -> Self {
-        Self { publish_handle }
+        Self {
+            publish_handle,
+            connectivity_topic: CONNECTIVITY_TOPIC.to_owned(),
+        }
+    }
+
+    /// Publishes connectivity updates to a given topic instead of the default one.
+    pub fn with_connectivity_topic(mut self, topic: impl Into<String>) -> Self {
+        self.connectivity_topic = topic.into();
+        self
     }
 }
 
@@ -58,13 +75,16 @@ impl PumpMessageHandler for LocalUpstreamPumpEventHandler {
 
     async fn handle(&mut self, message: Self::Message) {
         let maybe_publication = match message {
-            LocalUpstreamPumpEvent::ConnectivityUpdate(status) => {
+            LocalUpstreamPumpEvent::ConnectivityUpdate(status, endpoint) => {
                 debug!("changed connectivity status to {}", status);
 
-                let payload = json!({ "status": status });
+                let payload = match endpoint {
+                    Some(endpoint) => json!({ "status": status, "endpoint": endpoint }),
+                    None => json!({ "status": status }),
+                };
                 match serde_json::to_string(&payload) {
                     Ok(payload) => Some(Publication {
-                        topic_name: CONNECTIVITY_TOPIC.to_owned(),
+                        topic_name: self.connectivity_topic.clone(),
                         qos: QoS::AtLeastOnce,
                         retain: true,
                         payload: payload.into(),
@@ -103,6 +123,23 @@ impl PumpMessageHandler for LocalUpstreamPumpEventHandler {
                     }
                 }
             }
+            LocalUpstreamPumpEvent::RpcReply(command_id, reply) => {
+                debug!("sending rpc command reply {}", command_id);
+
+                let mut payload = Vec::new();
+                match reply.to_writer(&mut payload) {
+                    Ok(_) => Some(Publication {
+                        topic_name: format!("$downstream/rpc/ack/{}", command_id),
+                        qos: QoS::AtLeastOnce,
+                        retain: false,
+                        payload: payload.into(),
+                    }),
+                    Err(e) => {
+                        error!("unable to convert to BSON. {}", e);
+                        None
+                    }
+                }
+            }
             LocalUpstreamPumpEvent::Publication(publication) => {
                 debug!("sending incoming message on {}", publication.topic_name);
                 Some(publication)
@@ -150,7 +187,48 @@ mod tests {
 
         let mut handler = LocalUpstreamPumpEventHandler::new(pub_handle);
 
-        let event = LocalUpstreamPumpEvent::ConnectivityUpdate(state);
+        let event = LocalUpstreamPumpEvent::ConnectivityUpdate(state, None);
+        handler.handle(event).await;
+    }
+
+    #[tokio::test]
+    async fn it_sends_connectivity_update_with_endpoint() {
+        let payload = json!({ "status": ConnectivityState::Connected, "endpoint": "parent:8883" });
+        let payload = serde_json::to_vec(&payload).unwrap();
+
+        let mut pub_handle = MockPublishHandle::new();
+        pub_handle
+            .expect_publish()
+            .once()
+            .withf(move |publication| {
+                publication.topic_name == "$internal/connectivity" && publication.payload == payload
+            })
+            .returning(|_| Ok(()));
+
+        let mut handler = LocalUpstreamPumpEventHandler::new(pub_handle);
+
+        let event = LocalUpstreamPumpEvent::ConnectivityUpdate(
+            ConnectivityState::Connected,
+            Some("parent:8883".into()),
+        );
+        handler.handle(event).await;
+    }
+
+    #[tokio::test]
+    async fn it_sends_connectivity_update_to_custom_topic() {
+        let mut pub_handle = MockPublishHandle::new();
+        pub_handle
+            .expect_publish()
+            .once()
+            .withf(move |publication| {
+                publication.topic_name == "$internal/connectivity/floor2" && publication.retain
+            })
+            .returning(|_| Ok(()));
+
+        let mut handler = LocalUpstreamPumpEventHandler::new(pub_handle)
+            .with_connectivity_topic("$internal/connectivity/floor2");
+
+        let event = LocalUpstreamPumpEvent::ConnectivityUpdate(ConnectivityState::Connected, None);
         handler.handle(event).await;
     }
 
@@ -192,6 +270,27 @@ mod tests {
         handler.handle(event).await;
     }
 
+    #[tokio::test]
+    async fn it_sends_rpc_reply() {
+        let mut payload = Vec::new();
+        let doc = doc! { "status": "Connected" };
+        doc.to_writer(&mut payload).unwrap();
+
+        let mut pub_handle = MockPublishHandle::new();
+        pub_handle
+            .expect_publish()
+            .once()
+            .withf(move |publication| {
+                publication.topic_name == "$downstream/rpc/ack/1" && publication.payload == payload
+            })
+            .returning(|_| Ok(()));
+
+        let mut handler = LocalUpstreamPumpEventHandler::new(pub_handle);
+
+        let event = LocalUpstreamPumpEvent::RpcReply("1".into(), doc! { "status": "Connected" });
+        handler.handle(event).await;
+    }
+
     #[tokio::test]
     async fn it_sends_incoming_publication() {
         let mut pub_handle = MockPublishHandle::new();
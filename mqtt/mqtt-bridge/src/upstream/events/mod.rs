@@ -1,5 +1,6 @@
 mod local;
 mod remote;
 
+pub(crate) use local::CONNECTIVITY_TOPIC;
 pub use local::{LocalUpstreamPumpEvent, LocalUpstreamPumpEventHandler};
 pub use remote::{RemoteUpstreamPumpEvent, RemoteUpstreamPumpEventHandler};
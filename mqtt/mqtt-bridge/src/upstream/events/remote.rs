@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use bson::doc;
 use mockall_double::double;
 use mqtt3::proto::{Publication, QoS, SubscribeTo};
 use tracing::{error, warn};
@@ -6,7 +7,8 @@ use tracing::{error, warn};
 use crate::{
     pump::{PumpHandle, PumpMessageHandler},
     upstream::{
-        CommandId, LocalUpstreamPumpEvent, RpcCommand, RpcError, RpcPumpHandle, RpcSubscriptions,
+        CommandId, ConnectivityStatus, LocalUpstreamPumpEvent, RpcCommand, RpcError,
+        RpcPublication, RpcPumpHandle, RpcSubscriptions,
     },
 };
 
@@ -29,6 +31,7 @@ pub struct RemoteUpstreamPumpEventHandler {
     remote_pub_handle: PublishHandle,
     local_pump: RpcPumpHandle,
     subscriptions: RpcSubscriptions,
+    connectivity: ConnectivityStatus,
 }
 
 impl RemoteUpstreamPumpEventHandler {
@@ -37,12 +40,14 @@ impl RemoteUpstreamPumpEventHandler {
         remote_pub_handle: PublishHandle,
         local_pump_handle: PumpHandle<LocalUpstreamPumpEvent>,
         subscriptions: RpcSubscriptions,
+        connectivity: ConnectivityStatus,
     ) -> Self {
         Self {
             remote_sub_handle,
             remote_pub_handle,
             local_pump: RpcPumpHandle::new(local_pump_handle),
             subscriptions,
+            connectivity,
         }
     }
 
@@ -58,8 +63,34 @@ impl RemoteUpstreamPumpEventHandler {
             RpcCommand::Unsubscribe { topic_filter } => {
                 self.handle_unsubscribe(command_id, topic_filter).await
             }
-            RpcCommand::Publish { topic, payload } => {
-                self.handle_publish(command_id, topic, payload).await
+            RpcCommand::Publish {
+                topic,
+                payload,
+                qos,
+                retain,
+            } => {
+                let publication = RpcPublication {
+                    topic,
+                    payload,
+                    qos,
+                    retain,
+                };
+                self.handle_publish(command_id, publication).await
+            }
+            RpcCommand::BatchPublish { publications } => {
+                self.handle_batch_publish(command_id, publications).await
+            }
+            RpcCommand::Ping => {
+                let status = self.connectivity.get().to_string();
+                self.local_pump
+                    .send_reply(command_id, doc! { "status": status })
+                    .await
+            }
+            RpcCommand::ListSubscriptions => {
+                let subscriptions = self.subscriptions.active();
+                self.local_pump
+                    .send_reply(command_id, doc! { "subscriptions": subscriptions })
+                    .await
             }
         }
     }
@@ -125,26 +156,81 @@ impl RemoteUpstreamPumpEventHandler {
     async fn handle_publish(
         &mut self,
         command_id: CommandId,
-        topic_name: String,
-        payload: Vec<u8>,
+        publication: RpcPublication,
     ) -> Result<(), RpcError> {
-        let publication = Publication {
-            topic_name: topic_name.clone(),
-            qos: QoS::AtLeastOnce,
-            retain: false,
-            payload: payload.into(),
+        let result = match into_publication(publication) {
+            Ok(publication) => self.publish(publication).await,
+            Err(reason) => Err(reason),
         };
 
-        match self.remote_pub_handle.publish(publication).await {
+        match result {
             Ok(_) => self.local_pump.send_ack(command_id).await,
-            Err(e) => {
-                let reason = format!("unable to publish to upstream {}. {}", topic_name, e);
-                self.local_pump.send_nack(command_id, reason).await
+            Err(reason) => self.local_pump.send_nack(command_id, reason).await,
+        }
+    }
+
+    /// Publishes all messages of the batch. The whole batch is validated
+    /// first, so an invalid message rejects the batch without publishing
+    /// anything. A failure to publish still leaves messages published
+    /// before it, which is reported in nack reason.
+    async fn handle_batch_publish(
+        &mut self,
+        command_id: CommandId,
+        publications: Vec<RpcPublication>,
+    ) -> Result<(), RpcError> {
+        let publications = match publications
+            .into_iter()
+            .map(into_publication)
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(publications) => publications,
+            Err(reason) => return self.local_pump.send_nack(command_id, reason).await,
+        };
+
+        let total = publications.len();
+        for (published, publication) in publications.into_iter().enumerate() {
+            if let Err(reason) = self.publish(publication).await {
+                let reason = format!("published {} of {}. {}", published, total, reason);
+                return self.local_pump.send_nack(command_id, reason).await;
             }
         }
+
+        self.local_pump.send_ack(command_id).await
+    }
+
+    async fn publish(&mut self, publication: Publication) -> Result<(), String> {
+        let topic_name = publication.topic_name.clone();
+
+        self.remote_pub_handle
+            .publish(publication)
+            .await
+            .map_err(|e| format!("unable to publish to upstream {}. {}", topic_name, e))
     }
 }
 
+fn into_publication(publication: RpcPublication) -> Result<Publication, String> {
+    let RpcPublication {
+        topic: topic_name,
+        payload,
+        qos,
+        retain,
+    } = publication;
+
+    let qos = match qos {
+        Some(0) => QoS::AtMostOnce,
+        None | Some(1) => QoS::AtLeastOnce,
+        Some(2) => QoS::ExactlyOnce,
+        Some(qos) => return Err(format!("invalid qos {} for {}", qos, topic_name)),
+    };
+
+    Ok(Publication {
+        topic_name,
+        qos,
+        retain,
+        payload: payload.into(),
+    })
+}
+
 #[async_trait]
 impl PumpMessageHandler for RemoteUpstreamPumpEventHandler {
     type Message = RemoteUpstreamPumpEvent;
@@ -196,6 +282,7 @@ mod tests {
             remote_pub_handle,
             local_pump,
             rpc_subscriptions.clone(),
+            ConnectivityStatus::default(),
         );
 
         // handle a command to subscribe to topic /foo
@@ -230,6 +317,7 @@ mod tests {
             remote_pub_handle,
             local_pump,
             rpc_subscriptions.clone(),
+            ConnectivityStatus::default(),
         );
 
         // handle a command to unsubscribe from topic /foo
@@ -267,12 +355,15 @@ mod tests {
             remote_pub_handle,
             local_pump,
             rpc_subscriptions,
+            ConnectivityStatus::default(),
         );
 
         // handle a command to publish on topic /foo
         let command = RpcCommand::Publish {
             topic: "/foo".into(),
             payload: b"hello".to_vec(),
+            qos: None,
+            retain: false,
         };
         let event = RemoteUpstreamPumpEvent::RpcCommand("1".into(), command);
         handler.handle(event).await;
@@ -283,4 +374,202 @@ mod tests {
             Some(PumpMessage::Event(LocalUpstreamPumpEvent::RpcAck(id))) if id == "1".into()
         );
     }
+
+    #[tokio::test]
+    async fn it_handles_pub_command_with_qos_and_retain() {
+        let mut remote_pub_handle = MockPublishHandle::new();
+        remote_pub_handle
+            .expect_publish()
+            .once()
+            .withf(|publication| {
+                publication.topic_name == "/foo"
+                    && publication.qos == QoS::AtMostOnce
+                    && publication.retain
+            })
+            .returning(|_| Ok(()));
+
+        let remote_sub_handle = MockUpdateSubscriptionHandle::new();
+
+        let (local_pump, mut rx) = pump::channel();
+
+        let mut handler = RemoteUpstreamPumpEventHandler::new(
+            remote_sub_handle,
+            remote_pub_handle,
+            local_pump,
+            RpcSubscriptions::default(),
+            ConnectivityStatus::default(),
+        );
+
+        // handle a command to publish on topic /foo with qos 0 and retain flag
+        let command = RpcCommand::Publish {
+            topic: "/foo".into(),
+            payload: b"hello".to_vec(),
+            qos: Some(0),
+            retain: true,
+        };
+        let event = RemoteUpstreamPumpEvent::RpcCommand("1".into(), command);
+        handler.handle(event).await;
+
+        assert_matches!(
+            rx.recv().await,
+            Some(PumpMessage::Event(LocalUpstreamPumpEvent::RpcAck(id))) if id == "1".into()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_sends_nack_when_pub_command_has_invalid_qos() {
+        let remote_pub_handle = MockPublishHandle::new();
+        let remote_sub_handle = MockUpdateSubscriptionHandle::new();
+
+        let (local_pump, mut rx) = pump::channel();
+
+        let mut handler = RemoteUpstreamPumpEventHandler::new(
+            remote_sub_handle,
+            remote_pub_handle,
+            local_pump,
+            RpcSubscriptions::default(),
+            ConnectivityStatus::default(),
+        );
+
+        let command = RpcCommand::Publish {
+            topic: "/foo".into(),
+            payload: b"hello".to_vec(),
+            qos: Some(3),
+            retain: false,
+        };
+        let event = RemoteUpstreamPumpEvent::RpcCommand("1".into(), command);
+        handler.handle(event).await;
+
+        assert_matches!(
+            rx.recv().await,
+            Some(PumpMessage::Event(LocalUpstreamPumpEvent::RpcNack(id, _))) if id == "1".into()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_handles_batch_pub_command() {
+        let mut remote_pub_handle = MockPublishHandle::new();
+        remote_pub_handle
+            .expect_publish()
+            .times(2)
+            .returning(|_| Ok(()));
+
+        let remote_sub_handle = MockUpdateSubscriptionHandle::new();
+
+        let (local_pump, mut rx) = pump::channel();
+
+        let mut handler = RemoteUpstreamPumpEventHandler::new(
+            remote_sub_handle,
+            remote_pub_handle,
+            local_pump,
+            RpcSubscriptions::default(),
+            ConnectivityStatus::default(),
+        );
+
+        let publication = |topic: &str| RpcPublication {
+            topic: topic.into(),
+            payload: b"hello".to_vec(),
+            qos: None,
+            retain: false,
+        };
+        let command = RpcCommand::BatchPublish {
+            publications: vec![publication("/foo"), publication("/bar")],
+        };
+        let event = RemoteUpstreamPumpEvent::RpcCommand("1".into(), command);
+        handler.handle(event).await;
+
+        assert_matches!(
+            rx.recv().await,
+            Some(PumpMessage::Event(LocalUpstreamPumpEvent::RpcAck(id))) if id == "1".into()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_sends_nack_without_publishing_when_batch_has_invalid_qos() {
+        let mut remote_pub_handle = MockPublishHandle::new();
+        remote_pub_handle.expect_publish().never();
+
+        let remote_sub_handle = MockUpdateSubscriptionHandle::new();
+
+        let (local_pump, mut rx) = pump::channel();
+
+        let mut handler = RemoteUpstreamPumpEventHandler::new(
+            remote_sub_handle,
+            remote_pub_handle,
+            local_pump,
+            RpcSubscriptions::default(),
+            ConnectivityStatus::default(),
+        );
+
+        let publication = |topic: &str, qos| RpcPublication {
+            topic: topic.into(),
+            payload: b"hello".to_vec(),
+            qos,
+            retain: false,
+        };
+        let command = RpcCommand::BatchPublish {
+            publications: vec![publication("/foo", None), publication("/bar", Some(3))],
+        };
+        let event = RemoteUpstreamPumpEvent::RpcCommand("1".into(), command);
+        handler.handle(event).await;
+
+        assert_matches!(
+            rx.recv().await,
+            Some(PumpMessage::Event(LocalUpstreamPumpEvent::RpcNack(id, reason)))
+                if id == "1".into() && reason == "invalid qos 3 for /bar"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_handles_ping_command() {
+        let remote_pub_handle = MockPublishHandle::new();
+        let remote_sub_handle = MockUpdateSubscriptionHandle::new();
+
+        let (local_pump, mut rx) = pump::channel();
+
+        let mut handler = RemoteUpstreamPumpEventHandler::new(
+            remote_sub_handle,
+            remote_pub_handle,
+            local_pump,
+            RpcSubscriptions::default(),
+            ConnectivityStatus::default(),
+        );
+
+        let event = RemoteUpstreamPumpEvent::RpcCommand("1".into(), RpcCommand::Ping);
+        handler.handle(event).await;
+
+        assert_matches!(
+            rx.recv().await,
+            Some(PumpMessage::Event(LocalUpstreamPumpEvent::RpcReply(id, reply)))
+                if id == "1".into() && reply == doc! { "status": "Disconnected" }
+        );
+    }
+
+    #[tokio::test]
+    async fn it_handles_list_subscriptions_command() {
+        let remote_pub_handle = MockPublishHandle::new();
+        let remote_sub_handle = MockUpdateSubscriptionHandle::new();
+
+        let (local_pump, mut rx) = pump::channel();
+
+        let rpc_subscriptions = RpcSubscriptions::default();
+        rpc_subscriptions.subscribed("/foo");
+
+        let mut handler = RemoteUpstreamPumpEventHandler::new(
+            remote_sub_handle,
+            remote_pub_handle,
+            local_pump,
+            rpc_subscriptions,
+            ConnectivityStatus::default(),
+        );
+
+        let event = RemoteUpstreamPumpEvent::RpcCommand("1".into(), RpcCommand::ListSubscriptions);
+        handler.handle(event).await;
+
+        assert_matches!(
+            rx.recv().await,
+            Some(PumpMessage::Event(LocalUpstreamPumpEvent::RpcReply(id, reply)))
+                if id == "1".into() && reply == doc! { "subscriptions": ["/foo"] }
+        );
+    }
 }
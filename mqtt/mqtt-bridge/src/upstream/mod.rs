@@ -4,14 +4,17 @@ mod connectivity;
 mod events;
 mod rpc;
 
-pub use connectivity::{ConnectivityError, ConnectivityMqttEventHandler, ConnectivityState};
+pub use connectivity::{
+    ConnectivityError, ConnectivityMqttEventHandler, ConnectivityState, ConnectivityStatus,
+};
+pub(crate) use events::CONNECTIVITY_TOPIC;
 pub use events::{
     LocalUpstreamPumpEvent, LocalUpstreamPumpEventHandler, RemoteUpstreamPumpEvent,
     RemoteUpstreamPumpEventHandler,
 };
 pub use rpc::{
     CommandId, LocalRpcMqttEventHandler, RemoteRpcMqttEventHandler, RpcCommand, RpcError,
-    RpcPumpHandle, RpcSubscriptions,
+    RpcPublication, RpcPumpHandle, RpcSubscriptions,
 };
 
 use async_trait::async_trait;
@@ -66,7 +69,7 @@ where
 
 /// Handles all events that comes to remote clients received for upstream bridge.
 ///
-/// Contains several event handlers to process Connectivity, RPC and regular
+/// Contains several event handlers to process RPC, Connectivity and regular
 /// MQTT events in a chain.
 pub struct RemoteUpstreamMqttEventHandler<S> {
     messages: StoreMqttEventHandler<S>,
@@ -103,14 +106,15 @@ where
     }
 
     async fn handle(&mut self, event: Event) -> Result<Handled, Self::Error> {
-        // try to handle incoming connectivity event
-        let event = match self.connectivity.handle(event).await? {
+        // try to handle incoming messages as RPC command. It goes first since
+        // RPC handler also needs to observe new connection events
+        let event = match self.rpc.handle(event).await? {
             Handled::Fully => return Ok(Handled::Fully),
             Handled::Partially(event) | Handled::Skipped(event) => event,
         };
 
-        // try to handle incoming messages as RPC command
-        let event = match self.rpc.handle(event).await? {
+        // try to handle incoming connectivity event
+        let event = match self.connectivity.handle(event).await? {
             Handled::Fully => return Ok(Handled::Fully),
             Handled::Partially(event) | Handled::Skipped(event) => event,
         };
@@ -38,6 +38,15 @@ impl LocalRpcMqttEventHandler {
         }
     }
 
+    /// Creates a local part of RPC handler which does not subscribe to RPC
+    /// commands. Used by remote bridges other than upstream.
+    pub fn without_commands(remote_pump: PumpHandle<RemoteUpstreamPumpEvent>) -> Self {
+        Self {
+            remote_pump,
+            subscriptions: HashSet::new(),
+        }
+    }
+
     async fn handle_publication(
         &mut self,
         command_id: CommandId,
@@ -151,6 +160,8 @@ mod tests {
     };
     use test_case::test_case;
 
+    use crate::upstream::RpcPublication;
+
     use super::*;
 
     #[test]
@@ -186,7 +197,65 @@ mod tests {
                 VersionedRpcCommand::V1(RpcCommand::Publish {
                     topic: "/foo".into(),
                     payload: b"data".to_vec(),
+                    qos: None,
+                    retain: false,
+                }),
+            ),
+            (
+                bson!({
+                    "version": "v1",
+                    "cmd": "pub",
+                    "topic": "/foo",
+                    "payload": vec![100, 97, 116, 97],
+                    "qos": 0,
+                    "retain": true,
+                }),
+                VersionedRpcCommand::V1(RpcCommand::Publish {
+                    topic: "/foo".into(),
+                    payload: b"data".to_vec(),
+                    qos: Some(0),
+                    retain: true,
+                }),
+            ),
+            (
+                bson!({
+                    "version": "v1",
+                    "cmd": "batchpub",
+                    "publications": [
+                        { "topic": "/foo", "payload": vec![100, 97, 116, 97] },
+                        { "topic": "/bar", "payload": vec![100, 97, 116, 97], "qos": 2 },
+                    ],
+                }),
+                VersionedRpcCommand::V1(RpcCommand::BatchPublish {
+                    publications: vec![
+                        RpcPublication {
+                            topic: "/foo".into(),
+                            payload: b"data".to_vec(),
+                            qos: None,
+                            retain: false,
+                        },
+                        RpcPublication {
+                            topic: "/bar".into(),
+                            payload: b"data".to_vec(),
+                            qos: Some(2),
+                            retain: false,
+                        },
+                    ],
+                }),
+            ),
+            (
+                bson!({
+                    "version": "v1",
+                    "cmd": "ping",
+                }),
+                VersionedRpcCommand::V1(RpcCommand::Ping),
+            ),
+            (
+                bson!({
+                    "version": "v1",
+                    "cmd": "listsubs",
                 }),
+                VersionedRpcCommand::V1(RpcCommand::ListSubscriptions),
             ),
         ];
 
@@ -229,7 +298,7 @@ mod tests {
                 PumpMessage::Event(
                     RemoteUpstreamPumpEvent::RpcCommand(
                         id, 
-                        RpcCommand::Publish{topic, payload})
+                        RpcCommand::Publish{topic, payload, ..})
                     )
                 ) if topic == "/foo" && payload == b"hello" && id == "3".into());
     }
@@ -3,10 +3,19 @@
 //!
 //! The main purpose of this handler is to establish a communication channel
 //! between `EdgeHub` and the upstream bridge.
-//! `EdgeHub` will use low level commands SUB, UNSUB, PUB. In turn the bridge
-//! sends corresponding MQTT packet to upstream broker and waits for an ack
-//! from the upstream. After ack is received it sends a special publish to
+//! `EdgeHub` will use low level commands SUB, UNSUB, PUB, BATCHPUB. In turn the
+//! bridge sends corresponding MQTT packet to upstream broker and waits for an
+//! ack from the upstream. After ack is received it sends a special publish to
 //! downstream broker.
+//!
+//! Commands PING and LISTSUBS are executed by the bridge itself and the ack
+//! carries the result as a BSON document, e.g. `{ "status": "Connected" }`
+//! for PING and `{ "subscriptions": ["topic/#"] }` for LISTSUBS.
+//!
+//! Compatibility note: acks of SUB, UNSUB, PUB and BATCHPUB commands keep an
+//! empty payload and nacks keep a BSON document `{ "reason": "..." }`, so
+//! existing consumers are not affected. Only consumers sending PING or LISTSUBS
+//! should decode the ack payload.
 
 mod local;
 mod remote;
@@ -16,7 +25,7 @@ use parking_lot::Mutex;
 pub use remote::{RemoteRpcMqttEventHandler, RpcPumpHandle};
 
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeSet, HashMap, VecDeque},
     fmt::{Display, Formatter, Result as FmtResult},
     sync::Arc,
 };
@@ -58,6 +67,9 @@ pub enum RpcError {
     #[error("unable to send ack for {0}. Caused by: {1}")]
     SendAck(CommandId, #[source] PumpError),
 
+    #[error("unable to send reply for {0}. Caused by: {1}")]
+    SendReply(CommandId, #[source] PumpError),
+
     #[error("unable to send command for {0} to remote pump. Caused by: {1}")]
     SendToRemotePump(CommandId, #[source] PumpError),
 
@@ -84,13 +96,49 @@ pub enum RpcCommand {
     },
 
     /// A RPC command to publish a message to a given topic.
+    ///
+    /// Publication is sent with `QoS` 1 and no retain flag unless specified.
     #[serde(rename = "pub")]
     Publish {
         topic: String,
 
         #[serde(with = "serde_bytes")]
         payload: Vec<u8>,
+
+        #[serde(default)]
+        qos: Option<u8>,
+
+        #[serde(default)]
+        retain: bool,
     },
+
+    /// A RPC command to publish several messages. Acknowledged when all
+    /// messages are published.
+    #[serde(rename = "batchpub")]
+    BatchPublish { publications: Vec<RpcPublication> },
+
+    /// A RPC command to request the upstream connectivity state.
+    #[serde(rename = "ping")]
+    Ping,
+
+    /// A RPC command to request topic filters subscribed with RPC commands.
+    #[serde(rename = "listsubs")]
+    ListSubscriptions,
+}
+
+/// A message to publish with batch publish RPC command.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RpcPublication {
+    pub topic: String,
+
+    #[serde(with = "serde_bytes")]
+    pub payload: Vec<u8>,
+
+    #[serde(default)]
+    pub qos: Option<u8>,
+
+    #[serde(default)]
+    pub retain: bool,
 }
 
 impl Display for RpcCommand {
@@ -99,6 +147,9 @@ impl Display for RpcCommand {
             Self::Subscribe { topic_filter } => write!(f, "SUB {}", topic_filter),
             Self::Unsubscribe { topic_filter } => write!(f, "UNSUB {}", topic_filter),
             Self::Publish { topic, .. } => write!(f, "PUB {}", topic),
+            Self::BatchPublish { publications } => write!(f, "BATCHPUB {}", publications.len()),
+            Self::Ping => write!(f, "PING"),
+            Self::ListSubscriptions => write!(f, "LISTSUBS"),
         }
     }
 }
@@ -111,13 +162,22 @@ impl Display for RpcCommand {
 /// subscription has been made with `UpdateSubscriptionHandle` but the server
 /// response comes back as `mqtt3::Event` type which handled with the event
 /// handler.
+///
+/// In addition it keeps track of topic filters which were subscribed to with
+/// RPC commands and acknowledged by the upstream broker.
 #[derive(Debug, Clone, Default)]
-pub struct RpcSubscriptions(Arc<Mutex<HashMap<String, VecDeque<CommandId>>>>);
+pub struct RpcSubscriptions(Arc<Mutex<RpcSubscriptionsInner>>);
+
+#[derive(Debug, Default)]
+struct RpcSubscriptionsInner {
+    pending: HashMap<String, VecDeque<CommandId>>,
+    active: BTreeSet<String>,
+}
 
 impl RpcSubscriptions {
     /// Stores topic filter to command identifier mapping.
     pub fn insert(&self, topic_filter: &str, id: CommandId) -> Option<Vec<CommandId>> {
-        let mut inner = self.0.lock();
+        let inner = &mut self.0.lock().pending;
 
         let existing = inner
             .get(topic_filter)
@@ -131,7 +191,7 @@ impl RpcSubscriptions {
     /// Removes topic filter to command identifier mapping and returns
     /// `CommandId` if exists.
     pub fn remove(&self, topic_filter: &str) -> Option<CommandId> {
-        let mut inner = self.0.lock();
+        let inner = &mut self.0.lock().pending;
 
         inner
             .remove_entry(topic_filter)
@@ -145,6 +205,21 @@ impl RpcSubscriptions {
                 id
             })
     }
+
+    /// Marks topic filter as subscribed.
+    pub fn subscribed(&self, topic_filter: &str) {
+        self.0.lock().active.insert(topic_filter.into());
+    }
+
+    /// Marks topic filter as unsubscribed.
+    pub fn unsubscribed(&self, topic_filter: &str) {
+        self.0.lock().active.remove(topic_filter);
+    }
+
+    /// Returns topic filters currently subscribed with RPC commands.
+    pub fn active(&self) -> Vec<String> {
+        self.0.lock().active.iter().cloned().collect()
+    }
 }
 
 #[cfg(test)]
@@ -154,7 +229,7 @@ mod tests {
     #[test]
     fn it_handles_rpc_subscriptions() {
         let subs = RpcSubscriptions::default();
-        assert!(subs.0.lock().is_empty());
+        assert!(subs.0.lock().pending.is_empty());
 
         assert_eq!(subs.insert("topic/1", "1".into()), None);
         assert_eq!(commands(&subs, "topic/1"), Some(vec!["1".into()]));
@@ -179,9 +254,24 @@ mod tests {
         assert_eq!(commands(&subs, "topic/1"), Some(vec!["4".into()]));
     }
 
+    #[test]
+    fn it_tracks_active_rpc_subscriptions() {
+        let subs = RpcSubscriptions::default();
+        assert!(subs.active().is_empty());
+
+        subs.subscribed("topic/2");
+        subs.subscribed("topic/1");
+        subs.subscribed("topic/1");
+        assert_eq!(subs.active(), vec!["topic/1", "topic/2"]);
+
+        subs.unsubscribed("topic/1");
+        assert_eq!(subs.active(), vec!["topic/2"]);
+    }
+
     fn commands(subs: &RpcSubscriptions, topic_filter: &str) -> Option<Vec<CommandId>> {
         subs.0
             .lock()
+            .pending
             .get(topic_filter)
             .map(|ids| ids.iter().cloned().collect())
     }
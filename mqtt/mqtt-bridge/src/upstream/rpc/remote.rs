@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use bson::Document;
 use lazy_static::lazy_static;
 use regex::RegexSet;
 
@@ -24,6 +25,10 @@ use super::{CommandId, RpcError, RpcSubscriptions};
 /// 2. It receives a publication, identifies those which are for `IoTHub`
 /// topics, translates topic and sends a special `PumpMessage` event to
 /// local pump.
+///
+/// 3. It forgets topic filters subscribed with RPC commands when upstream
+/// broker rejects them. Filters are kept when upstream broker does not keep
+/// the session, since the client subscribes to them again by itself.
 pub struct RemoteRpcMqttEventHandler {
     subscriptions: RpcSubscriptions,
     local_pump: RpcPumpHandle,
@@ -85,6 +90,7 @@ impl RemoteRpcMqttEventHandler {
         match subscription {
             SubscriptionUpdateEvent::Subscribe(sub) => {
                 if let Some(command_id) = self.subscriptions.remove(&sub.topic_filter) {
+                    self.subscriptions.subscribed(&sub.topic_filter);
                     self.local_pump.send_ack(command_id).await?;
                     return Ok(true);
                 }
@@ -98,9 +104,13 @@ impl RemoteRpcMqttEventHandler {
                     self.local_pump.send_nack(command_id, reason).await?;
                     return Ok(true);
                 }
+
+                // resubscription after the session reset was rejected
+                self.subscriptions.unsubscribed(&rejected_from.topic_filter);
             }
             SubscriptionUpdateEvent::Unsubscribe(topic_filter) => {
                 if let Some(command_id) = self.subscriptions.remove(topic_filter) {
+                    self.subscriptions.unsubscribed(topic_filter);
                     self.local_pump.send_ack(command_id).await?;
                     return Ok(true);
                 }
@@ -167,7 +177,7 @@ fn translate(topic_name: &str) -> Option<String> {
 }
 
 /// Convenient wrapper around `PumpHandle` for local pump that encapsulates
-/// sending RPC command Ack, Nack or Publish.
+/// sending RPC command Ack, Nack, Reply or Publish.
 pub struct RpcPumpHandle(PumpHandle<LocalUpstreamPumpEvent>);
 
 impl RpcPumpHandle {
@@ -195,6 +205,18 @@ impl RpcPumpHandle {
             .map_err(|e| RpcError::SendNack(command_id, e))
     }
 
+    pub async fn send_reply(
+        &mut self,
+        command_id: CommandId,
+        reply: Document,
+    ) -> Result<(), RpcError> {
+        let event = LocalUpstreamPumpEvent::RpcReply(command_id.clone(), reply);
+        self.0
+            .send(PumpMessage::Event(event))
+            .await
+            .map_err(|e| RpcError::SendReply(command_id, e))
+    }
+
     pub async fn send_pub(&mut self, publication: Publication) -> Result<(), RpcError> {
         let topic_name = publication.topic_name.clone();
         let event = LocalUpstreamPumpEvent::Publication(publication);
@@ -228,7 +250,7 @@ mod tests {
         subscriptions.insert("/foo/unsubscribed", "3".into());
 
         let (local_pump, mut rx) = pump::channel();
-        let mut handler = RemoteRpcMqttEventHandler::new(subscriptions, local_pump);
+        let mut handler = RemoteRpcMqttEventHandler::new(subscriptions.clone(), local_pump);
 
         let event = Event::SubscriptionUpdates(vec![
             SubscriptionUpdateEvent::Subscribe(SubscribeTo {
@@ -257,6 +279,40 @@ mod tests {
             rx.recv().await,
             Some(PumpMessage::Event(LocalUpstreamPumpEvent::RpcAck(id))) if id == "3".into()
         );
+
+        assert_eq!(subscriptions.active(), vec!["/foo/subscribed"]);
+    }
+
+    #[tokio::test]
+    async fn it_keeps_active_subscriptions_resubscribed_after_session_reset() {
+        let subscriptions = RpcSubscriptions::default();
+        subscriptions.subscribed("/foo");
+        subscriptions.subscribed("/bar");
+
+        let (local_pump, _rx) = pump::channel();
+        let mut handler = RemoteRpcMqttEventHandler::new(subscriptions.clone(), local_pump);
+
+        let event = Event::NewConnection {
+            reset_session: true,
+        };
+        let res = handler.handle(event).await;
+        assert_matches!(res, Ok(Handled::Skipped(_)));
+        assert_eq!(subscriptions.active(), vec!["/bar", "/foo"]);
+
+        // client subscribes again to all topic filters
+        let event = Event::SubscriptionUpdates(vec![
+            SubscriptionUpdateEvent::Subscribe(SubscribeTo {
+                topic_filter: "/foo".into(),
+                qos: QoS::AtLeastOnce,
+            }),
+            SubscriptionUpdateEvent::RejectedByServer(SubscribeTo {
+                topic_filter: "/bar".into(),
+                qos: QoS::AtLeastOnce,
+            }),
+        ]);
+        let res = handler.handle(event).await;
+        assert_matches!(res, Ok(Handled::Skipped(_)));
+        assert_eq!(subscriptions.active(), vec!["/foo"]);
     }
 
     #[tokio::test]
@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use clap::{crate_version, value_t, App, Arg};
+
+use mqtt_broker_tests_util::load::{run, LoadSettings};
+
+/// Generates load on a broker and prints throughput and latency as JSON.
+#[tokio::main]
+async fn main() -> Result<()> {
+    mqtt_broker_tests_util::init_logging();
+
+    let matches = App::new("mqtt-load")
+        .version(crate_version!())
+        .about("Generates load on an MQTT broker and reports throughput and latency as JSON")
+        .arg(
+            Arg::with_name("server")
+                .short("s")
+                .long("server")
+                .value_name("ADDRESS")
+                .help("Address of the broker")
+                .default_value("localhost:1883"),
+        )
+        .arg(
+            Arg::with_name("publishers")
+                .short("p")
+                .long("publishers")
+                .value_name("COUNT")
+                .help("Number of publishing clients")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("subscribers")
+                .short("c")
+                .long("subscribers")
+                .value_name("COUNT")
+                .help("Number of subscribing clients")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("topics")
+                .short("t")
+                .long("topics")
+                .value_name("COUNT")
+                .help(
+                    "Number of topics to spread publications and subscribers over; \
+                     every publication is delivered to subscribers/topics subscribers",
+                )
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("qos0")
+                .long("qos0")
+                .value_name("WEIGHT")
+                .help("Relative share of QoS 0 publications")
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("qos1")
+                .long("qos1")
+                .value_name("WEIGHT")
+                .help("Relative share of QoS 1 publications")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("qos2")
+                .long("qos2")
+                .value_name("WEIGHT")
+                .help("Relative share of QoS 2 publications")
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("payload-size")
+                .long("payload-size")
+                .value_name("BYTES")
+                .help("Size of every publication")
+                .default_value("64"),
+        )
+        .arg(
+            Arg::with_name("rate")
+                .short("r")
+                .long("rate")
+                .value_name("PER_SECOND")
+                .help(
+                    "Publications per second for every publisher; 0 publishes as fast as possible",
+                )
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("max-inflight")
+                .long("max-inflight")
+                .value_name("COUNT")
+                .help("Number of unacknowledged publications every publisher may have")
+                .default_value("16"),
+        )
+        .arg(
+            Arg::with_name("duration")
+                .short("d")
+                .long("duration")
+                .value_name("SECONDS")
+                .help("How long to publish")
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("linger")
+                .long("linger")
+                .value_name("SECONDS")
+                .help("How long to wait for outstanding publications after publishing stopped")
+                .default_value("2"),
+        )
+        .arg(
+            Arg::with_name("churn-interval")
+                .long("churn-interval")
+                .value_name("SECONDS")
+                .help("Publishers reconnect with a new client this often; 0 never reconnects")
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("username")
+                .long("username")
+                .value_name("USERNAME")
+                .help("Username to connect with")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("password")
+                .long("password")
+                .value_name("PASSWORD")
+                .help("Password to connect with")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let server = matches.value_of("server").expect("has default value");
+    let rate = match value_t!(matches, "rate", f64)? {
+        rate if rate < 0.0 => bail!("rate must not be negative but was {}", rate),
+        rate if rate > 0.0 => Some(rate),
+        _ => None,
+    };
+    let churn_interval = match value_t!(matches, "churn-interval", f64)? {
+        interval if interval < 0.0 => {
+            bail!("churn interval must not be negative but was {}", interval)
+        }
+        interval if interval > 0.0 => Some(Duration::from_secs_f64(interval)),
+        _ => None,
+    };
+
+    let settings = LoadSettings {
+        publishers: value_t!(matches, "publishers", usize)?,
+        subscribers: value_t!(matches, "subscribers", usize)?,
+        topics: value_t!(matches, "topics", usize)?,
+        qos_weights: [
+            value_t!(matches, "qos0", u32)?,
+            value_t!(matches, "qos1", u32)?,
+            value_t!(matches, "qos2", u32)?,
+        ],
+        payload_size: value_t!(matches, "payload-size", usize)?,
+        rate,
+        max_inflight: value_t!(matches, "max-inflight", usize)?,
+        duration: Duration::from_secs(value_t!(matches, "duration", u64)?),
+        linger: Duration::from_secs(value_t!(matches, "linger", u64)?),
+        churn_interval,
+        username: matches.value_of("username").map(ToOwned::to_owned),
+        password: matches.value_of("password").map(ToOwned::to_owned),
+    };
+
+    let report = run(server, settings).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    Ok(())
+}
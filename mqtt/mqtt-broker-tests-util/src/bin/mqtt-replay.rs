@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use clap::{crate_version, value_t, App, Arg};
+use tracing::info;
+
+use mqtt3::recording::Recording;
+use mqtt_broker_tests_util::replay::{replay, Credentials, ReplaySpeed};
+
+/// Replays a connection recorded by the broker or by an `mqtt3` client against a broker.
+#[tokio::main]
+async fn main() -> Result<()> {
+    mqtt_broker_tests_util::init_logging();
+
+    let matches = App::new("mqtt-replay")
+        .version(crate_version!())
+        .about("Replays a recorded MQTT connection against a broker")
+        .arg(
+            Arg::with_name("recording")
+                .value_name("FILE")
+                .help("Recording file to replay")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("server")
+                .short("s")
+                .long("server")
+                .value_name("ADDRESS")
+                .help("Address of the broker")
+                .default_value("localhost:1883"),
+        )
+        .arg(
+            Arg::with_name("speed")
+                .long("speed")
+                .value_name("FACTOR")
+                .help("Replays the recording this many times faster than it was recorded")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("unlimited")
+                .long("unlimited")
+                .help("Replays the recording as fast as possible")
+                .conflicts_with("speed"),
+        )
+        .arg(
+            Arg::with_name("username")
+                .long("username")
+                .value_name("USERNAME")
+                .help("User name to connect with instead of the recorded one")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("password")
+                .long("password")
+                .value_name("PASSWORD")
+                .help("Password to connect with, since passwords are not recorded")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("linger")
+                .long("linger")
+                .value_name("SECONDS")
+                .help("How long to wait for the broker to respond after the last packet")
+                .default_value("1"),
+        )
+        .get_matches();
+
+    let path = matches.value_of("recording").expect("required argument");
+    let server = matches.value_of("server").expect("has default value");
+    let speed = if matches.is_present("unlimited") {
+        ReplaySpeed::Unlimited
+    } else {
+        match value_t!(matches, "speed", f64)? {
+            speed if (speed - 1.0).abs() < f64::EPSILON => ReplaySpeed::Original,
+            speed if speed > 0.0 => ReplaySpeed::Accelerated(speed),
+            speed => bail!("speed must be positive but was {}", speed),
+        }
+    };
+    let linger = Duration::from_secs(value_t!(matches, "linger", u64)?);
+    let credentials = Credentials {
+        username: matches.value_of("username").map(ToOwned::to_owned),
+        password: matches.value_of("password").map(ToOwned::to_owned),
+    };
+
+    let packets = Recording::open(path)
+        .with_context(|| format!("cannot open recording {}", path))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("cannot read recording {}", path))?;
+
+    info!(
+        "replaying {} packets from {} to {}",
+        packets.len(),
+        path,
+        server
+    );
+    let stats = replay(server, packets, &credentials, speed, linger).await;
+    info!(
+        "sent {} packets, received {} packets ({} when recorded), skipped {} acknowledgements",
+        stats.sent, stats.received, stats.recorded_received, stats.skipped
+    );
+
+    Ok(())
+}
@@ -264,7 +264,9 @@ where
                             Some(event) => {
                                 let event = event.expect("got error instead of event");
                                 match event {
-                                    Event::NewConnection { .. } | Event::Disconnected(_) => conn_sender
+                                    Event::NewConnection { .. }
+                                    | Event::Disconnected(_)
+                                    | Event::ConnectionAttemptFailed { .. } => conn_sender
                                         .send(event)
                                         .expect("can't send an event to a conn channel"),
                                     Event::Publication(publication) => pub_sender
@@ -0,0 +1,344 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::Duration as ChronoDuration;
+use futures_util::{future::BoxFuture, FutureExt};
+use tokio::{
+    io::DuplexStream,
+    sync::oneshot::{self, Sender},
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tracing::warn;
+
+use mqtt3::IoSource;
+use mqtt_broker::{
+    auth::AllowAll, clock, BrokerBuilder, BrokerConfig, BrokerHandle, BrokerSnapshot, Error,
+    MemoryConnector, Message, Persist, PersistError, Server, ShutdownHandle, Snapshotter,
+    StateSnapshotHandle, SystemEvent,
+};
+
+use crate::{packet_stream::PacketStream, server::DummyAuthenticator};
+
+/// How long [`BrokerHarness::settle`] lets virtual time pass.
+const SETTLE_TIME: Duration = Duration::from_millis(1);
+
+/// Runs a broker and its server in-process, with clients connected over in-memory streams.
+///
+/// The harness is meant for tests that run on a current-thread runtime with paused tokio time,
+/// e.g. `#[tokio::test(start_paused = true)]`. Nothing happens on a timer unless the test advances time,
+/// which makes keep-alive expiry, session cleanup and state snapshots deterministic.
+/// The broker is started with the same periodic session cleanup and state snapshot jobs as `mqttd`,
+/// and state snapshots are kept in memory so that the broker can be crashed and restarted from the last one.
+///
+/// While the harness is alive, sessions on the current thread age with tokio time rather than with the system clock.
+pub struct BrokerHarness {
+    config: BrokerConfig,
+    connector: Arc<Mutex<MemoryConnector>>,
+    persisted: Arc<Mutex<Option<BrokerSnapshot>>>,
+    snapshot_handle: StateSnapshotHandle,
+    snapshotter_shutdown: ShutdownHandle,
+    snapshotter: Option<JoinHandle<MemoryPersistor>>,
+    running: Option<RunningBroker>,
+}
+
+/// Tasks of a running broker.
+struct RunningBroker {
+    handle: BrokerHandle,
+    shutdown: Sender<()>,
+    server: JoinHandle<Result<BrokerSnapshot, Error>>,
+    jobs: Vec<JoinHandle<()>>,
+}
+
+impl BrokerHarness {
+    /// Starts a broker with the given configuration and without any state.
+    ///
+    /// Every client is allowed to connect and to do anything.
+    pub fn start(config: BrokerConfig) -> Self {
+        clock::follow_tokio_time();
+
+        let persistor = MemoryPersistor::default();
+        let persisted = persistor.state.clone();
+        let snapshotter = Snapshotter::new(persistor);
+        let snapshot_handle = snapshotter.snapshot_handle();
+        let snapshotter_shutdown = snapshotter.shutdown_handle();
+        let snapshotter = tokio::spawn(snapshotter.run());
+
+        let (running, connector) = run(&config, None, snapshot_handle.clone());
+
+        Self {
+            config,
+            connector: Arc::new(Mutex::new(connector)),
+            persisted,
+            snapshot_handle,
+            snapshotter_shutdown,
+            snapshotter: Some(snapshotter),
+            running: Some(running),
+        }
+    }
+
+    /// Advances virtual time by the given duration and lets every task that became ready run.
+    pub async fn advance(&self, duration: Duration) {
+        time::sleep(duration).await;
+        self.settle().await;
+    }
+
+    /// Lets every task that is ready run until all of them are idle.
+    ///
+    /// Tokio only moves paused time forward on its own once all tasks are idle,
+    /// so waiting for a short virtual timer is a barrier for all work that is ready now.
+    pub async fn settle(&self) {
+        time::sleep(SETTLE_TIME).await;
+    }
+
+    /// Removes offline sessions that have not been active for the configured session expiration,
+    /// without waiting for the next periodic cleanup.
+    pub async fn cleanup_sessions(&self) {
+        self.send(Message::System(SystemEvent::SessionCleanup(
+            expiration_cutoff(&self.config),
+        )));
+        self.settle().await;
+    }
+
+    /// Persists a snapshot of the broker state, without waiting for the next periodic snapshot.
+    pub async fn snapshot(&self) {
+        self.send(Message::System(SystemEvent::StateSnapshot(
+            self.snapshot_handle.clone(),
+        )));
+        self.settle().await;
+    }
+
+    /// Returns the last persisted snapshot of the broker state.
+    pub fn persisted(&self) -> Option<BrokerSnapshot> {
+        lock(&self.persisted).clone()
+    }
+
+    /// Stops the broker without persisting its state, as if the process crashed.
+    ///
+    /// All client connections are dropped and new connections are refused until the broker is restarted.
+    pub async fn crash(&mut self) {
+        let running = self.running.take().expect("broker is not running");
+        for job in &running.jobs {
+            job.abort();
+        }
+        running.server.abort();
+
+        // Stop the broker itself. It drops all connections, and the snapshot it returns is thrown away.
+        if let Err(e) = running.handle.send(Message::System(SystemEvent::Shutdown)) {
+            warn!(message = "failed to stop crashed broker", error = %e);
+        }
+
+        self.settle().await;
+    }
+
+    /// Starts the broker again from the last persisted snapshot.
+    ///
+    /// Clients connecting through [`BrokerHarness::io_source`] reconnect to the restarted broker.
+    pub async fn restart(&mut self) {
+        assert!(self.running.is_none(), "broker is still running");
+
+        let (running, connector) =
+            run(&self.config, self.persisted(), self.snapshot_handle.clone());
+        *lock(&self.connector) = connector;
+        self.running = Some(running);
+
+        self.settle().await;
+    }
+
+    /// Shuts down the broker gracefully and returns its final state.
+    pub async fn shutdown(mut self) -> BrokerSnapshot {
+        let running = self.running.take().expect("broker is not running");
+        for job in &running.jobs {
+            job.abort();
+        }
+
+        running.shutdown.send(()).expect("couldn't shutdown broker");
+        let state = running
+            .server
+            .await
+            .expect("broker task panicked")
+            .expect("broker failed");
+
+        self.snapshotter_shutdown
+            .shutdown()
+            .await
+            .expect("couldn't shutdown snapshotter");
+        if let Some(snapshotter) = self.snapshotter.take() {
+            snapshotter.await.expect("snapshotter task panicked");
+        }
+
+        state
+    }
+
+    /// Returns an I/O source for `mqtt3` clients that connects to the broker over an in-memory stream.
+    pub fn io_source(&self) -> HarnessIoSource {
+        HarnessIoSource {
+            connector: self.connector.clone(),
+            password: None,
+        }
+    }
+
+    /// Opens an in-memory connection to the broker to send packets over.
+    pub fn packet_stream(&self) -> PacketStream {
+        let io = lock(&self.connector)
+            .connect()
+            .expect("unable to connect to broker");
+        PacketStream::from_io(io)
+    }
+
+    fn send(&self, message: Message) {
+        self.running
+            .as_ref()
+            .expect("broker is not running")
+            .handle
+            .send(message)
+            .expect("couldn't send message to broker");
+    }
+}
+
+impl Drop for BrokerHarness {
+    fn drop(&mut self) {
+        if let Some(running) = self.running.take() {
+            for job in &running.jobs {
+                job.abort();
+            }
+            let _: Result<_, _> = running.shutdown.send(());
+        }
+
+        clock::follow_system_time();
+    }
+}
+
+fn run(
+    config: &BrokerConfig,
+    state: Option<BrokerSnapshot>,
+    snapshot_handle: StateSnapshotHandle,
+) -> (RunningBroker, MemoryConnector) {
+    let mut builder = BrokerBuilder::default()
+        .with_authorizer(AllowAll)
+        .with_config(config.clone());
+    if let Some(state) = state {
+        builder = builder.with_state(state);
+    }
+    let broker = builder.build();
+    let handle = broker.handle();
+
+    let mut server = Server::from_broker(broker);
+    let connector = server.with_memory(
+        SocketAddr::from(([127, 0, 0, 1], 1883)),
+        DummyAuthenticator::anonymous(),
+        None,
+    );
+
+    let (shutdown, rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(server.serve(rx.map(drop)));
+
+    let jobs = vec![
+        tokio::spawn(tick_snapshot(
+            config.persistence().time_interval(),
+            handle.clone(),
+            snapshot_handle,
+        )),
+        tokio::spawn(tick_cleanup(config.clone(), handle.clone())),
+    ];
+
+    let running = RunningBroker {
+        handle,
+        shutdown,
+        server,
+        jobs,
+    };
+    (running, connector)
+}
+
+async fn tick_snapshot(
+    period: Duration,
+    broker_handle: BrokerHandle,
+    snapshot_handle: StateSnapshotHandle,
+) {
+    let mut interval = time::interval_at(Instant::now() + period, period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = broker_handle.send(Message::System(SystemEvent::StateSnapshot(
+            snapshot_handle.clone(),
+        ))) {
+            warn!(message = "failed to tick the snapshotter", error = %e);
+        }
+    }
+}
+
+async fn tick_cleanup(config: BrokerConfig, broker_handle: BrokerHandle) {
+    let period = config.session().cleanup_interval();
+    let mut interval = time::interval_at(Instant::now() + period, period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = broker_handle.send(Message::System(SystemEvent::SessionCleanup(
+            expiration_cutoff(&config),
+        ))) {
+            warn!(message = "failed to tick the cleanup job", error = %e);
+        }
+    }
+}
+
+fn expiration_cutoff(config: &BrokerConfig) -> chrono::DateTime<chrono::Utc> {
+    let expiration = ChronoDuration::from_std(config.session().expiration())
+        .expect("session expiration is out of range");
+    clock::now() - expiration
+}
+
+/// Connects `mqtt3` clients to a [`BrokerHarness`].
+#[derive(Clone, Debug)]
+pub struct HarnessIoSource {
+    connector: Arc<Mutex<MemoryConnector>>,
+    password: Option<String>,
+}
+
+impl HarnessIoSource {
+    /// Sends the given password when connecting.
+    pub fn with_password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+}
+
+impl IoSource for HarnessIoSource {
+    type Io = DuplexStream;
+    type Error = std::io::Error;
+    type Future = BoxFuture<'static, Result<(DuplexStream, Option<String>), std::io::Error>>;
+
+    fn connect(&mut self) -> Self::Future {
+        let io = lock(&self.connector).connect();
+        let password = self.password.clone();
+        Box::pin(async move { io.map(|io| (io, password)) })
+    }
+}
+
+/// Keeps the last persisted broker state in memory.
+#[derive(Debug, Default)]
+struct MemoryPersistor {
+    state: Arc<Mutex<Option<BrokerSnapshot>>>,
+}
+
+#[async_trait]
+impl Persist for MemoryPersistor {
+    type Error = PersistError;
+
+    async fn load(&mut self) -> Result<Option<BrokerSnapshot>, Self::Error> {
+        Ok(lock(&self.state).clone())
+    }
+
+    async fn store(&mut self, state: BrokerSnapshot) -> Result<(), Self::Error> {
+        *lock(&self.state) = Some(state);
+        Ok(())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
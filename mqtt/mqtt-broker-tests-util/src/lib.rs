@@ -13,7 +13,10 @@
 
 pub mod client;
 pub mod env;
+pub mod harness;
+pub mod load;
 pub mod packet_stream;
+pub mod replay;
 pub mod server;
 
 pub fn init_logging() {
@@ -0,0 +1,535 @@
+use std::{
+    cmp,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures_util::{future::BoxFuture, StreamExt};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    rngs::StdRng,
+    SeedableRng,
+};
+use serde::Serialize;
+use tokio::{
+    net::TcpStream,
+    sync::{oneshot, watch, Semaphore},
+    time::{self, Instant},
+};
+use tracing::{debug, info, warn};
+
+use mqtt3::{
+    proto::{Publication, QoS, SubscribeTo},
+    Client, Event, IoSource, PublishHandle,
+};
+
+const QOS: [QoS; 3] = [QoS::AtMostOnce, QoS::AtLeastOnce, QoS::ExactlyOnce];
+
+/// Size of the timestamp every load publication starts with.
+const TIMESTAMP_LEN: usize = 8;
+
+/// Describes the load to put on the broker.
+#[derive(Debug, Clone)]
+pub struct LoadSettings {
+    /// Number of clients publishing messages.
+    pub publishers: usize,
+
+    /// Number of clients receiving messages.
+    pub subscribers: usize,
+
+    /// Number of topics publications are spread over.
+    ///
+    /// Subscribers are spread over the same topics, so every publication is delivered to
+    /// `subscribers / topics` subscribers on average.
+    pub topics: usize,
+
+    /// Relative weights of QoS 0, 1 and 2 publications.
+    pub qos_weights: [u32; 3],
+
+    /// Size of every publication in bytes. Publications are never smaller than the timestamp they carry.
+    pub payload_size: usize,
+
+    /// Publications per second for every publisher, or `None` to publish as fast as possible.
+    pub rate: Option<f64>,
+
+    /// Number of publications every publisher waits to be acknowledged at the same time.
+    pub max_inflight: usize,
+
+    /// How long publishers publish.
+    pub duration: Duration,
+
+    /// How long subscribers keep receiving after publishers stopped.
+    pub linger: Duration,
+
+    /// If set, publishers disconnect and connect with a new client this often.
+    pub churn_interval: Option<Duration>,
+
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Results of a load run.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadReport {
+    /// Time publishers spent publishing in seconds.
+    pub duration_secs: f64,
+
+    /// Number of connections established by publishers and subscribers.
+    pub connections: u64,
+
+    /// Number of publications acknowledged by the broker.
+    pub published: u64,
+
+    /// Number of publications that could not be published.
+    pub publish_failures: u64,
+
+    /// Number of publications received by subscribers.
+    pub received: u64,
+
+    /// Number of publications subscribers would have received if every publication had been delivered.
+    pub expected: u64,
+
+    /// Acknowledged publications per second.
+    pub publish_throughput: f64,
+
+    /// Received publications per second.
+    pub receive_throughput: f64,
+
+    /// Time between starting to publish a publication and its acknowledgement.
+    pub publish_latency: Option<LatencyReport>,
+
+    /// Time between starting to publish a publication and a subscriber receiving it.
+    pub delivery_latency: Option<LatencyReport>,
+}
+
+/// Latency percentiles in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LatencyReport {
+    pub min: u64,
+    pub mean: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+impl LatencyReport {
+    /// Computes the percentiles of the given samples, or `None` if there are none.
+    pub fn from_samples(mut samples: Vec<u64>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        samples.sort_unstable();
+
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_precision_loss,
+            clippy::cast_sign_loss
+        )]
+        let percentile = |p: f64| {
+            let rank = (p * samples.len() as f64).ceil() as usize;
+            samples[cmp::min(samples.len(), cmp::max(rank, 1)) - 1]
+        };
+
+        let sum: u128 = samples.iter().map(|sample| u128::from(*sample)).sum();
+        #[allow(clippy::cast_possible_truncation)]
+        let mean = (sum / samples.len() as u128) as u64;
+
+        Some(Self {
+            min: samples[0],
+            mean,
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            p999: percentile(0.999),
+            max: samples[samples.len() - 1],
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    connections: AtomicU64,
+    published: Vec<AtomicU64>,
+    publish_failures: AtomicU64,
+    received: AtomicU64,
+    publish_latencies: Mutex<Vec<u64>>,
+}
+
+/// Puts the load described by `settings` on the broker at `address` and measures how it copes.
+///
+/// Subscribers connect and subscribe first. Then publishers publish for the configured duration,
+/// and subscribers receive for `linger` longer before everything disconnects.
+pub async fn run(address: impl Into<String>, settings: LoadSettings) -> Result<LoadReport> {
+    let address: Arc<str> = address.into().into();
+    if settings.topics == 0 {
+        bail!("at least one topic is required");
+    }
+    if settings.max_inflight == 0 {
+        bail!("at least one publication must be allowed inflight");
+    }
+    let qos = WeightedIndex::new(&settings.qos_weights).context("invalid QoS weights")?;
+
+    let settings = Arc::new(settings);
+    let counters = Arc::new(Counters {
+        published: (0..settings.topics).map(|_| AtomicU64::default()).collect(),
+        ..Counters::default()
+    });
+    let start = Instant::now();
+
+    info!("connecting {} subscribers", settings.subscribers);
+    let mut subscribers = Vec::with_capacity(settings.subscribers);
+    let mut subscribed = Vec::with_capacity(settings.subscribers);
+    let (stop_sender, stop) = watch::channel(false);
+    for id in 0..settings.subscribers {
+        let (subscribed_sender, subscribed_receiver) = oneshot::channel();
+        subscribed.push(subscribed_receiver);
+        subscribers.push(tokio::spawn(subscriber(
+            address.clone(),
+            id,
+            settings.clone(),
+            start,
+            counters.clone(),
+            subscribed_sender,
+            stop.clone(),
+        )));
+    }
+
+    for subscribed in subscribed {
+        time::timeout(Duration::from_secs(30), subscribed)
+            .await
+            .context("timed out waiting for subscribers to subscribe")?
+            .context("subscriber stopped before it subscribed")?;
+    }
+
+    info!(
+        "publishing with {} publishers for {:?}",
+        settings.publishers, settings.duration
+    );
+    let publish_start = Instant::now();
+    let end = publish_start + settings.duration;
+    let publishers: Vec<_> = (0..settings.publishers)
+        .map(|id| {
+            tokio::spawn(publisher(
+                address.clone(),
+                id,
+                settings.clone(),
+                qos.clone(),
+                start,
+                end,
+                counters.clone(),
+            ))
+        })
+        .collect();
+
+    for publisher in publishers {
+        publisher.await.context("publisher panicked")?;
+    }
+    let duration = publish_start.elapsed();
+
+    time::sleep(settings.linger).await;
+    // Sending cannot fail since `stop` is still alive.
+    let _: Result<_, _> = stop_sender.send(true);
+
+    let mut delivery_latencies = Vec::new();
+    for subscriber in subscribers {
+        delivery_latencies.extend(subscriber.await.context("subscriber panicked")?);
+    }
+
+    let published_per_topic: Vec<_> = counters
+        .published
+        .iter()
+        .map(|published| published.load(Ordering::Relaxed))
+        .collect();
+    let expected = published_per_topic
+        .iter()
+        .enumerate()
+        .map(|(topic, published)| published * subscribers_of(topic, &settings))
+        .sum();
+    let published = published_per_topic.iter().sum();
+    let received = counters.received.load(Ordering::Relaxed);
+    let publish_latencies = std::mem::take(&mut *lock(&counters.publish_latencies));
+
+    #[allow(clippy::cast_precision_loss)]
+    let report = LoadReport {
+        duration_secs: duration.as_secs_f64(),
+        connections: counters.connections.load(Ordering::Relaxed),
+        published,
+        publish_failures: counters.publish_failures.load(Ordering::Relaxed),
+        received,
+        expected,
+        publish_throughput: published as f64 / duration.as_secs_f64(),
+        receive_throughput: received as f64 / duration.as_secs_f64(),
+        publish_latency: LatencyReport::from_samples(publish_latencies),
+        delivery_latency: LatencyReport::from_samples(delivery_latencies),
+    };
+    Ok(report)
+}
+
+async fn publisher(
+    address: Arc<str>,
+    id: usize,
+    settings: Arc<LoadSettings>,
+    qos: WeightedIndex<u32>,
+    start: Instant,
+    end: Instant,
+    counters: Arc<Counters>,
+) {
+    let mut rng = StdRng::from_entropy();
+    let mut ticker = settings
+        .rate
+        .map(|rate| time::interval(Duration::from_secs_f64(1.0 / rate)));
+    let inflight = Arc::new(Semaphore::new(settings.max_inflight));
+    let mut next_topic = id % settings.topics;
+
+    while Instant::now() < end {
+        let connection_end = settings
+            .churn_interval
+            .map_or(end, |interval| cmp::min(end, Instant::now() + interval));
+
+        let client = client(&address, format!("load-pub-{}", id), &settings);
+        let publish_handle = client.publish_handle().expect("client is up");
+        let mut shutdown_handle = client.shutdown_handle().expect("client is up");
+        let event_loop = tokio::spawn(event_loop(client, counters.clone()));
+
+        while Instant::now() < connection_end {
+            if let Some(ticker) = &mut ticker {
+                ticker.tick().await;
+            }
+
+            let permit = inflight
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("semaphore is never closed");
+
+            let topic = next_topic;
+            next_topic = (next_topic + 1) % settings.topics;
+            let publication = Publication {
+                topic_name: topic_name(topic),
+                qos: QOS[qos.sample(&mut rng)],
+                retain: false,
+                payload: payload(start, settings.payload_size),
+            };
+
+            let publish_handle = publish_handle.clone();
+            let counters = counters.clone();
+            tokio::spawn(async move {
+                publish(publish_handle, publication, topic, &counters).await;
+                drop(permit);
+            });
+        }
+
+        // Give outstanding publications a chance to be acknowledged before disconnecting.
+        #[allow(clippy::cast_possible_truncation)]
+        let outstanding = inflight.acquire_many(settings.max_inflight as u32);
+        if time::timeout(settings.linger, outstanding).await.is_err() {
+            warn!("publisher {} disconnects with publications outstanding", id);
+        }
+
+        if let Err(err) = shutdown_handle.shutdown().await {
+            warn!("could not shut down publisher {}: {}", id, err);
+        }
+        if let Err(err) = event_loop.await {
+            warn!("event loop of publisher {} failed: {}", id, err);
+        }
+    }
+}
+
+async fn publish(
+    mut publish_handle: PublishHandle,
+    publication: Publication,
+    topic: usize,
+    counters: &Counters,
+) {
+    let publish_start = Instant::now();
+    match publish_handle.publish(publication).await {
+        Ok(()) => {
+            let latency = micros(publish_start.elapsed());
+            counters.published[topic].fetch_add(1, Ordering::Relaxed);
+            lock(&counters.publish_latencies).push(latency);
+        }
+        Err(err) => {
+            debug!("could not publish: {}", err);
+            counters.publish_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+async fn event_loop(mut client: Client<BrokerConnection>, counters: Arc<Counters>) {
+    while let Some(event) = client.next().await {
+        match event {
+            Ok(Event::NewConnection { .. }) => {
+                counters.connections.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(event) => debug!("publisher event: {:?}", event),
+            Err(err) => warn!("publisher error: {}", err),
+        }
+    }
+}
+
+async fn subscriber(
+    address: Arc<str>,
+    id: usize,
+    settings: Arc<LoadSettings>,
+    start: Instant,
+    counters: Arc<Counters>,
+    subscribed: oneshot::Sender<()>,
+    mut stop: watch::Receiver<bool>,
+) -> Vec<u64> {
+    let mut client = client(&address, format!("load-sub-{}", id), &settings);
+    let topic_filter = topic_name(id % settings.topics);
+    client
+        .subscribe(SubscribeTo {
+            topic_filter,
+            qos: QoS::ExactlyOnce,
+        })
+        .expect("client is up");
+
+    let mut shutdown_handle = client.shutdown_handle().expect("client is up");
+    tokio::spawn(async move {
+        while !*stop.borrow() {
+            if stop.changed().await.is_err() {
+                break;
+            }
+        }
+
+        if let Err(err) = shutdown_handle.shutdown().await {
+            warn!("could not shut down subscriber {}: {}", id, err);
+        }
+    });
+
+    let mut subscribed = Some(subscribed);
+    let mut latencies = Vec::new();
+    while let Some(event) = client.next().await {
+        match event {
+            Ok(Event::NewConnection { .. }) => {
+                counters.connections.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(Event::SubscriptionUpdates(_)) => {
+                if let Some(subscribed) = subscribed.take() {
+                    let _: Result<_, _> = subscribed.send(());
+                }
+            }
+            Ok(Event::Publication(publication)) => {
+                counters.received.fetch_add(1, Ordering::Relaxed);
+                if let Some(latency) = latency(start, &publication.payload) {
+                    latencies.push(latency);
+                }
+            }
+            Ok(event) => debug!("subscriber event: {:?}", event),
+            Err(err) => warn!("subscriber error: {}", err),
+        }
+    }
+
+    latencies
+}
+
+/// Connects load clients to the broker over TCP.
+#[derive(Debug)]
+struct BrokerConnection {
+    address: Arc<str>,
+    password: Option<String>,
+}
+
+impl IoSource for BrokerConnection {
+    type Io = TcpStream;
+    type Error = std::io::Error;
+    type Future = BoxFuture<'static, Result<(TcpStream, Option<String>), std::io::Error>>;
+
+    fn connect(&mut self) -> Self::Future {
+        let address = self.address.clone();
+        let password = self.password.clone();
+        Box::pin(async move {
+            let io = TcpStream::connect(&*address).await;
+            io.map(|io| (io, password))
+        })
+    }
+}
+
+fn client(
+    address: &Arc<str>,
+    client_id: String,
+    settings: &LoadSettings,
+) -> Client<BrokerConnection> {
+    let io_source = BrokerConnection {
+        address: address.clone(),
+        password: settings.password.clone(),
+    };
+
+    Client::new(
+        Some(client_id),
+        settings.username.clone(),
+        None,
+        io_source,
+        Duration::from_secs(1),
+        Duration::from_secs(60),
+    )
+}
+
+fn topic_name(topic: usize) -> String {
+    format!("load/{}", topic)
+}
+
+/// Creates a payload that starts with the time elapsed since `start` in microseconds.
+fn payload(start: Instant, size: usize) -> Bytes {
+    let mut payload = BytesMut::with_capacity(cmp::max(size, TIMESTAMP_LEN));
+    payload.put_u64(micros(start.elapsed()));
+    payload.resize(cmp::max(size, TIMESTAMP_LEN), 0);
+    payload.freeze()
+}
+
+/// Returns the time in microseconds since the payload was created, or `None` if it is not a load publication.
+fn latency(start: Instant, payload: &[u8]) -> Option<u64> {
+    let mut timestamp = [0_u8; TIMESTAMP_LEN];
+    timestamp.copy_from_slice(payload.get(..TIMESTAMP_LEN)?);
+    micros(start.elapsed()).checked_sub(u64::from_be_bytes(timestamp))
+}
+
+fn subscribers_of(topic: usize, settings: &LoadSettings) -> u64 {
+    (0..settings.subscribers)
+        .filter(|subscriber| subscriber % settings.topics == topic)
+        .count() as u64
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn micros(duration: Duration) -> u64 {
+    duration.as_micros() as u64
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LatencyReport;
+
+    #[test]
+    fn latency_percentiles() {
+        let report = LatencyReport::from_samples((1..=1000).rev().collect()).unwrap();
+
+        assert_eq!(
+            report,
+            LatencyReport {
+                min: 1,
+                mean: 500,
+                p50: 500,
+                p90: 900,
+                p99: 990,
+                p999: 999,
+                max: 1000,
+            }
+        );
+        assert_eq!(LatencyReport::from_samples(vec![]), None);
+    }
+}
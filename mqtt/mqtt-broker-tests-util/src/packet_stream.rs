@@ -6,7 +6,10 @@ use std::{
 
 use futures_util::{sink::SinkExt, stream::Stream, StreamExt};
 use lazy_static::lazy_static;
-use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpStream, ToSocketAddrs},
+};
 use tokio_io_timeout::TimeoutStream;
 use tokio_util::codec::Framed;
 
@@ -23,9 +26,14 @@ lazy_static! {
 /// to a broker for more granular integration testing.
 #[derive(Debug)]
 pub struct PacketStream {
-    codec: Pin<Box<Framed<TimeoutStream<TcpStream>, PacketCodec>>>,
+    codec: Pin<Box<Framed<TimeoutStream<Box<dyn Io>>, PacketCodec>>>,
 }
 
+/// An I/O object a `PacketStream` can be layered onto.
+trait Io: AsyncRead + AsyncWrite + Send + Unpin + std::fmt::Debug {}
+
+impl<T> Io for T where T: AsyncRead + AsyncWrite + Send + Unpin + std::fmt::Debug {}
+
 #[allow(dead_code)]
 impl PacketStream {
     /// Creates a client and opens TCP connection to the server.
@@ -44,7 +52,16 @@ impl PacketStream {
         }
 
         let tcp_stream = result.expect("unable to establish tcp connection");
-        let mut timeout = TimeoutStream::new(tcp_stream);
+        Self::from_io(tcp_stream)
+    }
+
+    /// Creates a client on top of an already established connection, e.g. an in-memory one.
+    /// No MQTT packets are sent at this moment.
+    pub fn from_io(
+        io: impl AsyncRead + AsyncWrite + Send + Unpin + std::fmt::Debug + 'static,
+    ) -> Self {
+        let io: Box<dyn Io> = Box::new(io);
+        let mut timeout = TimeoutStream::new(io);
 
         timeout.set_read_timeout(Some(*DEFAULT_TIMEOUT));
         timeout.set_write_timeout(Some(*DEFAULT_TIMEOUT));
@@ -90,6 +107,14 @@ impl PacketStream {
         self.send_packet(Packet::Subscribe(subscribe)).await;
     }
 
+    /// Sets how long to wait for the broker to send a packet before failing.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.codec
+            .as_mut()
+            .get_pin_mut()
+            .set_read_timeout_pinned(timeout);
+    }
+
     pub async fn send_packet(&mut self, packet: Packet) {
         self.codec
             .send(packet)
@@ -0,0 +1,471 @@
+use std::{collections::HashMap, time::Duration};
+
+use futures_util::StreamExt;
+use tokio::{net::ToSocketAddrs, time::Instant};
+use tracing::{debug, info};
+
+use mqtt3::{
+    proto::{
+        Connect, Packet, PacketIdentifier, PacketIdentifierDupQoS, PubAck, PubComp, PubRec, Publish,
+    },
+    recording::{Direction, RecordedPacket},
+};
+
+use crate::packet_stream::PacketStream;
+
+/// How fast packets of a recording are replayed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Packets are sent with the same delays between them as they were recorded.
+    Original,
+
+    /// Delays between packets are divided by the given factor.
+    Accelerated(f64),
+
+    /// Packets are sent without any delay.
+    Unlimited,
+}
+
+impl ReplaySpeed {
+    fn scale(self, elapsed: Duration) -> Duration {
+        match self {
+            ReplaySpeed::Original => elapsed,
+            ReplaySpeed::Accelerated(factor) => elapsed.div_f64(factor),
+            ReplaySpeed::Unlimited => Duration::from_secs(0),
+        }
+    }
+}
+
+/// Credentials a replayed connection authenticates with.
+///
+/// Recordings never contain passwords, so a broker which authenticates clients with a password
+/// (e.g. a SAS token) rejects a replayed connection unless the password is supplied again.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Credentials {
+    /// Replaces the recorded user name, if given.
+    pub username: Option<String>,
+
+    /// Password sent in place of the one which was not recorded.
+    pub password: Option<String>,
+}
+
+impl Credentials {
+    fn apply(&self, packet: Packet) -> Packet {
+        match packet {
+            Packet::Connect(connect) => Packet::Connect(Connect {
+                username: self.username.clone().or(connect.username),
+                password: self.password.clone(),
+                ..connect
+            }),
+            packet => packet,
+        }
+    }
+}
+
+/// Summary of a replayed recording.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ReplayStats {
+    /// Number of packets sent to the broker.
+    pub sent: usize,
+
+    /// Number of packets the broker sent back.
+    pub received: usize,
+
+    /// Number of packets the broker sent to the client when the recording was made.
+    pub recorded_received: usize,
+
+    /// Number of acknowledgements not sent, because the broker didn't send a matching publication.
+    pub skipped: usize,
+}
+
+/// Replays packets a client sent in a recorded connection against the broker at `server_addr`.
+///
+/// Packets the broker sends back are read and logged but not compared with the recording.
+/// Acknowledgements of publications the broker sent are rewritten to refer to packet identifiers
+/// the broker assigns during replay, see `PacketIdentifierMap`.
+/// After the last packet has been sent, the broker is given `linger` to respond before the connection is closed.
+/// The recorded CONNECT packet is sent with `credentials`, see `Credentials`.
+pub async fn replay(
+    server_addr: impl ToSocketAddrs,
+    recording: impl IntoIterator<Item = RecordedPacket>,
+    credentials: &Credentials,
+    speed: ReplaySpeed,
+    linger: Duration,
+) -> ReplayStats {
+    let mut stream = PacketStream::open(server_addr).await;
+    stream.set_read_timeout(None);
+
+    let mut stats = ReplayStats::default();
+    let mut identifiers = PacketIdentifierMap::default();
+    let start = Instant::now();
+
+    for recorded in recording {
+        if recorded.direction == Direction::ServerToClient {
+            identifiers.recorded(&recorded.packet);
+            stats.recorded_received += 1;
+            continue;
+        }
+
+        let deadline = start + speed.scale(recorded.elapsed);
+        if !receive_until(&mut stream, deadline, &mut stats, &mut identifiers, None).await {
+            info!("broker closed the connection");
+            return stats;
+        }
+
+        // the broker may send a publication later than it did when the recording was made
+        let deadline = Instant::now() + linger;
+        let acknowledgement = Some(&recorded.packet);
+        if !receive_until(
+            &mut stream,
+            deadline,
+            &mut stats,
+            &mut identifiers,
+            acknowledgement,
+        )
+        .await
+        {
+            info!("broker closed the connection");
+            return stats;
+        }
+
+        match identifiers.remap(&recorded.packet) {
+            Some(packet) => {
+                let packet = credentials.apply(packet);
+                debug!("sending {:?}", packet);
+                stream.send_packet(packet).await;
+                stats.sent += 1;
+            }
+            None => {
+                debug!(
+                    "skipping {:?} since the broker has not sent a matching publication",
+                    recorded.packet
+                );
+                stats.skipped += 1;
+            }
+        }
+    }
+
+    let deadline = Instant::now() + linger;
+    receive_until(&mut stream, deadline, &mut stats, &mut identifiers, None).await;
+    stats
+}
+
+/// Reads packets from the broker until the deadline, or until the broker sends the publication
+/// `acknowledgement` refers to, if given. Returns `false` if the broker closed the connection.
+async fn receive_until(
+    stream: &mut PacketStream,
+    deadline: Instant,
+    stats: &mut ReplayStats,
+    identifiers: &mut PacketIdentifierMap,
+    acknowledgement: Option<&Packet>,
+) -> bool {
+    loop {
+        if matches!(acknowledgement, Some(packet) if identifiers.remap(packet).is_some()) {
+            return true;
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline) => return true,
+            packet = stream.next() => match packet {
+                Some(packet) => {
+                    debug!("received {:?}", packet);
+                    identifiers.live(&packet);
+                    stats.received += 1;
+                }
+                None => return false,
+            }
+        }
+    }
+}
+
+/// Maps packet identifiers of QoS 1 and QoS 2 publications the broker sent when the recording was made
+/// to packet identifiers of publications the broker sends during replay.
+///
+/// The broker assigns packet identifiers to publications it sends, so the identifiers in a recording
+/// don't match a live session. Publications are matched in the order the broker sends them,
+/// retransmissions (DUP) are not counted.
+#[derive(Debug, Default)]
+struct PacketIdentifierMap {
+    /// Recorded identifier to the sequence number of the recorded publication
+    recorded: HashMap<PacketIdentifier, usize>,
+    recorded_count: usize,
+    /// Identifiers of publications received during replay by sequence number
+    live: Vec<PacketIdentifier>,
+}
+
+impl PacketIdentifierMap {
+    fn recorded(&mut self, packet: &Packet) {
+        if let Some(packet_identifier) = publish_identifier(packet) {
+            self.recorded.insert(packet_identifier, self.recorded_count);
+            self.recorded_count += 1;
+        }
+    }
+
+    fn live(&mut self, packet: &Packet) {
+        if let Some(packet_identifier) = publish_identifier(packet) {
+            self.live.push(packet_identifier);
+        }
+    }
+
+    /// Rewrites PUBACK, PUBREC and PUBCOMP packets to refer to the matching live publication.
+    /// Returns `None` if the broker has not sent the matching publication yet.
+    fn remap(&self, packet: &Packet) -> Option<Packet> {
+        let remap = |packet_identifier| match self.recorded.get(&packet_identifier) {
+            Some(&sequence) => self.live.get(sequence).copied(),
+            // not an acknowledgement of a recorded publication, sent as is
+            None => Some(packet_identifier),
+        };
+
+        let packet = match packet {
+            Packet::PubAck(PubAck { packet_identifier }) => Packet::PubAck(PubAck {
+                packet_identifier: remap(*packet_identifier)?,
+            }),
+            Packet::PubRec(PubRec { packet_identifier }) => Packet::PubRec(PubRec {
+                packet_identifier: remap(*packet_identifier)?,
+            }),
+            Packet::PubComp(PubComp { packet_identifier }) => Packet::PubComp(PubComp {
+                packet_identifier: remap(*packet_identifier)?,
+            }),
+            packet => packet.clone(),
+        };
+        Some(packet)
+    }
+}
+
+/// Returns the packet identifier of a QoS 1 or QoS 2 publication which is not a retransmission.
+fn publish_identifier(packet: &Packet) -> Option<PacketIdentifier> {
+    match packet {
+        Packet::Publish(Publish {
+            packet_identifier_dup_qos:
+                PacketIdentifierDupQoS::AtLeastOnce(packet_identifier, false)
+                | PacketIdentifierDupQoS::ExactlyOnce(packet_identifier, false),
+            ..
+        }) => Some(*packet_identifier),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures_util::StreamExt;
+
+    use mqtt3::{
+        proto::{
+            ClientId, ConnAck, Connect, ConnectReturnCode, Disconnect, Packet, PacketIdentifier,
+            PacketIdentifierDupQoS, PubAck, Publish, QoS, SubAck, SubAckQos, Subscribe,
+            SubscribeTo,
+        },
+        recording::{Direction, RecordedPacket},
+        PROTOCOL_LEVEL, PROTOCOL_NAME,
+    };
+    use mqtt_broker::{
+        auth::{authenticate_fn_ok, AllowAll},
+        AuthId, BrokerBuilder,
+    };
+
+    use crate::{
+        packet_stream::PacketStream,
+        server::{start_server, DummyAuthenticator},
+    };
+
+    use super::{replay, Credentials, PacketIdentifierMap, ReplaySpeed};
+
+    const TOPIC: &str = "replay/topic";
+
+    #[test]
+    fn it_remaps_acknowledgements_to_live_publications() {
+        let mut identifiers = PacketIdentifierMap::default();
+        identifiers.recorded(&publish(42, false));
+        identifiers.recorded(&publish(43, false));
+        identifiers.recorded(&publish(42, true));
+
+        // no live publication yet
+        assert_eq!(identifiers.remap(&puback(42)), None);
+
+        identifiers.live(&publish(1, false));
+        identifiers.live(&publish(1, true));
+        assert_eq!(identifiers.remap(&puback(42)), Some(puback(1)));
+        assert_eq!(identifiers.remap(&puback(43)), None);
+
+        identifiers.live(&publish(2, false));
+        assert_eq!(identifiers.remap(&puback(43)), Some(puback(2)));
+
+        // identifiers the broker never used are sent as is
+        assert_eq!(identifiers.remap(&puback(7)), Some(puback(7)));
+        assert_eq!(
+            identifiers.remap(&Packet::Disconnect(Disconnect)),
+            Some(Packet::Disconnect(Disconnect))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_acknowledges_live_publications() {
+        let broker = BrokerBuilder::default().with_authorizer(AllowAll).build();
+        let server_handle = start_server(broker, DummyAuthenticator::anonymous());
+
+        let client_id = ClientId::IdWithExistingSession("replay-client".into());
+
+        // the client subscribes to its own publications, which the broker
+        // delivered with a packet identifier the live broker won't use
+        let recording = vec![
+            client(Packet::Connect(Connect {
+                username: None,
+                password: None,
+                will: None,
+                client_id: client_id.clone(),
+                keep_alive: Duration::from_secs(30),
+                protocol_name: PROTOCOL_NAME.into(),
+                protocol_level: PROTOCOL_LEVEL,
+            })),
+            server(Packet::ConnAck(ConnAck {
+                session_present: false,
+                return_code: ConnectReturnCode::Accepted,
+            })),
+            client(Packet::Subscribe(Subscribe {
+                packet_identifier: id(1),
+                subscribe_to: vec![SubscribeTo {
+                    topic_filter: TOPIC.into(),
+                    qos: QoS::AtLeastOnce,
+                }],
+            })),
+            server(Packet::SubAck(SubAck {
+                packet_identifier: id(1),
+                qos: vec![SubAckQos::Success(QoS::AtLeastOnce)],
+            })),
+            client(publish(2, false)),
+            server(puback(2)),
+            server(publish(42, false)),
+            client(puback(42)),
+            client(Packet::Disconnect(Disconnect)),
+        ];
+
+        let stats = replay(
+            server_handle.address(),
+            recording,
+            &Credentials::default(),
+            ReplaySpeed::Unlimited,
+            Duration::from_millis(100),
+        )
+        .await;
+        assert_eq!(stats.sent, 5);
+        assert_eq!(stats.skipped, 0);
+        assert_eq!(stats.recorded_received, 4);
+
+        // the publication has been acknowledged, so it is not delivered again
+        let mut client =
+            PacketStream::connect(client_id, server_handle.address(), None, None, None).await;
+        assert!(matches!(
+            client.next().await,
+            Some(Packet::ConnAck(ConnAck {
+                session_present: true,
+                ..
+            }))
+        ));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(500), client.next())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_connects_with_supplied_credentials() {
+        let broker = BrokerBuilder::default().with_authorizer(AllowAll).build();
+        let authenticator =
+            authenticate_fn_ok(|context| match (context.username(), context.password()) {
+                (Some("replay-user"), Some("secret")) => Some(AuthId::from("replay-user")),
+                _ => None,
+            });
+        let server_handle = start_server(broker, authenticator);
+
+        // the password is not recorded
+        let recording = || {
+            vec![
+                client(Packet::Connect(Connect {
+                    username: Some("recorded-user".into()),
+                    password: None,
+                    will: None,
+                    client_id: ClientId::IdWithCleanSession("replay-client".into()),
+                    keep_alive: Duration::from_secs(30),
+                    protocol_name: PROTOCOL_NAME.into(),
+                    protocol_level: PROTOCOL_LEVEL,
+                })),
+                client(Packet::Subscribe(Subscribe {
+                    packet_identifier: id(1),
+                    subscribe_to: vec![SubscribeTo {
+                        topic_filter: TOPIC.into(),
+                        qos: QoS::AtLeastOnce,
+                    }],
+                })),
+            ]
+        };
+
+        // the broker refuses the connection without the password
+        let stats = replay(
+            server_handle.address(),
+            recording(),
+            &Credentials::default(),
+            ReplaySpeed::Unlimited,
+            Duration::from_millis(100),
+        )
+        .await;
+        assert_eq!(stats.received, 1);
+
+        let credentials = Credentials {
+            username: Some("replay-user".into()),
+            password: Some("secret".into()),
+        };
+        let stats = replay(
+            server_handle.address(),
+            recording(),
+            &credentials,
+            ReplaySpeed::Unlimited,
+            Duration::from_millis(100),
+        )
+        .await;
+        assert_eq!(stats.sent, 2);
+        // CONNACK and SUBACK
+        assert_eq!(stats.received, 2);
+    }
+
+    fn client(packet: Packet) -> RecordedPacket {
+        RecordedPacket {
+            elapsed: Duration::from_secs(0),
+            direction: Direction::ClientToServer,
+            packet,
+        }
+    }
+
+    fn server(packet: Packet) -> RecordedPacket {
+        RecordedPacket {
+            elapsed: Duration::from_secs(0),
+            direction: Direction::ServerToClient,
+            packet,
+        }
+    }
+
+    fn id(packet_identifier: u16) -> PacketIdentifier {
+        PacketIdentifier::new(packet_identifier).unwrap()
+    }
+
+    fn publish(packet_identifier: u16, dup: bool) -> Packet {
+        Packet::Publish(Publish {
+            packet_identifier_dup_qos: PacketIdentifierDupQoS::AtLeastOnce(
+                id(packet_identifier),
+                dup,
+            ),
+            retain: false,
+            topic_name: TOPIC.into(),
+            payload: Bytes::from_static(b"payload"),
+        })
+    }
+
+    fn puback(packet_identifier: u16) -> Packet {
+        Packet::PubAck(PubAck {
+            packet_identifier: id(packet_identifier),
+        })
+    }
+}
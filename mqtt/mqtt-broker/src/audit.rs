@@ -0,0 +1,482 @@
+use std::{
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::mpsc::{self, SyncSender, TrySendError},
+    thread,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{error, warn};
+
+use mqtt3::proto;
+
+use crate::{
+    auth::{Activity, AuthId, Authorization, Operation},
+    settings::{AuditConfig, AuditFileConfig},
+    Auth, BrokerHandle, ClientId, Message, SystemEvent,
+};
+
+/// Reserved topic audit records are published to.
+pub const AUDIT_TOPIC: &str = "$audit";
+
+/// Max number of audit records waiting to be written to file. When the
+/// writer falls behind, new records are dropped.
+const FILE_QUEUE_CAPACITY: usize = 1024;
+
+/// Checks whether the topic (or topic filter) is reserved for audit records.
+/// Clients are not allowed to publish to these topics, and only configured
+/// audit subscribers are allowed to subscribe to them.
+pub(crate) fn is_audit_topic(topic: &str) -> bool {
+    topic == AUDIT_TOPIC
+        || topic
+            .strip_prefix(AUDIT_TOPIC)
+            .map_or(false, |rest| rest.starts_with('/'))
+}
+
+/// Audit log of connect attempts, authentication and authorization decisions
+/// and disconnects caused by reauthorization.
+///
+/// Records are serialized to JSON and written to a rotating file by a
+/// dedicated thread, so that broker loop never waits for disk, and/or
+/// published to `$audit` topic. Records are passed to the file writer through
+/// a bounded queue, and dropped if the queue is full. Dropped records are
+/// counted and reported once the writer catches up.
+pub(crate) struct AuditLog {
+    file: Option<SyncSender<String>>,
+    dropped: u64,
+    handle: Option<BrokerHandle>,
+    allowed_sample_interval: u32,
+    allowed_count: u32,
+    subscribers: Vec<String>,
+}
+
+impl AuditLog {
+    pub fn new(config: &AuditConfig, handle: &BrokerHandle) -> Self {
+        let file = config.file().and_then(|file| match spawn_writer(file) {
+            Ok(sender) => Some(sender),
+            Err(e) => {
+                error!(message = "unable to start audit log file writer", error = %e);
+                None
+            }
+        });
+
+        Self {
+            file,
+            dropped: 0,
+            handle: config.topic().then(|| handle.clone()),
+            allowed_sample_interval: config.allowed_sample_interval(),
+            allowed_count: 0,
+            subscribers: config.subscribers().to_vec(),
+        }
+    }
+
+    /// Checks whether the client is allowed to subscribe to audit topics.
+    pub fn is_subscriber(&self, auth_id: &AuthId) -> bool {
+        match auth_id {
+            AuthId::Anonymous => false,
+            AuthId::Identity(identity) => self
+                .subscribers
+                .iter()
+                .any(|subscriber| subscriber == identity.as_str()),
+        }
+    }
+
+    pub fn authentication(&mut self, client_id: &ClientId, peer_addr: SocketAddr, auth: &Auth) {
+        let (auth_id, result) = match auth {
+            Auth::Identity(auth_id) => (Some(auth_id.to_string()), AuthenticationResult::Success),
+            Auth::Unknown => (None, AuthenticationResult::Failure),
+            Auth::Failure => (None, AuthenticationResult::Unavailable),
+        };
+
+        if !self.sample(result == AuthenticationResult::Success) {
+            return;
+        }
+
+        self.record(AuditEvent::Authentication {
+            client_id: client_id.to_string(),
+            peer_addr,
+            auth_id,
+            result,
+        });
+    }
+
+    /// Records a connect refused before authentication, e.g. because of
+    /// an unsupported protocol.
+    pub fn connect_refused(
+        &mut self,
+        client_id: &ClientId,
+        peer_addr: SocketAddr,
+        reason: impl Into<String>,
+    ) {
+        if !self.sample(false) {
+            return;
+        }
+
+        self.record(AuditEvent::ConnectRefused {
+            client_id: client_id.to_string(),
+            peer_addr,
+            reason: reason.into(),
+        });
+    }
+
+    pub fn authorization<E>(&mut self, activity: &Activity, auth: &Result<Authorization, E>)
+    where
+        E: Display,
+    {
+        let (decision, reason) = match auth {
+            Ok(Authorization::Allowed) => (AuthorizationDecision::Allowed, None),
+            Ok(Authorization::Forbidden(reason)) => {
+                (AuthorizationDecision::Denied, Some(reason.clone()))
+            }
+            Err(e) => (AuthorizationDecision::Error, Some(e.to_string())),
+        };
+
+        if !self.sample(decision == AuthorizationDecision::Allowed) {
+            return;
+        }
+
+        let (operation, resource) = match activity.operation() {
+            Operation::Connect => ("connect", None),
+            Operation::Publish(publish) => (
+                "publish",
+                Some(publish.publication().topic_name().to_string()),
+            ),
+            Operation::Subscribe(subscribe) => {
+                ("subscribe", Some(subscribe.topic_filter().to_string()))
+            }
+        };
+
+        self.record(AuditEvent::Authorization {
+            client_id: activity.client_id().to_string(),
+            peer_addr: activity.client_info().peer_addr(),
+            auth_id: activity.client_info().auth_id().to_string(),
+            operation,
+            resource,
+            decision,
+            reason,
+        });
+    }
+
+    pub fn disconnect(&mut self, activity: &Activity, reason: impl Into<String>) {
+        if !self.sample(false) {
+            return;
+        }
+
+        self.record(AuditEvent::Disconnect {
+            client_id: activity.client_id().to_string(),
+            peer_addr: activity.client_info().peer_addr(),
+            auth_id: activity.client_info().auth_id().to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    /// Checks whether an event should be recorded.
+    /// Only every n-th allowed event is recorded.
+    fn sample(&mut self, allowed: bool) -> bool {
+        if self.file.is_none() && self.handle.is_none() {
+            return false;
+        }
+
+        if !allowed {
+            return true;
+        }
+
+        if self.allowed_sample_interval == 0 {
+            return false;
+        }
+
+        let sampled = self.allowed_count == 0;
+        self.allowed_count = (self.allowed_count + 1) % self.allowed_sample_interval;
+        sampled
+    }
+
+    fn record(&mut self, event: AuditEvent) {
+        let record = AuditRecord {
+            time: Utc::now(),
+            event,
+        };
+
+        let json = match serde_json::to_string(&record) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!(message = "unable to serialize audit record", error = %e);
+                return;
+            }
+        };
+
+        if let Some(handle) = &self.handle {
+            let publication = proto::Publication {
+                topic_name: AUDIT_TOPIC.to_string(),
+                qos: proto::QoS::AtMostOnce,
+                retain: false,
+                payload: json.clone().into(),
+            };
+            let message = Message::System(SystemEvent::Publish(publication));
+            if let Err(e) = handle.send(message) {
+                warn!(message = "unable to publish audit record", error = %e);
+            }
+        }
+
+        if let Some(file) = &self.file {
+            match file.try_send(json) {
+                Ok(()) => {
+                    if self.dropped > 0 {
+                        warn!(
+                            "{} audit records were dropped as audit log file writer could not keep up",
+                            self.dropped
+                        );
+                        self.dropped = 0;
+                    }
+                }
+                Err(TrySendError::Full(_)) => self.dropped += 1,
+                Err(TrySendError::Disconnected(_)) => {
+                    warn!(
+                        "audit log file writer has stopped, audit records are not written to file"
+                    );
+                    self.file = None;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AuditRecord {
+    time: DateTime<Utc>,
+    #[serde(flatten)]
+    event: AuditEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum AuditEvent {
+    ConnectRefused {
+        client_id: String,
+        peer_addr: SocketAddr,
+        reason: String,
+    },
+    Authentication {
+        client_id: String,
+        peer_addr: SocketAddr,
+        auth_id: Option<String>,
+        result: AuthenticationResult,
+    },
+    Authorization {
+        client_id: String,
+        peer_addr: SocketAddr,
+        auth_id: String,
+        operation: &'static str,
+        resource: Option<String>,
+        decision: AuthorizationDecision,
+        reason: Option<String>,
+    },
+    Disconnect {
+        client_id: String,
+        peer_addr: SocketAddr,
+        auth_id: String,
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum AuthenticationResult {
+    Success,
+    Failure,
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum AuthorizationDecision {
+    Allowed,
+    Denied,
+    Error,
+}
+
+fn spawn_writer(config: &AuditFileConfig) -> io::Result<SyncSender<String>> {
+    let mut file = RotatingFile::open(config)?;
+
+    let (sender, receiver) = mpsc::sync_channel::<String>(FILE_QUEUE_CAPACITY);
+    thread::Builder::new()
+        .name("audit-log".into())
+        .spawn(move || {
+            for line in receiver {
+                if let Err(e) = file.write_line(&line) {
+                    warn!(message = "unable to write audit record", error = %e);
+                }
+            }
+        })?;
+
+    Ok(sender)
+}
+
+/// A file which is rotated once it reaches max size. Rotated files are
+/// renamed with numeric suffix (`audit.log.1`, `audit.log.2`, ...), and only
+/// `max_files` most recent of them are kept.
+struct RotatingFile {
+    path: PathBuf,
+    max_file_size: usize,
+    max_files: usize,
+    file: File,
+    size: usize,
+}
+
+impl RotatingFile {
+    fn open(config: &AuditFileConfig) -> io::Result<Self> {
+        let path = config.path().to_path_buf();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        #[allow(clippy::cast_possible_truncation)]
+        let size = file.metadata()?.len() as usize;
+
+        Ok(Self {
+            path,
+            max_file_size: config.max_file_size(),
+            max_files: config.max_files(),
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() + 1 > self.max_file_size {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()?;
+        self.size += line.len() + 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::mpsc};
+
+    use tempfile::TempDir;
+
+    use crate::{
+        settings::{AuditConfig, AuditFileConfig, HumanSize},
+        BrokerHandle, ClientId,
+    };
+
+    use super::{is_audit_topic, AuditLog, RotatingFile};
+
+    #[test]
+    fn it_samples_allowed_events() {
+        let mut audit = AuditLog::new(&AuditConfig::new(None, true, 3), &handle());
+
+        let sampled = (0..7).map(|_| audit.sample(true)).collect::<Vec<_>>();
+        assert_eq!(sampled, vec![true, false, false, true, false, false, true]);
+        assert!(audit.sample(false));
+    }
+
+    #[test]
+    fn it_skips_allowed_events_when_sampling_disabled() {
+        let mut audit = AuditLog::new(&AuditConfig::new(None, true, 0), &handle());
+
+        assert!(!audit.sample(true));
+        assert!(audit.sample(false));
+    }
+
+    #[test]
+    fn it_skips_all_events_when_disabled() {
+        let mut audit = AuditLog::new(&AuditConfig::default(), &handle());
+
+        assert!(!audit.sample(true));
+        assert!(!audit.sample(false));
+    }
+
+    #[test]
+    fn it_drops_records_when_file_queue_is_full() {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let mut audit = AuditLog::new(&AuditConfig::default(), &handle());
+        audit.file = Some(sender);
+
+        let client_id = ClientId::from("client_a");
+        for _ in 0..3 {
+            audit.connect_refused(&client_id, crate::tests::peer_addr(), "bad protocol");
+        }
+        assert_eq!(audit.dropped, 2);
+        assert_eq!(receiver.try_iter().count(), 1);
+
+        // drop counter is reset once records are written again
+        audit.connect_refused(&client_id, crate::tests::peer_addr(), "bad protocol");
+        assert_eq!(audit.dropped, 0);
+        assert_eq!(receiver.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn it_checks_audit_topic() {
+        assert!(is_audit_topic("$audit"));
+        assert!(is_audit_topic("$audit/"));
+        assert!(is_audit_topic("$audit/records"));
+        assert!(!is_audit_topic("$auditlog"));
+        assert!(!is_audit_topic("audit"));
+        assert!(!is_audit_topic("events/$audit"));
+    }
+
+    #[test]
+    fn it_rotates_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("audit.log");
+        let config = AuditFileConfig::new(&path, HumanSize::new_bytes(10), 2);
+
+        let mut file = RotatingFile::open(&config).unwrap();
+        for line in &["line-1", "line-2", "line-3", "line-4"] {
+            file.write_line(line).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line-4\n");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("audit.log.1")).unwrap(),
+            "line-3\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("audit.log.2")).unwrap(),
+            "line-2\n"
+        );
+        assert!(!dir.path().join("audit.log.3").exists());
+    }
+
+    fn handle() -> BrokerHandle {
+        crate::BrokerBuilder::default().build().handle()
+    }
+}
@@ -165,6 +165,7 @@ pub struct Publication {
     topic_name: String,
     qos: proto::QoS,
     retain: bool,
+    payload_size: usize,
 }
 
 impl Publication {
@@ -179,6 +180,10 @@ impl Publication {
     pub fn retain(&self) -> bool {
         self.retain
     }
+
+    pub fn payload_size(&self) -> usize {
+        self.payload_size
+    }
 }
 
 impl From<proto::Publication> for Publication {
@@ -187,6 +192,7 @@ impl From<proto::Publication> for Publication {
             topic_name: publication.topic_name,
             qos: publication.qos,
             retain: publication.retain,
+            payload_size: publication.payload.len(),
         }
     }
 }
@@ -214,6 +220,7 @@ impl From<proto::Publish> for Publish {
                     proto::PacketIdentifierDupQoS::ExactlyOnce(_, _) => proto::QoS::ExactlyOnce,
                 },
                 retain: publish.retain,
+                payload_size: publish.payload.len(),
             },
         }
     }
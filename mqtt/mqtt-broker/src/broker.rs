@@ -9,7 +9,9 @@ use tracing::{debug, error, info, info_span, warn};
 use mqtt3::proto;
 
 use crate::{
+    audit::{self, AuditLog, AUDIT_TOPIC},
     auth::{Activity, AuthId, Authorization, Authorizer, DenyAll, Operation},
+    clock,
     session::{ConnectedSession, Session, SessionState},
     state_change::StateChange,
     stream::{self, SelectOrdered},
@@ -36,6 +38,7 @@ pub struct Broker<Z> {
     retained: HashMap<String, proto::Publication>,
     authorizer: Z,
     config: BrokerConfig,
+    audit: AuditLog,
 
     #[cfg(feature = "__internal_broker_callbacks")]
     pub on_publish: Option<tokio::sync::mpsc::UnboundedSender<std::time::Duration>>,
@@ -131,24 +134,29 @@ where
     }
 
     fn reauthorize(&mut self) {
-        let disconnecting = self
+        let activities = self
             .sessions
             .iter()
             .flat_map(|(_, session)| Self::prepare_activities(session))
-            .filter(|activity| match self.authorizer.authorize(activity) {
-                Ok(Authorization::Allowed) => false,
+            .collect::<Vec<_>>();
+
+        let mut disconnecting = Vec::new();
+        for activity in activities {
+            match self.authorizer.authorize(&activity) {
+                Ok(Authorization::Allowed) => {}
                 Ok(Authorization::Forbidden(reason)) => {
                     warn!("not authorized: {}; reason: {}", &activity, reason);
-                    true
+                    disconnecting.push((activity, reason));
                 }
                 Err(e) => {
                     warn!(message="error authorizing client: {}", error = %e);
-                    true
+                    disconnecting.push((activity, e.to_string()));
                 }
-            })
-            .collect::<Vec<_>>();
+            }
+        }
 
-        for activity in disconnecting {
+        for (activity, reason) in disconnecting {
+            self.audit.disconnect(&activity, reason);
             if let Err(reason) = self.drop_session(activity.client_id()) {
                 warn!(
                     "error dropping session for client {}; reason: {}",
@@ -323,6 +331,11 @@ where
                 "invalid protocol name received from client: {}",
                 connreq.connect().protocol_name
             );
+            self.audit.connect_refused(
+                &client_id,
+                connreq.peer_addr(),
+                format!("invalid protocol name: {}", connreq.connect().protocol_name),
+            );
             debug!("dropping connection due to invalid protocol name");
             let message = Message::Client(client_id, ClientEvent::DropConnection);
             try_send!(connreq.handle_mut(), message);
@@ -338,6 +351,14 @@ where
                 "invalid protocol level received from client: {}",
                 connreq.connect().protocol_level
             );
+            self.audit.connect_refused(
+                &client_id,
+                connreq.peer_addr(),
+                format!(
+                    "invalid protocol level: {}",
+                    connreq.connect().protocol_level
+                ),
+            );
             refuse_connection!(proto::ConnectionRefusedReason::UnacceptableProtocolVersion);
             return Ok(());
         }
@@ -347,6 +368,8 @@ where
         // and authorization checks. If any of these checks fail, it SHOULD send an
         // appropriate CONNACK response with a non-zero return code as described in
         // section 3.2 and it MUST close the Network Connection.
+        self.audit
+            .authentication(&client_id, connreq.peer_addr(), connreq.auth());
         let auth_id = match connreq.auth() {
             Auth::Identity(auth_id) => {
                 debug!(
@@ -374,7 +397,9 @@ where
         );
         let operation = Operation::new_connect();
         let activity = Activity::new(client_info, operation);
-        match self.authorizer.authorize(&activity) {
+        let auth = self.authorizer.authorize(&activity);
+        self.audit.authorization(&activity, &auth);
+        match auth {
             Ok(Authorization::Allowed) => {
                 debug!("successfully authorized: {}", &activity);
             }
@@ -494,7 +519,8 @@ where
         sub: proto::Subscribe,
     ) -> Result<(), Error> {
         let subscriptions = if let Some(session) = self.sessions.get_mut(client_id) {
-            let (suback, subscriptions) = subscribe(&self.authorizer, session, sub);
+            let (suback, subscriptions) =
+                subscribe(&self.authorizer, &mut self.audit, session, sub);
             session.send(ClientEvent::SubAck(suback))?;
             subscriptions
         } else {
@@ -586,7 +612,17 @@ where
         if let Some(session) = self.sessions.get_mut(client_id) {
             let client_info = session.client_info().clone();
             let activity = Activity::new(client_info, operation);
-            match self.authorizer.authorize(&activity) {
+            // audit topics are reserved for records published by the broker
+            let auth = if audit::is_audit_topic(&publish.topic_name) {
+                Ok(Authorization::Forbidden(format!(
+                    "{} topics are reserved for audit records",
+                    AUDIT_TOPIC
+                )))
+            } else {
+                self.authorizer.authorize(&activity)
+            };
+            self.audit.authorization(&activity, &auth);
+            match auth {
                 Ok(Authorization::Allowed) => {
                     debug!("successfully authorized: {}", &activity);
                     let (maybe_publication, maybe_event) = session.handle_publish(publish)?;
@@ -887,7 +923,7 @@ where
 
                 let (state, will, handle) = connected.into_parts();
                 let client_info = state.client_info().clone();
-                let new_session = Session::new_offline(state, Utc::now());
+                let new_session = Session::new_offline(state, clock::now());
                 self.sessions.insert(client_id.clone(), new_session);
                 Some(Session::new_disconnecting(client_info, will, handle))
             }
@@ -970,6 +1006,7 @@ where
 
 fn subscribe<Z>(
     authorizer: &Z,
+    audit: &mut AuditLog,
     session: &mut Session,
     subscribe: proto::Subscribe,
 ) -> (proto::SubAck, Vec<Subscription>)
@@ -984,7 +1021,18 @@ where
     let auth_results = subscribe.subscribe_to.into_iter().map(|subscribe_to| {
         let operation = Operation::new_subscribe(subscribe_to.clone());
         let activity = Activity::new(client_info.clone(), operation);
-        let auth = authorizer.authorize(&activity);
+        // audit records are only available to configured subscribers
+        let auth = if audit::is_audit_topic(&subscribe_to.topic_filter)
+            && !audit.is_subscriber(client_info.auth_id())
+        {
+            Ok(Authorization::Forbidden(format!(
+                "{} topics are reserved for audit subscribers",
+                AUDIT_TOPIC
+            )))
+        } else {
+            authorizer.authorize(&activity)
+        };
+        audit.authorization(&activity, &auth);
         auth.map(|auth| (auth, subscribe_to, activity))
     });
 
@@ -1105,6 +1153,8 @@ where
             acks: ack_sender,
         };
 
+        let audit = AuditLog::new(config.audit(), &handle);
+
         Broker {
             messages,
             handle,
@@ -1112,6 +1162,7 @@ where
             retained,
             authorizer: self.authorizer,
             config,
+            audit,
 
             #[cfg(feature = "__internal_broker_callbacks")]
             on_publish: None,
@@ -1156,11 +1207,18 @@ pub struct NoSessionError;
 #[cfg(test)]
 #[allow(clippy::semicolon_if_nothing_returned)]
 pub(crate) mod tests {
-    use std::time::Duration;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
 
     use bytes::Bytes;
     use futures_util::future::FutureExt;
     use matches::assert_matches;
+    use serde_json::{json, Value};
     use tokio::sync::mpsc::{self, UnboundedReceiver};
     use uuid::Uuid;
 
@@ -1172,9 +1230,10 @@ pub(crate) mod tests {
         broker::{BrokerBuilder, BrokerHandle},
         error::Error,
         session::Session,
+        settings::AuditConfig,
         tests::peer_addr,
-        Auth, AuthId, ClientEvent, ClientId, ClientInfo, ConnReq, ConnectionHandle, Message,
-        Publish,
+        Auth, AuthId, BrokerConfig, ClientEvent, ClientId, ClientInfo, ConnReq, ConnectionHandle,
+        Message, Publish, SystemEvent,
     };
 
     pub fn connection_handle() -> ConnectionHandle {
@@ -2159,6 +2218,193 @@ pub(crate) mod tests {
         assert_matches!(rx.recv().await, None);
     }
 
+    #[tokio::test]
+    async fn test_publish_to_audit_topic_not_allowed() {
+        let broker = BrokerBuilder::default().with_authorizer(AllowAll).build();
+
+        let broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let (client_id, mut rx) = connect_client("pub", &broker_handle).await.unwrap();
+
+        let publish = proto::Publish {
+            packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtMostOnce,
+            retain: false,
+            topic_name: "$audit/fake".to_string(),
+            payload: Bytes::new(),
+        };
+
+        let message = Message::Client(client_id.clone(), ClientEvent::PublishFrom(publish, None));
+        broker_handle.send(message).unwrap();
+
+        assert_matches!(
+            rx.recv().await,
+            Some(Message::Client(_, ClientEvent::DropConnection))
+        );
+        assert_matches!(rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_to_audit_topic_allowed_only_for_subscribers() {
+        let audit = AuditConfig::new(None, true, 0).with_subscribers(vec!["auditor".into()]);
+        let config = BrokerConfig::default().with_audit(audit);
+        let broker = BrokerBuilder::default()
+            .with_authorizer(AllowAll)
+            .with_config(config)
+            .build();
+
+        let broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let clients = vec![
+            ("anonymous", AuthId::Anonymous, false),
+            ("other", "other".into(), false),
+            ("auditor", "auditor".into(), true),
+        ];
+        for (client_id, auth_id, allowed) in clients {
+            let (client_id, mut rx) = connect_client_as(client_id, auth_id, &broker_handle)
+                .await
+                .unwrap();
+
+            let subscribe = proto::Subscribe {
+                packet_identifier: proto::PacketIdentifier::new(1).unwrap(),
+                subscribe_to: vec![
+                    proto::SubscribeTo {
+                        topic_filter: "$audit/#".into(),
+                        qos: proto::QoS::AtMostOnce,
+                    },
+                    proto::SubscribeTo {
+                        topic_filter: "topic".into(),
+                        qos: proto::QoS::AtMostOnce,
+                    },
+                ],
+            };
+            let message = Message::Client(client_id, ClientEvent::Subscribe(subscribe));
+            broker_handle.send(message).unwrap();
+
+            let expected = if allowed {
+                proto::SubAckQos::Success(proto::QoS::AtMostOnce)
+            } else {
+                proto::SubAckQos::Failure
+            };
+            assert_matches!(
+                rx.recv().await,
+                Some(Message::Client(_, ClientEvent::SubAck(ack)))
+                    if ack.qos == vec![expected, proto::SubAckQos::Success(proto::QoS::AtMostOnce)]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audit_records_on_connect_deny_and_reauthorize() {
+        let revoked = Arc::new(AtomicBool::new(false));
+        let authorizer = {
+            let revoked = revoked.clone();
+            authorize_fn_ok(move |activity| match activity.client_id().as_str() {
+                "client_b" => match activity.operation() {
+                    Operation::Publish(publish)
+                        if publish.publication().topic_name() == "denied" =>
+                    {
+                        Authorization::Forbidden("denied".to_string())
+                    }
+                    _ => Authorization::Allowed,
+                },
+                "client_c" if revoked.load(Ordering::SeqCst) => {
+                    Authorization::Forbidden("revoked".to_string())
+                }
+                _ => Authorization::Allowed,
+            })
+        };
+
+        let audit = AuditConfig::new(None, true, 1).with_subscribers(vec!["auditor".into()]);
+        let config = BrokerConfig::default().with_audit(audit);
+        let broker = BrokerBuilder::default()
+            .with_authorizer(authorizer)
+            .with_config(config)
+            .build();
+
+        let broker_handle = broker.handle();
+        tokio::spawn(broker.run().map(drop));
+
+        let (observer_id, mut observer_rx) =
+            connect_client_as("observer", "auditor".into(), &broker_handle)
+                .await
+                .unwrap();
+        send_subscribe(&broker_handle, &mut observer_rx, observer_id, &["$audit"]).await;
+
+        // connect is allowed
+        let (b_id, _b_rx) = connect_client("client_b", &broker_handle).await.unwrap();
+
+        // publish is denied
+        let publish = proto::Publish {
+            packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtMostOnce,
+            retain: false,
+            topic_name: "denied".to_string(),
+            payload: Bytes::new(),
+        };
+        let message = Message::Client(b_id, ClientEvent::PublishFrom(publish, None));
+        broker_handle.send(message).unwrap();
+
+        // client is disconnected on reauthorization
+        let (_c_id, _c_rx) = connect_client("client_c", &broker_handle).await.unwrap();
+        revoked.store(true, Ordering::SeqCst);
+        broker_handle
+            .send(Message::System(SystemEvent::AuthorizationUpdate(Box::new(
+                (),
+            ))))
+            .unwrap();
+
+        let expected = vec![
+            json!({
+                "event": "authentication",
+                "client_id": "client_b",
+                "result": "success"
+            }),
+            json!({
+                "event": "authorization",
+                "client_id": "client_b",
+                "operation": "connect",
+                "decision": "allowed"
+            }),
+            json!({
+                "event": "authorization",
+                "client_id": "client_b",
+                "operation": "publish",
+                "resource": "denied",
+                "decision": "denied"
+            }),
+            json!({
+                "event": "disconnect",
+                "client_id": "client_c",
+                "reason": "revoked"
+            }),
+        ];
+
+        let mut records = vec![];
+        while !expected
+            .iter()
+            .all(|expected| records.iter().any(|record| contains(record, expected)))
+        {
+            match tokio::time::timeout(Duration::from_secs(5), observer_rx.recv()).await {
+                Ok(Some(Message::Client(_, ClientEvent::PublishTo(Publish::QoS0(_, publish))))) => {
+                    assert_eq!(publish.topic_name, "$audit");
+                    records.push(serde_json::from_slice::<Value>(&publish.payload).unwrap());
+                }
+                Ok(Some(_)) => {}
+                _ => panic!("expected audit records not received: {:?}", records),
+            }
+        }
+    }
+
+    /// Checks that all fields of `expected` are present in `record`.
+    fn contains(record: &Value, expected: &Value) -> bool {
+        expected
+            .as_object()
+            .unwrap()
+            .iter()
+            .all(|(key, value)| record.get(key) == Some(value))
+    }
+
     #[tokio::test]
     async fn test_subscribe_client_has_no_permissions() {
         let broker = BrokerBuilder::default()
@@ -2391,6 +2637,14 @@ pub(crate) mod tests {
     async fn connect_client(
         client_id: &str,
         broker_handle: &BrokerHandle,
+    ) -> Result<(ClientId, UnboundedReceiver<Message>), Error> {
+        connect_client_as(client_id, AuthId::Anonymous, broker_handle).await
+    }
+
+    async fn connect_client_as(
+        client_id: &str,
+        auth_id: AuthId,
+        broker_handle: &BrokerHandle,
     ) -> Result<(ClientId, UnboundedReceiver<Message>), Error> {
         let connect = persistent_connect(client_id.into());
 
@@ -2401,7 +2655,7 @@ pub(crate) mod tests {
             client_id.clone(),
             peer_addr(),
             connect,
-            Auth::Identity(AuthId::Anonymous),
+            Auth::Identity(auth_id),
             conn,
         );
         broker_handle.send(Message::Client(
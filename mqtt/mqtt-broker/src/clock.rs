@@ -0,0 +1,53 @@
+//! Wall-clock time the broker uses to track when sessions were last active.
+//!
+//! This is the system time unless a thread opts into virtual time with `follow_tokio_time`,
+//! which is only available in tests or with the `test-util` feature.
+//! Tests that pause tokio time can do so to make sessions age when tokio time is advanced.
+
+use chrono::{DateTime, Utc};
+
+#[cfg(any(test, feature = "test-util"))]
+pub use virtual_time::{follow_system_time, follow_tokio_time};
+
+/// Returns the current wall-clock time.
+#[cfg(not(any(test, feature = "test-util")))]
+pub fn now() -> DateTime<Utc> {
+    Utc::now()
+}
+
+/// Returns the current wall-clock time.
+#[cfg(any(test, feature = "test-util"))]
+pub fn now() -> DateTime<Utc> {
+    virtual_time::now().unwrap_or_else(Utc::now)
+}
+
+#[cfg(any(test, feature = "test-util"))]
+mod virtual_time {
+    use std::cell::Cell;
+
+    use chrono::{DateTime, Duration, Utc};
+    use tokio::time::Instant;
+
+    thread_local! {
+        static VIRTUAL_EPOCH: Cell<Option<(DateTime<Utc>, Instant)>> = Cell::new(None);
+    }
+
+    /// Returns the virtual time if the current thread follows tokio time.
+    pub(super) fn now() -> Option<DateTime<Utc>> {
+        VIRTUAL_EPOCH.with(Cell::get).map(|(epoch, start)| {
+            epoch + Duration::from_std(start.elapsed()).unwrap_or_else(|_| Duration::max_value())
+        })
+    }
+
+    /// Makes [`super::now`] on the current thread start at the current system time and advance with tokio time.
+    ///
+    /// Only meaningful on a current-thread runtime, which is also the only kind of runtime tokio time can be paused on.
+    pub fn follow_tokio_time() {
+        VIRTUAL_EPOCH.with(|epoch| epoch.set(Some((Utc::now(), Instant::now()))));
+    }
+
+    /// Makes [`super::now`] on the current thread return the system time again.
+    pub fn follow_system_time() {
+        VIRTUAL_EPOCH.with(|epoch| epoch.set(None));
+    }
+}
@@ -1,5 +1,10 @@
 mod packet;
+mod translate;
 pub use packet::*;
+pub use translate::{
+    MakeTranslationPacketProcessor, TopicTranslation, TopicTranslationError,
+    TranslationPacketProcessor,
+};
 
 use std::{
     fmt::{Display, Formatter, Result as FmtResult},
@@ -24,11 +29,15 @@ use tracing::{debug, info, info_span, trace, warn};
 use tracing_futures::Instrument;
 use uuid::Uuid;
 
-use mqtt3::proto::{self, DecodeError, Packet, PacketCodec};
+use mqtt3::{
+    proto::{self, DecodeError, Packet, PacketCodec},
+    recording::{Direction, Recorder},
+};
 
 use crate::{
     auth::{AuthenticationContext, Authenticator, Certificate},
     broker::BrokerHandle,
+    settings::RecordingConfig,
     transport::GetPeerInfo,
     Auth, ClientEvent, ClientId, ConnReq, Error, Message,
 };
@@ -82,6 +91,8 @@ impl PartialEq for ConnectionHandle {
 ///
 /// Receives a source of packets and a handle to the Broker.
 /// Starts two tasks (sending and receiving)
+///
+/// If `recording` is set, packets of the connection are recorded to a new file in the configured directory.
 #[allow(clippy::too_many_lines)]
 pub async fn process<I, N, P>(
     io: I,
@@ -89,6 +100,7 @@ pub async fn process<I, N, P>(
     broker_handle: BrokerHandle,
     authenticator: &N,
     make_processor: P,
+    recording: Option<&RecordingConfig>,
 ) -> Result<(), Error>
 where
     I: AsyncRead + AsyncWrite + GetPeerInfo<Certificate = Certificate> + Unpin,
@@ -126,6 +138,22 @@ where
                 info!("new client connection");
                 debug!("received CONNECT: {:?}", connect);
 
+                let recorder = recording.and_then(|recording| {
+                    let path = recording.directory().join(format!("{}.mqttrec", connection_handle));
+                    match Recorder::create(&path) {
+                        Ok(recorder) => {
+                            let recorder = recorder.with_max_size(recording.max_file_size() as u64);
+                            info!("recording connection to {}", path.display());
+                            recorder.record(Direction::ClientToServer, &Packet::Connect(connect.clone()));
+                            Some(recorder)
+                        }
+                        Err(e) => {
+                            warn!(message = "unable to create connection recording", path = %path.display(), error = %e);
+                            None
+                        }
+                    }
+                });
+
                 // [MQTT-3.1.2-24] - If the Keep Alive value is non-zero and
                 // the Server does not receive a Control Packet from the
                 // Client within one and a half times the Keep Alive time
@@ -165,21 +193,22 @@ where
                     }
                 };
 
+                let (outgoing_processor, incoming_processor) = make_processor.make(&client_id, &auth);
+
                 let req = ConnReq::new(client_id.clone(), peer_addr, connect, auth, connection_handle);
                 let event = ClientEvent::ConnReq(req);
                 let message = Message::Client(client_id.clone(), event);
                 broker_handle.send(message)?;
 
                 let (outgoing, incoming) = codec.split();
-                let (outgoing_processor, incoming_processor) = make_processor.make(&client_id);
 
                 // prepare processing incoming packets
                 let incoming_task =
-                    incoming_task(client_id.clone(), incoming, broker_handle.clone(), incoming_processor);
+                    incoming_task(client_id.clone(), incoming, broker_handle.clone(), incoming_processor, recorder.clone());
                 pin_mut!(incoming_task);
 
                 // prepare processing outgoing packets
-                let outgoing_task = outgoing_task(events, outgoing, broker_handle.clone(), outgoing_processor);
+                let outgoing_task = outgoing_task(events, outgoing, broker_handle.clone(), outgoing_processor, recorder);
                 pin_mut!(outgoing_task);
 
                 match select(incoming_task, outgoing_task).await {
@@ -254,6 +283,7 @@ async fn incoming_task<S, P>(
     mut incoming: S,
     broker: BrokerHandle,
     mut processor: P,
+    recorder: Option<Recorder>,
 ) -> Result<(), Error>
 where
     S: Stream<Item = Result<Packet, DecodeError>> + Unpin,
@@ -261,6 +291,10 @@ where
 {
     debug!("incoming_task start");
     while let Some(maybe_packet) = incoming.next().await {
+        if let (Some(recorder), Ok(packet)) = (&recorder, &maybe_packet) {
+            recorder.record(Direction::ClientToServer, packet);
+        }
+
         match maybe_packet {
             Ok(packet) => match processor.process(packet).await? {
                 PacketAction::Continue(message) => {
@@ -290,6 +324,7 @@ async fn outgoing_task<S, P>(
     mut outgoing: S,
     broker: BrokerHandle,
     mut processor: P,
+    recorder: Option<Recorder>,
 ) -> Result<(), (UnboundedReceiver<Message>, Error)>
 where
     S: Sink<Packet, Error = proto::EncodeError> + Unpin,
@@ -301,6 +336,10 @@ where
         match processor.process(message).await {
             PacketAction::Continue(Some((packet, message))) => {
                 // send a packet to a client
+                if let Some(recorder) = &recorder {
+                    recorder.record(Direction::ServerToClient, &packet);
+                }
+
                 if let Err(e) = outgoing.send(packet).await {
                     warn!(message = "error occurred while writing to connection", error = %e);
                     return Err((messages, e.into()));
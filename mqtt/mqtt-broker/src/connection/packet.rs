@@ -6,7 +6,7 @@ use tracing::{debug, warn};
 
 use mqtt3::proto::Packet;
 
-use crate::{ClientEvent, ClientId, Error, Message, Publish};
+use crate::{Auth, ClientEvent, ClientId, Error, Message, Publish};
 
 /// Action result of packet processing operation.
 /// * `Continue` - processor suggests move to the next packet.
@@ -41,8 +41,12 @@ pub trait MakePacketProcessor {
     type OutgoingProcessor: OutgoingPacketProcessor + Send;
     type IncomingProcessor: IncomingPacketProcessor + Send;
 
-    /// Creates a new instances of packet processors.
-    fn make(&self, client_id: &ClientId) -> (Self::OutgoingProcessor, Self::IncomingProcessor);
+    /// Creates a new instances of packet processors for an authenticated client connection.
+    fn make(
+        &self,
+        client_id: &ClientId,
+        auth: &Auth,
+    ) -> (Self::OutgoingProcessor, Self::IncomingProcessor);
 }
 
 /// Makes a new instance of default MQTT packet processor.
@@ -54,7 +58,11 @@ impl MakePacketProcessor for MakeMqttPacketProcessor {
 
     type IncomingProcessor = MqttIncomingPacketProcessor;
 
-    fn make(&self, client_id: &ClientId) -> (Self::OutgoingProcessor, Self::IncomingProcessor) {
+    fn make(
+        &self,
+        client_id: &ClientId,
+        _auth: &Auth,
+    ) -> (Self::OutgoingProcessor, Self::IncomingProcessor) {
         (
             Self::OutgoingProcessor::new(client_id.clone()),
             Self::IncomingProcessor::new(client_id.clone(), 10),
@@ -0,0 +1,419 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tracing::debug;
+
+use mqtt3::proto::Packet;
+
+use crate::{
+    topic_rules::{TopicRule, TopicRuleError, TopicRules},
+    Auth, ClientId, Error, IncomingPacketProcessor, MakeMqttPacketProcessor, MakePacketProcessor,
+    Message, OutgoingPacketProcessor, PacketAction,
+};
+
+const CLIENT_ID: &str = "client_id";
+const AUTH_ID: &str = "auth_id";
+
+/// Topic translation rules for devices which use their own topic layouts
+/// (e.g. Sparkplug B or custom `devices/<id>/...` layouts).
+///
+/// Rules are loaded from a JSON file with two ordered lists of rules:
+/// * `incoming` rules rewrite topics of publications received from clients
+///   and topic filters clients subscribe or unsubscribe to.
+/// * `outgoing` rules rewrite topics of publications delivered to clients.
+///
+/// Rule templates may refer to named capture groups of the pattern, as well
+/// as to `{client_id}` and `{auth_id}` of the connected client.
+///
+/// ```json
+/// {
+///     "incoming": [
+///         {
+///             "name": "sparkplug_device_data",
+///             "pattern": "spBv1.0/(?P<group>[^/]+)/DDATA/(?P<node>[^/]+)/{device_id}",
+///             "template": "devices/{client_id}/sparkplug/{group}/{node}/data"
+///         }
+///     ],
+///     "outgoing": [
+///         {
+///             "name": "sparkplug_device_command",
+///             "pattern": "devices/{device_id}/sparkplug/(?P<group>[^/]+)/(?P<node>[^/]+)/command",
+///             "template": "spBv1.0/{group}/DCMD/{node}/{device_id}"
+///         }
+///     ]
+/// }
+/// ```
+#[derive(Debug)]
+pub struct TopicTranslation {
+    incoming: TopicRules,
+    outgoing: TopicRules,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopicTranslationDefinition {
+    #[serde(default)]
+    incoming: Vec<TopicRuleDefinition>,
+    #[serde(default)]
+    outgoing: Vec<TopicRuleDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopicRuleDefinition {
+    name: String,
+    pattern: String,
+    template: String,
+}
+
+impl TopicRuleDefinition {
+    fn as_rule(&self) -> TopicRule<'_> {
+        TopicRule::new(&self.name, &self.pattern, &self.template)
+    }
+}
+
+impl TopicTranslation {
+    /// Loads rules from a given JSON file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, TopicTranslationError> {
+        let path = path.as_ref();
+        let contents =
+            fs::read(path).map_err(|e| TopicTranslationError::Read(path.to_path_buf(), e))?;
+        let definition = serde_json::from_slice(&contents)
+            .map_err(|e| TopicTranslationError::Parse(path.to_path_buf(), e))?;
+
+        let translation = Self::from_definition(&definition)?;
+        Ok(translation)
+    }
+
+    fn from_definition(definition: &TopicTranslationDefinition) -> Result<Self, TopicRuleError> {
+        let variables = [CLIENT_ID, AUTH_ID];
+        Ok(Self {
+            incoming: TopicRules::new(
+                definition.incoming.iter().map(TopicRuleDefinition::as_rule),
+                &variables,
+            )?,
+            outgoing: TopicRules::new(
+                definition.outgoing.iter().map(TopicRuleDefinition::as_rule),
+                &variables,
+            )?,
+        })
+    }
+
+    /// Translates a topic (or a topic filter) received from a client.
+    pub fn incoming(&self, topic: &str, client_id: &ClientId, auth_id: &str) -> Option<String> {
+        self.incoming.rewrite(
+            topic,
+            &[(CLIENT_ID, client_id.as_str()), (AUTH_ID, auth_id)],
+        )
+    }
+
+    /// Translates a topic of a publication to be delivered to a client.
+    pub fn outgoing(&self, topic: &str, client_id: &ClientId, auth_id: &str) -> Option<String> {
+        self.outgoing.rewrite(
+            topic,
+            &[(CLIENT_ID, client_id.as_str()), (AUTH_ID, auth_id)],
+        )
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TopicTranslationError {
+    #[error("unable to read topic translation rules file {0}")]
+    Read(PathBuf, #[source] io::Error),
+
+    #[error("unable to parse topic translation rules file {0}")]
+    Parse(PathBuf, #[source] serde_json::Error),
+
+    #[error(transparent)]
+    Rule(#[from] TopicRuleError),
+}
+
+/// Makes packet processors which translate topics with configured rules
+/// before passing packets to inner processors. When no rules configured,
+/// packets are passed as is.
+#[derive(Debug, Clone)]
+pub struct MakeTranslationPacketProcessor<P> {
+    translation: Option<Arc<TopicTranslation>>,
+    inner: P,
+}
+
+impl MakeTranslationPacketProcessor<MakeMqttPacketProcessor> {
+    pub fn new_default(translation: Option<TopicTranslation>) -> Self {
+        Self::new(translation, MakeMqttPacketProcessor)
+    }
+}
+
+impl<P> MakeTranslationPacketProcessor<P> {
+    pub fn new(translation: Option<TopicTranslation>, inner: P) -> Self {
+        Self {
+            translation: translation.map(Arc::new),
+            inner,
+        }
+    }
+}
+
+impl<P> MakePacketProcessor for MakeTranslationPacketProcessor<P>
+where
+    P: MakePacketProcessor,
+{
+    type OutgoingProcessor = TranslationPacketProcessor<P::OutgoingProcessor>;
+
+    type IncomingProcessor = TranslationPacketProcessor<P::IncomingProcessor>;
+
+    fn make(
+        &self,
+        client_id: &ClientId,
+        auth: &Auth,
+    ) -> (Self::OutgoingProcessor, Self::IncomingProcessor) {
+        let (outgoing_inner, incoming_inner) = self.inner.make(client_id, auth);
+
+        let auth_id = match auth {
+            Auth::Identity(auth_id) => auth_id.to_string(),
+            Auth::Unknown | Auth::Failure => String::new(),
+        };
+
+        let outgoing = TranslationPacketProcessor {
+            translation: self.translation.clone(),
+            client_id: client_id.clone(),
+            auth_id: auth_id.clone(),
+            inner: outgoing_inner,
+        };
+
+        let incoming = TranslationPacketProcessor {
+            translation: self.translation.clone(),
+            client_id: client_id.clone(),
+            auth_id,
+            inner: incoming_inner,
+        };
+
+        (outgoing, incoming)
+    }
+}
+
+/// MQTT packet processor wrapper. It translates topics of incoming
+/// publications, subscriptions and unsubscriptions, and topics of outgoing
+/// publications with configured rules.
+pub struct TranslationPacketProcessor<P> {
+    translation: Option<Arc<TopicTranslation>>,
+    client_id: ClientId,
+    auth_id: String,
+    inner: P,
+}
+
+impl<P> TranslationPacketProcessor<P> {
+    fn translate_incoming(&self, packet: &mut Packet) {
+        let translation = match &self.translation {
+            Some(translation) => translation,
+            None => return,
+        };
+
+        let translate = |topic: &mut String| {
+            if let Some(new_topic) = translation.incoming(topic, &self.client_id, &self.auth_id) {
+                debug!("translating incoming topic {} to {}", topic, new_topic);
+                *topic = new_topic;
+            }
+        };
+
+        match packet {
+            Packet::Publish(publish) => translate(&mut publish.topic_name),
+            Packet::Subscribe(subscribe) => {
+                for subscribe_to in &mut subscribe.subscribe_to {
+                    translate(&mut subscribe_to.topic_filter);
+                }
+            }
+            Packet::Unsubscribe(unsubscribe) => {
+                for unsubscribe_from in &mut unsubscribe.unsubscribe_from {
+                    translate(unsubscribe_from);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn translate_outgoing(&self, packet: &mut Packet) {
+        if let (Some(translation), Packet::Publish(publish)) = (&self.translation, packet) {
+            if let Some(new_topic) =
+                translation.outgoing(&publish.topic_name, &self.client_id, &self.auth_id)
+            {
+                debug!(
+                    "translating outgoing topic {} to {}",
+                    publish.topic_name, new_topic
+                );
+                publish.topic_name = new_topic;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<P> IncomingPacketProcessor for TranslationPacketProcessor<P>
+where
+    P: IncomingPacketProcessor + Send,
+{
+    async fn process(
+        &mut self,
+        mut packet: Packet,
+    ) -> Result<PacketAction<Message, Message>, Error> {
+        self.translate_incoming(&mut packet);
+        self.inner.process(packet).await
+    }
+}
+
+#[async_trait]
+impl<P> OutgoingPacketProcessor for TranslationPacketProcessor<P>
+where
+    P: OutgoingPacketProcessor + Send,
+{
+    async fn process(
+        &mut self,
+        message: Message,
+    ) -> PacketAction<Option<(Packet, Option<Message>)>, ()> {
+        let mut action = self.inner.process(message).await;
+
+        if let PacketAction::Continue(Some((packet, _))) = &mut action {
+            self.translate_outgoing(packet);
+        }
+
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use bytes::Bytes;
+    use matches::assert_matches;
+    use tempfile::TempDir;
+
+    use mqtt3::proto::{self, Packet};
+
+    use crate::{
+        Auth, ClientEvent, ClientId, IncomingPacketProcessor, MakeMqttPacketProcessor,
+        MakePacketProcessor, Message, OutgoingPacketProcessor, PacketAction, Publish,
+    };
+
+    use super::{MakeTranslationPacketProcessor, TopicTranslation, TopicTranslationError};
+
+    const RULES: &str = r#"{
+        "incoming": [
+            {
+                "name": "sparkplug_device_data",
+                "pattern": "spBv1.0/(?P<group>[^/]+)/DDATA/(?P<node>[^/]+)/{device_id}",
+                "template": "devices/{client_id}/sparkplug/{group}/{node}/data"
+            },
+            {
+                "name": "legacy_telemetry",
+                "pattern": "legacy/{device_id}/telemetry(?P<path>.*)",
+                "template": "telemetry/{auth_id}{path}"
+            }
+        ],
+        "outgoing": [
+            {
+                "name": "sparkplug_device_command",
+                "pattern": "devices/{device_id}/sparkplug/(?P<group>[^/]+)/(?P<node>[^/]+)/command",
+                "template": "spBv1.0/{group}/DCMD/{node}/{device_id}"
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn it_translates_with_client_variables() {
+        let translation = translation(RULES).unwrap();
+        let client_id = ClientId::from("device_1");
+
+        assert_eq!(
+            translation.incoming(
+                "spBv1.0/plant/DDATA/node_1/device_1",
+                &client_id,
+                "hub/device_1"
+            ),
+            Some("devices/device_1/sparkplug/plant/node_1/data".into())
+        );
+        assert_eq!(
+            translation.incoming("legacy/device_1/telemetry/temp", &client_id, "hub/device_1"),
+            Some("telemetry/hub/device_1/temp".into())
+        );
+        assert_eq!(
+            translation.incoming("devices/device_1/messages", &client_id, "hub/device_1"),
+            None
+        );
+        assert_eq!(
+            translation.outgoing(
+                "devices/device_1/sparkplug/plant/node_1/command",
+                &client_id,
+                "hub/device_1"
+            ),
+            Some("spBv1.0/plant/DCMD/node_1/device_1".into())
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_rules() {
+        let rules =
+            r#"{ "incoming": [{ "name": "rule", "pattern": "a/(?P<b>.*)", "template": "{c}" }] }"#;
+        assert_matches!(translation(rules), Err(TopicTranslationError::Rule(_)));
+
+        assert_matches!(
+            translation(r#"{ "incoming": 1 }"#),
+            Err(TopicTranslationError::Parse(_, _))
+        );
+        assert_matches!(
+            translation("incoming"),
+            Err(TopicTranslationError::Parse(_, _))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_translates_packets() {
+        let translation = translation(RULES).unwrap();
+        let make = MakeTranslationPacketProcessor::new(Some(translation), MakeMqttPacketProcessor);
+
+        let client_id = ClientId::from("device_1");
+        let auth = Auth::Identity("hub/device_1".into());
+        let (mut outgoing, mut incoming) = make.make(&client_id, &auth);
+
+        let action = incoming
+            .process(Packet::Publish(publish("legacy/device_1/telemetry")))
+            .await;
+        assert_matches!(
+            action,
+            Ok(PacketAction::Continue(Message::Client(_, ClientEvent::PublishFrom(publish, _))))
+                if publish.topic_name == "telemetry/hub/device_1"
+        );
+
+        let message = Message::Client(
+            client_id.clone(),
+            ClientEvent::PublishTo(Publish::QoS12(
+                proto::PacketIdentifier::new(1).unwrap(),
+                publish("devices/device_1/sparkplug/plant/node_1/command"),
+            )),
+        );
+        let action = outgoing.process(message).await;
+        assert_matches!(
+            action,
+            PacketAction::Continue(Some((Packet::Publish(publish), None)))
+                if publish.topic_name == "spBv1.0/plant/DCMD/node_1/device_1"
+        );
+    }
+
+    fn translation(rules: &str) -> Result<TopicTranslation, TopicTranslationError> {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("rules.json");
+        fs::write(&path, rules).unwrap();
+
+        TopicTranslation::from_file(path)
+    }
+
+    fn publish(topic_name: &str) -> proto::Publish {
+        proto::Publish {
+            packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtMostOnce,
+            retain: false,
+            topic_name: topic_name.into(),
+            payload: Bytes::new(),
+        }
+    }
+}
@@ -91,6 +91,9 @@ pub enum InitializeBrokerError {
 
     #[error("An error occurred  bootstrapping TLS. {0}")]
     Tls(#[from] openssl::error::ErrorStack),
+
+    #[error("Server-wide packet processor must be set before listeners with their own packet processor.")]
+    ListenerProcessorAlreadySet,
 }
 
 pub struct DetailedErrorValue<'a, E>(pub &'a E);
@@ -11,8 +11,10 @@
     clippy::missing_errors_doc
 )]
 
+mod audit;
 pub mod auth;
 mod broker;
+pub mod clock;
 mod connection;
 mod error;
 mod persist;
@@ -26,6 +28,7 @@ mod state_change;
 mod stream;
 mod subscription;
 pub mod tls;
+pub mod topic_rules;
 mod transport;
 
 #[cfg(any(test, feature = "proptest"))]
@@ -45,11 +48,13 @@ use tokio::sync::OwnedSemaphorePermit;
 
 use mqtt3::proto;
 
+pub use crate::audit::AUDIT_TOPIC;
 pub use crate::auth::{AuthId, Identity};
 pub use crate::broker::{Broker, BrokerBuilder, BrokerHandle};
 pub use crate::connection::{
     ConnectionHandle, IncomingPacketProcessor, MakeMqttPacketProcessor, MakePacketProcessor,
-    OutgoingPacketProcessor, PacketAction,
+    MakeTranslationPacketProcessor, OutgoingPacketProcessor, PacketAction, TopicTranslation,
+    TopicTranslationError, TranslationPacketProcessor,
 };
 pub use crate::error::{DetailedErrorValue, Error, InitializeBrokerError};
 pub use crate::persist::{
@@ -63,6 +68,7 @@ pub use crate::snapshot::{
 };
 pub use crate::subscription::{Segment, Subscription, TopicFilter};
 pub use crate::tls::ServerCertificate;
+pub use crate::transport::MemoryConnector;
 pub use ready::BrokerReadyEvent;
 
 pub type BrokerReady = ready::BrokerReady<ready::BrokerReadyEvent>;
@@ -1,4 +1,10 @@
-use std::{error::Error as StdError, fmt::Display, future::Future, net::ToSocketAddrs, sync::Arc};
+use std::{
+    error::Error as StdError,
+    fmt::Display,
+    future::Future,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::Arc,
+};
 
 use futures_util::{
     future::{self, Either, FutureExt},
@@ -13,7 +19,8 @@ use crate::{
     auth::{Authenticator, Authorizer, DynAuthenticator},
     broker::{Broker, BrokerHandle},
     connection::{self, MakeMqttPacketProcessor, MakePacketProcessor},
-    transport::{GetPeerInfo, Transport},
+    settings::RecordingConfig,
+    transport::{GetPeerInfo, MemoryConnector, Transport},
     BrokerReadySignal, BrokerSnapshot, DetailedErrorValue, Error, InitializeBrokerError, Message,
     ServerCertificate, SystemEvent,
 };
@@ -21,7 +28,9 @@ use crate::{
 pub struct Server<Z, P> {
     broker: Broker<Z>,
     listeners: Vec<Listener>,
+    listener_processors: Vec<Option<P>>,
     make_processor: P,
+    recording: Option<Arc<RecordingConfig>>,
 }
 
 impl<Z> Server<Z, MakeMqttPacketProcessor>
@@ -32,7 +41,9 @@ where
         Self {
             broker,
             listeners: Vec::new(),
+            listener_processors: Vec::new(),
             make_processor: MakeMqttPacketProcessor,
+            recording: None,
         }
     }
 }
@@ -66,7 +77,32 @@ where
             ready,
         );
 
-        self.listeners.push(listener);
+        self.push_listener(listener, None);
+        Ok(self)
+    }
+
+    /// Same as `with_tcp`, but connections accepted by this listener are
+    /// processed with a given packet processor instead of the server-wide one.
+    pub fn with_tcp_processor<A, N, E>(
+        &mut self,
+        addr: A,
+        authenticator: N,
+        ready: Option<BrokerReadySignal>,
+        make_processor: P,
+    ) -> Result<&mut Self, InitializeBrokerError>
+    where
+        A: ToSocketAddrs + Display,
+        N: Authenticator<Error = E> + Send + Sync + 'static,
+        E: StdError + Send + Sync + 'static,
+    {
+        let listener = Listener::new(
+            Transport::new_tcp(addr)?,
+            authenticator,
+            self.broker.handle(),
+            ready,
+        );
+
+        self.push_listener(listener, Some(make_processor));
         Ok(self)
     }
 
@@ -89,16 +125,61 @@ where
             ready,
         );
 
-        self.listeners.push(listener);
+        self.push_listener(listener, None);
         Ok(self)
     }
 
-    pub fn with_packet_processor<P1>(self, make_processor: P1) -> Server<Z, P1> {
-        Server {
+    /// Accepts connections opened with the returned connector over in-memory streams.
+    ///
+    /// `addr` is only used to identify the listener and its clients, no port is bound.
+    pub fn with_memory<N, E>(
+        &mut self,
+        addr: SocketAddr,
+        authenticator: N,
+        ready: Option<BrokerReadySignal>,
+    ) -> MemoryConnector
+    where
+        N: Authenticator<Error = E> + Send + Sync + 'static,
+        E: StdError + Send + Sync + 'static,
+    {
+        let (transport, connector) = Transport::new_memory(addr);
+        let listener = Listener::new(transport, authenticator, self.broker.handle(), ready);
+
+        self.push_listener(listener, None);
+        connector
+    }
+
+    /// Replaces the server-wide packet processor.
+    ///
+    /// Returns an error if a listener was already added with `with_tcp_processor`,
+    /// as its packet processor can't be kept with the new processor type. Listeners
+    /// with their own packet processor must be added after this call.
+    pub fn with_packet_processor<P1>(
+        self,
+        make_processor: P1,
+    ) -> Result<Server<Z, P1>, InitializeBrokerError> {
+        if self.listener_processors.iter().any(Option::is_some) {
+            return Err(InitializeBrokerError::ListenerProcessorAlreadySet);
+        }
+
+        Ok(Server {
             broker: self.broker,
             listeners: self.listeners,
+            listener_processors: self.listener_processors.iter().map(|_| None).collect(),
             make_processor,
-        }
+            recording: self.recording,
+        })
+    }
+
+    fn push_listener(&mut self, listener: Listener, make_processor: Option<P>) {
+        self.listeners.push(listener);
+        self.listener_processors.push(make_processor);
+    }
+
+    /// Records packets of every client connection to a separate file in the configured directory.
+    pub fn with_recording(&mut self, recording: RecordingConfig) -> &mut Self {
+        self.recording = Some(Arc::new(recording));
+        self
     }
 
     pub async fn serve<F>(self, shutdown_signal: F) -> Result<BrokerSnapshot, Error>
@@ -108,7 +189,9 @@ where
         let Server {
             broker,
             listeners,
+            listener_processors,
             make_processor,
+            recording,
         } = self;
         let handle = broker.handle();
 
@@ -119,11 +202,13 @@ where
         // prepare each transport listener
         let mut incoming_tasks = Vec::new();
         let mut shutdown_handles = Vec::new();
-        for listener in listeners {
+        for (listener, listener_processor) in listeners.into_iter().zip(listener_processors) {
             let (itx, irx) = oneshot::channel::<()>();
             shutdown_handles.push(itx);
 
-            let incoming_task = Box::pin(listener.run(irx.map(drop), make_processor.clone()));
+            let make_processor = listener_processor.unwrap_or_else(|| make_processor.clone());
+            let incoming_task =
+                Box::pin(listener.run(irx.map(drop), make_processor, recording.clone()));
             incoming_tasks.push(incoming_task);
         }
 
@@ -266,7 +351,12 @@ impl Listener {
         &self.transport
     }
 
-    async fn run<F, P>(self, shutdown_signal: F, make_processor: P) -> Result<(), Error>
+    async fn run<F, P>(
+        self,
+        shutdown_signal: F,
+        make_processor: P,
+        recording: Option<Arc<RecordingConfig>>,
+    ) -> Result<(), Error>
     where
         F: Future<Output = ()> + Unpin,
         P: MakePacketProcessor + Clone + Send + 'static,
@@ -313,10 +403,11 @@ impl Listener {
                                 let span = inner_span.clone();
                                 let authenticator = authenticator.clone();
                                 let make_processor = make_processor.clone();
+                                let recording = recording.clone();
 
                                 tokio::spawn(async move {
                                     if let Err(e) =
-                                        connection::process(stream, peer, broker_handle, &*authenticator, make_processor)
+                                        connection::process(stream, peer, broker_handle, &*authenticator, make_processor, recording.as_deref())
                                             .instrument(span)
                                             .await
                                     {
@@ -360,3 +451,35 @@ impl Listener {
         .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use crate::{
+        auth::{AllowAll, DefaultAuthenticator},
+        BrokerBuilder, InitializeBrokerError, MakeMqttPacketProcessor,
+    };
+
+    use super::Server;
+
+    #[tokio::test]
+    async fn server_processor_set_after_listener_processor_fails() {
+        let broker = BrokerBuilder::default().with_authorizer(AllowAll).build();
+        let mut server = Server::from_broker(broker);
+
+        server
+            .with_tcp_processor(
+                "127.0.0.1:0",
+                DefaultAuthenticator,
+                None,
+                MakeMqttPacketProcessor,
+            )
+            .unwrap();
+
+        assert_matches!(
+            server.with_packet_processor(MakeMqttPacketProcessor).err(),
+            Some(InitializeBrokerError::ListenerProcessorAlreadySet)
+        );
+    }
+}
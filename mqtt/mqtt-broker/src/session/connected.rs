@@ -1,13 +1,12 @@
 use std::collections::HashMap;
 
-use chrono::Utc;
 use tracing::warn;
 
 use mqtt3::proto;
 
 use crate::{
-    snapshot::SessionSnapshot, subscription::Subscription, ClientEvent, ConnectionHandle, Error,
-    Message, SessionState,
+    clock, snapshot::SessionSnapshot, subscription::Subscription, ClientEvent, ConnectionHandle,
+    Error, Message, SessionState,
 };
 
 #[derive(Debug)]
@@ -35,11 +34,11 @@ impl ConnectedSession {
     }
 
     pub fn snapshot(&self) -> SessionSnapshot {
-        self.state.clone().into_snapshot(Utc::now())
+        self.state.clone().into_snapshot(clock::now())
     }
 
     pub fn into_snapshot(self) -> SessionSnapshot {
-        self.state.into_snapshot(Utc::now())
+        self.state.into_snapshot(clock::now())
     }
 
     pub fn state(&self) -> &SessionState {
@@ -2,7 +2,11 @@ mod size;
 
 pub use size::HumanSize;
 
-use std::{num::NonZeroUsize, path::PathBuf, time::Duration};
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use serde::Deserialize;
 
@@ -14,6 +18,12 @@ pub struct BrokerConfig {
     retained_messages: RetainedMessagesConfig,
     session: SessionConfig,
     persistence: SessionPersistenceConfig,
+    #[serde(default)]
+    audit: AuditConfig,
+    #[serde(default)]
+    translation: Enable<TranslationConfig>,
+    #[serde(default)]
+    recording: Enable<RecordingConfig>,
 }
 
 impl BrokerConfig {
@@ -26,9 +36,27 @@ impl BrokerConfig {
             retained_messages,
             session,
             persistence,
+            audit: AuditConfig::default(),
+            translation: Enable::disabled(),
+            recording: Enable::disabled(),
         }
     }
 
+    pub fn with_audit(mut self, audit: AuditConfig) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    pub fn with_translation(mut self, translation: TranslationConfig) -> Self {
+        self.translation = Enable::enabled(translation);
+        self
+    }
+
+    pub fn with_recording(mut self, recording: RecordingConfig) -> Self {
+        self.recording = Enable::enabled(recording);
+        self
+    }
+
     pub fn retained_messages(&self) -> &RetainedMessagesConfig {
         &self.retained_messages
     }
@@ -40,6 +68,18 @@ impl BrokerConfig {
     pub fn persistence(&self) -> &SessionPersistenceConfig {
         &self.persistence
     }
+
+    pub fn audit(&self) -> &AuditConfig {
+        &self.audit
+    }
+
+    pub fn translation(&self) -> Option<&TranslationConfig> {
+        self.translation.as_inner()
+    }
+
+    pub fn recording(&self) -> Option<&RecordingConfig> {
+        self.recording.as_inner()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -188,6 +228,148 @@ impl Default for SessionPersistenceConfig {
     }
 }
 
+/// Audit log of authentication and authorization decisions.
+///
+/// Audit records are written as JSON lines to a rotating file and/or published
+/// to the reserved `$audit` topic. Failed authentications, authorization
+/// denials and disconnects are always recorded, while successful ones are
+/// sampled: only every `allowed_sample_interval`-th of them is recorded,
+/// `0` means that successful authentications and authorizations are not
+/// recorded at all.
+///
+/// Audit records expose client addresses and identities, so only clients
+/// with identities listed in `subscribers` can subscribe to `$audit` topics.
+/// Anonymous clients can never subscribe to them.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AuditConfig {
+    #[serde(default = "Enable::disabled")]
+    file: Enable<AuditFileConfig>,
+    #[serde(default)]
+    topic: bool,
+    #[serde(default)]
+    allowed_sample_interval: u32,
+    #[serde(default)]
+    subscribers: Vec<String>,
+}
+
+impl AuditConfig {
+    pub fn new(file: Option<AuditFileConfig>, topic: bool, allowed_sample_interval: u32) -> Self {
+        Self {
+            file: file.into(),
+            topic,
+            allowed_sample_interval,
+            subscribers: Vec::new(),
+        }
+    }
+
+    pub fn with_subscribers(mut self, subscribers: Vec<String>) -> Self {
+        self.subscribers = subscribers;
+        self
+    }
+
+    pub fn file(&self) -> Option<&AuditFileConfig> {
+        self.file.as_inner()
+    }
+
+    pub fn topic(&self) -> bool {
+        self.topic
+    }
+
+    pub fn allowed_sample_interval(&self) -> u32 {
+        self.allowed_sample_interval
+    }
+
+    pub fn subscribers(&self) -> &[String] {
+        &self.subscribers
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file().is_some() || self.topic
+    }
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig::new(None, false, 0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AuditFileConfig {
+    path: PathBuf,
+    max_file_size: HumanSize,
+    max_files: usize,
+}
+
+impl AuditFileConfig {
+    pub fn new(path: impl Into<PathBuf>, max_file_size: HumanSize, max_files: usize) -> Self {
+        Self {
+            path: path.into(),
+            max_file_size,
+            max_files,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn max_file_size(&self) -> usize {
+        self.max_file_size.get()
+    }
+
+    /// Number of rotated files kept along with the current one.
+    pub fn max_files(&self) -> usize {
+        self.max_files
+    }
+}
+
+/// Topic translation for devices which use their own topic layouts.
+/// See `TopicTranslation` for the format of rules file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TranslationConfig {
+    rules_path: PathBuf,
+}
+
+impl TranslationConfig {
+    pub fn new(rules_path: impl Into<PathBuf>) -> Self {
+        Self {
+            rules_path: rules_path.into(),
+        }
+    }
+
+    pub fn rules_path(&self) -> &Path {
+        &self.rules_path
+    }
+}
+
+/// Recording of client connections for later replay.
+/// Packets of every connection are written to a separate file in `directory`.
+/// A file stops growing once it reaches `max_file_size`.
+/// Passwords are not recorded, they have to be supplied again when a connection is replayed.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RecordingConfig {
+    directory: PathBuf,
+    max_file_size: HumanSize,
+}
+
+impl RecordingConfig {
+    pub fn new(directory: impl Into<PathBuf>, max_file_size: HumanSize) -> Self {
+        Self {
+            directory: directory.into(),
+            max_file_size,
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn max_file_size(&self) -> usize {
+        self.max_file_size.get()
+    }
+}
+
 /// This type is a Option-like wrapper around any type T. The primary goal is
 /// to make config section to be enabled/disabled during desirialization.
 #[derive(Debug, Clone, Deserialize)]
@@ -225,6 +407,12 @@ impl<T> Enable<T> {
     }
 }
 
+impl<T> Default for Enable<T> {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
 impl<T> From<Option<T>> for Enable<T> {
     fn from(value: Option<T>) -> Self {
         match value {
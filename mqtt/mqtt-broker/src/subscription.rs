@@ -83,6 +83,32 @@ impl TopicFilter {
         }
         true
     }
+
+    /// Checks whether every topic matched by `other` filter is matched by
+    /// this filter as well.
+    pub fn includes(&self, other: &TopicFilter) -> bool {
+        // wildcards on the first level do not match topics starting with '$'.
+        if let (Some(Segment::MultiLevelWildcard), Some(Segment::Level(l)))
+        | (Some(Segment::SingleLevelWildcard), Some(Segment::Level(l))) =
+            (self.segments.first(), other.segments.first())
+        {
+            if l.starts_with('$') {
+                return false;
+            }
+        }
+
+        let mut segments = self.segments.iter();
+        let mut others = other.segments.iter();
+        loop {
+            match (segments.next(), others.next()) {
+                (Some(Segment::MultiLevelWildcard), _) | (None, None) => return true,
+                (Some(Segment::SingleLevelWildcard), Some(Segment::SingleLevelWildcard))
+                | (Some(Segment::SingleLevelWildcard), Some(Segment::Level(_))) => (),
+                (Some(Segment::Level(s)), Some(Segment::Level(o))) if s == o => (),
+                _ => return false,
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -303,4 +329,35 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_topic_filter_includes() {
+        let cases = vec![
+            ("#", "#", true),
+            ("#", "blah/+", true),
+            ("blah/#", "blah", true),
+            ("blah/#", "blah/+/blah2", true),
+            ("blah/+", "blah/blah1", true),
+            ("blah/+", "blah/+", true),
+            ("blah/+", "blah/#", false),
+            ("blah/blah1", "blah/+", false),
+            ("blah/+/blah2", "blah/blah1/#", false),
+            ("blah/blah1/#", "blah/#", false),
+            ("#", "$SYS/#", false),
+            ("+", "$SYS", false),
+            ("$SYS/#", "$SYS/blah", true),
+        ];
+
+        for (filter, other, expected) in &cases {
+            let parsed = TopicFilter::from_str(filter).unwrap();
+            let other_parsed = TopicFilter::from_str(other).unwrap();
+            assert_eq!(
+                *expected,
+                parsed.includes(&other_parsed),
+                "filter \"{}\" includes topic filter \"{}\"",
+                filter,
+                other
+            );
+        }
+    }
 }
@@ -0,0 +1,246 @@
+//! Declarative topic rewrite rules.
+//!
+//! A rule consists of a regular expression pattern to match a topic and a
+//! template to build a new topic. Pattern may refer to common topic segments
+//! (`{device_id}`, `{module_id}`, `{device_or_module_id}`), which expand to
+//! named capture groups. Template refers to named capture groups of the
+//! pattern and to variables provided along with the topic (e.g. `{client_id}`)
+//! with `{name}` placeholders.
+use regex::{Captures, Regex};
+
+const SEGMENTS: &[(&str, &str)] = &[
+    ("{device_id}", r"(?P<device_id>[^/]+)"),
+    ("{module_id}", r"(?P<module_id>[^/]+)"),
+    (
+        "{device_or_module_id}",
+        r"(?P<device_id>[^/]+)(/(?P<module_id>[^/]+))?",
+    ),
+];
+
+/// A single topic rewrite rule.
+#[derive(Debug, Clone, Copy)]
+pub struct TopicRule<'a> {
+    name: &'a str,
+    pattern: &'a str,
+    template: &'a str,
+}
+
+impl<'a> TopicRule<'a> {
+    pub const fn new(name: &'a str, pattern: &'a str, template: &'a str) -> Self {
+        Self {
+            name,
+            pattern,
+            template,
+        }
+    }
+}
+
+/// An ordered list of compiled topic rules. The first matching rule wins.
+#[derive(Debug)]
+pub struct TopicRules(Vec<CompiledRule>);
+
+#[derive(Debug)]
+struct CompiledRule {
+    regex: Regex,
+    template: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Placeholder(String),
+}
+
+impl TopicRules {
+    /// Compiles rules. Every template placeholder must refer either to a
+    /// named capture group of the pattern or to one of `variables`.
+    pub fn new<'a>(
+        rules: impl IntoIterator<Item = TopicRule<'a>>,
+        variables: &[&str],
+    ) -> Result<Self, TopicRuleError> {
+        rules
+            .into_iter()
+            .map(|rule| CompiledRule::new(rule, variables))
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    /// Rewrites the topic with the first matching rule.
+    pub fn rewrite(&self, topic: &str, variables: &[(&str, &str)]) -> Option<String> {
+        self.0.iter().find_map(|rule| {
+            rule.regex
+                .captures(topic)
+                .map(|captures| rule.render(&captures, variables))
+        })
+    }
+}
+
+impl CompiledRule {
+    fn new(rule: TopicRule<'_>, variables: &[&str]) -> Result<Self, TopicRuleError> {
+        let mut pattern = rule.pattern.to_string();
+        for (segment, expansion) in SEGMENTS {
+            pattern = pattern.replace(segment, expansion);
+        }
+
+        // rule must match the whole topic, not just its prefix.
+        let regex = Regex::new(&format!("^(?:{})$", pattern))
+            .map_err(|e| TopicRuleError::Pattern(rule.name.into(), e))?;
+
+        let template = parse_template(rule.template)
+            .ok_or_else(|| TopicRuleError::Template(rule.name.into(), rule.template.into()))?;
+
+        for part in &template {
+            if let Part::Placeholder(name) = part {
+                let known = variables.contains(&name.as_str())
+                    || regex.capture_names().flatten().any(|group| group == name);
+                if !known {
+                    return Err(TopicRuleError::UnknownPlaceholder(
+                        rule.name.into(),
+                        name.clone(),
+                    ));
+                }
+            }
+        }
+
+        Ok(Self { regex, template })
+    }
+
+    fn render(&self, captures: &Captures<'_>, variables: &[(&str, &str)]) -> String {
+        let mut topic = String::new();
+        for part in &self.template {
+            match part {
+                Part::Literal(literal) => topic.push_str(literal),
+                Part::Placeholder(name) => {
+                    // optional capture groups which did not participate
+                    // in the match render as empty string.
+                    let value = variables
+                        .iter()
+                        .find(|(variable, _)| *variable == name.as_str())
+                        .map(|(_, value)| *value)
+                        .or_else(|| captures.name(name).map(|value| value.as_str()))
+                        .unwrap_or_default();
+                    topic.push_str(value);
+                }
+            }
+        }
+        topic
+    }
+}
+
+/// Splits template into literal parts and `{name}` placeholders.
+fn parse_template(template: &str) -> Option<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(Part::Literal(rest[..start].into()));
+        }
+
+        let end = rest[start..].find('}')? + start;
+        let name = &rest[start + 1..end];
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return None;
+        }
+        parts.push(Part::Placeholder(name.into()));
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest.into()));
+    }
+    Some(parts)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TopicRuleError {
+    #[error("invalid pattern of topic rule {0}: {1}")]
+    Pattern(String, #[source] regex::Error),
+
+    #[error("invalid template of topic rule {0}: {1}")]
+    Template(String, String),
+
+    #[error("unknown placeholder in template of topic rule {0}: {1}")]
+    UnknownPlaceholder(String, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::{parse_template, Part, TopicRule, TopicRuleError, TopicRules};
+
+    #[test]
+    fn it_parses_template() {
+        assert_eq!(
+            parse_template("$edgehub/{client_id}/twin/{params}"),
+            Some(vec![
+                Part::Literal("$edgehub/".into()),
+                Part::Placeholder("client_id".into()),
+                Part::Literal("/twin/".into()),
+                Part::Placeholder("params".into()),
+            ])
+        );
+        assert_eq!(parse_template("{client_id"), None);
+        assert_eq!(parse_template("{}"), None);
+        assert_eq!(parse_template("{a/b}"), None);
+    }
+
+    #[test]
+    fn it_rewrites_with_first_matching_rule() {
+        let rules = TopicRules::new(
+            vec![
+                TopicRule::new(
+                    "module",
+                    "devices/{device_id}/modules/{module_id}/(?P<path>.*)",
+                    "{device_id}/{module_id}/{path}",
+                ),
+                TopicRule::new(
+                    "device",
+                    "devices/{device_id}/(?P<path>.*)",
+                    "{client_id}/{device_id}/{path}",
+                ),
+            ],
+            &["client_id"],
+        )
+        .unwrap();
+
+        assert_eq!(
+            rules.rewrite("devices/d1/modules/m1/events", &[("client_id", "c1")]),
+            Some("d1/m1/events".into())
+        );
+        assert_eq!(
+            rules.rewrite("devices/d1/events", &[("client_id", "c1")]),
+            Some("c1/d1/events".into())
+        );
+        assert_eq!(rules.rewrite("foo/devices/d1/events", &[]), None);
+    }
+
+    #[test]
+    fn it_does_not_match_topic_longer_than_rule() {
+        let rules = TopicRules::new(
+            vec![TopicRule::new(
+                "device_twin",
+                "devices/{device_id}/twin",
+                "$edgehub/{device_id}/twin/get",
+            )],
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            rules.rewrite("devices/d1/twin", &[]),
+            Some("$edgehub/d1/twin/get".into())
+        );
+        assert_eq!(rules.rewrite("devices/d1/twin/extra", &[]), None);
+        assert_eq!(rules.rewrite("devices/d1/twinned", &[]), None);
+    }
+
+    #[test]
+    fn it_rejects_unknown_placeholders() {
+        let rules = TopicRules::new(
+            vec![TopicRule::new("rule", "devices/{device_id}", "{module_id}")],
+            &["client_id"],
+        );
+
+        assert_matches!(rules, Err(TopicRuleError::UnknownPlaceholder(_, name)) if name == "module_id");
+    }
+}
@@ -13,8 +13,9 @@ use openssl::{
     x509::X509Ref,
 };
 use tokio::{
-    io::{AsyncRead, AsyncWrite},
+    io::{AsyncRead, AsyncWrite, DuplexStream},
     net::{TcpListener, TcpStream},
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
 };
 use tokio_openssl::SslStream;
 use tracing::{debug, error, warn};
@@ -61,6 +62,19 @@ impl Transport {
         }
     }
 
+    /// Creates a new instance of a transport protocol over in-memory streams.
+    ///
+    /// Clients connect through the returned [`MemoryConnector`] instead of the network,
+    /// so that a broker can be run in tests without binding any ports.
+    /// `addr` is reported as the address of the listener and of every client.
+    pub fn new_memory(addr: SocketAddr) -> (Self, MemoryConnector) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let transport = Self {
+            protocol: Protocol::Memory(addr, receiver),
+        };
+        (transport, MemoryConnector { addr, sender })
+    }
+
     /// Starts to listen incoming connections from remote clients.
     pub async fn incoming(self) -> Result<Incoming, InitializeBrokerError> {
         match self.protocol {
@@ -79,6 +93,9 @@ impl Transport {
 
                 Ok(Incoming::Tls(IncomingTls::new(tcp, acceptor)))
             }
+            Protocol::Memory(addr, receiver) => {
+                Ok(Incoming::Memory(IncomingMemory::new(addr, receiver)))
+            }
         }
     }
 
@@ -87,13 +104,14 @@ impl Transport {
         match self.protocol {
             Protocol::Tcp(addr) => addr,
             Protocol::Tls(addr, _) => addr,
+            Protocol::Memory(addr, _) => addr,
         }
     }
 
     /// Returns a server certificate if any.
     pub fn identity(&self) -> Option<&ServerCertificate> {
         match &self.protocol {
-            Protocol::Tcp(_) => None,
+            Protocol::Tcp(_) | Protocol::Memory(..) => None,
             Protocol::Tls(_, identity) => Some(identity),
         }
     }
@@ -102,6 +120,33 @@ impl Transport {
 enum Protocol {
     Tcp(SocketAddr),
     Tls(SocketAddr, ServerCertificate),
+    Memory(SocketAddr, UnboundedReceiver<DuplexStream>),
+}
+
+/// Size of the buffer of every in-memory connection in each direction.
+const MEMORY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Opens connections to a broker listening on a transport created by [`Transport::new_memory`].
+#[derive(Clone, Debug)]
+pub struct MemoryConnector {
+    addr: SocketAddr,
+    sender: UnboundedSender<DuplexStream>,
+}
+
+impl MemoryConnector {
+    /// Opens a new connection to the broker and returns the client side of it.
+    ///
+    /// Fails with `ConnectionRefused` once the listener has been shut down.
+    pub fn connect(&self) -> std::io::Result<DuplexStream> {
+        let (client, server) = tokio::io::duplex(MEMORY_BUFFER_SIZE);
+        self.sender.send(server).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                format!("no broker is listening on {}", self.addr),
+            )
+        })?;
+        Ok(client)
+    }
 }
 
 fn prepare_acceptor(identity: ServerCertificate) -> Result<SslAcceptor, InitializeBrokerError> {
@@ -144,6 +189,7 @@ type HandshakeFuture =
 pub enum Incoming {
     Tcp(IncomingTcp),
     Tls(IncomingTls),
+    Memory(IncomingMemory),
 }
 
 impl Incoming {
@@ -151,6 +197,7 @@ impl Incoming {
         let addr = match self {
             Self::Tcp(incoming) => incoming.listener.local_addr(),
             Self::Tls(incoming) => incoming.listener.local_addr(),
+            Self::Memory(incoming) => Ok(incoming.addr),
         };
         addr.map_err(InitializeBrokerError::ConnectionLocalAddress)
     }
@@ -163,6 +210,7 @@ impl Stream for Incoming {
         match self.get_mut() {
             Self::Tcp(incoming) => Pin::new(incoming).poll_next(cx),
             Self::Tls(incoming) => Pin::new(incoming).poll_next(cx),
+            Self::Memory(incoming) => Pin::new(incoming).poll_next(cx),
         }
     }
 }
@@ -291,9 +339,35 @@ impl Stream for IncomingTls {
     }
 }
 
+pub struct IncomingMemory {
+    addr: SocketAddr,
+    receiver: UnboundedReceiver<DuplexStream>,
+}
+
+impl IncomingMemory {
+    fn new(addr: SocketAddr, receiver: UnboundedReceiver<DuplexStream>) -> Self {
+        Self { addr, receiver }
+    }
+}
+
+impl Stream for IncomingMemory {
+    type Item = std::io::Result<StreamSelector>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let addr = self.addr;
+        self.receiver.poll_recv(cx).map(|stream| {
+            stream.map(|stream| {
+                debug!("accepted in-memory connection from client");
+                Ok(StreamSelector::Memory(stream, addr))
+            })
+        })
+    }
+}
+
 pub enum StreamSelector {
     Tcp(TcpStream),
     Tls(SslStream<TcpStream>),
+    Memory(DuplexStream, SocketAddr),
 }
 
 pub trait GetPeerInfo {
@@ -311,7 +385,7 @@ impl GetPeerInfo for StreamSelector {
 
     fn peer_certificate(&self) -> Result<Option<Self::Certificate>, Error> {
         match self {
-            Self::Tcp(_) => Ok(None),
+            Self::Tcp(_) | Self::Memory(..) => Ok(None),
             Self::Tls(stream) => stream
                 .ssl()
                 .peer_certificate()
@@ -322,7 +396,7 @@ impl GetPeerInfo for StreamSelector {
 
     fn peer_cert_chain(&self) -> Result<Option<Vec<Self::Certificate>>, Error> {
         match self {
-            Self::Tcp(_) => Ok(None),
+            Self::Tcp(_) | Self::Memory(..) => Ok(None),
             Self::Tls(stream) => stream
                 .ssl()
                 .peer_cert_chain()
@@ -335,6 +409,7 @@ impl GetPeerInfo for StreamSelector {
         let stream = match self {
             Self::Tcp(stream) => stream,
             Self::Tls(stream) => stream.get_ref(),
+            Self::Memory(_, addr) => return Ok(*addr),
         };
 
         stream.peer_addr().map_err(Error::PeerAddr)
@@ -359,6 +434,7 @@ impl AsyncRead for StreamSelector {
         match self.get_mut() {
             Self::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
             Self::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Memory(stream, _) => Pin::new(stream).poll_read(cx, buf),
         }
     }
 }
@@ -372,6 +448,7 @@ impl AsyncWrite for StreamSelector {
         match self.get_mut() {
             Self::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
             Self::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Memory(stream, _) => Pin::new(stream).poll_write(cx, buf),
         }
     }
 
@@ -380,6 +457,7 @@ impl AsyncWrite for StreamSelector {
         match self.get_mut() {
             Self::Tcp(stream) => Pin::new(stream).poll_flush(cx),
             Self::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Memory(stream, _) => Pin::new(stream).poll_flush(cx),
         }
     }
 
@@ -387,6 +465,7 @@ impl AsyncWrite for StreamSelector {
         match self.get_mut() {
             Self::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
             Self::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Memory(stream, _) => Pin::new(stream).poll_shutdown(cx),
         }
     }
 }
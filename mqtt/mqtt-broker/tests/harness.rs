@@ -0,0 +1,95 @@
+///! This module contains tests that run the broker in-process with paused time,
+///! to verify behavior that depends on timers and restarts (session expiry, persistence, etc...).
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use mqtt3::{
+    proto::{ClientId, ConnAck, Connect, ConnectReturnCode, Disconnect, Packet},
+    PROTOCOL_LEVEL, PROTOCOL_NAME,
+};
+use mqtt_broker::{settings::*, BrokerConfig};
+use mqtt_broker_tests_util::{harness::BrokerHarness, packet_stream::PacketStream};
+
+/// Validates that a persistent session saved by a periodic snapshot survives a broker crash.
+#[tokio::test(start_paused = true)]
+async fn restore_session_after_crash() {
+    let mut harness = BrokerHarness::start(BrokerConfig::default());
+
+    let mut client = connect(&harness, "client_1").await;
+    assert_eq!(client.next().await, Some(connack(false)));
+    client.send_packet(Packet::Disconnect(Disconnect)).await;
+    harness.settle().await;
+
+    // wait for the periodic state snapshot
+    harness
+        .advance(BrokerConfig::default().persistence().time_interval())
+        .await;
+    assert!(harness.persisted().is_some());
+
+    harness.crash().await;
+    harness.restart().await;
+
+    let mut client = connect(&harness, "client_1").await;
+    assert_eq!(client.next().await, Some(connack(true)));
+}
+
+/// Validates that the periodic cleanup removes an offline session once it expired.
+#[tokio::test(start_paused = true)]
+async fn drop_offline_session_on_expiry_in_virtual_time() {
+    let config = BrokerConfig::new(
+        RetainedMessagesConfig::default(),
+        SessionConfig::new(
+            Duration::from_secs(60 * 60),
+            Duration::from_secs(10 * 60),
+            None,
+            16,
+            1000,
+            None,
+            QueueFullAction::DropNew,
+        ),
+        SessionPersistenceConfig::default(),
+    );
+    let harness = BrokerHarness::start(config);
+
+    let mut client = connect(&harness, "client_1").await;
+    assert_eq!(client.next().await, Some(connack(false)));
+    client.send_packet(Packet::Disconnect(Disconnect)).await;
+    harness.settle().await;
+
+    // the session is still there before it expires
+    harness.advance(Duration::from_secs(30 * 60)).await;
+    harness.snapshot().await;
+    let (_, sessions) = harness.persisted().expect("snapshot").into_parts();
+    assert_eq!(sessions.len(), 1);
+
+    // next cleanup after expiration removes the session
+    harness.advance(Duration::from_secs(45 * 60)).await;
+
+    let mut client = connect(&harness, "client_1").await;
+    assert_eq!(client.next().await, Some(connack(false)));
+
+    harness.shutdown().await;
+}
+
+async fn connect(harness: &BrokerHarness, client_id: &str) -> PacketStream {
+    let mut client = harness.packet_stream();
+    client
+        .send_connect(Connect {
+            username: None,
+            password: None,
+            will: None,
+            client_id: ClientId::IdWithExistingSession(client_id.into()),
+            keep_alive: Duration::from_secs(30),
+            protocol_name: PROTOCOL_NAME.into(),
+            protocol_level: PROTOCOL_LEVEL,
+        })
+        .await;
+    client
+}
+
+fn connack(session_present: bool) -> Packet {
+    Packet::ConnAck(ConnAck {
+        session_present,
+        return_code: ConnectReturnCode::Accepted,
+    })
+}
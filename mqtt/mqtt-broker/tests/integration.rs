@@ -2,19 +2,32 @@
 ///! of the broker (like config, storage, cleanup, etc...).
 ///!
 ///! For tests related to MQTT protocol please use `compliance.rs`.
-use std::{any::Any, convert::Infallible, time::Duration};
+use std::{
+    any::Any,
+    convert::Infallible,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
+use async_trait::async_trait;
 use chrono::Utc;
 use futures_util::StreamExt;
-use mqtt3::{proto::ClientId, ConnectionError, Event};
+use mqtt3::{
+    proto::{ClientId, Packet, QoS},
+    ConnectionError, Event,
+};
 use mqtt_broker::{
     auth::AllowAll,
     auth::{Activity, Authorization, Authorizer},
-    BrokerBuilder, BrokerSnapshot, Message, SystemEvent,
+    Auth, BrokerBuilder, BrokerSnapshot, Error, IncomingPacketProcessor, MakeMqttPacketProcessor,
+    MakePacketProcessor, Message, PacketAction, Server, SystemEvent,
 };
 use mqtt_broker_tests_util::{
     client::TestClientBuilder,
-    server::{start_server, DummyAuthenticator},
+    server::{run, start_server, DummyAuthenticator},
 };
 
 /// Validates the case when offline session is dropped if expired.
@@ -217,6 +230,81 @@ async fn drop_sessions_on_reauthorize() {
     root_client.shutdown().await;
 }
 
+/// Validates that connections accepted by a listener with its own packet
+/// processor are processed by it instead of the server-wide one.
+#[tokio::test]
+async fn listener_packet_processor_overrides_server_processor() {
+    let broker = BrokerBuilder::default().with_authorizer(AllowAll).build();
+
+    let server_packets = Arc::new(AtomicUsize::new(0));
+    let listener_packets = Arc::new(AtomicUsize::new(0));
+
+    let mut server_handle = run(|addr| {
+        let mut server = Server::from_broker(broker)
+            .with_packet_processor(MakeCountingPacketProcessor(server_packets.clone()))
+            .unwrap();
+        server
+            .with_tcp_processor(
+                addr,
+                DummyAuthenticator::anonymous(),
+                None,
+                MakeCountingPacketProcessor(listener_packets.clone()),
+            )
+            .unwrap();
+        server
+    });
+
+    let mut client = TestClientBuilder::new(server_handle.address())
+        .with_client_id(ClientId::IdWithCleanSession("client".into()))
+        .build();
+
+    client.subscribe("topic", QoS::AtLeastOnce).await;
+    assert!(client.subscriptions().next().await.is_some());
+
+    client.publish_qos1("topic", "payload", false).await;
+
+    // publication is delivered back through the listener.
+    assert!(client.publications().next().await.is_some());
+
+    assert!(listener_packets.load(Ordering::SeqCst) > 0);
+    assert_eq!(server_packets.load(Ordering::SeqCst), 0);
+
+    client.shutdown().await;
+    server_handle.shutdown().await;
+}
+
+/// Counts incoming packets of all connections it makes processors for.
+#[derive(Clone)]
+struct MakeCountingPacketProcessor(Arc<AtomicUsize>);
+
+impl MakePacketProcessor for MakeCountingPacketProcessor {
+    type OutgoingProcessor = <MakeMqttPacketProcessor as MakePacketProcessor>::OutgoingProcessor;
+    type IncomingProcessor = CountingIncomingPacketProcessor;
+
+    fn make(
+        &self,
+        client_id: &mqtt_broker::ClientId,
+        auth: &Auth,
+    ) -> (Self::OutgoingProcessor, Self::IncomingProcessor) {
+        let (outgoing, incoming) = MakeMqttPacketProcessor.make(client_id, auth);
+        let incoming = CountingIncomingPacketProcessor(incoming, self.0.clone());
+        (outgoing, incoming)
+    }
+}
+
+struct CountingIncomingPacketProcessor(
+    <MakeMqttPacketProcessor as MakePacketProcessor>::IncomingProcessor,
+    Arc<AtomicUsize>,
+);
+
+#[async_trait]
+impl IncomingPacketProcessor for CountingIncomingPacketProcessor {
+    async fn process(&mut self, packet: Packet) -> Result<PacketAction<Message, Message>, Error> {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        self.0.process(packet).await
+    }
+}
+
 struct BooleanAuthorizer(bool);
 
 impl Authorizer for BooleanAuthorizer {
@@ -12,25 +12,42 @@ use hyper::{body, client::HttpConnector, Body, Client, Request};
 use serde::{Deserialize, Serialize};
 use serde_repr::Deserialize_repr;
 use tokio::time;
-use tracing::info;
+use tracing::{info, warn};
 
 use mqtt_broker::{
     auth::{AuthenticationContext, Authenticator},
     AuthId,
 };
 
+use super::OfflineAuthCache;
+
 const API_VERSION: &str = "2020-04-20";
 
+/// Authenticates clients against `EdgeHub` core.
+///
+/// When offline authentication cache is enabled and `EdgeHub` core is
+/// unavailable, a client which recently authenticated with the same
+/// credentials is authenticated from the cache.
 #[derive(Clone)]
 pub struct EdgeHubAuthenticator {
     client: Client<HttpConnector>,
     url: String,
+    offline: Option<OfflineAuthCache>,
 }
 
 impl EdgeHubAuthenticator {
     pub fn new(url: String) -> Self {
         let client = Client::new();
-        Self { client, url }
+        Self {
+            client,
+            url,
+            offline: None,
+        }
+    }
+
+    pub fn with_offline_cache(mut self, cache: OfflineAuthCache) -> Self {
+        self.offline = Some(cache);
+        self
     }
 
     async fn authenticate(
@@ -83,9 +100,23 @@ impl Authenticator for EdgeHubAuthenticator {
             info!("authenticate client");
             match self.authenticate(&context).await {
                 Err(e) if e.can_retry() => {
+                    // no need to wait for EdgeHub core if the client
+                    // has recently authenticated with the same credentials.
+                    if let Some(auth_id) = self.authenticate_offline(&context) {
+                        return Ok(Some(auth_id));
+                    }
                     time::sleep(Duration::from_millis(500)).await;
                 }
-                result => return result,
+                result => {
+                    if let Some(offline) = &self.offline {
+                        match &result {
+                            Ok(Some(auth_id)) => offline.insert(&context, auth_id),
+                            Ok(None) => offline.remove(&context),
+                            Err(_) => {}
+                        }
+                    }
+                    return result;
+                }
             }
         }
 
@@ -93,6 +124,18 @@ impl Authenticator for EdgeHubAuthenticator {
     }
 }
 
+impl EdgeHubAuthenticator {
+    fn authenticate_offline(&self, context: &AuthenticationContext) -> Option<AuthId> {
+        let auth_id = self.offline.as_ref()?.get(context)?;
+        warn!(
+            "EdgeHub core is unavailable, client {} authenticated from offline cache as {}",
+            context.client_id(),
+            auth_id
+        );
+        Some(auth_id)
+    }
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct EdgeHubAuthRequest<'a> {
@@ -190,11 +233,12 @@ mod tests {
 
     use matches::assert_matches;
     use mockito::{mock, Matcher};
+    use tempfile::TempDir;
     use tokio::{sync::oneshot, time};
 
     use mqtt_broker::auth::{AuthenticationContext, Authenticator, Certificate};
 
-    use super::{AuthenticateError, EdgeHubAuthenticator};
+    use super::{AuthenticateError, EdgeHubAuthenticator, OfflineAuthCache};
 
     const CERT: &str = "-----BEGIN CERTIFICATE-----
 MIIEbjCCAlagAwIBAgIEdLDcUTANBgkqhkiG9w0BAQsFADAfMR0wGwYDVQQDDBRp
@@ -433,6 +477,44 @@ ov2gTgQyaRE8rbX4SSPZghE5km7p6FAIjm/uqU9kGMUk3A==
         assert_eq!(result, "somehub/somedevice".into());
     }
 
+    #[tokio::test]
+    async fn it_authenticates_from_offline_cache_when_edgehub_unavailable() {
+        let dir = TempDir::new().unwrap();
+        let cache = OfflineAuthCache::load(dir.path().join("auth.json"), Duration::from_secs(60));
+        let authenticator = authenticator().with_offline_cache(cache);
+
+        let context = || {
+            let mut context = AuthenticationContext::new("client_1".into(), peer_addr());
+            context.with_username("somehub/somedevice/api-version=2018-06-30");
+            context.with_password("qwerty123");
+            context
+        };
+
+        let online = mock("POST", "/authenticate/")
+            .with_status(200)
+            .with_body(
+                r#"{"result": 200, "identity":"somehub/somedevice","version": "2020-04-20"}"#,
+            )
+            .create();
+
+        let authenticator: &dyn Authenticator<Error = AuthenticateError> = &authenticator;
+        let result = authenticator.authenticate(context()).await.unwrap();
+        assert_eq!(result, Some("somehub/somedevice".into()));
+
+        // auth endpoint is unreachable
+        drop(online);
+        let _mock = mock("POST", "/unused").create();
+
+        let result = time::timeout(
+            Duration::from_secs(5),
+            authenticator.authenticate(context()),
+        )
+        .await
+        .expect("offline authentication must not wait for EdgeHub core")
+        .unwrap();
+        assert_eq!(result, Some("somehub/somedevice".into()));
+    }
+
     fn authenticator() -> EdgeHubAuthenticator {
         EdgeHubAuthenticator::new(mockito::server_url() + "/authenticate/")
     }
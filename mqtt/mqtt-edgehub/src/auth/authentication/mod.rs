@@ -1,5 +1,7 @@
 mod edgehub;
 mod local;
+mod offline;
 
 pub use edgehub::EdgeHubAuthenticator;
 pub use local::LocalAuthenticator;
+pub use offline::OfflineAuthCache;
@@ -0,0 +1,401 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use openssl::{hash::MessageDigest, sha, x509::X509};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use mqtt_broker::{auth::AuthenticationContext, AuthId};
+
+/// A cache of recently successful authentications, which lets clients
+/// reconnect when `EdgeHub` core is unavailable.
+///
+/// Credentials are never stored as is: an entry is keyed by SHA-256 hash of the
+/// username along with either SAS token or certificate thumbprint. An entry
+/// expires after the grace period since last successful authentication, or
+/// when SAS token expires, whichever comes first.
+///
+/// Entries are persisted to a file, so they survive broker restarts.
+/// The file is written on a blocking thread without holding the lock, and
+/// changes made while it is being written are batched into the next write.
+#[derive(Debug, Clone)]
+pub struct OfflineAuthCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    path: PathBuf,
+    grace_period: Duration,
+    entries: HashMap<String, CachedAuth>,
+    // entries have changed since they were last written
+    dirty: bool,
+    // entries are being written
+    persisting: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedAuth {
+    identity: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl OfflineAuthCache {
+    /// Loads entries persisted in a given file. Missing or corrupted file
+    /// results in an empty cache.
+    pub fn load(path: impl Into<PathBuf>, grace_period: Duration) -> Self {
+        let path = path.into();
+        let entries = match read_entries(&path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                warn!(message = "unable to load offline authentication cache", error = %e);
+                HashMap::new()
+            }
+        };
+        info!(
+            "loaded {} offline authentication cache entries from {}",
+            entries.len(),
+            path.display()
+        );
+
+        let mut inner = Inner {
+            path,
+            grace_period,
+            entries,
+            dirty: false,
+            persisting: false,
+        };
+        inner.remove_expired();
+
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Remembers that the client has been authenticated with given credentials.
+    pub fn insert(&self, context: &AuthenticationContext, auth_id: &AuthId) {
+        let key = match credentials_key(context) {
+            Some(key) => key,
+            None => return,
+        };
+
+        let mut inner = self.inner.lock();
+        let mut expires_at = chrono::Duration::from_std(inner.grace_period)
+            .ok()
+            .and_then(|grace_period| Utc::now().checked_add_signed(grace_period))
+            .unwrap_or(chrono::MAX_DATETIME);
+        if let Some(token_expiry) = context.password().and_then(sas_token_expiry) {
+            expires_at = expires_at.min(token_expiry);
+        }
+
+        let auth = CachedAuth {
+            identity: auth_id.to_string(),
+            expires_at,
+        };
+        inner.entries.insert(key, auth);
+        inner.dirty = true;
+        drop(inner);
+
+        self.persist();
+    }
+
+    /// Forgets credentials which have been rejected.
+    pub fn remove(&self, context: &AuthenticationContext) {
+        if let Some(key) = credentials_key(context) {
+            let mut inner = self.inner.lock();
+            if inner.entries.remove(&key).is_some() {
+                inner.dirty = true;
+                drop(inner);
+
+                self.persist();
+            }
+        }
+    }
+
+    /// Returns identity the client has been authenticated as with given
+    /// credentials, unless the entry has expired.
+    pub fn get(&self, context: &AuthenticationContext) -> Option<AuthId> {
+        let key = credentials_key(context)?;
+
+        let inner = self.inner.lock();
+        inner
+            .entries
+            .get(&key)
+            .filter(|auth| auth.expires_at > Utc::now())
+            .map(|auth| auth.identity.clone().into())
+    }
+
+    /// Drops entries for identities which are not in the list of authorized
+    /// identities anymore.
+    ///
+    /// Authorized identities don't contain `IoTHub` hostname
+    /// (`device_id` or `device_id/module_id`), while authenticated ones do.
+    pub fn retain_identities<'a>(&self, identities: impl IntoIterator<Item = &'a str>) {
+        let identities = identities.into_iter().collect::<HashSet<_>>();
+
+        let mut inner = self.inner.lock();
+        let count = inner.entries.len();
+        inner.entries.retain(|_, auth| {
+            auth.identity
+                .splitn(2, '/')
+                .nth(1)
+                .map_or(false, |identity| identities.contains(identity))
+        });
+
+        let removed = count - inner.entries.len();
+        if removed > 0 {
+            info!(
+                "removed {} revoked identities from offline authentication cache",
+                removed
+            );
+            inner.dirty = true;
+            drop(inner);
+
+            self.persist();
+        }
+    }
+
+    /// Writes changed entries to the file on a blocking thread, unless
+    /// a write is already in progress, in which case changes are picked up
+    /// by that write once it completes.
+    ///
+    /// Entries are written on the current thread if called outside of
+    /// the tokio runtime.
+    fn persist(&self) {
+        {
+            let mut inner = self.inner.lock();
+            if inner.persisting {
+                return;
+            }
+            inner.persisting = true;
+        }
+
+        let cache = self.clone();
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(move || cache.write_changes());
+            }
+            Err(_) => cache.write_changes(),
+        }
+    }
+
+    fn write_changes(&self) {
+        loop {
+            let (path, entries) = {
+                let mut inner = self.inner.lock();
+                if !inner.dirty {
+                    inner.persisting = false;
+                    return;
+                }
+
+                inner.dirty = false;
+                inner.remove_expired();
+                (inner.path.clone(), inner.entries.clone())
+            };
+
+            if let Err(e) = write_entries(&path, &entries) {
+                warn!(message = "unable to persist offline authentication cache", error = %e);
+            }
+        }
+    }
+}
+
+impl Inner {
+    fn remove_expired(&mut self) {
+        let now = Utc::now();
+        self.entries.retain(|_, auth| auth.expires_at > now);
+    }
+}
+
+fn read_entries(path: &Path) -> io::Result<HashMap<String, CachedAuth>> {
+    let contents = fs::read(path)?;
+    let entries = serde_json::from_slice(&contents)?;
+    Ok(entries)
+}
+
+fn write_entries(path: &Path, entries: &HashMap<String, CachedAuth>) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // write to a temporary file first, so the cache is never left half-written.
+    let contents = serde_json::to_vec(entries)?;
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, contents)?;
+    fs::rename(temp, path)
+}
+
+/// Hashes the username along with SAS token or certificate thumbprint.
+/// Returns `None` for anonymous clients or unsupported credentials.
+fn credentials_key(context: &AuthenticationContext) -> Option<String> {
+    let username = context.username()?;
+
+    let secret = if let Some(certificate) = context.certificate() {
+        let certificate = X509::from_pem(certificate.as_ref())
+            .map_err(|e| debug!(message = "unable to parse client certificate", error = %e))
+            .ok()?;
+        certificate
+            .digest(MessageDigest::sha256())
+            .map_err(|e| debug!(message = "unable to compute certificate thumbprint", error = %e))
+            .ok()?
+            .to_vec()
+    } else {
+        context.password()?.as_bytes().to_vec()
+    };
+
+    let mut hasher = sha::Sha256::new();
+    hasher.update(username.as_bytes());
+    hasher.update(&[0]);
+    hasher.update(&secret);
+
+    Some(
+        hasher
+            .finish()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect(),
+    )
+}
+
+/// Parses expiry (`se` field) of a SAS token like
+/// `SharedAccessSignature sr=...&sig=...&se=1600000000`.
+fn sas_token_expiry(token: &str) -> Option<DateTime<Utc>> {
+    let token = token.strip_prefix("SharedAccessSignature ")?;
+    token
+        .split('&')
+        .find_map(|field| field.strip_prefix("se="))
+        .and_then(|expiry| expiry.parse().ok())
+        .and_then(|expiry| Utc.timestamp_opt(expiry, 0).single())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use tempfile::TempDir;
+    use tokio::time;
+
+    use mqtt_broker::auth::AuthenticationContext;
+
+    use super::{sas_token_expiry, OfflineAuthCache};
+
+    #[test]
+    fn it_returns_cached_identity() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("auth.json");
+
+        let cache = OfflineAuthCache::load(&path, Duration::from_secs(60));
+        cache.insert(&context("qwerty123"), &"myhub/device_1".into());
+
+        assert_eq!(
+            cache.get(&context("qwerty123")),
+            Some("myhub/device_1".into())
+        );
+        assert_eq!(cache.get(&context("wrong")), None);
+
+        // entries survive restarts
+        let cache = OfflineAuthCache::load(&path, Duration::from_secs(60));
+        assert_eq!(
+            cache.get(&context("qwerty123")),
+            Some("myhub/device_1".into())
+        );
+
+        // credentials are not stored as is
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("qwerty123"));
+    }
+
+    #[test]
+    fn it_expires_entries() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("auth.json");
+
+        let cache = OfflineAuthCache::load(&path, Duration::from_secs(0));
+        cache.insert(&context("qwerty123"), &"myhub/device_1".into());
+        assert_eq!(cache.get(&context("qwerty123")), None);
+
+        let cache = OfflineAuthCache::load(&path, Duration::from_secs(60));
+        let token = "SharedAccessSignature sr=myhub%2Fdevices%2Fdevice_1&sig=abc&se=1600000000";
+        cache.insert(&context(token), &"myhub/device_1".into());
+        assert_eq!(cache.get(&context(token)), None);
+    }
+
+    #[test]
+    fn it_removes_rejected_and_revoked_identities() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("auth.json");
+
+        let cache = OfflineAuthCache::load(&path, Duration::from_secs(60));
+        cache.insert(&context("qwerty123"), &"myhub/device_1".into());
+        cache.insert(&context("qwerty456"), &"myhub/device_2/module_a".into());
+        cache.insert(&context("qwerty789"), &"myhub/device_3".into());
+
+        cache.remove(&context("qwerty123"));
+        assert_eq!(cache.get(&context("qwerty123")), None);
+
+        cache.retain_identities(vec!["device_2/module_a"]);
+        assert_eq!(
+            cache.get(&context("qwerty456")),
+            Some("myhub/device_2/module_a".into())
+        );
+        assert_eq!(cache.get(&context("qwerty789")), None);
+    }
+
+    #[tokio::test]
+    async fn it_persists_entries_in_background() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("auth.json");
+
+        let cache = OfflineAuthCache::load(&path, Duration::from_secs(60));
+        cache.insert(&context("qwerty123"), &"myhub/device_1".into());
+        cache.insert(&context("qwerty456"), &"myhub/device_2".into());
+
+        // wait for the blocking task to write all changes
+        let mut persisted = false;
+        for _ in 0..50 {
+            if !cache.inner.lock().persisting {
+                persisted = true;
+                break;
+            }
+            time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(persisted);
+
+        let cache = OfflineAuthCache::load(&path, Duration::from_secs(60));
+        assert_eq!(
+            cache.get(&context("qwerty123")),
+            Some("myhub/device_1".into())
+        );
+        assert_eq!(
+            cache.get(&context("qwerty456")),
+            Some("myhub/device_2".into())
+        );
+    }
+
+    #[test]
+    fn it_parses_sas_token_expiry() {
+        let token = "SharedAccessSignature sr=myhub%2Fdevices%2Fdevice_1&sig=abc&se=1600000000";
+        assert_eq!(
+            sas_token_expiry(token).map(|expiry| expiry.timestamp()),
+            Some(1_600_000_000)
+        );
+        assert_eq!(sas_token_expiry("qwerty123"), None);
+    }
+
+    fn context(password: &str) -> AuthenticationContext {
+        let peer_addr: SocketAddr = "127.0.0.1:12345".parse().unwrap();
+        let mut context = AuthenticationContext::new("client_1".into(), peer_addr);
+        context.with_username("myhub/device_1/api-version=2018-06-30");
+        context.with_password(password);
+        context
+    }
+}
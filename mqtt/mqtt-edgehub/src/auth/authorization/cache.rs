@@ -0,0 +1,138 @@
+use std::hash::Hash;
+
+use linked_hash_map::LinkedHashMap;
+use parking_lot::Mutex;
+use tracing::debug;
+
+/// Max number of decisions kept in `PolicyAuthorizer` cache.
+pub(super) const DEFAULT_DECISION_CACHE_CAPACITY: usize = 10_000;
+
+/// Number of lookups after which cache statistics are logged.
+const STATS_LOG_INTERVAL: u64 = 100_000;
+
+/// Bounded LRU cache of authorization decisions.
+///
+/// When the cache is full, the least recently used decision is evicted.
+/// The cache counts hits and misses, and periodically logs them.
+#[derive(Debug)]
+pub(super) struct DecisionCache<K, V>
+where
+    K: Hash + Eq,
+{
+    inner: Mutex<Inner<K, V>>,
+    capacity: usize,
+}
+
+#[derive(Debug)]
+struct Inner<K, V>
+where
+    K: Hash + Eq,
+{
+    entries: LinkedHashMap<K, V>,
+    stats: CacheStats,
+}
+
+/// Decision cache hit/miss statistics.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(super) struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl<K, V> DecisionCache<K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                entries: LinkedHashMap::new(),
+                stats: CacheStats::default(),
+            }),
+            capacity,
+        }
+    }
+
+    /// Returns cached decision and marks it as the most recently used one.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut inner = self.inner.lock();
+
+        let value = inner.entries.get_refresh(key).cloned();
+        if value.is_some() {
+            inner.stats.hits += 1;
+        } else {
+            inner.stats.misses += 1;
+        }
+
+        let stats = inner.stats;
+        if (stats.hits + stats.misses) % STATS_LOG_INTERVAL == 0 {
+            debug!(
+                "decision cache: {} hits, {} misses, {} entries",
+                stats.hits,
+                stats.misses,
+                inner.entries.len()
+            );
+        }
+
+        value
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut inner = self.inner.lock();
+        inner.entries.insert(key, value);
+        while inner.entries.len() > self.capacity {
+            inner.entries.pop_front();
+        }
+    }
+
+    /// Drops all cached decisions and returns statistics collected so far.
+    pub fn clear(&mut self) -> CacheStats {
+        let inner = self.inner.get_mut();
+        inner.entries.clear();
+        std::mem::take(&mut inner.stats)
+    }
+
+    #[cfg(test)]
+    pub fn stats(&self) -> CacheStats {
+        self.inner.lock().stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheStats, DecisionCache};
+
+    #[test]
+    fn it_evicts_least_recently_used() {
+        let cache = DecisionCache::new(2);
+
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        cache.insert("c", 3);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"c"), Some(3));
+
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 1 });
+    }
+
+    #[test]
+    fn it_clears_entries_and_stats() {
+        let mut cache = DecisionCache::new(2);
+
+        cache.insert("a", 1);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"b"), None);
+
+        assert_eq!(cache.clear(), CacheStats { hits: 1, misses: 1 });
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 1 });
+    }
+}
@@ -10,6 +10,8 @@ use mqtt_broker::{
     AuthId, BrokerReadyEvent, BrokerReadyHandle, ClientId,
 };
 
+use super::GroupsUpdate;
+
 /// `EdgeHubAuthorizer` implements authorization rules for iothub-specific primitives.
 ///
 /// For example, it allows a client to publish (or subscribe for) twin updates, direct messages,
@@ -184,7 +186,7 @@ fn get_actor_id(topic: &str) -> Option<ClientId> {
             // - to extract device_id and module_id.
             //
             // format! is for ease of reading only.
-            &format!(r"^(\$edgehub|\$iothub)/(?P<device_id>[^/\+\#]+)(/(?P<module_id>[^/\+\#]+))?/({}|{}|{}|{}|{}|{}|{}|{}|{})",
+            &format!(r"^(\$edgehub|\$iothub)/(?P<device_id>[^/\+\#]+)(/(?P<module_id>[^/\+\#]+))?/({}|{}|{}|{}|{}|{}|{}|{}|{}|{})",
                 "messages/events",
                 "messages/c2d/post",
                 "twin/desired",
@@ -193,6 +195,7 @@ fn get_actor_id(topic: &str) -> Option<ClientId> {
                 "twin/res",
                 "methods/post",
                 "methods/res",
+                "files/",
                 "\\+/inputs")
         ).expect("failed to create new Regex from pattern");
     }
@@ -239,6 +242,10 @@ where
     fn update(&mut self, update: Box<dyn Any>) -> Result<(), Self::Error> {
         match update.downcast::<AuthorizerUpdate>() {
             Ok(update) => {
+                // update identity groups of inner authorizer
+                let groups = GroupsUpdate::from_identities(&self.iothub_id, &update.0);
+                self.inner.update(Box::new(groups))?;
+
                 // update identities cache
                 self.identities_cache = update
                     .0
@@ -266,6 +273,12 @@ where
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthorizerUpdate(Vec<IdentityUpdate>);
 
+impl AuthorizerUpdate {
+    pub fn identities(&self) -> &[IdentityUpdate] {
+        &self.0
+    }
+}
+
 /// Represents an update to an identity.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct IdentityUpdate {
@@ -276,6 +289,11 @@ pub struct IdentityUpdate {
     /// Auth chain is used to authorize "on-behalf-of" operations.
     #[serde(rename = "AuthChain")]
     auth_chain: Option<String>,
+
+    /// Identity tags. Identity is a member of policy identity groups
+    /// named after its tags.
+    #[serde(rename = "Tags", default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
 }
 
 impl IdentityUpdate {
@@ -283,9 +301,15 @@ impl IdentityUpdate {
         Self {
             identity,
             auth_chain,
+            tags: Vec::new(),
         }
     }
 
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
     pub fn identity(&self) -> &str {
         &self.identity
     }
@@ -293,6 +317,10 @@ impl IdentityUpdate {
     pub fn auth_chain(&self) -> Option<&str> {
         self.auth_chain.as_deref()
     }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
 }
 
 impl fmt::Display for IdentityUpdate {
@@ -346,10 +374,12 @@ mod tests {
     #[test_case(&tests::subscribe_activity("leaf-1", "leaf-1", "$iothub/leaf-1/twin/res"); "iothub device twin response")]
     #[test_case(&tests::subscribe_activity("leaf-1", "leaf-1", "$iothub/leaf-1/methods/post"); "iothub device DM request")]
     #[test_case(&tests::subscribe_activity("leaf-1", "leaf-1", "$iothub/leaf-1/methods/res"); "iothub device DM response")]
+    #[test_case(&tests::subscribe_activity("leaf-1", "leaf-1", "$edgehub/leaf-1/files/res/#"); "device file upload responses")]
     fn it_allows_to_subscribe_for_leaf(activity: &Activity) {
         let identities = vec![IdentityUpdate {
             identity: "leaf-1".to_string(),
             auth_chain: Some("leaf-1;this_edge".to_string()),
+            tags: Vec::new(),
         }];
         let authorizer = authorizer(DenyAll, identities);
 
@@ -379,6 +409,7 @@ mod tests {
         let identities = vec![IdentityUpdate {
             identity: "this_edge/module-a".to_string(),
             auth_chain: Some("this_edge/module-a;this_edge".to_string()),
+            tags: Vec::new(),
         }];
         let authorizer = authorizer(DenyAll, identities);
 
@@ -408,11 +439,13 @@ mod tests {
             IdentityUpdate {
                 identity: "edge-1/$edgeHub".to_string(),
                 auth_chain: Some("edge-1/$edgeHub;this_edge".to_string()),
+                tags: Vec::new(),
             },
             // grandchild module
             IdentityUpdate {
                 identity: "edge-1/module-a".to_string(),
                 auth_chain: Some("edge-1/module-a;edge-1;this_edge".to_string()),
+                tags: Vec::new(),
             },
         ];
         let authorizer = authorizer(DenyAll, identities);
@@ -443,11 +476,13 @@ mod tests {
             IdentityUpdate {
                 identity: "edge-1/$edgeHub".to_string(),
                 auth_chain: Some("edge-1/$edgeHub;this_edge".to_string()),
+                tags: Vec::new(),
             },
             // grandchild leaf
             IdentityUpdate {
                 identity: "leaf-2".to_string(),
                 auth_chain: Some("leaf-2;edge-1;this_edge".to_string()),
+                tags: Vec::new(),
             },
         ];
         let authorizer = authorizer(DenyAll, identities);
@@ -509,10 +544,13 @@ mod tests {
     #[test_case(&tests::publish_activity("leaf-1", "leaf-1", "$iothub/leaf-1/twin/res"); "iothub device twin response")]
     #[test_case(&tests::publish_activity("leaf-1", "leaf-1", "$iothub/leaf-1/methods/post"); "iothub device DM request")]
     #[test_case(&tests::publish_activity("leaf-1", "leaf-1", "$iothub/leaf-1/methods/res"); "iothub device DM response")]
+    #[test_case(&tests::publish_activity("leaf-1", "leaf-1", "$edgehub/leaf-1/files/sasuri/?rid=6"); "device file upload SAS URI request")]
+    #[test_case(&tests::publish_activity("leaf-1", "leaf-1", "$edgehub/leaf-1/files/notifications/?rid=7"); "device file upload notification")]
     fn it_allows_to_publish_for_leaf(activity: &Activity) {
         let identities = vec![IdentityUpdate {
             identity: "leaf-1".to_string(),
             auth_chain: Some("leaf-1;this_edge".to_string()),
+            tags: Vec::new(),
         }];
         let authorizer = authorizer(DenyAll, identities);
 
@@ -537,10 +575,12 @@ mod tests {
     #[test_case(&tests::publish_activity("this_edge/module-a", "this_edge/module-a", "$iothub/this_edge/module-a/twin/res"); "iothub device twin response")]
     #[test_case(&tests::publish_activity("this_edge/module-a", "this_edge/module-a", "$iothub/this_edge/module-a/methods/post"); "iothub device DM request")]
     #[test_case(&tests::publish_activity("this_edge/module-a", "this_edge/module-a", "$iothub/this_edge/module-a/methods/res"); "iothub device DM response")]
+    #[test_case(&tests::publish_activity("this_edge/module-a", "this_edge/module-a", "$edgehub/this_edge/module-a/files/sasuri/?rid=6"); "module file upload SAS URI request")]
     fn it_allows_to_publish_for_module(activity: &Activity) {
         let identities = vec![IdentityUpdate {
             identity: "this_edge/module-a".to_string(),
             auth_chain: Some("this_edge/module-a;this_edge".to_string()),
+            tags: Vec::new(),
         }];
         let authorizer = authorizer(DenyAll, identities);
 
@@ -571,11 +611,13 @@ mod tests {
             IdentityUpdate {
                 identity: "edge-1/$edgeHub".to_string(),
                 auth_chain: Some("edge-1/$edgeHub;this_edge".to_string()),
+                tags: Vec::new(),
             },
             // grandchild leaf
             IdentityUpdate {
                 identity: "leaf-2".to_string(),
                 auth_chain: Some("leaf-2;edge-1;this_edge".to_string()),
+                tags: Vec::new(),
             },
         ];
         let authorizer = authorizer(DenyAll, identities);
@@ -607,11 +649,13 @@ mod tests {
             IdentityUpdate {
                 identity: "edge-1/$edgeHub".to_string(),
                 auth_chain: Some("edge-1/$edgeHub;this_edge".to_string()),
+                tags: Vec::new(),
             },
             // grandchild module
             IdentityUpdate {
                 identity: "edge-1/module-a".to_string(),
                 auth_chain: Some("edge-1/module-a;edge-1;this_edge".to_string()),
+                tags: Vec::new(),
             },
         ];
         let authorizer = authorizer(DenyAll, identities);
@@ -625,17 +669,21 @@ mod tests {
     #[test_case(&tests::publish_activity("edge-1/module-a", "edge-1/module-a", "$edgehub/edge-1/module-a/messages/events"); "module events pub")]
     #[test_case(&tests::subscribe_activity("leaf-1", "leaf-1", "$edgehub/leaf-1/messages/events"); "leaf events sub")]
     #[test_case(&tests::publish_activity("leaf-1", "leaf-1", "$edgehub/leaf-1/messages/events"); "leaf events pub")]
+    #[test_case(&tests::publish_activity("leaf-1", "leaf-1", "$edgehub/leaf-1/files/sasuri/?rid=6"); "leaf file upload pub")]
+    #[test_case(&tests::subscribe_activity("leaf-1", "leaf-1", "$edgehub/leaf-1/files/res/#"); "leaf file upload responses sub")]
     fn it_forbids_operations_for_not_in_scope_identities(activity: &Activity) {
         let identities = vec![
             // leaf
             IdentityUpdate {
                 identity: "another-leaf".to_string(),
                 auth_chain: Some("another-leaf;this_edge".to_string()),
+                tags: Vec::new(),
             },
             // module
             IdentityUpdate {
                 identity: "this_edge/another-module".to_string(),
                 auth_chain: Some("edge-1/another-module;this_edge".to_string()),
+                tags: Vec::new(),
             },
         ];
         let authorizer = authorizer(DenyAll, identities);
@@ -1,8 +1,9 @@
+mod cache;
 mod edgehub;
 mod local;
 mod policy;
 
-pub use self::policy::{PolicyAuthorizer, PolicyUpdate};
+pub use self::policy::{GroupsUpdate, PolicyAuthorizer, PolicyUpdate};
 pub use edgehub::{AuthorizerUpdate, EdgeHubAuthorizer, IdentityUpdate};
 pub use local::LocalAuthorizer;
 
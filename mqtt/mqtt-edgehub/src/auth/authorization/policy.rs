@@ -1,13 +1,20 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tracing::{debug, info};
+use tracing::{debug, info, level_filters::LevelFilter, warn};
 
 use mqtt_broker::{
     auth::{Activity, Authorization, Authorizer, Operation},
     BrokerReadyEvent, BrokerReadyHandle,
 };
 use mqtt_policy::{MqttSubstituter, MqttTopicFilterMatcher, MqttValidator};
-use policy::{Decision, Policy, PolicyBuilder, Request};
+use policy::{Decision, Policy, PolicyBuilder, PolicyDefinition, Request};
+
+use super::{
+    cache::{DecisionCache, DEFAULT_DECISION_CACHE_CAPACITY},
+    IdentityUpdate,
+};
 
 /// `PolicyAuthorizer` uses policy engine to evaluate the activity.
 ///
@@ -16,26 +23,39 @@ use policy::{Decision, Policy, PolicyBuilder, Request};
 ///
 /// This is the last authorizer in the chain of edgehub-specific authorizers (see `EdgeHubAuthorizer`).
 /// It's purpose is to evaluate customer rules for generic MQTT topics.
+///
+/// Decisions are cached by identity, client id, operation and resource, unless
+/// the policy has statements with conditions, as those may depend on other
+/// request attributes. The cache is dropped on every update.
 pub struct PolicyAuthorizer {
     policy: Option<Policy<MqttTopicFilterMatcher, MqttSubstituter>>,
+    groups: HashMap<String, Vec<String>>,
     device_id: String,
     broker_ready: Option<BrokerReadyHandle>,
+    cache: DecisionCache<DecisionKey, Authorization>,
+    cacheable: bool,
 }
 
 impl PolicyAuthorizer {
     pub fn new(device_id: impl Into<String>, broker_ready: BrokerReadyHandle) -> Self {
         Self {
             policy: None,
+            groups: HashMap::new(),
             device_id: device_id.into(),
             broker_ready: Some(broker_ready),
+            cache: DecisionCache::new(DEFAULT_DECISION_CACHE_CAPACITY),
+            cacheable: false,
         }
     }
 
     pub fn without_ready_handle(device_id: impl Into<String>) -> Self {
         Self {
             policy: None,
+            groups: HashMap::new(),
             device_id: device_id.into(),
             broker_ready: None,
+            cache: DecisionCache::new(DEFAULT_DECISION_CACHE_CAPACITY),
+            cacheable: false,
         }
     }
 }
@@ -44,6 +64,18 @@ impl Authorizer for PolicyAuthorizer {
     type Error = Error;
 
     fn authorize(&self, activity: &Activity) -> Result<Authorization, Self::Error> {
+        let policy = self.policy.as_ref().ok_or(Error::PolicyNotReady)?;
+
+        let key = if self.cacheable {
+            Some(DecisionKey::new(activity))
+        } else {
+            None
+        };
+        if let Some(auth) = key.as_ref().and_then(|key| self.cache.get(key)) {
+            debug!("cached decision for activity: {:?}", activity);
+            return Ok(auth);
+        }
+
         let request = Request::with_context(
             identity(activity),
             operation(activity),
@@ -54,22 +86,60 @@ impl Authorizer for PolicyAuthorizer {
 
         debug!("authorizing request: {:?}", request);
 
-        match &self.policy {
-            Some(policy) => Ok(
-                match policy.evaluate(&request).map_err(Error::Authorization)? {
-                    Decision::Allowed => Authorization::Allowed,
-                    Decision::Denied => Authorization::Forbidden("denied by policy".into()),
-                },
-            ),
-            None => Err(Error::PolicyNotReady),
+        // explanation is more expensive to build than a decision,
+        // so it is only produced when debug logging is enabled.
+        let decision = policy.evaluate(&request).map_err(Error::Authorization)?;
+        if LevelFilter::current() >= LevelFilter::DEBUG {
+            let explanation = policy.explain(&request).map_err(Error::Authorization)?;
+            debug!("request {}", explanation);
+        }
+
+        let auth = match decision {
+            Decision::Allowed => Authorization::Allowed,
+            Decision::Denied => Authorization::Forbidden("denied by policy".into()),
+        };
+
+        if let Some(key) = key {
+            self.cache.insert(key, auth.clone());
         }
+        Ok(auth)
     }
 
     fn update(&mut self, update: Box<dyn std::any::Any>) -> Result<(), Self::Error> {
+        // any update may change decisions, so previous ones are dropped.
+        let stats = self.cache.clear();
+        info!(
+            "decision cache has been invalidated: {} hits, {} misses.",
+            stats.hits, stats.misses
+        );
+
+        if let Some(groups_update) = update.downcast_ref::<GroupsUpdate>() {
+            self.groups = groups_update.0.clone();
+            if let Some(policy) = &mut self.policy {
+                policy.update_groups(&self.groups);
+            }
+            debug!("policy identity groups have been updated.");
+        }
+
         if let Some(policy_update) = update.downcast_ref::<PolicyUpdate>() {
-            self.policy = Some(build_policy(&policy_update.definition, &self.device_id)?);
+            // definition is parsed once and shared by the policy builder,
+            // the cacheability check and the analyzer.
+            let definition = PolicyDefinition::from_json(&policy_update.definition)
+                .map_err(Error::BuildPolicy)?;
+            let cacheable = is_cacheable(&definition);
+            let findings = mqtt_policy::policy_analyzer().analyze(&definition);
+
+            let mut policy = build_policy(definition, &self.device_id)?;
+            policy.update_groups(&self.groups);
+            self.policy = Some(policy);
+            self.cacheable = cacheable;
             info!("policy engine has been updated.");
 
+            // report statements which are likely to be a mistake.
+            for finding in findings {
+                warn!("policy definition: {}", finding);
+            }
+
             // signal that policy has been initialized
             if let Some(mut broker_ready) = self.broker_ready.take() {
                 broker_ready.send(BrokerReadyEvent::PolicyReady);
@@ -94,6 +164,50 @@ impl PolicyUpdate {
     }
 }
 
+/// Represents updates to identity groups of a `PolicyAuthorizer` policy.
+///
+/// Groups are populated from identity tags, so that every identity becomes
+/// a member of groups named after its tags.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GroupsUpdate(HashMap<String, Vec<String>>);
+
+impl GroupsUpdate {
+    pub fn from_identities(iothub_id: &str, identities: &[IdentityUpdate]) -> Self {
+        let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+        for identity in identities {
+            for tag in identity.tags() {
+                groups.entry(tag.clone()).or_default().push(format!(
+                    "{}/{}",
+                    iothub_id,
+                    identity.identity()
+                ));
+            }
+        }
+        Self(groups)
+    }
+}
+
+/// Key of a cached decision. Client id is a part of the key as it can be
+/// referred to by policy variables.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DecisionKey {
+    identity: String,
+    client_id: String,
+    operation: &'static str,
+    resource: String,
+}
+
+impl DecisionKey {
+    fn new(activity: &Activity) -> Self {
+        Self {
+            identity: identity(activity).into(),
+            client_id: activity.client_id().as_str().into(),
+            operation: operation(activity),
+            resource: resource(activity).into(),
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("An error occurred authorizing the request: {0}")]
@@ -107,10 +221,10 @@ pub enum Error {
 }
 
 fn build_policy(
-    definition: impl Into<String>,
+    definition: PolicyDefinition,
     device_id: impl Into<String>,
 ) -> Result<Policy<MqttTopicFilterMatcher, MqttSubstituter>, Error> {
-    PolicyBuilder::from_json(definition)
+    PolicyBuilder::from_definition(definition)
         .with_validator(MqttValidator)
         .with_matcher(MqttTopicFilterMatcher)
         .with_substituter(MqttSubstituter::new(device_id))
@@ -119,11 +233,21 @@ fn build_policy(
         .map_err(Error::BuildPolicy)
 }
 
+/// Checks that decisions of the policy depend only on request identity,
+/// client id, operation and resource, which is not the case for statements
+/// with conditions.
+fn is_cacheable(definition: &PolicyDefinition) -> bool {
+    definition
+        .statements()
+        .iter()
+        .all(|statement| statement.conditions().is_empty())
+}
+
 fn identity(activity: &Activity) -> &str {
     activity.client_info().auth_id().as_str() //TODO: think about anonymous case.
 }
 
-fn operation(activity: &Activity) -> &str {
+fn operation(activity: &Activity) -> &'static str {
     match activity.operation() {
         Operation::Connect => "mqtt:connect",
         Operation::Publish(_) => "mqtt:publish",
@@ -147,9 +271,9 @@ mod tests {
 
     use mqtt_broker::auth::{Activity, Authorization, Authorizer};
 
-    use crate::auth::authorization::tests;
+    use crate::auth::authorization::{cache::CacheStats, tests};
 
-    use super::{Error, PolicyAuthorizer, PolicyUpdate};
+    use super::{Error, GroupsUpdate, IdentityUpdate, PolicyAuthorizer, PolicyUpdate};
 
     #[test]
     fn error_on_uninitialized_policy() {
@@ -188,6 +312,268 @@ mod tests {
         assert_matches!(auth, Ok(Authorization::Forbidden(_)));
     }
 
+    #[test]
+    fn it_authorizes_identity_groups_and_patterns() {
+        let mut authorizer = PolicyAuthorizer::without_ready_handle("test_device_id");
+
+        let definition = r###"{
+            "schemaVersion": "2020-10-30",
+            "groups": {
+                "sensors": ["myhub/sensor-1"]
+            },
+            "statements": [
+                {
+                    "effect": "allow",
+                    "groups": [
+                        "sensors"
+                    ],
+                    "identityPatterns": [
+                        "myhub/monitor-*"
+                    ],
+                    "operations": [
+                        "mqtt:publish"
+                    ],
+                    "resources": [
+                        "telemetry/#"
+                    ]
+                }
+            ]
+        }"###
+            .into();
+        authorizer
+            .update(Box::new(PolicyUpdate { definition }))
+            .expect("invalid policy definition");
+
+        let activity = tests::publish_activity("sensor-1", "myhub/sensor-1", "telemetry/temp");
+        assert_matches!(authorizer.authorize(&activity), Ok(Authorization::Allowed));
+
+        let activity = tests::publish_activity("monitor-2", "myhub/monitor-2", "telemetry/temp");
+        assert_matches!(authorizer.authorize(&activity), Ok(Authorization::Allowed));
+
+        let activity = tests::publish_activity("sensor-2", "myhub/sensor-2", "telemetry/temp");
+        assert_matches!(
+            authorizer.authorize(&activity),
+            Ok(Authorization::Forbidden(_))
+        );
+
+        let activity = tests::publish_activity(
+            "monitor-2/module-a",
+            "myhub/monitor-2/module-a",
+            "telemetry/temp",
+        );
+        assert_matches!(
+            authorizer.authorize(&activity),
+            Ok(Authorization::Forbidden(_))
+        );
+    }
+
+    #[test]
+    fn it_authorizes_identity_groups_from_tags() {
+        let mut authorizer = PolicyAuthorizer::without_ready_handle("test_device_id");
+
+        let identities = vec![
+            IdentityUpdate::new("sensor-1".into(), None).with_tags(vec!["sensors".into()]),
+            IdentityUpdate::new("monitor-1".into(), None),
+        ];
+        authorizer
+            .update(Box::new(GroupsUpdate::from_identities(
+                "myhub",
+                &identities,
+            )))
+            .unwrap();
+
+        let definition = r###"{
+            "schemaVersion": "2020-10-30",
+            "groups": {
+                "sensors": ["myhub/sensor-3"]
+            },
+            "statements": [
+                {
+                    "effect": "allow",
+                    "groups": [
+                        "sensors"
+                    ],
+                    "operations": [
+                        "mqtt:publish"
+                    ],
+                    "resources": [
+                        "telemetry/#"
+                    ]
+                }
+            ]
+        }"###
+            .into();
+        authorizer
+            .update(Box::new(PolicyUpdate { definition }))
+            .expect("invalid policy definition");
+
+        // members from tags and declared members are both in the group.
+        let activity = tests::publish_activity("sensor-1", "myhub/sensor-1", "telemetry/temp");
+        assert_matches!(authorizer.authorize(&activity), Ok(Authorization::Allowed));
+
+        let activity = tests::publish_activity("sensor-3", "myhub/sensor-3", "telemetry/temp");
+        assert_matches!(authorizer.authorize(&activity), Ok(Authorization::Allowed));
+
+        let activity = tests::publish_activity("monitor-1", "myhub/monitor-1", "telemetry/temp");
+        assert_matches!(
+            authorizer.authorize(&activity),
+            Ok(Authorization::Forbidden(_))
+        );
+
+        // groups are updated for an existing policy.
+        let identities = vec![
+            IdentityUpdate::new("sensor-1".into(), None),
+            IdentityUpdate::new("monitor-1".into(), None).with_tags(vec!["sensors".into()]),
+        ];
+        authorizer
+            .update(Box::new(GroupsUpdate::from_identities(
+                "myhub",
+                &identities,
+            )))
+            .unwrap();
+
+        let activity = tests::publish_activity("sensor-1", "myhub/sensor-1", "telemetry/temp");
+        assert_matches!(
+            authorizer.authorize(&activity),
+            Ok(Authorization::Forbidden(_))
+        );
+
+        let activity = tests::publish_activity("sensor-3", "myhub/sensor-3", "telemetry/temp");
+        assert_matches!(authorizer.authorize(&activity), Ok(Authorization::Allowed));
+
+        let activity = tests::publish_activity("monitor-1", "myhub/monitor-1", "telemetry/temp");
+        assert_matches!(authorizer.authorize(&activity), Ok(Authorization::Allowed));
+    }
+
+    #[test]
+    fn it_invalidates_cached_decisions_on_update() {
+        let mut authorizer = authorizer();
+
+        let activity = tests::publish_activity("monitor_a", "monitor_a", "topic/a");
+        assert_matches!(authorizer.authorize(&activity), Ok(Authorization::Allowed));
+        assert_matches!(authorizer.authorize(&activity), Ok(Authorization::Allowed));
+        assert_eq!(authorizer.cache.stats(), CacheStats { hits: 1, misses: 1 });
+
+        let definition = r###"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "deny",
+                    "identities": [
+                        "monitor_a"
+                    ],
+                    "operations": [
+                        "mqtt:publish"
+                    ],
+                    "resources": [
+                        "topic/a"
+                    ]
+                }
+            ]
+        }"###
+            .into();
+        authorizer
+            .update(Box::new(PolicyUpdate { definition }))
+            .expect("invalid policy definition");
+
+        assert_matches!(
+            authorizer.authorize(&activity),
+            Ok(Authorization::Forbidden(_))
+        );
+        assert_eq!(authorizer.cache.stats(), CacheStats { hits: 0, misses: 1 });
+    }
+
+    #[test]
+    fn it_does_not_cache_decisions_of_policy_with_conditions() {
+        let mut authorizer = PolicyAuthorizer::without_ready_handle("test_device_id");
+
+        let definition = r###"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identities": [
+                        "monitor_a"
+                    ],
+                    "operations": [
+                        "mqtt:publish"
+                    ],
+                    "resources": [
+                        "topic/a"
+                    ],
+                    "conditions": [
+                        {
+                            "operator": "numericEquals",
+                            "key": "mqtt:qos",
+                            "value": 0
+                        }
+                    ]
+                }
+            ]
+        }"###
+            .into();
+        authorizer
+            .update(Box::new(PolicyUpdate { definition }))
+            .expect("invalid policy definition");
+
+        let activity = tests::publish_activity("monitor_a", "monitor_a", "topic/a");
+        assert_matches!(authorizer.authorize(&activity), Ok(Authorization::Allowed));
+        assert_matches!(authorizer.authorize(&activity), Ok(Authorization::Allowed));
+        assert_eq!(authorizer.cache.stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn it_invalidates_resolved_variable_rules_on_update() {
+        // conditions disable decision cache, so only variable rules are cached
+        let policy = |topic: &str| {
+            format!(
+                r###"{{
+                    "schemaVersion": "2020-10-30",
+                    "statements": [
+                        {{
+                            "effect": "allow",
+                            "identities": [
+                                "{{{{iot:identity}}}}"
+                            ],
+                            "operations": [
+                                "mqtt:publish"
+                            ],
+                            "resources": [
+                                "{}/{{{{iot:identity}}}}"
+                            ],
+                            "conditions": [
+                                {{
+                                    "operator": "numericEquals",
+                                    "key": "mqtt:qos",
+                                    "value": 0
+                                }}
+                            ]
+                        }}
+                    ]
+                }}"###,
+                topic
+            )
+        };
+
+        let mut authorizer = PolicyAuthorizer::without_ready_handle("test_device_id");
+        authorizer
+            .update(Box::new(PolicyUpdate::new(policy("events"))))
+            .expect("invalid policy definition");
+
+        let activity = tests::publish_activity("monitor_a", "monitor_a", "events/monitor_a");
+        assert_matches!(authorizer.authorize(&activity), Ok(Authorization::Allowed));
+        assert_matches!(authorizer.authorize(&activity), Ok(Authorization::Allowed));
+
+        authorizer
+            .update(Box::new(PolicyUpdate::new(policy("alerts"))))
+            .expect("invalid policy definition");
+
+        assert_matches!(
+            authorizer.authorize(&activity),
+            Ok(Authorization::Forbidden(_))
+        );
+    }
+
     fn authorizer() -> PolicyAuthorizer {
         let mut authorizer = PolicyAuthorizer::without_ready_handle("test_device_id");
 
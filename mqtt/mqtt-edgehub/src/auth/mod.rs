@@ -1,8 +1,8 @@
 mod authentication;
 mod authorization;
 
-pub use authentication::{EdgeHubAuthenticator, LocalAuthenticator};
+pub use authentication::{EdgeHubAuthenticator, LocalAuthenticator, OfflineAuthCache};
 pub use authorization::{
-    AuthorizerUpdate, EdgeHubAuthorizer, IdentityUpdate, LocalAuthorizer, PolicyAuthorizer,
-    PolicyUpdate,
+    AuthorizerUpdate, EdgeHubAuthorizer, GroupsUpdate, IdentityUpdate, LocalAuthorizer,
+    PolicyAuthorizer, PolicyUpdate,
 };
@@ -3,7 +3,10 @@ use tracing::info;
 use mqtt3::ReceivedPublication;
 use mqtt_broker::{BrokerHandle, Message, SystemEvent};
 
-use crate::{auth::AuthorizerUpdate, command::Command};
+use crate::{
+    auth::{AuthorizerUpdate, IdentityUpdate, OfflineAuthCache},
+    command::Command,
+};
 
 /// `AuthorizedIdentitiesCommand` is executed when `EdgeHub` sends a special packet
 /// to notify the broker that the list of authorized `IoTHub` identities has changed.
@@ -12,17 +15,25 @@ use crate::{auth::AuthorizerUpdate, command::Command};
 /// - nested edge hierarchy changed.
 ///
 /// If the list of identities has changed, we need to update authorizer
-/// in the broker, that's what this command is doing.
+/// in the broker, that's what this command is doing. Identities which are
+/// not authorized anymore are also removed from offline authentication cache.
 pub struct AuthorizedIdentitiesCommand {
     broker_handle: BrokerHandle,
+    offline_auth: Option<OfflineAuthCache>,
 }
 
 impl AuthorizedIdentitiesCommand {
     pub fn new(broker_handle: &BrokerHandle) -> Self {
         Self {
             broker_handle: broker_handle.clone(),
+            offline_auth: None,
         }
     }
+
+    pub fn with_offline_cache(mut self, cache: OfflineAuthCache) -> Self {
+        self.offline_auth = Some(cache);
+        self
+    }
 }
 
 impl Command for AuthorizedIdentitiesCommand {
@@ -37,6 +48,11 @@ impl Command for AuthorizedIdentitiesCommand {
         let update: AuthorizerUpdate = serde_json::from_slice(&publication.payload)
             .map_err(Error::ParseAuthorizedIdentities)?;
 
+        if let Some(offline_auth) = &self.offline_auth {
+            offline_auth
+                .retain_identities(update.identities().iter().map(IdentityUpdate::identity));
+        }
+
         let message = Message::System(SystemEvent::AuthorizationUpdate(Box::new(update)));
         self.broker_handle
             .send(message)
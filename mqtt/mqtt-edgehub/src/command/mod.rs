@@ -3,12 +3,14 @@ mod bridge_update;
 mod disconnect;
 mod handler;
 mod policy_update;
+mod remotes_update;
 
 pub use authorized_identities::AuthorizedIdentitiesCommand;
 pub use bridge_update::BridgeUpdateCommand;
 pub use disconnect::DisconnectCommand;
 pub use handler::{CommandHandler, CommandHandlerError, ShutdownHandle};
 pub use policy_update::PolicyUpdateCommand;
+pub use remotes_update::RemotesUpdateCommand;
 
 use std::error::Error as StdError;
 
@@ -0,0 +1,49 @@
+use tracing::info;
+
+use mqtt3::ReceivedPublication;
+
+use mqtt_bridge::{BridgeControllerHandle, RemotesUpdate};
+
+use crate::command::Command;
+
+const REMOTES_UPDATE_TOPIC: &str = "$internal/bridge/remotes";
+
+/// `RemotesUpdateCommand` is executed when `EdgeHub` sends a special packet
+/// with a complete list of remote bridges the broker should run.
+pub struct RemotesUpdateCommand {
+    controller_handle: BridgeControllerHandle,
+}
+
+impl RemotesUpdateCommand {
+    pub fn new(controller_handle: BridgeControllerHandle) -> Self {
+        Self { controller_handle }
+    }
+}
+
+impl Command for RemotesUpdateCommand {
+    type Error = Error;
+
+    fn topic(&self) -> &str {
+        REMOTES_UPDATE_TOPIC
+    }
+
+    fn handle(&mut self, publication: &ReceivedPublication) -> Result<(), Self::Error> {
+        info!("received remote bridges update from EdgeHub.");
+        let update: RemotesUpdate =
+            serde_json::from_slice(&publication.payload).map_err(Error::ParseRemotesUpdate)?;
+
+        self.controller_handle
+            .send_remotes_update(update)
+            .map_err(Error::SendRemotesUpdate)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to parse remote bridges update from message payload: {0}")]
+    ParseRemotesUpdate(#[source] serde_json::Error),
+
+    #[error("failed while sending remote bridges update to bridge controller: {0}")]
+    SendRemotesUpdate(#[source] mqtt_bridge::Error),
+}
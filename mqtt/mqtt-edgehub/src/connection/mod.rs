@@ -6,7 +6,7 @@ use translate::TranslateTopic;
 
 use std::{collections::HashMap, sync::Arc};
 
-use mqtt_broker::{BrokerHandle, ClientId, MakeMqttPacketProcessor, MakePacketProcessor};
+use mqtt_broker::{Auth, BrokerHandle, ClientId, MakeMqttPacketProcessor, MakePacketProcessor};
 use parking_lot::Mutex;
 
 /// Creates a wrapper around default MQTT packet processor.
@@ -39,10 +39,14 @@ where
 
     type IncomingProcessor = TranslateTopic<PublicationDelivery<P::IncomingProcessor>>;
 
-    fn make(&self, client_id: &ClientId) -> (Self::OutgoingProcessor, Self::IncomingProcessor) {
+    fn make(
+        &self,
+        client_id: &ClientId,
+        auth: &Auth,
+    ) -> (Self::OutgoingProcessor, Self::IncomingProcessor) {
         let waited_to_be_acked = Arc::new(Mutex::new(HashMap::new()));
 
-        let (outgoing_inner, incoming_inner) = self.inner.make(client_id);
+        let (outgoing_inner, incoming_inner) = self.inner.make(client_id, auth);
 
         let inner = PublicationDelivery::new(
             self.broker_handle.clone(),
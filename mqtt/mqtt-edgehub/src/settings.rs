@@ -1,6 +1,7 @@
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 use config::{Config, ConfigError, Environment, File, FileFormat, Source, Value};
@@ -328,6 +329,8 @@ impl CertificateConfig {
 pub struct AuthConfig {
     port: u16,
     base_url: String,
+    #[serde(default = "Enable::disabled")]
+    offline: Enable<OfflineAuthConfig>,
 }
 
 impl AuthConfig {
@@ -335,12 +338,50 @@ impl AuthConfig {
         Self {
             port,
             base_url: base_url.into(),
+            offline: Enable::disabled(),
         }
     }
 
+    pub fn with_offline(mut self, offline: OfflineAuthConfig) -> Self {
+        self.offline = Enable::enabled(offline);
+        self
+    }
+
     pub fn url(&self) -> String {
         format!("http://localhost:{}{}", self.port, self.base_url)
     }
+
+    pub fn offline(&self) -> Option<&OfflineAuthConfig> {
+        self.offline.as_inner()
+    }
+}
+
+/// Offline authentication cache settings. When enabled, clients which have
+/// successfully authenticated within the grace period are able to reconnect
+/// while `EdgeHub` core is unavailable.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct OfflineAuthConfig {
+    path: PathBuf,
+    #[serde(with = "humantime_serde")]
+    grace_period: Duration,
+}
+
+impl OfflineAuthConfig {
+    pub fn new(path: impl Into<PathBuf>, grace_period: Duration) -> Self {
+        Self {
+            path: path.into(),
+            grace_period,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn grace_period(&self) -> Duration {
+        self.grace_period
+    }
 }
 
 #[cfg(test)]
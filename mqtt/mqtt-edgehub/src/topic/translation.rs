@@ -5,12 +5,17 @@
 //! This does not work well in he broker message since edgehub core doesn't hold the connection to the device/module.
 //!
 //! This translation allows edgehub core to subscribe to the new topics that include client id to identify requests
+//!
+//! Translations are defined as declarative tables of topic rules (see `mqtt_broker::topic_rules`),
+//! so a new `IoTHub` topic family only needs a new entry in the corresponding table.
 use lazy_static::lazy_static;
-use regex::Regex;
 use tracing::debug;
 
 use mqtt3::proto;
-use mqtt_broker::ClientId;
+use mqtt_broker::{
+    topic_rules::{TopicRule, TopicRuleError, TopicRules},
+    ClientId,
+};
 
 lazy_static! {
     static ref TRANSLATE_D2C: TranslateD2C =
@@ -59,216 +64,191 @@ pub fn translate_outgoing_publish(publish: &mut proto::Publish) {
     }
 }
 
-const DEVICE_ID: &str = r"(?P<device_id>[^/]+)";
-
-const MODULE_ID: &str = r"(?P<module_id>[^/]+)";
+const CLIENT_ID: &str = "client_id";
 
-const DEVICE_OR_MODULE_ID: &str = r"(?P<device_id>[^/]+)(/(?P<module_id>[^/]+))?";
-
-macro_rules! translate_d2c {
-    ($(
-        $translate_name:ident {
-            to_internal { $new_from:expr, $new_to:expr }
-        }
-    ),*) => {
-        struct TranslateD2C {
-            $(
-                $translate_name: Regex,
-            )*
-        }
-
-        impl TranslateD2C {
-            fn new() -> Result<Self, regex::Error> {
-                Ok(Self {
-                    $(
-                        $translate_name: Regex::new(&$new_from)?,
-                    )*
-                })
-            }
-
-            fn to_internal(&self, topic: &str, client_id: &ClientId) -> Option<String> {
-                if topic.starts_with("$iothub") || topic.starts_with("devices") {
-                    $(
-                        if let Some(captures) = self.$translate_name.captures(topic) {
-                            return Some($new_to(captures, client_id).into());
-                        }
-                    )*
-                }
-
-                None
-            }
-        }
-    };
+/// Device-to-cloud translations of topics devices and modules publish to.
+const D2C_TO_INTERNAL: &[TopicRule<'static>] = &[
+    // Message Translation
+    TopicRule::new(
+        "module_send_message",
+        "devices/{device_id}/modules/{module_id}/messages/events(?P<path>.*)",
+        "$edgehub/{device_id}/{module_id}/messages/events{path}",
+    ),
+    // note this may have to be split into 2 patterns for device and modules, depending on how client_id encodes device and module id
+    TopicRule::new(
+        "device_send_message",
+        "devices/{device_id}/messages/events(?P<path>.*)",
+        "$edgehub/{device_id}/messages/events{path}",
+    ),
+    // Twin Translation
+    TopicRule::new(
+        "twin_send_update_to_hub",
+        r"\$iothub/twin/PATCH/properties/reported/(?P<params>.*)",
+        "$edgehub/{client_id}/twin/reported/{params}",
+    ),
+    TopicRule::new(
+        "twin_get_from_hub",
+        r"\$iothub/twin/GET/(?P<params>.*)",
+        "$edgehub/{client_id}/twin/get/{params}",
+    ),
+    // Direct Methods
+    TopicRule::new(
+        "direct_method_response",
+        r"\$iothub/methods/res/(?P<status>.*)",
+        "$edgehub/{client_id}/methods/res/{status}",
+    ),
+    // File Upload
+    TopicRule::new(
+        "file_upload_sas_uri_request",
+        r"\$iothub/files/sasuri/(?P<params>.*)",
+        "$edgehub/{client_id}/files/sasuri/{params}",
+    ),
+    TopicRule::new(
+        "file_upload_notification",
+        r"\$iothub/files/notifications/(?P<params>.*)",
+        "$edgehub/{client_id}/files/notifications/{params}",
+    ),
+];
+
+/// Cloud-to-device translations of topic filters devices and modules subscribe to.
+const C2D_TO_INTERNAL: &[TopicRule<'static>] = &[
+    // Message Translation
+    TopicRule::new(
+        "module_c2d_message",
+        "devices/{device_id}/modules/{module_id}/messages/devicebound(?P<path>.*)",
+        "$edgehub/{device_id}/{module_id}/messages/c2d/post{path}",
+    ),
+    TopicRule::new(
+        "c2d_message",
+        "devices/{device_id}/messages/devicebound(?P<path>.*)",
+        "$edgehub/{device_id}/messages/c2d/post{path}",
+    ),
+    // Twin Translation
+    TopicRule::new(
+        "twin_receive_update_from_hub",
+        r"\$iothub/twin/PATCH/properties/desired/(?P<params>.*)",
+        "$edgehub/{client_id}/twin/desired/{params}",
+    ),
+    TopicRule::new(
+        "twin_response_from_hub",
+        r"\$iothub/twin/res/(?P<params>.*)",
+        "$edgehub/{client_id}/twin/res/{params}",
+    ),
+    // Direct Methods
+    TopicRule::new(
+        "receive_direct_method_request",
+        r"\$iothub/methods/POST/(?P<params>.*)",
+        "$edgehub/{client_id}/methods/post/{params}",
+    ),
+    // File Upload
+    TopicRule::new(
+        "file_upload_response_from_hub",
+        r"\$iothub/files/res/(?P<params>.*)",
+        "$edgehub/{client_id}/files/res/{params}",
+    ),
+    // Module-to-Module inputs
+    TopicRule::new(
+        "module_to_module_inputs",
+        "devices/{device_id}/modules/{module_id}/(#|inputs/.*)",
+        "$edgehub/{device_id}/{module_id}/+/inputs/#",
+    ),
+];
+
+/// Cloud-to-device translations of topics edgehub core publishes to.
+const C2D_TO_EXTERNAL: &[TopicRule<'static>] = &[
+    // Message Translation
+    TopicRule::new(
+        "module_c2d_message",
+        r"\$edgehub/{device_id}/{module_id}/messages/c2d/post(?P<path>.*)",
+        "devices/{device_id}/modules/{module_id}/messages/devicebound{path}",
+    ),
+    TopicRule::new(
+        "c2d_message",
+        r"\$edgehub/{device_id}/messages/c2d/post(?P<path>.*)",
+        "devices/{device_id}/messages/devicebound{path}",
+    ),
+    // Twin Translation
+    TopicRule::new(
+        "twin_receive_update_from_hub",
+        r"\$edgehub/{device_or_module_id}/twin/desired/(?P<params>.*)",
+        "$iothub/twin/PATCH/properties/desired/{params}",
+    ),
+    TopicRule::new(
+        "twin_response_from_hub",
+        r"\$edgehub/{device_or_module_id}/twin/res/(?P<params>.*)",
+        "$iothub/twin/res/{params}",
+    ),
+    // Direct Methods
+    TopicRule::new(
+        "receive_direct_method_request",
+        r"\$edgehub/{device_or_module_id}/methods/post/(?P<params>.*)",
+        "$iothub/methods/POST/{params}",
+    ),
+    // File Upload
+    TopicRule::new(
+        "file_upload_response_from_hub",
+        r"\$edgehub/{device_or_module_id}/files/res/(?P<params>.*)",
+        "$iothub/files/res/{params}",
+    ),
+    // Module-to-Module inputs
+    TopicRule::new(
+        "module_to_module_inputs",
+        r"\$edgehub/{device_id}/{module_id}/[^/]+/inputs/(?P<path>.+)",
+        "devices/{device_id}/modules/{module_id}/inputs/{path}",
+    ),
+];
+
+struct TranslateD2C {
+    to_internal: TopicRules,
 }
 
-macro_rules! translate_c2d {
-    ($(
-        $translate_name:ident {
-            to_internal { $new_from:expr, $new_to:expr },
-            to_external { $old_from:expr, $old_to:expr }
-        }
-    ),*) => {
-        struct TranslateC2D {
-            to_internal: TranslateRegex,
-            to_external: TranslateRegex,
-        }
+impl TranslateD2C {
+    fn new() -> Result<Self, TopicRuleError> {
+        Ok(Self {
+            to_internal: TopicRules::new(D2C_TO_INTERNAL.iter().copied(), &[CLIENT_ID])?,
+        })
+    }
 
-        struct TranslateRegex {
-            $(
-                $translate_name: Regex,
-            )*
+    fn to_internal(&self, topic: &str, client_id: &ClientId) -> Option<String> {
+        if topic.starts_with("$iothub") || topic.starts_with("devices") {
+            return self
+                .to_internal
+                .rewrite(topic, &[(CLIENT_ID, client_id.as_str())]);
         }
 
-        impl TranslateC2D {
-            fn new() -> Result<Self, regex::Error> {
-                Ok(Self {
-                    to_internal: TranslateRegex {
-                        $(
-                            $translate_name: Regex::new(&$new_from)?,
-                        )*
-                    },
-                    to_external: TranslateRegex {
-                        $(
-                            $translate_name: Regex::new(&$old_from)?,
-                        )*
-                    },
-                })
-            }
-
-            fn to_internal(&self, topic: &str, client_id: &ClientId) -> Option<String> {
-                if topic.starts_with("$iothub/") || topic.starts_with("devices/") {
-                    $(
-                        if let Some(captures) = self.to_internal.$translate_name.captures(topic) {
-                            return Some($new_to(captures, client_id).into());
-                        }
-                    )*
-                }
-
-                None
-            }
-
-            fn to_external(&self, topic: &str) -> Option<String> {
-                if topic.starts_with("$edgehub/") {
-                    $(
-                        if let Some(captures) = self.to_external.$translate_name.captures(topic) {
-                            return Some($old_to(captures).into());
-                        }
-                    )*
-                }
-
-                None
-            }
-        }
-    };
+        None
+    }
 }
 
-translate_d2c! {
-    // Message Translation
-    module_send_message {
-        to_internal {
-            format!("devices/{}/modules/{}/messages/events(?P<path>.*)", DEVICE_ID, MODULE_ID),
-            {|captures: regex::Captures<'_>, _| format!("$edgehub/{}/{}/messages/events{}", &captures["device_id"], &captures["module_id"], &captures["path"])}
-        }
-    },
-
-    device_send_message { // note this may have to be split into 2 patterns for device and modules, depending on how client_id encodes device and module id
-        to_internal {
-            format!("devices/{}/messages/events(?P<path>.*)", DEVICE_ID),
-            {|captures: regex::Captures<'_>, _| format!("$edgehub/{}/messages/events{}", &captures["device_id"], &captures["path"])}
-        }
-    },
-
-    // Twin Translation
-    twin_send_update_to_hub {
-        to_internal {
-            "\\$iothub/twin/PATCH/properties/reported/(?P<params>.*)",
-            {|captures: regex::Captures<'_>, client_id| format!("$edgehub/{}/twin/reported/{}", client_id, &captures["params"])}
-        }
-    },
-    twin_get_from_hub {
-        to_internal {
-            "\\$iothub/twin/GET/(?P<params>.*)",
-            {|captures: regex::Captures<'_>, client_id| format!("$edgehub/{}/twin/get/{}", client_id, &captures["params"])}
-        }
-    },
+struct TranslateC2D {
+    to_internal: TopicRules,
+    to_external: TopicRules,
+}
 
-    // Direct Methods
-    direct_method_response {
-        to_internal {
-            "\\$iothub/methods/res/(?P<status>.*)",
-            {|captures: regex::Captures<'_>, client_id| format!("$edgehub/{}/methods/res/{}", client_id, &captures["status"])}
-        }
+impl TranslateC2D {
+    fn new() -> Result<Self, TopicRuleError> {
+        Ok(Self {
+            to_internal: TopicRules::new(C2D_TO_INTERNAL.iter().copied(), &[CLIENT_ID])?,
+            to_external: TopicRules::new(C2D_TO_EXTERNAL.iter().copied(), &[])?,
+        })
     }
-}
 
-translate_c2d! {
-    // Message Translation
-    module_c2d_message {
-        to_internal {
-            format!("devices/{}/modules/{}/messages/devicebound(?P<path>.*)", DEVICE_ID, MODULE_ID),
-            {|captures: regex::Captures<'_>, _| format!("$edgehub/{}/{}/messages/c2d/post{}", &captures["device_id"], &captures["module_id"], &captures["path"])}
-        },
-        to_external {
-            format!("\\$edgehub/{}/{}/messages/c2d/post(?P<path>.*)", DEVICE_ID, MODULE_ID),
-            {|captures: regex::Captures<'_>| format!("devices/{}/modules/{}/messages/devicebound{}", &captures["device_id"], &captures["module_id"], &captures["path"])}
-        }
-    },
-
-    c2d_message {
-        to_internal {
-            format!("devices/{}/messages/devicebound(?P<path>.*)", DEVICE_ID),
-            {|captures: regex::Captures<'_>, _| format!("$edgehub/{}/messages/c2d/post{}", &captures["device_id"], &captures["path"])}
-        },
-        to_external {
-            format!("\\$edgehub/{}/messages/c2d/post(?P<path>.*)", DEVICE_ID),
-            {|captures: regex::Captures<'_>| format!("devices/{}/messages/devicebound{}", &captures["device_id"], &captures["path"])}
+    fn to_internal(&self, topic: &str, client_id: &ClientId) -> Option<String> {
+        if topic.starts_with("$iothub/") || topic.starts_with("devices/") {
+            return self
+                .to_internal
+                .rewrite(topic, &[(CLIENT_ID, client_id.as_str())]);
         }
-    },
 
-    // Twin Translation
-    twin_receive_update_from_hub {
-        to_internal {
-            "\\$iothub/twin/PATCH/properties/desired/(?P<params>.*)",
-            {|captures: regex::Captures<'_>, client_id| format!("$edgehub/{}/twin/desired/{}", client_id, &captures["params"])}
-        },
-        to_external {
-            format!("\\$edgehub/{}/twin/desired/(?P<params>.*)", DEVICE_OR_MODULE_ID),
-            {|captures: regex::Captures<'_>| format!("$iothub/twin/PATCH/properties/desired/{}", &captures["params"])}
-        }
-    },
-    twin_response_from_hub {
-        to_internal {
-            "\\$iothub/twin/res/(?P<params>.*)",
-            {|captures: regex::Captures<'_>, client_id| format!("$edgehub/{}/twin/res/{}", client_id, &captures["params"])}
-        },
-        to_external {
-            format!("\\$edgehub/{}/twin/res/(?P<params>.*)", DEVICE_OR_MODULE_ID),
-            {|captures: regex::Captures<'_>| format!("$iothub/twin/res/{}", &captures["params"])}
-        }
-    },
+        None
+    }
 
-    // Direct Methods
-    receive_direct_method_request {
-        to_internal {
-            "\\$iothub/methods/POST/(?P<params>.*)",
-            {|captures: regex::Captures<'_>, client_id| format!("$edgehub/{}/methods/post/{}", client_id, &captures["params"])}
-        },
-        to_external {
-            format!("\\$edgehub/{}/methods/post/(?P<params>.*)", DEVICE_OR_MODULE_ID),
-            {|captures: regex::Captures<'_>| format!("$iothub/methods/POST/{}", &captures["params"])}
+    fn to_external(&self, topic: &str) -> Option<String> {
+        if topic.starts_with("$edgehub/") {
+            return self.to_external.rewrite(topic, &[]);
         }
-    },
 
-    // Module-to-Module inputs
-    module_to_module_inputs {
-        to_internal {
-            format!("devices/{}/modules/{}/(#|inputs/.*)", DEVICE_ID, MODULE_ID),
-            {|captures: regex::Captures<'_>, _| format!("$edgehub/{}/{}/+/inputs/#", &captures["device_id"], &captures["module_id"])}
-        },
-        to_external {
-            format!("\\$edgehub/{}/{}/[^/]+/inputs/(?P<path>.+)", DEVICE_ID, MODULE_ID),
-            {|captures: regex::Captures<'_>| format!("devices/{}/modules/{}/inputs/{}", &captures["device_id"], &captures["module_id"], &captures["path"])}
-        }
+        None
     }
 }
 
@@ -540,4 +520,155 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn it_translates_file_upload() {
+        let d2c = TranslateD2C::new().unwrap();
+        let c2d = TranslateC2D::new().unwrap();
+
+        let client_id = "device_1/module_a".into();
+
+        // File Upload d2c
+        assert_eq!(
+            d2c.to_internal("$iothub/files/sasuri/?rid=6", &client_id),
+            Some("$edgehub/device_1/module_a/files/sasuri/?rid=6".to_owned())
+        );
+        assert_eq!(
+            d2c.to_internal("$iothub/files/notifications/?rid=7", &client_id),
+            Some("$edgehub/device_1/module_a/files/notifications/?rid=7".to_owned())
+        );
+
+        // File Upload c2d
+        assert_eq!(
+            c2d.to_internal("$iothub/files/res/#", &client_id),
+            Some("$edgehub/device_1/module_a/files/res/#".to_owned())
+        );
+        assert_eq!(
+            c2d.to_external("$edgehub/device_1/module_a/files/res/200/?rid=6"),
+            Some("$iothub/files/res/200/?rid=6".to_owned())
+        );
+        assert_eq!(
+            c2d.to_external("$edgehub/device_1/files/res/200/?rid=6"),
+            Some("$iothub/files/res/200/?rid=6".to_owned())
+        );
+    }
+
+    #[cfg(feature = "proptest")]
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::super::{TranslateC2D, TranslateD2C};
+
+        fn arb_id() -> impl Strategy<Value = String> {
+            "[a-zA-Z0-9_-]{1,16}"
+        }
+
+        fn arb_path() -> impl Strategy<Value = String> {
+            "(/[a-zA-Z0-9_%.=&-]{1,16}){0,3}"
+        }
+
+        fn arb_params() -> impl Strategy<Value = String> {
+            "[a-zA-Z0-9_%.=&?/-]{0,32}"
+        }
+
+        proptest! {
+            #[test]
+            fn c2d_messages_round_trip(
+                device_id in arb_id(),
+                module_id in arb_id(),
+                path in arb_path(),
+            ) {
+                let c2d = TranslateC2D::new().unwrap();
+                let client_id = format!("{}/{}", device_id, module_id).into();
+
+                for topic in &[
+                    format!("devices/{}/messages/devicebound{}", device_id, path),
+                    format!("devices/{}/modules/{}/messages/devicebound{}", device_id, module_id, path),
+                ] {
+                    let internal = c2d.to_internal(topic, &client_id);
+                    prop_assert!(internal.is_some());
+
+                    let external = internal.and_then(|internal| c2d.to_external(&internal));
+                    prop_assert_eq!(external.as_ref(), Some(topic));
+                }
+            }
+
+            #[test]
+            fn c2d_iothub_topics_round_trip(
+                device_id in arb_id(),
+                module_id in prop::option::of(arb_id()),
+                params in arb_params(),
+            ) {
+                let c2d = TranslateC2D::new().unwrap();
+                let client_id = match module_id {
+                    Some(module_id) => format!("{}/{}", device_id, module_id),
+                    None => device_id,
+                }
+                .into();
+
+                for prefix in &[
+                    "$iothub/twin/PATCH/properties/desired/",
+                    "$iothub/twin/res/",
+                    "$iothub/methods/POST/",
+                    "$iothub/files/res/",
+                ] {
+                    let topic = format!("{}{}", prefix, params);
+
+                    let internal = c2d.to_internal(&topic, &client_id);
+                    prop_assert!(internal.is_some());
+
+                    let external = internal.and_then(|internal| c2d.to_external(&internal));
+                    prop_assert_eq!(external, Some(topic));
+                }
+            }
+
+            #[test]
+            fn d2c_iothub_topics_include_client_id(
+                device_id in arb_id(),
+                module_id in prop::option::of(arb_id()),
+                params in arb_params(),
+            ) {
+                let d2c = TranslateD2C::new().unwrap();
+                let client_id = match module_id {
+                    Some(module_id) => format!("{}/{}", device_id, module_id),
+                    None => device_id,
+                };
+
+                for (external, internal) in &[
+                    ("$iothub/twin/PATCH/properties/reported/", "twin/reported/"),
+                    ("$iothub/twin/GET/", "twin/get/"),
+                    ("$iothub/methods/res/", "methods/res/"),
+                    ("$iothub/files/sasuri/", "files/sasuri/"),
+                    ("$iothub/files/notifications/", "files/notifications/"),
+                ] {
+                    let topic = format!("{}{}", external, params);
+                    let translated = d2c.to_internal(&topic, &client_id.clone().into());
+                    prop_assert_eq!(
+                        translated,
+                        Some(format!("$edgehub/{}/{}{}", client_id, internal, params))
+                    );
+                }
+            }
+
+            #[test]
+            fn d2c_module_topics_translated(
+                device_id in arb_id(),
+                module_id in arb_id(),
+                path in arb_path(),
+            ) {
+                let d2c = TranslateD2C::new().unwrap();
+                let client_id = format!("{}/{}", device_id, module_id).into();
+
+                let events = format!("devices/{}/modules/{}/messages/events{}", device_id, module_id, path);
+                let internal = d2c.to_internal(&events, &client_id);
+                prop_assert_eq!(
+                    internal.as_ref(),
+                    Some(&format!("$edgehub/{}/{}/messages/events{}", device_id, module_id, path))
+                );
+
+                // internal topics are never translated again
+                prop_assert_eq!(internal.and_then(|internal| d2c.to_internal(&internal, &client_id)), None);
+            }
+        }
+    }
 }
@@ -22,9 +22,12 @@ async fn it_sends_delivery_confirmation_for_m2m_messages() {
     let make_server = |addr| {
         let broker_handle = broker.handle();
 
-        let mut server = Server::from_broker(broker).with_packet_processor(
-            MakeEdgeHubPacketProcessor::new(broker_handle, MakeMqttPacketProcessor),
-        );
+        let mut server = Server::from_broker(broker)
+            .with_packet_processor(MakeEdgeHubPacketProcessor::new(
+                broker_handle,
+                MakeMqttPacketProcessor,
+            ))
+            .unwrap();
 
         let authenticator = DummyAuthenticator::anonymous();
         server.with_tcp(&addr, authenticator, None).unwrap();
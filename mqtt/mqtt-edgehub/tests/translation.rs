@@ -333,9 +333,12 @@ where
     let make_server = |addr| {
         let broker_handle = broker.handle();
 
-        let mut server = Server::from_broker(broker).with_packet_processor(
-            MakeEdgeHubPacketProcessor::new(broker_handle, MakeMqttPacketProcessor),
-        );
+        let mut server = Server::from_broker(broker)
+            .with_packet_processor(MakeEdgeHubPacketProcessor::new(
+                broker_handle,
+                MakeMqttPacketProcessor,
+            ))
+            .unwrap();
 
         let authenticator = DummyAuthenticator::anonymous();
         server.with_tcp(&addr, authenticator, None).unwrap();
@@ -0,0 +1,119 @@
+//! `evaluate_policy` benches build a policy with a given number of variable rules
+//! (rules with `{{..}}` variables in identities or resources) and measure the time
+//! to evaluate publish requests against it.
+//!
+//! Scenarios supported:
+//! * requests from the same client, so variable rules resolved for it are reused
+//! * requests from distinct clients, so variable rules are resolved for each client
+//!
+//! How to run benches
+//! ```bash
+//! cd mqtt
+//! cargo bench --bench evaluate_policy \
+//!   --features="benches" \
+//!   --manifest-path mqtt-policy/Cargo.toml
+//! ```
+//! or
+//! ```bash
+//! cd mqtt/mqtt-policy
+//! cargo bench --bench evaluate_policy --features="benches"
+//! ```
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use serde_json::json;
+
+use mqtt3::proto;
+use mqtt_broker::{
+    auth::{Activity, Operation},
+    ClientInfo,
+};
+use mqtt_policy::{MqttSubstituter, MqttTopicFilterMatcher, MqttValidator};
+use policy::{Decision, Policy, PolicyBuilder, Request};
+
+const NUM_CLIENTS: usize = 100;
+
+fn build_policy(num_rules: usize) -> Policy<MqttTopicFilterMatcher, MqttSubstituter> {
+    let statements = (0..num_rules)
+        .map(|i| {
+            json!({
+                "effect": if i % 2 == 0 { "allow" } else { "deny" },
+                "identities": ["{{iot:identity}}"],
+                "operations": ["mqtt:publish"],
+                "resources": [format!("devices/{{{{iot:device_id}}}}/rules/{}/#", i)]
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let definition = json!({
+        "schemaVersion": "2020-10-30",
+        "statements": statements
+    });
+
+    PolicyBuilder::from_json(definition.to_string())
+        .with_validator(MqttValidator)
+        .with_matcher(MqttTopicFilterMatcher)
+        .with_substituter(MqttSubstituter::new("edge_device"))
+        .with_default_decision(Decision::Denied)
+        .build()
+        .expect("valid policy")
+}
+
+fn create_request(client: usize, num_rules: usize) -> Request<Activity> {
+    let identity = format!("myhub.azure-devices.net/device_{}", client);
+    let topic = format!(
+        "devices/device_{}/rules/{}/telemetry",
+        client,
+        num_rules - 1
+    );
+
+    let activity = Activity::new(
+        ClientInfo::new(
+            format!("device_{}", client),
+            "127.0.0.1:80".parse().unwrap(),
+            identity.as_str(),
+        ),
+        Operation::new_publish(proto::Publish {
+            packet_identifier_dup_qos: proto::PacketIdentifierDupQoS::AtMostOnce,
+            retain: false,
+            topic_name: topic.clone(),
+            payload: Bytes::new(),
+        }),
+    );
+
+    Request::with_context(identity, "mqtt:publish", topic, activity).expect("valid request")
+}
+
+fn evaluate_variable_rules(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evaluate_variable_rules");
+
+    for num_rules in &[10, 100, 1000] {
+        let policy = build_policy(*num_rules);
+        let requests = (0..NUM_CLIENTS)
+            .map(|client| create_request(client, *num_rules))
+            .collect::<Vec<_>>();
+
+        group.bench_with_input(
+            BenchmarkId::new("same_client", num_rules),
+            &requests[0],
+            |b, request| b.iter(|| policy.evaluate(request).expect("evaluated")),
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("distinct_clients", num_rules),
+            &requests,
+            |b, requests| {
+                b.iter(|| {
+                    for request in requests {
+                        policy.evaluate(request).expect("evaluated");
+                    }
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, evaluate_variable_rules);
+criterion_main!(benches);
@@ -0,0 +1,233 @@
+//! Dry-run evaluation of an MQTT broker policy definition.
+//!
+//! Loads a policy definition and evaluates a batch of requests against it,
+//! printing the decision for every request along with the statement which
+//! produced it. If a request specifies an expected decision and the policy
+//! decides otherwise, the tool exits with an error, so it can be used to test
+//! policies in CI. Shadowed, redundant and conflicting statements found in
+//! the policy definition are reported as warnings.
+//!
+//! Requests file contains a JSON array of requests:
+//! ```json
+//! [
+//!     {
+//!         "identity": "contoso.azure-devices.net/sensor_a",
+//!         "operation": "mqtt:publish",
+//!         "resource": "events/alerts",
+//!         "qos": 1,
+//!         "expected": "allowed"
+//!     }
+//! ]
+//! ```
+//! Only `identity` and `operation` are required. Client id defaults to the
+//! identity and peer address defaults to `127.0.0.1:0`.
+//!
+//! Note that only customer policy is evaluated, requests to system topics
+//! handled by other edgehub authorizers are not taken into account.
+#![deny(rust_2018_idioms, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+#![allow(clippy::module_name_repetitions)]
+
+use std::{fs, net::SocketAddr, path::Path, process};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
+use clap::{crate_version, App, Arg};
+use serde::Deserialize;
+
+use mqtt3::proto;
+use mqtt_broker::{
+    auth::{Activity, Operation},
+    ClientInfo,
+};
+use mqtt_policy::{MqttSubstituter, MqttTopicFilterMatcher, MqttValidator};
+use policy::{Decision, Policy, PolicyBuilder, PolicyDefinition, Request};
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{:?}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let matches = create_app().get_matches();
+
+    let policy = load_policy(
+        matches.value_of("policy").expect("policy is required"),
+        matches
+            .value_of("device-id")
+            .expect("device-id has default"),
+    )?;
+    let requests = load_requests(matches.value_of("requests").expect("requests is required"))?;
+
+    let mut failed = 0;
+    for request in &requests {
+        let explanation = policy
+            .explain(&request.to_policy_request()?)
+            .map_err(|e| anyhow!("unable to evaluate request: {}", e))?;
+
+        let status = match request.expected {
+            Some(expected) if expected == explanation.decision() => "PASS",
+            Some(_) => {
+                failed += 1;
+                "FAIL"
+            }
+            None => "----",
+        };
+
+        println!(
+            "{} {} {} {}: {}",
+            status, request.identity, request.operation, request.resource, explanation
+        );
+    }
+
+    if failed > 0 {
+        bail!(
+            "{} of {} requests did not match expected decision",
+            failed,
+            requests.len()
+        );
+    }
+
+    Ok(())
+}
+
+fn create_app() -> App<'static, 'static> {
+    App::new("policy-eval")
+        .version(crate_version!())
+        .about("Evaluates a batch of requests against MQTT broker policy definition")
+        .arg(
+            Arg::with_name("policy")
+                .short("p")
+                .long("policy")
+                .value_name("FILE")
+                .help("Policy definition JSON file")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("requests")
+                .short("r")
+                .long("requests")
+                .value_name("FILE")
+                .help("JSON file with a list of requests to evaluate")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("device-id")
+                .short("d")
+                .long("device-id")
+                .value_name("DEVICE_ID")
+                .help("Edge device id substituted for {{iot:this_device_id}} variable")
+                .takes_value(true)
+                .default_value("edge_device"),
+        )
+}
+
+fn load_policy(
+    path: impl AsRef<Path>,
+    device_id: &str,
+) -> Result<Policy<MqttTopicFilterMatcher, MqttSubstituter>> {
+    let path = path.as_ref();
+    let definition = fs::read_to_string(path)
+        .with_context(|| format!("unable to read policy definition {}", path.display()))?;
+
+    if let Ok(definition) = PolicyDefinition::from_json(&definition) {
+        for finding in mqtt_policy::policy_analyzer().analyze(&definition) {
+            eprintln!("WARN {}", finding);
+        }
+    }
+
+    let policy = PolicyBuilder::from_json(definition)
+        .with_validator(MqttValidator)
+        .with_matcher(MqttTopicFilterMatcher)
+        .with_substituter(MqttSubstituter::new(device_id))
+        .with_default_decision(Decision::Denied)
+        .build()
+        .map_err(|e| anyhow!("unable to build policy from {}: {}", path.display(), e))?;
+
+    Ok(policy)
+}
+
+fn load_requests(path: impl AsRef<Path>) -> Result<Vec<TestRequest>> {
+    let path = path.as_ref();
+    let requests = fs::read_to_string(path)
+        .with_context(|| format!("unable to read requests {}", path.display()))?;
+
+    serde_json::from_str(&requests)
+        .with_context(|| format!("unable to parse requests {}", path.display()))
+}
+
+/// Represents a request to evaluate against the policy.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TestRequest {
+    identity: String,
+    operation: String,
+    #[serde(default)]
+    resource: String,
+    client_id: Option<String>,
+    peer_addr: Option<SocketAddr>,
+    #[serde(default)]
+    qos: u8,
+    #[serde(default)]
+    retain: bool,
+    #[serde(default)]
+    payload_size: usize,
+    expected: Option<Decision>,
+}
+
+impl TestRequest {
+    fn to_policy_request(&self) -> Result<Request<Activity>> {
+        let activity = Activity::new(
+            ClientInfo::new(
+                self.client_id.as_ref().unwrap_or(&self.identity).as_str(),
+                self.peer_addr.unwrap_or_else(|| ([127, 0, 0, 1], 0).into()),
+                self.identity.as_str(),
+            ),
+            self.to_operation()?,
+        );
+
+        let request = Request::with_context(
+            self.identity.as_str(),
+            self.operation.as_str(),
+            self.resource.as_str(),
+            activity,
+        )
+        .map_err(|e| anyhow!("invalid request: {}", e))?;
+        Ok(request)
+    }
+
+    fn to_operation(&self) -> Result<Operation> {
+        let operation = match self.operation.as_str() {
+            "mqtt:connect" => Operation::new_connect(),
+            "mqtt:publish" => {
+                let packet_identifier = proto::PacketIdentifier::new(1).expect("non-zero");
+                Operation::new_publish(proto::Publish {
+                    packet_identifier_dup_qos: match self.qos {
+                        0 => proto::PacketIdentifierDupQoS::AtMostOnce,
+                        1 => proto::PacketIdentifierDupQoS::AtLeastOnce(packet_identifier, false),
+                        2 => proto::PacketIdentifierDupQoS::ExactlyOnce(packet_identifier, false),
+                        qos => bail!("invalid qos {}", qos),
+                    },
+                    retain: self.retain,
+                    topic_name: self.resource.clone(),
+                    payload: Bytes::from(vec![0; self.payload_size]),
+                })
+            }
+            "mqtt:subscribe" => Operation::new_subscribe(proto::SubscribeTo {
+                topic_filter: self.resource.clone(),
+                qos: match self.qos {
+                    0 => proto::QoS::AtMostOnce,
+                    1 => proto::QoS::AtLeastOnce,
+                    2 => proto::QoS::ExactlyOnce,
+                    qos => bail!("invalid qos {}", qos),
+                },
+            }),
+            operation => bail!("unknown operation {}", operation),
+        };
+        Ok(operation)
+    }
+}
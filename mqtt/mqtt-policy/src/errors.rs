@@ -28,4 +28,10 @@ pub enum Error {
 
     #[error("Invalid resource variable name: {0}")]
     InvalidResourceVariable(String),
+
+    #[error("Invalid condition attribute name: {0}")]
+    InvalidConditionKey(String),
+
+    #[error("Condition operator {0} cannot be applied to attribute {1}")]
+    InvalidConditionOperator(String, String),
 }
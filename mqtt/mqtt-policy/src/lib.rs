@@ -19,12 +19,28 @@ pub use crate::matcher::MqttTopicFilterMatcher;
 pub use crate::substituter::MqttSubstituter;
 pub use crate::validator::MqttValidator;
 
+use policy::PolicyAnalyzer;
+
 pub(crate) const IDENTITY_VAR: &str = "{{iot:identity}}";
 pub(crate) const DEVICE_ID_VAR: &str = "{{iot:device_id}}";
 pub(crate) const MODULE_ID_VAR: &str = "{{iot:module_id}}";
 pub(crate) const CLIENT_ID_VAR: &str = "{{mqtt:client_id}}";
 pub(crate) const EDGEHUB_ID_VAR: &str = "{{iot:this_device_id}}";
 
+pub(crate) const IDENTITY_ATTR: &str = "iot:identity";
+pub(crate) const CLIENT_ID_ATTR: &str = "mqtt:client_id";
+pub(crate) const PEER_ADDR_ATTR: &str = "mqtt:peer_addr";
+pub(crate) const QOS_ATTR: &str = "mqtt:qos";
+pub(crate) const RETAIN_ATTR: &str = "mqtt:retain";
+pub(crate) const PAYLOAD_SIZE_ATTR: &str = "mqtt:payload_size";
+
+/// Creates a `PolicyAnalyzer` for MQTT broker policy definitions which
+/// compares resources as topic filters.
+pub fn policy_analyzer() -> PolicyAnalyzer<MqttTopicFilterMatcher> {
+    PolicyAnalyzer::new(MqttTopicFilterMatcher)
+        .with_variables(validator::VALID_VARIABLES.iter().cloned())
+}
+
 #[cfg(test)]
 mod tests {
     use bytes::Bytes;
@@ -45,6 +45,13 @@ impl ResourceMatcher for MqttTopicFilterMatcher {
             None => false,
         }
     }
+
+    fn includes(&self, policy: &str, other: &str) -> bool {
+        match (TopicFilter::from_str(policy), TopicFilter::from_str(other)) {
+            (Ok(policy), Ok(other)) => policy.includes(&other),
+            _ => policy == other,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -93,4 +100,13 @@ mod tests {
         assert!(MqttTopicFilterMatcher.do_match(&request, "any_value", "ignored_value1"));
         assert!(MqttTopicFilterMatcher.do_match(&request, "some_value", "ignored_value2"));
     }
+
+    #[test_case("/foo/#", "/foo/bar", true; "multi-level wildcard includes topic")]
+    #[test_case("/foo/+", "/foo/+", true; "same filter")]
+    #[test_case("/foo/+", "/foo/#", false; "single-level wildcard does not include multi-level")]
+    #[test_case("/foo/bar", "/foo/+", false; "topic does not include filter")]
+    #[test_case("{{iot:identity}}/#", "{{iot:identity}}/events", true; "filter with variables")]
+    fn includes_test(policy: &str, other: &str, result: bool) {
+        assert_eq!(result, MqttTopicFilterMatcher.includes(policy, other));
+    }
 }
@@ -1,5 +1,5 @@
-use mqtt_broker::auth::Activity;
-use policy::{Request, Result, Substituter};
+use mqtt_broker::auth::{Activity, Operation};
+use policy::{Request, Result, Substituter, Value};
 
 #[allow(clippy::doc_markdown)]
 /// MQTT-specific implementation of `Substituter`. It replaces MQTT and IoT Hub specific variables:
@@ -8,6 +8,14 @@ use policy::{Request, Result, Substituter};
 /// * `iot:module_id`
 /// * `iot:client_id`
 /// * `iot:topic`
+///
+/// It also resolves request attributes for statement conditions:
+/// * `iot:identity`
+/// * `mqtt:client_id`
+/// * `mqtt:peer_addr`
+/// * `mqtt:qos` (publish and subscribe only)
+/// * `mqtt:retain` (publish only)
+/// * `mqtt:payload_size` (publish only)
 #[derive(Debug)]
 pub struct MqttSubstituter {
     device_id: String,
@@ -65,6 +73,49 @@ impl Substituter for MqttSubstituter {
     fn visit_resource(&self, value: &str, context: &Request<Self::Context>) -> Result<String> {
         Ok(self.replace_variable(value, context))
     }
+
+    fn cache_key(&self, context: &Request<Self::Context>) -> Option<String> {
+        // all variables are derived from client id, auth id and device id,
+        // the latter does not change.
+        Some(context.context().map_or_else(String::new, |activity| {
+            let client_info = activity.client_info();
+            format!(
+                "{}\n{}",
+                client_info.client_id().as_str(),
+                client_info.auth_id().as_str()
+            )
+        }))
+    }
+
+    fn visit_attribute(&self, key: &str, context: &Request<Self::Context>) -> Option<Value> {
+        let activity = context.context()?;
+        let client_info = activity.client_info();
+
+        match (key, activity.operation()) {
+            (crate::IDENTITY_ATTR, _) => {
+                Some(Value::String(client_info.auth_id().as_str().to_owned()))
+            }
+            (crate::CLIENT_ID_ATTR, _) => {
+                Some(Value::String(client_info.client_id().as_str().to_owned()))
+            }
+            (crate::PEER_ADDR_ATTR, _) => Some(Value::Address(client_info.peer_addr().ip())),
+            (crate::QOS_ATTR, Operation::Publish(publish)) => {
+                Some(Value::Number(u8::from(publish.publication().qos()).into()))
+            }
+            (crate::QOS_ATTR, Operation::Subscribe(subscribe)) => {
+                Some(Value::Number(u8::from(subscribe.qos()).into()))
+            }
+            (crate::RETAIN_ATTR, Operation::Publish(publish)) => {
+                Some(Value::Bool(publish.publication().retain()))
+            }
+            (crate::PAYLOAD_SIZE_ATTR, Operation::Publish(publish)) => {
+                #[allow(clippy::cast_precision_loss)]
+                let size = publish.publication().payload_size() as f64;
+                Some(Value::Number(size))
+            }
+            _ => None,
+        }
+    }
 }
 
 /// A simple iterator that returns all occurrences
@@ -316,6 +367,47 @@ mod tests {
         );
     }
 
+    #[test_case("iot:identity", Some(Value::String("test_device_auth_id".into())); "identity")]
+    #[test_case("mqtt:client_id", Some(Value::String("test_device_client_id".into())); "client id")]
+    #[test_case("mqtt:peer_addr", Some(Value::Address("127.0.0.1".parse().unwrap())); "peer addr")]
+    #[test_case("mqtt:qos", Some(Value::Number(1.0)); "qos")]
+    #[test_case("mqtt:retain", Some(Value::Bool(true)); "retain")]
+    #[test_case("mqtt:payload_size", Some(Value::Number(0.0)); "payload size")]
+    #[test_case("mqtt:unknown", None; "unknown attribute")]
+    fn visit_attribute_test(key: &str, expected: Option<Value>) {
+        let request = Request::with_context(
+            "some_identity",
+            "some_operation",
+            "some_resource",
+            tests::create_publish_activity("test_device_client_id", "test_device_auth_id"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            expected,
+            MqttSubstituter::new("edge_device").visit_attribute(key, &request)
+        );
+    }
+
+    #[test]
+    fn visit_attribute_publish_only() {
+        let request = Request::with_context(
+            "some_identity",
+            "some_operation",
+            "some_resource",
+            tests::create_connect_activity("test_device_client_id", "test_device_auth_id"),
+        )
+        .unwrap();
+
+        let substituter = MqttSubstituter::new("edge_device");
+        assert_eq!(None, substituter.visit_attribute("mqtt:qos", &request));
+        assert_eq!(None, substituter.visit_attribute("mqtt:retain", &request));
+        assert_eq!(
+            None,
+            substituter.visit_attribute("mqtt:payload_size", &request)
+        );
+    }
+
     proptest! {
         #[test]
         fn iterator_does_not_crash(value in "[a-z\\{\\}]+") {
@@ -4,7 +4,7 @@ use std::{iter::FromIterator, str::FromStr};
 use lazy_static::lazy_static;
 
 use mqtt_broker::TopicFilter;
-use policy::{PolicyDefinition, PolicyValidator, Statement};
+use policy::{Condition, PolicyDefinition, PolicyValidator, Statement};
 
 use crate::{errors::Error, substituter::VariableIter};
 
@@ -14,6 +14,7 @@ use crate::{errors::Error, substituter::VariableIter};
 /// * Valid list of operations: mqtt:connect, mqtt:publish, mqtt:subscribe.
 /// * Valid topic filter structure.
 /// * Valid variable names.
+/// * Valid condition attribute names and operators applicable to them.
 #[derive(Debug)]
 pub struct MqttValidator;
 
@@ -43,12 +44,17 @@ fn visit_definition(definition: &PolicyDefinition) -> Result<(), Error> {
                 .resources()
                 .iter()
                 .filter_map(|r| visit_resource(r).err());
+            let condition_errors = statement
+                .conditions()
+                .iter()
+                .filter_map(|c| visit_condition(c).err());
 
             statement_errors
                 .into_iter()
                 .chain(identity_errors)
                 .chain(operation_errors)
                 .chain(resource_errors)
+                .chain(condition_errors)
                 .collect::<Vec<_>>()
         })
         .collect::<Vec<_>>();
@@ -61,7 +67,10 @@ fn visit_definition(definition: &PolicyDefinition) -> Result<(), Error> {
 
 fn visit_statement(statement: &Statement) -> Vec<Error> {
     let mut result = vec![];
-    if statement.identities().is_empty() {
+    if statement.identities().is_empty()
+        && statement.identity_patterns().is_empty()
+        && statement.groups().is_empty()
+    {
         result.push(Error::EmptyIdentities);
     }
     if statement.operations().is_empty() {
@@ -108,19 +117,60 @@ fn visit_resource(value: &str) -> Result<(), Error> {
     Ok(())
 }
 
+fn visit_condition(condition: &Condition) -> Result<(), Error> {
+    let key = condition.key();
+    if let Condition::TimeOfDay { .. } = condition {
+        return Ok(());
+    }
+    if VALID_ATTRIBUTES.get(key).is_none() {
+        return Err(Error::InvalidConditionKey(key.into()));
+    }
+
+    let applicable = match condition {
+        Condition::IpAddress { .. } => key == crate::PEER_ADDR_ATTR,
+        Condition::NumericEquals { .. }
+        | Condition::NumericLessThan { .. }
+        | Condition::NumericLessThanEquals { .. }
+        | Condition::NumericGreaterThan { .. }
+        | Condition::NumericGreaterThanEquals { .. } => {
+            key == crate::QOS_ATTR || key == crate::PAYLOAD_SIZE_ATTR
+        }
+        // string operators compare string representation of any attribute.
+        Condition::StringEquals { .. }
+        | Condition::StringLike { .. }
+        | Condition::TimeOfDay { .. } => true,
+    };
+
+    if !applicable {
+        return Err(Error::InvalidConditionOperator(
+            condition.operator().into(),
+            key.into(),
+        ));
+    }
+    Ok(())
+}
+
 fn is_connect_op(statement: &Statement) -> bool {
     // check that there is exactly one operation and it is mqtt:connect.
     statement.operations().len() == 1 && statement.operations()[0] == "mqtt:connect"
 }
 
 lazy_static! {
-    static ref VALID_VARIABLES: HashSet<String> = HashSet::from_iter(vec![
+    pub(crate) static ref VALID_VARIABLES: HashSet<String> = HashSet::from_iter(vec![
         crate::IDENTITY_VAR.into(),
         crate::DEVICE_ID_VAR.into(),
         crate::MODULE_ID_VAR.into(),
         crate::CLIENT_ID_VAR.into(),
         crate::EDGEHUB_ID_VAR.into(),
     ]);
+    static ref VALID_ATTRIBUTES: HashSet<String> = HashSet::from_iter(vec![
+        crate::IDENTITY_ATTR.into(),
+        crate::CLIENT_ID_ATTR.into(),
+        crate::PEER_ADDR_ATTR.into(),
+        crate::QOS_ATTR.into(),
+        crate::RETAIN_ATTR.into(),
+        crate::PAYLOAD_SIZE_ATTR.into(),
+    ]);
 }
 
 #[cfg(test)]
@@ -305,4 +355,73 @@ mod tests {
 
         assert!(MqttValidator.validate(&build_definition(json)).is_ok());
     }
+
+    #[test]
+    fn valid_conditions() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identities": [
+                        "contoso.azure-devices.net/monitor_a"
+                    ],
+                    "operations": [
+                        "mqtt:publish"
+                    ],
+                    "resources": [
+                        "topic/a"
+                    ],
+                    "conditions": [
+                        { "operator": "ipAddress", "key": "mqtt:peer_addr", "values": ["10.0.0.0/8"] },
+                        { "operator": "numericLessThanEquals", "key": "mqtt:payload_size", "value": 1024 },
+                        { "operator": "numericEquals", "key": "mqtt:qos", "value": 1 },
+                        { "operator": "stringEquals", "key": "mqtt:retain", "values": ["false"] },
+                        { "operator": "stringLike", "key": "mqtt:client_id", "values": ["sensor-*"] },
+                        { "operator": "timeOfDay", "start": "08:00", "end": "18:00" }
+                    ]
+                }
+            ]
+        }"#;
+
+        assert_matches!(MqttValidator.validate(&build_definition(json)), Ok(()));
+    }
+
+    #[test]
+    fn invalid_conditions() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identities": [
+                        "contoso.azure-devices.net/monitor_a"
+                    ],
+                    "operations": [
+                        "mqtt:publish"
+                    ],
+                    "resources": [
+                        "topic/a"
+                    ],
+                    "conditions": [
+                        { "operator": "stringEquals", "key": "mqtt:unknown", "values": ["a"] },
+                        { "operator": "numericEquals", "key": "mqtt:unknown", "value": 1 },
+                        { "operator": "numericEquals", "key": "mqtt:client_id", "value": 1 },
+                        { "operator": "ipAddress", "key": "mqtt:qos", "values": ["10.0.0.0/8"] }
+                    ]
+                }
+            ]
+        }"#;
+
+        let err = MqttValidator.validate(&build_definition(json)).unwrap_err();
+        assert_eq!(
+            err.into_summary(),
+            vec![
+                Error::InvalidConditionKey("mqtt:unknown".into()),
+                Error::InvalidConditionKey("mqtt:unknown".into()),
+                Error::InvalidConditionOperator("numericEquals".into(), "mqtt:client_id".into()),
+                Error::InvalidConditionOperator("ipAddress".into(), "mqtt:qos".into())
+            ]
+        );
+    }
 }
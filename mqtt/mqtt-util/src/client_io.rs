@@ -20,6 +20,8 @@ use url::form_urlencoded::Serializer as UrlSerializer;
 use edgelet_client::IOTHUB_ENCODE_SET;
 use mqtt3::IoSource;
 
+use crate::failover::Failover;
+
 const DEFAULT_TOKEN_DURATION_MINS: i64 = 60;
 
 #[derive(Clone)]
@@ -43,6 +45,7 @@ where
     address: String,
     token_source: Option<T>,
     trust_bundle_source: Option<TrustBundleSource>,
+    failover: Option<Failover>,
 }
 
 impl<T> TcpConnection<T>
@@ -58,8 +61,34 @@ where
             address: address.into(),
             token_source,
             trust_bundle_source,
+            failover: None,
         }
     }
+
+    /// Connects to endpoints provided by failover instead of a single address.
+    pub fn with_failover(mut self, failover: Failover) -> Self {
+        self.failover = Some(failover);
+        self
+    }
+
+    /// Returns an index of endpoint and its address to connect to.
+    fn next_address(&self) -> (usize, String) {
+        self.failover
+            .as_ref()
+            .map_or_else(|| (0, self.address.clone()), Failover::next_attempt)
+    }
+}
+
+/// Closes connections to a secondary endpoint when failover requests it.
+fn watch_failover(
+    failover: Option<&Failover>,
+    index: usize,
+    stream: Pin<Box<dyn ClientIo>>,
+) -> Pin<Box<dyn ClientIo>> {
+    match failover {
+        Some(failover) => failover.watch(index, stream),
+        None => stream,
+    }
 }
 
 impl IoSource for ClientIoSource {
@@ -80,8 +109,9 @@ impl IoSource for ClientIoSource {
 
 impl ClientIoSource {
     fn get_tcp_source(connection_settings: TcpConnection<SasTokenSource>) -> ClientIoSourceFuture {
-        let address = connection_settings.address;
+        let (index, address) = connection_settings.next_address();
         let token_source = connection_settings.token_source;
+        let failover = connection_settings.failover;
 
         Box::pin(async move {
             let expiry = Utc::now() + chrono::Duration::minutes(DEFAULT_TOKEN_DURATION_MINS);
@@ -107,14 +137,16 @@ impl ClientIoSource {
             }
 
             let stream: Pin<Box<dyn ClientIo>> = Box::pin(io);
+            let stream = watch_failover(failover.as_ref(), index, stream);
             Ok((stream, password))
         })
     }
 
     fn get_tls_source(connection_settings: TcpConnection<SasTokenSource>) -> ClientIoSourceFuture {
-        let address = connection_settings.address.clone();
+        let (index, address) = connection_settings.next_address();
         let token_source = connection_settings.token_source.as_ref().cloned();
         let trust_bundle_source = connection_settings.trust_bundle_source;
+        let failover = connection_settings.failover;
 
         Box::pin(async move {
             let expiry = Utc::now() + chrono::Duration::minutes(DEFAULT_TOKEN_DURATION_MINS);
@@ -175,6 +207,7 @@ impl ClientIoSource {
                 .map_err(|e| Error::new(ErrorKind::NotConnected, e))?;
 
             let stream: Pin<Box<dyn ClientIo>> = Box::pin(ssl);
+            let stream = watch_failover(failover.as_ref(), index, stream);
             Ok((stream, password))
         })
     }
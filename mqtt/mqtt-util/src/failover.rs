@@ -0,0 +1,420 @@
+use std::{
+    future::Future,
+    io::{Error, ErrorKind},
+    num::NonZeroU32,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_util::future;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+    sync::oneshot,
+};
+use tracing::{debug, info, warn};
+
+use crate::client_io::ClientIo;
+
+/// Number of consecutive successful probes of primary endpoint required
+/// before failing back to it.
+const FAIL_BACK_PROBES: u32 = 3;
+
+/// Upper bound of the multiplier applied to fail back interval after
+/// fail backs which did not end up with MQTT connection to primary endpoint.
+const MAX_FAIL_BACK_BACKOFF: u32 = 32;
+
+/// Ordered list of endpoints a client connects to.
+///
+/// The first endpoint is primary. Client switches to the next endpoint in
+/// the list after a number of consecutive connection attempts without
+/// establishing MQTT connection. While connected to a secondary endpoint
+/// primary endpoint is periodically probed and client fails back once it
+/// accepted several consecutive probes.
+///
+/// Probe only checks that primary endpoint accepts TCP connections. If a fail
+/// back does not end up with MQTT connection to primary endpoint, the
+/// interval between probes is doubled until the next successful MQTT
+/// connection to primary endpoint, so that a broker which accepts TCP
+/// connections but rejects MQTT ones does not cause constant flapping.
+///
+/// The state is shared between all clones.
+#[derive(Clone)]
+pub struct Failover {
+    state: Arc<Mutex<FailoverState>>,
+    max_attempts: u32,
+    fail_back_interval: Option<Duration>,
+}
+
+struct FailoverState {
+    addresses: Vec<String>,
+    current: usize,
+    attempts: u32,
+    failing_back: bool,
+    failed_fail_backs: u32,
+}
+
+impl Failover {
+    pub fn new(
+        primary: impl Into<String>,
+        secondaries: Vec<String>,
+        max_attempts: NonZeroU32,
+        fail_back_interval: Option<Duration>,
+    ) -> Self {
+        let mut addresses = vec![primary.into()];
+        addresses.extend(secondaries);
+
+        Self {
+            state: Arc::new(Mutex::new(FailoverState {
+                addresses,
+                current: 0,
+                attempts: 0,
+                failing_back: false,
+                failed_fail_backs: 0,
+            })),
+            max_attempts: max_attempts.get(),
+            fail_back_interval,
+        }
+    }
+
+    /// Returns the address of endpoint currently in use.
+    pub fn current(&self) -> String {
+        let state = self.lock();
+        state.addresses[state.current].clone()
+    }
+
+    /// Returns whether endpoint currently in use is primary one.
+    pub fn is_primary(&self) -> bool {
+        self.lock().current == 0
+    }
+
+    /// Records a successful MQTT connection to the current endpoint.
+    pub fn connected(&self) {
+        let mut state = self.lock();
+        state.attempts = 0;
+
+        if state.current == 0 {
+            state.failing_back = false;
+            state.failed_fail_backs = 0;
+        }
+    }
+
+    /// Returns an address for the next connection attempt. Switches to the
+    /// next endpoint if the current one exhausted its attempts.
+    pub(crate) fn next_attempt(&self) -> (usize, String) {
+        let mut state = self.lock();
+
+        if state.attempts >= self.max_attempts {
+            let failed = state.current;
+            state.current = (state.current + 1) % state.addresses.len();
+            state.attempts = 0;
+
+            if failed == 0 && state.failing_back {
+                state.failing_back = false;
+                state.failed_fail_backs = state.failed_fail_backs.saturating_add(1);
+            }
+
+            warn!(
+                "failed to connect to {} after {} attempts, switching to {}",
+                state.addresses[failed], self.max_attempts, state.addresses[state.current]
+            );
+        }
+
+        state.attempts += 1;
+        (state.current, state.addresses[state.current].clone())
+    }
+
+    fn fail_back(&self) {
+        let mut state = self.lock();
+        state.current = 0;
+        state.attempts = 0;
+        state.failing_back = true;
+    }
+
+    /// Returns an interval between probes of primary endpoint, which grows
+    /// with every fail back which did not establish MQTT connection.
+    fn probe_interval(&self, interval: Duration) -> Duration {
+        let failed_fail_backs = self.lock().failed_fail_backs.min(31);
+        let backoff = 2_u32
+            .saturating_pow(failed_fail_backs)
+            .min(MAX_FAIL_BACK_BACKOFF);
+        interval * backoff
+    }
+
+    fn primary(&self) -> String {
+        self.lock().addresses[0].clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, FailoverState> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Wraps a connection to a secondary endpoint so that it is closed once
+    /// primary endpoint becomes reachable again.
+    pub(crate) fn watch(&self, index: usize, io: Pin<Box<dyn ClientIo>>) -> Pin<Box<dyn ClientIo>> {
+        match self.fail_back_interval {
+            Some(interval) if index != 0 => {
+                let (sender, receiver) = oneshot::channel();
+                tokio::spawn(probe_primary(self.clone(), interval, sender));
+
+                Box::pin(FailBackIo {
+                    io,
+                    fail_back: Some(receiver),
+                })
+            }
+            _ => io,
+        }
+    }
+}
+
+/// Periodically checks whether primary endpoint accepts connections and
+/// notifies the connection to a secondary endpoint to close once it accepted
+/// `FAIL_BACK_PROBES` consecutive probes.
+async fn probe_primary(failover: Failover, interval: Duration, mut sender: oneshot::Sender<()>) {
+    let primary = failover.primary();
+    let interval = failover.probe_interval(interval);
+    let mut successes = 0;
+
+    loop {
+        let closed = Box::pin(sender.closed());
+        let delay = Box::pin(tokio::time::sleep(interval));
+        if let future::Either::Left(_) = future::select(closed, delay).await {
+            debug!("connection to secondary endpoint closed, stop probing primary endpoint");
+            return;
+        }
+
+        match TcpStream::connect(&primary).await {
+            Ok(_) => {
+                successes += 1;
+                if successes < FAIL_BACK_PROBES {
+                    debug!(
+                        "primary endpoint {} is reachable ({}/{})",
+                        primary, successes, FAIL_BACK_PROBES
+                    );
+                    continue;
+                }
+
+                info!("primary endpoint {} is reachable, failing back", primary);
+                failover.fail_back();
+                if sender.send(()).is_err() {
+                    debug!("connection to secondary endpoint already closed");
+                }
+                return;
+            }
+            Err(e) => {
+                successes = 0;
+                debug!("primary endpoint {} is still unreachable: {}", primary, e);
+            }
+        }
+    }
+}
+
+/// A connection to a secondary endpoint which fails with an error once
+/// fail back to primary endpoint is requested.
+struct FailBackIo {
+    io: Pin<Box<dyn ClientIo>>,
+    fail_back: Option<oneshot::Receiver<()>>,
+}
+
+impl FailBackIo {
+    fn poll_fail_back(&mut self, cx: &mut Context<'_>) -> Poll<Error> {
+        if let Some(fail_back) = &mut self.fail_back {
+            match Pin::new(fail_back).poll(cx) {
+                Poll::Ready(Ok(())) => {
+                    self.fail_back = None;
+                    return Poll::Ready(Error::new(
+                        ErrorKind::ConnectionAborted,
+                        "closing connection to fail back to primary endpoint",
+                    ));
+                }
+                Poll::Ready(Err(_)) => self.fail_back = None,
+                Poll::Pending => {}
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl AsyncRead for FailBackIo {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if let Poll::Ready(e) = this.poll_fail_back(cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        this.io.as_mut().poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for FailBackIo {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.get_mut().io.as_mut().poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.get_mut().io.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.get_mut().io.as_mut().poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::ErrorKind, num::NonZeroU32, time::Duration};
+
+    use tokio::{
+        io::{self, AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::oneshot,
+        time::{self, Instant},
+    };
+
+    use super::{probe_primary, FailBackIo, Failover, FAIL_BACK_PROBES};
+
+    const INTERVAL: Duration = Duration::from_millis(10);
+
+    fn failover(primary: impl Into<String>) -> Failover {
+        Failover::new(
+            primary,
+            vec!["secondary:8883".into()],
+            NonZeroU32::new(2).unwrap(),
+            Some(INTERVAL),
+        )
+    }
+
+    #[test]
+    fn it_switches_to_secondary_after_max_attempts() {
+        let failover = failover("primary:8883");
+
+        assert_eq!(failover.next_attempt(), (0, "primary:8883".into()));
+        assert_eq!(failover.next_attempt(), (0, "primary:8883".into()));
+        assert_eq!(failover.next_attempt(), (1, "secondary:8883".into()));
+        assert!(!failover.is_primary());
+
+        assert_eq!(failover.next_attempt(), (1, "secondary:8883".into()));
+        assert_eq!(failover.next_attempt(), (0, "primary:8883".into()));
+        assert!(failover.is_primary());
+    }
+
+    #[test]
+    fn it_resets_attempts_when_connected() {
+        let failover = failover("primary:8883");
+
+        failover.next_attempt();
+        failover.next_attempt();
+        failover.connected();
+
+        assert_eq!(failover.next_attempt(), (0, "primary:8883".into()));
+        assert_eq!(failover.current(), "primary:8883");
+    }
+
+    #[test]
+    fn it_backs_off_probes_after_failed_fail_back() {
+        let failover = failover("primary:8883");
+        failover.next_attempt();
+        failover.next_attempt();
+        failover.next_attempt();
+        assert_eq!(failover.probe_interval(INTERVAL), INTERVAL);
+
+        // primary endpoint accepts TCP but not MQTT connections
+        failover.fail_back();
+        failover.next_attempt();
+        failover.next_attempt();
+        assert_eq!(failover.next_attempt(), (1, "secondary:8883".into()));
+        assert_eq!(failover.probe_interval(INTERVAL), INTERVAL * 2);
+
+        failover.fail_back();
+        failover.next_attempt();
+        failover.next_attempt();
+        failover.next_attempt();
+        assert_eq!(failover.probe_interval(INTERVAL), INTERVAL * 4);
+
+        // successful MQTT connection to primary endpoint resets backoff
+        failover.fail_back();
+        failover.next_attempt();
+        failover.connected();
+        assert_eq!(failover.probe_interval(INTERVAL), INTERVAL);
+    }
+
+    #[tokio::test]
+    async fn it_fails_back_after_consecutive_probes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let failover = failover(listener.local_addr().unwrap().to_string());
+        failover.next_attempt();
+        failover.next_attempt();
+        failover.next_attempt();
+        assert!(!failover.is_primary());
+
+        let (sender, receiver) = oneshot::channel();
+        let start = Instant::now();
+        tokio::spawn(probe_primary(failover.clone(), INTERVAL, sender));
+
+        receiver.await.unwrap();
+        assert!(start.elapsed() >= INTERVAL * FAIL_BACK_PROBES);
+        assert!(failover.is_primary());
+    }
+
+    #[tokio::test]
+    async fn it_does_not_fail_back_when_primary_unreachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let primary = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let failover = failover(primary);
+        failover.next_attempt();
+        failover.next_attempt();
+        failover.next_attempt();
+
+        let (sender, receiver) = oneshot::channel();
+        tokio::spawn(probe_primary(failover.clone(), INTERVAL, sender));
+
+        let result = time::timeout(INTERVAL * FAIL_BACK_PROBES * 5, receiver).await;
+        assert!(result.is_err());
+        assert!(!failover.is_primary());
+    }
+
+    #[tokio::test]
+    async fn it_stops_probing_when_connection_closed() {
+        let failover = failover("127.0.0.1:1");
+        let (sender, receiver) = oneshot::channel();
+        let probe = tokio::spawn(probe_primary(failover, INTERVAL, sender));
+
+        drop(receiver);
+
+        time::timeout(INTERVAL * 10, probe).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn fail_back_io_fails_once_fail_back_requested() {
+        let (client, mut server) = io::duplex(64);
+        let (sender, receiver) = oneshot::channel();
+        let mut io = FailBackIo {
+            io: Box::pin(client),
+            fail_back: Some(receiver),
+        };
+
+        server.write_all(b"hello").await.unwrap();
+        let mut buf = [0; 5];
+        io.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        sender.send(()).unwrap();
+        let err = io.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionAborted);
+    }
+}
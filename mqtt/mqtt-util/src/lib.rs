@@ -11,8 +11,10 @@
 )]
 
 mod client_io;
+mod failover;
 
 pub use client_io::{
     AuthenticationSettings, ClientIoSource, CredentialProviderSettings, Credentials,
     SasTokenSource, TcpConnection, TrustBundleSource,
 };
+pub use failover::Failover;
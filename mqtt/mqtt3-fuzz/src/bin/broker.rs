@@ -0,0 +1,325 @@
+/// Feeds arbitrary sequences of client events for several clients into the broker state machine.
+///
+/// The fuzzer input is used as the source of randomness for the `proptest` generators of `mqtt-broker`,
+/// so every input maps to a well-formed sequence of events. After every event the following invariants are checked:
+///
+/// - processing an event never panics,
+/// - the broker never reuses a packet identifier on a connection while a publication with that identifier is still inflight.
+///
+/// Once all events are processed, the broker state is snapshotted and restored into a new broker,
+/// and the snapshot of the restored broker must equal the original one.
+///
+/// Usage:
+///
+///     rm -rf out/ && cargo afl build && cargo afl fuzz -i in -o out target/debug/broker
+use std::collections::{HashMap, HashSet};
+
+use proptest::{
+    prelude::*,
+    sample::Index,
+    strategy::ValueTree,
+    test_runner::{Config, RngAlgorithm, TestRng, TestRunner},
+};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use mqtt3::proto;
+use mqtt_broker::{
+    auth::AllowAll,
+    proptest::{arb_connect, arb_proto_publish, arb_subscribe, arb_topic, arb_unsubscribe},
+    Auth, AuthId, Broker, BrokerBuilder, BrokerSnapshot, ClientEvent, ClientId, ConnReq,
+    ConnectionHandle, Message, Publish, SessionSnapshot,
+};
+
+const MAX_CLIENTS: usize = 4;
+const MAX_EVENTS: usize = 64;
+
+fn main() {
+    afl::fuzz(true, |data| {
+        let mut runner = TestRunner::new_with_rng(
+            Config::default(),
+            TestRng::from_seed(RngAlgorithm::PassThrough, data),
+        );
+
+        let events = match proptest::collection::vec(arb_fuzz_event(), 0..MAX_EVENTS)
+            .new_tree(&mut runner)
+        {
+            Ok(tree) => tree.current(),
+            Err(_) => return,
+        };
+
+        let mut broker = BrokerBuilder::default().with_authorizer(AllowAll).build();
+        let mut connections: HashMap<ClientId, Connection> = HashMap::new();
+
+        for event in events {
+            process_event(&mut broker, &mut connections, event);
+
+            for (client_id, connection) in &mut connections {
+                connection.drain(client_id);
+            }
+        }
+
+        let snapshot = broker.clone_state();
+        let restored = BrokerBuilder::default()
+            .with_authorizer(AllowAll)
+            .with_state(snapshot.clone())
+            .build();
+
+        assert_eq!(by_client_id(snapshot), by_client_id(restored.clone_state()));
+    })
+}
+
+#[derive(Debug)]
+enum FuzzEvent {
+    ConnReq(usize, proto::Connect),
+    Disconnect(usize),
+    DropConnection(usize),
+    CloseSession(usize),
+    PingReq(usize),
+    Subscribe(usize, proto::Subscribe),
+    Unsubscribe(usize, proto::Unsubscribe),
+    PublishFrom(usize, proto::Publish),
+    Ack(usize, Index),
+}
+
+fn arb_fuzz_event() -> impl Strategy<Value = FuzzEvent> {
+    let client = 0..MAX_CLIENTS;
+
+    prop_oneof![
+        (client.clone(), any::<bool>()).prop_flat_map(|(client, clean_session)| {
+            let id = client_id(client).to_string();
+            let client_id = if clean_session {
+                proto::ClientId::IdWithCleanSession(id)
+            } else {
+                proto::ClientId::IdWithExistingSession(id)
+            };
+            arb_connect(client_id).prop_map(move |connect| FuzzEvent::ConnReq(client, connect))
+        }),
+        client.clone().prop_map(FuzzEvent::Disconnect),
+        client.clone().prop_map(FuzzEvent::DropConnection),
+        client.clone().prop_map(FuzzEvent::CloseSession),
+        client.clone().prop_map(FuzzEvent::PingReq),
+        (client.clone(), arb_subscribe())
+            .prop_map(|(client, subscribe)| FuzzEvent::Subscribe(client, subscribe)),
+        (client.clone(), arb_unsubscribe())
+            .prop_map(|(client, unsubscribe)| FuzzEvent::Unsubscribe(client, unsubscribe)),
+        (client.clone(), arb_proto_publish(), arb_topic_name()).prop_map(
+            |(client, publish, topic_name)| {
+                FuzzEvent::PublishFrom(
+                    client,
+                    proto::Publish {
+                        topic_name,
+                        ..publish
+                    },
+                )
+            }
+        ),
+        (client, any::<Index>()).prop_map(|(client, index)| FuzzEvent::Ack(client, index)),
+    ]
+}
+
+/// Mostly picks topics that match the filters generated by `arb_topic_filter_weighted`,
+/// so that publications are actually routed to subscribers.
+fn arb_topic_name() -> impl Strategy<Value = String> {
+    prop_oneof![
+        arb_topic(),
+        3 => (0..10).prop_map(|n| format!("topic/{}", n)),
+    ]
+}
+
+fn client_id(client: usize) -> ClientId {
+    format!("client_{}", client).into()
+}
+
+fn process_event(
+    broker: &mut Broker<AllowAll>,
+    connections: &mut HashMap<ClientId, Connection>,
+    event: FuzzEvent,
+) {
+    let (client_id, event) = match event {
+        FuzzEvent::ConnReq(client, connect) => {
+            let client_id = client_id(client);
+            let (tx, rx) = mpsc::unbounded_channel();
+
+            // Messages for the previous connection have already been checked,
+            // so its inflight packet identifiers no longer matter.
+            connections.insert(client_id.clone(), Connection::new(rx));
+
+            let connreq = ConnReq::new(
+                client_id.clone(),
+                "127.0.0.1:12345".parse().expect("peer_addr"),
+                connect,
+                Auth::Identity(AuthId::Anonymous),
+                ConnectionHandle::from_sender(tx),
+            );
+            (client_id, ClientEvent::ConnReq(connreq))
+        }
+        FuzzEvent::Disconnect(client) => (
+            client_id(client),
+            ClientEvent::Disconnect(proto::Disconnect),
+        ),
+        FuzzEvent::DropConnection(client) => (client_id(client), ClientEvent::DropConnection),
+        FuzzEvent::CloseSession(client) => (client_id(client), ClientEvent::CloseSession),
+        FuzzEvent::PingReq(client) => (client_id(client), ClientEvent::PingReq(proto::PingReq)),
+        FuzzEvent::Subscribe(client, subscribe) => {
+            (client_id(client), ClientEvent::Subscribe(subscribe))
+        }
+        FuzzEvent::Unsubscribe(client, unsubscribe) => {
+            (client_id(client), ClientEvent::Unsubscribe(unsubscribe))
+        }
+        FuzzEvent::PublishFrom(client, publish) => {
+            (client_id(client), ClientEvent::PublishFrom(publish, None))
+        }
+        FuzzEvent::Ack(client, index) => {
+            let client_id = client_id(client);
+            let connection = match connections.get_mut(&client_id) {
+                Some(connection) => connection,
+                None => return,
+            };
+
+            let event = match connection.ack(index) {
+                Some(event) => event,
+                None => return,
+            };
+            (client_id, event)
+        }
+    };
+
+    broker
+        .process_client_event(client_id, event)
+        .expect("process_client_event");
+}
+
+/// Tracks the packet identifiers the broker has used on a single connection
+/// for publications that have not been acknowledged yet.
+#[derive(Debug)]
+struct Connection {
+    receiver: UnboundedReceiver<Message>,
+    inflight: HashMap<proto::PacketIdentifier, Stage>,
+    inflight_qos0: HashSet<proto::PacketIdentifier>,
+}
+
+/// How far a QoS 1 or QoS 2 publication sent to the client has progressed
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stage {
+    AwaitingPubAck,
+    AwaitingPubRec,
+    AwaitingPubComp,
+}
+
+impl Connection {
+    fn new(receiver: UnboundedReceiver<Message>) -> Self {
+        Self {
+            receiver,
+            inflight: HashMap::new(),
+            inflight_qos0: HashSet::new(),
+        }
+    }
+
+    /// Checks all messages the broker has sent to this connection so far.
+    fn drain(&mut self, client_id: &ClientId) {
+        while let Ok(message) = self.receiver.try_recv() {
+            let event = match message {
+                Message::Client(_, event) => event,
+                Message::System(_) => continue,
+            };
+
+            match event {
+                ClientEvent::PublishTo(Publish::QoS12(id, publish)) => {
+                    let (stage, dup) = match publish.packet_identifier_dup_qos {
+                        proto::PacketIdentifierDupQoS::AtLeastOnce(_, dup) => {
+                            (Stage::AwaitingPubAck, dup)
+                        }
+                        proto::PacketIdentifierDupQoS::ExactlyOnce(_, dup) => {
+                            (Stage::AwaitingPubRec, dup)
+                        }
+                        proto::PacketIdentifierDupQoS::AtMostOnce => {
+                            panic!("QoS 0 publication {} sent as QoS 1 or 2", id)
+                        }
+                    };
+
+                    assert!(
+                        self.inflight.insert(id, stage).is_none() || dup,
+                        "packet identifier {} reused for {} while inflight",
+                        id,
+                        client_id
+                    );
+                }
+                ClientEvent::PublishTo(Publish::QoS0(id, _)) => {
+                    assert!(
+                        self.inflight_qos0.insert(id),
+                        "QoS 0 packet identifier {} reused for {} while inflight",
+                        id,
+                        client_id
+                    );
+                }
+                ClientEvent::PubRel(pubrel) => {
+                    self.inflight
+                        .insert(pubrel.packet_identifier, Stage::AwaitingPubComp);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Acknowledges one of the inflight publications, as the connection would once it has been sent
+    /// or the client has responded to it.
+    fn ack(&mut self, index: Index) -> Option<ClientEvent> {
+        let mut ids: Vec<_> = self
+            .inflight
+            .iter()
+            .map(|(id, stage)| (*id, Some(*stage)))
+            .chain(self.inflight_qos0.iter().map(|id| (*id, None)))
+            .collect();
+        if ids.is_empty() {
+            return None;
+        }
+
+        ids.sort_unstable_by_key(|(id, stage)| (stage.is_none(), *id));
+        let (packet_identifier, stage) = *index.get(&ids);
+
+        let event = match stage {
+            None => {
+                self.inflight_qos0.remove(&packet_identifier);
+                ClientEvent::PubAck0(packet_identifier)
+            }
+            Some(Stage::AwaitingPubAck) => {
+                self.inflight.remove(&packet_identifier);
+                ClientEvent::PubAck(proto::PubAck { packet_identifier })
+            }
+            Some(Stage::AwaitingPubRec) => ClientEvent::PubRec(proto::PubRec { packet_identifier }),
+            Some(Stage::AwaitingPubComp) => {
+                self.inflight.remove(&packet_identifier);
+                ClientEvent::PubComp(proto::PubComp { packet_identifier })
+            }
+        };
+        Some(event)
+    }
+}
+
+/// Sessions in a snapshot are not ordered, so compare them by client id.
+fn by_client_id(
+    snapshot: BrokerSnapshot,
+) -> (
+    HashMap<String, proto::Publication>,
+    HashMap<ClientId, SessionSnapshot>,
+) {
+    let (retained, sessions) = snapshot.into_parts();
+    let sessions = sessions
+        .into_iter()
+        .map(|session| {
+            let (client_info, subscriptions, waiting_to_be_sent, waiting_to_be_acked, last_active) =
+                session.into_parts();
+            let client_id = client_info.client_id().clone();
+            let session = SessionSnapshot::from_parts(
+                client_info,
+                subscriptions,
+                waiting_to_be_sent,
+                waiting_to_be_acked,
+                last_active,
+            );
+            (client_id, session)
+        })
+        .collect();
+
+    (retained, sessions)
+}
@@ -6,16 +6,28 @@ where
     IoS: super::IoSource,
 {
     io_source: IoS,
-    max_back_off: std::time::Duration,
-    current_back_off: std::time::Duration,
+    retries: Retries,
+    recording: Option<Recording>,
     state: State<IoS>,
 }
 
+/// Consecutive failed connection attempts and the policy that decides when to make the next one
+#[derive(Debug)]
+struct Retries {
+    reconnect_policy: Box<dyn super::ReconnectPolicy + Send>,
+    failed_attempts: u32,
+}
+
+#[derive(Debug)]
+struct Recording {
+    directory: std::path::PathBuf,
+    connections: u64,
+}
+
 enum State<IoS>
 where
     IoS: super::IoSource,
 {
-    BeginBackOff,
     EndBackOff(std::pin::Pin<Box<tokio::time::Sleep>>),
     BeginConnecting,
     WaitingForIoToConnect(<IoS as super::IoSource>::Future),
@@ -24,6 +36,7 @@ where
         framed_state: FramedState,
         password: Option<String>,
     },
+    GaveUp,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -43,7 +56,6 @@ where
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            State::BeginBackOff => f.write_str("BeginBackOff"),
             State::EndBackOff(_) => f.write_str("EndBackOff"),
             State::BeginConnecting => f.write_str("BeginConnecting"),
             State::WaitingForIoToConnect(_) => f.write_str("WaitingForIoToConnect"),
@@ -51,6 +63,7 @@ where
                 .debug_struct("Framed")
                 .field("framed_state", framed_state)
                 .finish(),
+            State::GaveUp => f.write_str("GaveUp"),
         }
     }
 }
@@ -59,17 +72,30 @@ impl<IoS> Connect<IoS>
 where
     IoS: super::IoSource,
 {
-    pub(super) fn new(io_source: IoS, max_back_off: std::time::Duration) -> Self {
+    pub(super) fn new(
+        io_source: IoS,
+        reconnect_policy: Box<dyn super::ReconnectPolicy + Send>,
+    ) -> Self {
         Connect {
             io_source,
-            max_back_off,
-            current_back_off: std::time::Duration::from_secs(0),
+            retries: Retries {
+                reconnect_policy,
+                failed_attempts: 0,
+            },
+            recording: None,
             state: State::BeginConnecting,
         }
     }
 
+    pub(super) fn set_recording(&mut self, directory: std::path::PathBuf) {
+        self.recording = Some(Recording {
+            directory,
+            connections: 0,
+        });
+    }
+
     pub(super) fn reconnect(&mut self) {
-        self.state = State::BeginBackOff;
+        self.state = State::BeginConnecting;
     }
 }
 
@@ -88,7 +114,7 @@ where
         will: Option<&crate::proto::Publication>,
         client_id: &mut crate::proto::ClientId,
         keep_alive: std::time::Duration,
-    ) -> std::task::Poll<Connected<'a, IoS>> {
+    ) -> std::task::Poll<ConnectResult<'a, IoS>> {
         use futures_core::Stream;
         use futures_sink::Sink;
 
@@ -98,20 +124,6 @@ where
             log::trace!("    {:?}", state);
 
             match state {
-                State::BeginBackOff => match self.current_back_off {
-                    back_off if back_off.as_secs() == 0 => {
-                        self.current_back_off = std::time::Duration::from_secs(1);
-                        *state = State::BeginConnecting;
-                    }
-
-                    back_off => {
-                        log::debug!("Backing off for {:?}", back_off);
-                        self.current_back_off =
-                            std::cmp::min(self.max_back_off, self.current_back_off * 2);
-                        *state = State::EndBackOff(Box::pin(tokio::time::sleep(back_off)));
-                    }
-                },
-
                 State::EndBackOff(back_off_timer) => {
                     use futures_util::FutureExt;
                     match back_off_timer.poll_unpin(cx) {
@@ -128,7 +140,12 @@ where
 
                 State::WaitingForIoToConnect(io) => match std::pin::Pin::new(io).poll(cx) {
                     std::task::Poll::Ready(Ok((io, password))) => {
-                        let framed = crate::logging_framed::LoggingFramed::new(io);
+                        let mut framed = crate::logging_framed::LoggingFramed::new(io);
+                        if let Some(recording) = &mut self.recording {
+                            if let Some(recorder) = recording.new_connection() {
+                                framed = framed.with_recorder(recorder);
+                            }
+                        }
                         *state = State::Framed {
                             framed,
                             framed_state: FramedState::BeginSendingConnect,
@@ -137,8 +154,10 @@ where
                     }
 
                     std::task::Poll::Ready(Err(err)) => {
-                        log::warn!("could not connect to server: {}", err);
-                        *state = State::BeginBackOff;
+                        return self.retries.attempt_failed(
+                            state,
+                            super::ConnectionAttemptError::IoSource(err.to_string()),
+                        );
                     }
 
                     std::task::Poll::Pending => return std::task::Poll::Pending,
@@ -163,15 +182,19 @@ where
                         match std::pin::Pin::new(&mut *framed).start_send(packet) {
                             Ok(()) => *framed_state = FramedState::EndSendingConnect,
                             Err(err) => {
-                                log::warn!("could not connect to server: {}", err);
-                                *state = State::BeginBackOff;
+                                return self.retries.attempt_failed(
+                                    state,
+                                    super::ConnectionAttemptError::EncodePacket(err),
+                                );
                             }
                         }
                     }
 
                     std::task::Poll::Ready(Err(err)) => {
-                        log::warn!("could not connect to server: {}", err);
-                        *state = State::BeginBackOff;
+                        return self.retries.attempt_failed(
+                            state,
+                            super::ConnectionAttemptError::EncodePacket(err),
+                        );
                     }
 
                     std::task::Poll::Pending => return std::task::Poll::Pending,
@@ -186,8 +209,10 @@ where
                         *framed_state = FramedState::WaitingForConnAck;
                     }
                     std::task::Poll::Ready(Err(err)) => {
-                        log::warn!("could not connect to server: {}", err);
-                        *state = State::BeginBackOff;
+                        return self.retries.attempt_failed(
+                            state,
+                            super::ConnectionAttemptError::EncodePacket(err),
+                        );
                     }
                     std::task::Poll::Pending => return std::task::Poll::Pending,
                 },
@@ -202,7 +227,8 @@ where
                             session_present,
                             return_code: crate::proto::ConnectReturnCode::Accepted,
                         }) => {
-                            self.current_back_off = std::time::Duration::from_secs(0);
+                            self.retries.failed_attempts = 0;
+                            self.retries.reconnect_policy.reset();
 
                             let reset_session = match client_id {
                                 crate::proto::ClientId::ServerGenerated => true,
@@ -230,27 +256,32 @@ where
                             return_code: crate::proto::ConnectReturnCode::Refused(return_code),
                             ..
                         }) => {
-                            log::warn!(
-                                "could not connect to server: connection refused: {:?}",
-                                return_code
+                            return self.retries.attempt_failed(
+                                state,
+                                super::ConnectionAttemptError::ConnectionRefused(return_code),
                             );
-                            *state = State::BeginBackOff;
                         }
 
                         packet => {
-                            log::warn!("could not connect to server: expected to receive ConnAck but received {:?}", packet);
-                            *state = State::BeginBackOff;
+                            return self.retries.attempt_failed(
+                                state,
+                                super::ConnectionAttemptError::UnexpectedPacket(Box::new(packet)),
+                            );
                         }
                     },
 
                     std::task::Poll::Ready(Some(Err(err))) => {
-                        log::warn!("could not connect to server: {}", err);
-                        *state = State::BeginBackOff;
+                        return self.retries.attempt_failed(
+                            state,
+                            super::ConnectionAttemptError::DecodePacket(err),
+                        );
                     }
 
                     std::task::Poll::Ready(None) => {
-                        log::warn!("could not connect to server: connection closed by server");
-                        *state = State::BeginBackOff;
+                        return self.retries.attempt_failed(
+                            state,
+                            super::ConnectionAttemptError::ServerClosedConnection,
+                        );
                     }
 
                     std::task::Poll::Pending => return std::task::Poll::Pending,
@@ -272,13 +303,94 @@ where
                     };
                     *new_connection = false;
                     *reset_session = false;
-                    return std::task::Poll::Ready(result);
+                    return std::task::Poll::Ready(ConnectResult::Connected(result));
+                }
+
+                State::GaveUp => {
+                    return std::task::Poll::Ready(ConnectResult::GaveUp {
+                        attempts: self.retries.failed_attempts,
+                    })
                 }
             }
         }
     }
 }
 
+impl Retries {
+    /// Records a failed connection attempt, and backs off before the next one unless the reconnect policy gives up.
+    fn attempt_failed<'a, IoS>(
+        &mut self,
+        state: &mut State<IoS>,
+        reason: super::ConnectionAttemptError,
+    ) -> std::task::Poll<ConnectResult<'a, IoS>>
+    where
+        IoS: super::IoSource,
+    {
+        log::warn!("could not connect to server: {}", reason);
+
+        self.failed_attempts = self.failed_attempts.saturating_add(1);
+        let attempt = self.failed_attempts;
+        let retry_in = self.reconnect_policy.back_off(attempt);
+
+        *state = match retry_in {
+            Some(back_off) => {
+                log::debug!("Backing off for {:?}", back_off);
+                State::EndBackOff(Box::pin(tokio::time::sleep(back_off)))
+            }
+
+            None => {
+                log::warn!("giving up connecting to server after {} attempts", attempt);
+                State::GaveUp
+            }
+        };
+
+        std::task::Poll::Ready(ConnectResult::AttemptFailed {
+            attempt,
+            reason,
+            retry_in,
+        })
+    }
+}
+
+impl Recording {
+    fn new_connection(&mut self) -> Option<crate::recording::Recorder> {
+        let started = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = self
+            .directory
+            .join(format!("{}-{}.mqttrec", started, self.connections));
+        self.connections += 1;
+
+        match crate::recording::Recorder::create(&path) {
+            Ok(recorder) => {
+                log::info!("recording connection to {}", path.display());
+                Some(recorder)
+            }
+            Err(err) => {
+                log::warn!("could not create recording {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+}
+
+pub(super) enum ConnectResult<'a, IoS>
+where
+    IoS: super::IoSource,
+{
+    Connected(Connected<'a, IoS>),
+    AttemptFailed {
+        attempt: u32,
+        reason: super::ConnectionAttemptError,
+        retry_in: Option<std::time::Duration>,
+    },
+    GaveUp {
+        attempts: u32,
+    },
+}
+
 pub(super) struct Connected<'a, IoS>
 where
     IoS: super::IoSource,
@@ -2,11 +2,19 @@ use std::future::Future;
 
 mod connect;
 
+mod outbox;
+pub use outbox::{FileOutbox, Outbox};
+
 mod ping;
 
 mod publish;
 pub use publish::{PublishError, PublishHandle};
 
+mod reconnect;
+pub use reconnect::{
+    DecorrelatedJitterBackOff, ExponentialBackOff, FixedBackOff, MaxAttempts, ReconnectPolicy,
+};
+
 mod subscriptions;
 pub use subscriptions::{UpdateSubscriptionError, UpdateSubscriptionHandle};
 
@@ -46,9 +54,11 @@ where
     ///
     ///     The MQTT protocol is layered onto the I/O object returned by this source.
     ///
-    /// * `max_reconnect_back_off`
+    /// * `reconnect_policy`
     ///
-    ///     Every connection failure will double the back-off period, to a maximum of this value.
+    ///     Decides how long to wait after a failed connection attempt, and when to stop reconnecting.
+    ///     A [`std::time::Duration`] retries immediately after the first connection failure, and then doubles the back-off period
+    ///     after every further connection failure, to a maximum of this value.
     ///
     /// * `keep_alive`
     ///
@@ -58,7 +68,7 @@ where
         username: Option<String>,
         will: Option<crate::proto::Publication>,
         io_source: IoS,
-        max_reconnect_back_off: std::time::Duration,
+        reconnect_policy: impl ReconnectPolicy + Send + 'static,
         keep_alive: std::time::Duration,
     ) -> Self {
         let client_id = match client_id {
@@ -71,7 +81,7 @@ where
             username,
             will,
             io_source,
-            max_reconnect_back_off,
+            Box::new(reconnect_policy),
             keep_alive,
         )
     }
@@ -91,9 +101,11 @@ where
     ///
     ///     The MQTT protocol is layered onto the I/O object returned by this source.
     ///
-    /// * `max_reconnect_back_off`
+    /// * `reconnect_policy`
     ///
-    ///     Every connection failure will double the back-off period, to a maximum of this value.
+    ///     Decides how long to wait after a failed connection attempt, and when to stop reconnecting.
+    ///     A [`std::time::Duration`] retries immediately after the first connection failure, and then doubles the back-off period
+    ///     after every further connection failure, to a maximum of this value.
     ///
     /// * `keep_alive`
     ///
@@ -103,7 +115,7 @@ where
         username: Option<String>,
         will: Option<crate::proto::Publication>,
         io_source: IoS,
-        max_reconnect_back_off: std::time::Duration,
+        reconnect_policy: impl ReconnectPolicy + Send + 'static,
         keep_alive: std::time::Duration,
     ) -> Self {
         Self::create(
@@ -111,7 +123,7 @@ where
             username,
             will,
             io_source,
-            max_reconnect_back_off,
+            Box::new(reconnect_policy),
             keep_alive,
         )
     }
@@ -121,7 +133,7 @@ where
         username: Option<String>,
         will: Option<crate::proto::Publication>,
         io_source: IoS,
-        max_reconnect_back_off: std::time::Duration,
+        reconnect_policy: Box<dyn ReconnectPolicy + Send>,
         keep_alive: std::time::Duration,
     ) -> Self {
         let (shutdown_send, shutdown_recv) = futures_channel::mpsc::channel(0);
@@ -141,7 +153,7 @@ where
 
             packet_identifiers: Default::default(),
 
-            connect: connect::Connect::new(io_source, max_reconnect_back_off),
+            connect: connect::Connect::new(io_source, reconnect_policy),
             ping: ping::State::BeginWaitingForNextPing,
             publish: Default::default(),
             subscriptions: Default::default(),
@@ -150,6 +162,46 @@ where
         })
     }
 
+    /// Persist QoS 1 and QoS 2 publications in the given outbox until they are acknowledged by the server.
+    ///
+    /// Publications left in the outbox by a previous instance of the client are published again,
+    /// with the DUP flag set, before any other publication. Note that they are sent with new packet identifiers,
+    /// so the server may deliver a QoS 2 publication left in the outbox more than once.
+    pub fn with_outbox(
+        mut self,
+        outbox: impl Outbox + Send + 'static,
+    ) -> Result<Self, std::io::Error> {
+        if let ClientState::Up { publish, .. } = &mut self.0 {
+            publish.set_outbox(Box::new(outbox))?;
+        }
+
+        Ok(self)
+    }
+
+    /// Limit the number of AtLeastOnce and ExactlyOnce publications sent to the server and not acknowledged yet.
+    ///
+    /// Once the limit is reached, further publications are queued until the server acknowledges earlier ones,
+    /// and publications made via [`PublishHandle`] wait for the client to accept them.
+    /// By default the number of such publications is limited only by available packet identifiers.
+    pub fn with_max_inflight(mut self, max_inflight: std::num::NonZeroUsize) -> Self {
+        if let ClientState::Up { publish, .. } = &mut self.0 {
+            publish.set_max_inflight(max_inflight);
+        }
+
+        self
+    }
+
+    /// Record the packets of every connection to the server to a new file in the given directory.
+    ///
+    /// See [`crate::recording`] for the format of the files.
+    pub fn with_recording(mut self, directory: impl Into<std::path::PathBuf>) -> Self {
+        if let ClientState::Up { connect, .. } = &mut self.0 {
+            connect.set_recording(directory.into());
+        }
+
+        self
+    }
+
     /// Queues a message to be published to the server
     pub fn publish(
         &mut self,
@@ -276,7 +328,26 @@ where
                         client_id,
                         *keep_alive,
                     ) {
-                        std::task::Poll::Ready(framed) => framed,
+                        std::task::Poll::Ready(connect::ConnectResult::Connected(framed)) => framed,
+
+                        std::task::Poll::Ready(connect::ConnectResult::AttemptFailed {
+                            attempt,
+                            reason,
+                            retry_in,
+                        }) => {
+                            return std::task::Poll::Ready(Some(Ok(
+                                Event::ConnectionAttemptFailed {
+                                    attempt,
+                                    reason,
+                                    retry_in,
+                                },
+                            )))
+                        }
+
+                        std::task::Poll::Ready(connect::ConnectResult::GaveUp { attempts }) => {
+                            break Some(Error::ReconnectAttemptsExhausted(attempts))
+                        }
+
                         std::task::Poll::Pending => return std::task::Poll::Pending,
                     };
 
@@ -370,8 +441,12 @@ where
                         client_id,
                         *keep_alive,
                     ) {
-                        std::task::Poll::Ready(framed) => framed,
-                        std::task::Poll::Pending => {
+                        std::task::Poll::Ready(connect::ConnectResult::Connected(framed)) => framed,
+                        std::task::Poll::Ready(
+                            connect::ConnectResult::AttemptFailed { .. }
+                            | connect::ConnectResult::GaveUp { .. },
+                        )
+                        | std::task::Poll::Pending => {
                             // Already disconnected
                             self.0 = ClientState::ShutDown {
                                 reason: reason.take(),
@@ -516,6 +591,18 @@ pub enum Event {
 
     Disconnected(ConnectionError),
 
+    /// An attempt to connect to the server failed.
+    ConnectionAttemptFailed {
+        /// The number of consecutive failed connection attempts, including this one
+        attempt: u32,
+
+        /// Why the attempt failed
+        reason: ConnectionAttemptError,
+
+        /// How long the client waits before the next attempt, or `None` if the [`ReconnectPolicy`] gave up
+        retry_in: Option<std::time::Duration>,
+    },
+
     /// A publication received from the server
     Publication(ReceivedPublication),
 
@@ -779,6 +866,7 @@ pub enum Error {
     DuplicateExactlyOncePublishPacketNotMarkedDuplicate(crate::proto::PacketIdentifier),
     EncodePacket(crate::proto::EncodeError),
     PacketIdentifiersExhausted,
+    ReconnectAttemptsExhausted(u32),
     ServerClosedConnection,
     SubAckDoesNotContainEnoughQoS(crate::proto::PacketIdentifier, usize, usize),
     SubscriptionDowngraded(String, crate::proto::QoS, crate::proto::QoS),
@@ -845,6 +933,9 @@ impl std::fmt::Display for Error {
 			Error::PacketIdentifiersExhausted =>
 				write!(f, "all packet identifiers exhausted"),
 
+			Error::ReconnectAttemptsExhausted(attempts) =>
+				write!(f, "gave up connecting to server after {} attempts", attempts),
+
 			Error::ServerClosedConnection =>
 				write!(f, "connection closed by server"),
 
@@ -871,6 +962,7 @@ impl std::error::Error for Error {
             Error::DuplicateExactlyOncePublishPacketNotMarkedDuplicate(_) => None,
             Error::EncodePacket(err) => Some(err),
             Error::PacketIdentifiersExhausted => None,
+            Error::ReconnectAttemptsExhausted(_) => None,
             Error::ServerClosedConnection => None,
             Error::SubAckDoesNotContainEnoughQoS(_, _, _) => None,
             Error::SubscriptionDowngraded(_, _, _) => None,
@@ -930,6 +1022,87 @@ impl From<Error> for ConnectionError {
     }
 }
 
+#[derive(Debug)]
+pub enum ConnectionAttemptError {
+    IoSource(String),
+    EncodePacket(crate::proto::EncodeError),
+    DecodePacket(crate::proto::DecodeError),
+    ConnectionRefused(crate::proto::ConnectionRefusedReason),
+    UnexpectedPacket(Box<crate::proto::Packet>),
+    ServerClosedConnection,
+}
+
+impl std::fmt::Display for ConnectionAttemptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionAttemptError::IoSource(err) => write!(f, "{}", err),
+            ConnectionAttemptError::EncodePacket(err) => {
+                write!(f, "could not encode packet: {}", err)
+            }
+            ConnectionAttemptError::DecodePacket(err) => {
+                write!(f, "could not decode packet: {}", err)
+            }
+            ConnectionAttemptError::ConnectionRefused(reason) => {
+                write!(f, "connection refused: {:?}", reason)
+            }
+            ConnectionAttemptError::UnexpectedPacket(packet) => {
+                write!(f, "expected to receive ConnAck but received {:?}", packet)
+            }
+            ConnectionAttemptError::ServerClosedConnection => {
+                write!(f, "connection closed by server")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConnectionAttemptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        #[allow(clippy::match_same_arms)]
+        match self {
+            ConnectionAttemptError::EncodePacket(err) => Some(err),
+            ConnectionAttemptError::DecodePacket(err) => Some(err),
+            ConnectionAttemptError::IoSource(_)
+            | ConnectionAttemptError::ConnectionRefused(_)
+            | ConnectionAttemptError::UnexpectedPacket(_)
+            | ConnectionAttemptError::ServerClosedConnection => None,
+        }
+    }
+}
+
+impl PartialEq for ConnectionAttemptError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ConnectionAttemptError::IoSource(left), ConnectionAttemptError::IoSource(right)) => {
+                left == right
+            }
+            // Encode and decode errors may wrap I/O errors, which can't be compared, so they are compared by their messages.
+            (
+                ConnectionAttemptError::EncodePacket(left),
+                ConnectionAttemptError::EncodePacket(right),
+            ) => left.to_string() == right.to_string(),
+            (
+                ConnectionAttemptError::DecodePacket(left),
+                ConnectionAttemptError::DecodePacket(right),
+            ) => left.to_string() == right.to_string(),
+            (
+                ConnectionAttemptError::ConnectionRefused(left),
+                ConnectionAttemptError::ConnectionRefused(right),
+            ) => left == right,
+            (
+                ConnectionAttemptError::UnexpectedPacket(left),
+                ConnectionAttemptError::UnexpectedPacket(right),
+            ) => left == right,
+            (
+                ConnectionAttemptError::ServerClosedConnection,
+                ConnectionAttemptError::ServerClosedConnection,
+            ) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ConnectionAttemptError {}
+
 #[derive(Debug)]
 pub enum ShutdownError {
     ClientDoesNotExist,
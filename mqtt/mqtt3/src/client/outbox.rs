@@ -0,0 +1,704 @@
+/// A persistent store of QoS 1 and QoS 2 publications that have not been acknowledged by the server yet.
+///
+/// When a `Client` is given an outbox with [`crate::Client::with_outbox`], every QoS 1 and QoS 2 publication is stored
+/// in the outbox as soon as the client accepts it, and removed once the server acknowledges it (PUBACK or PUBCOMP).
+/// Publications left in the outbox by a previous instance of the client are replayed with the DUP flag set.
+pub trait Outbox: std::fmt::Debug {
+    /// Returns publications stored by a previous instance of the client along with their keys,
+    /// in the order they were stored.
+    fn load(&mut self) -> std::io::Result<Vec<(u64, crate::proto::Publication)>>;
+
+    /// Stores a publication. Returns a key to remove the publication with.
+    ///
+    /// The publication doesn't have to be durable yet when this returns, see [`Outbox::poll_flush`].
+    fn insert(&mut self, publication: &crate::proto::Publication) -> std::io::Result<u64>;
+
+    /// Removes a publication acknowledged by the server.
+    fn remove(&mut self, key: u64) -> std::io::Result<()>;
+
+    /// Resolves once the publication stored with the given key is durable. The client doesn't send
+    /// a stored publication to the server before that.
+    ///
+    /// Resolves with an error if the publication could not be written. The client then removes the publication
+    /// and fails it with [`crate::PublishError::Outbox`] instead of sending it.
+    ///
+    /// The default implementation considers publications durable as soon as they are stored.
+    fn poll_flush(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+        _key: u64,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+/// A constant set of bytes that marks the beginning of a record.
+const RECORD_HINT: u32 = 0xdead_beef;
+
+/// Size of a record header: hint, kind, key, data size, data crc and header crc.
+const RECORD_HEADER_SIZE: usize = 4 + 1 + 8 + 4 + 4 + 4;
+
+/// Number of removed records after which the file is compacted, if it holds more removed records than live ones.
+const COMPACTION_THRESHOLD: usize = 1024;
+
+const RECORD_INSERT: u8 = 1;
+const RECORD_REMOVE: u8 = 2;
+
+/// An [`Outbox`] that stores publications in an append-only file.
+///
+/// The file is a sequence of records. Each record is a header followed by data:
+///
+/// ```text
+/// +------+------+-----+-----------+----------+------------+---------+
+/// | hint | kind | key | data size | data crc | header crc | data... |
+/// +------+------+-----+-----------+----------+------------+---------+
+/// ```
+///
+/// An insert record holds an encoded publication, a remove record holds no data. Crcs are validated on load,
+/// and the file is truncated at the first invalid record, which is the result of an interrupted write.
+///
+/// Records are written by a dedicated thread, so that the client task is never blocked on disk I/O.
+/// The thread writes all records queued since the previous write at once and syncs them to disk
+/// with a single call, and inserted publications are sent to the server only after that.
+/// If the write fails, the file is truncated back to its previous length and publications
+/// inserted by the failed write are reported as failed by [`Outbox::poll_flush`].
+///
+/// The file is compacted once it accumulates enough removed publications.
+#[derive(Debug)]
+pub struct FileOutbox {
+    path: std::path::PathBuf,
+    /// The file until it is handed over to the writer thread
+    file: Option<std::fs::File>,
+    writer: Option<Writer>,
+    live: std::collections::BTreeSet<u64>,
+    removed: usize,
+    next_key: u64,
+}
+
+impl FileOutbox {
+    /// Opens an outbox file, creating it if it does not exist.
+    pub fn open(path: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = open_file(&path)?;
+
+        Ok(FileOutbox {
+            path,
+            file: Some(file),
+            writer: None,
+            live: Default::default(),
+            removed: 0,
+            next_key: 0,
+        })
+    }
+
+    fn send(&mut self, command: Command) -> std::io::Result<()> {
+        if self.writer.is_none() {
+            let file = self.file.take().ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::Other, "outbox file is closed")
+            })?;
+            self.writer = Some(Writer::spawn(self.path.clone(), file, self.next_key)?);
+        }
+
+        let writer_stopped = || {
+            std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "outbox writer thread has stopped",
+            )
+        };
+        self.writer
+            .as_ref()
+            .and_then(|writer| writer.sender.as_ref())
+            .ok_or_else(writer_stopped)?
+            .send(command)
+            .map_err(|_| writer_stopped())
+    }
+}
+
+impl Outbox for FileOutbox {
+    fn load(&mut self) -> std::io::Result<Vec<(u64, crate::proto::Publication)>> {
+        let file = self.file.as_mut().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "outbox cannot be loaded after publications are stored",
+            )
+        })?;
+        let records = read_records(&self.path, file)?;
+
+        // Keys of removed publications are not reused, since their remove records are still in the file.
+        self.next_key = records
+            .iter()
+            .map(|record| record.key + 1)
+            .max()
+            .unwrap_or(0);
+        self.removed = records
+            .iter()
+            .filter(|record| record.publication.is_none())
+            .count();
+
+        let records = live_records(records);
+        self.live = records.iter().map(|record| record.key).collect();
+
+        Ok(records
+            .into_iter()
+            .filter_map(|record| {
+                let key = record.key;
+                record.publication.map(|publication| (key, publication))
+            })
+            .collect())
+    }
+
+    fn insert(&mut self, publication: &crate::proto::Publication) -> std::io::Result<u64> {
+        let key = self.next_key;
+        self.send(Command::Append(Record {
+            key,
+            publication: Some(publication.clone()),
+        }))?;
+
+        self.next_key += 1;
+        self.live.insert(key);
+        Ok(key)
+    }
+
+    fn remove(&mut self, key: u64) -> std::io::Result<()> {
+        if !self.live.remove(&key) {
+            return Ok(());
+        }
+
+        self.send(Command::Append(Record {
+            key,
+            publication: None,
+        }))?;
+
+        self.removed += 1;
+        if self.removed >= COMPACTION_THRESHOLD && self.removed > self.live.len() {
+            self.send(Command::Compact)?;
+            self.removed = 0;
+        }
+
+        Ok(())
+    }
+
+    fn poll_flush(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+        key: u64,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let writer = match &self.writer {
+            Some(writer) => writer,
+            None => return std::task::Poll::Ready(Ok(())),
+        };
+
+        // Register before checking the progress, so that a write completed in between is not missed.
+        writer.state.waker.register(cx.waker());
+
+        if writer
+            .state
+            .processed
+            .load(std::sync::atomic::Ordering::Acquire)
+            <= key
+        {
+            return std::task::Poll::Pending;
+        }
+
+        match lock(&writer.state.failed).remove(&key) {
+            Some(err) => std::task::Poll::Ready(Err(err)),
+            None => std::task::Poll::Ready(Ok(())),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Command {
+    Append(Record),
+    Compact,
+}
+
+/// A thread that writes records to the outbox file.
+#[derive(Debug)]
+struct Writer {
+    sender: Option<std::sync::mpsc::Sender<Command>>,
+    state: std::sync::Arc<WriterState>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+/// Progress of the writer thread shared with the outbox.
+#[derive(Debug)]
+struct WriterState {
+    /// Key following the last insert record processed by the writer thread, whether it was written or not
+    processed: std::sync::atomic::AtomicU64,
+    /// Errors of insert records which could not be written, reported once by `poll_flush` for their keys
+    failed: std::sync::Mutex<std::collections::BTreeMap<u64, std::io::Error>>,
+    waker: futures_util::task::AtomicWaker,
+}
+
+impl Writer {
+    /// Spawns the writer thread. Publications with keys below `next_key` are already in the file.
+    fn spawn(
+        path: std::path::PathBuf,
+        file: std::fs::File,
+        next_key: u64,
+    ) -> std::io::Result<Self> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let state = std::sync::Arc::new(WriterState {
+            processed: next_key.into(),
+            failed: Default::default(),
+            waker: Default::default(),
+        });
+
+        let thread = std::thread::Builder::new()
+            .name("outbox-writer".into())
+            .spawn({
+                let state = state.clone();
+                move || run_writer(&path, file, &receiver, &state)
+            })?;
+
+        Ok(Writer {
+            sender: Some(sender),
+            state,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        // Closing the channel stops the thread once it has written all queued records.
+        drop(self.sender.take());
+
+        // Waiting for the thread blocks on disk I/O, so on a tokio runtime it's done by a blocking task.
+        // Without a runtime, the thread is joined on the calling thread.
+        if let Some(thread) = self.thread.take() {
+            let join = move || {
+                if thread.join().is_err() {
+                    log::warn!("outbox writer thread panicked");
+                }
+            };
+
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    runtime.spawn_blocking(join);
+                }
+                Err(_) => join(),
+            }
+        }
+    }
+}
+
+fn run_writer(
+    path: &std::path::Path,
+    mut file: std::fs::File,
+    receiver: &std::sync::mpsc::Receiver<Command>,
+    state: &WriterState,
+) {
+    while let Ok(command) = receiver.recv() {
+        // Group all records queued so far into a single write and sync.
+        let mut bytes = bytes::BytesMut::new();
+        let mut inserted = vec![];
+        let mut compact = false;
+        for command in std::iter::once(command).chain(receiver.try_iter()) {
+            match command {
+                Command::Append(record) => {
+                    if record.publication.is_some() {
+                        inserted.push(record.key);
+                    }
+                    record.encode(&mut bytes);
+                }
+                Command::Compact => compact = true,
+            }
+        }
+
+        match write_batch(&mut file, &bytes) {
+            Ok(()) => {
+                if compact {
+                    match compact_file(path, &mut file) {
+                        Ok(compacted) => file = compacted,
+                        Err(err) => {
+                            log::warn!("could not compact outbox file {}: {}", path.display(), err)
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                // Removed publications are kept in the file, and replayed if the client restarts before
+                // they are removed by a later write.
+                log::warn!(
+                    "could not write {} publications to outbox file {}: {}",
+                    inserted.len(),
+                    path.display(),
+                    err
+                );
+
+                let mut failed = lock(&state.failed);
+                for key in &inserted {
+                    failed.insert(*key, std::io::Error::new(err.kind(), err.to_string()));
+                }
+            }
+        }
+
+        // Failed keys are recorded before the progress is published, see `FileOutbox::poll_flush`.
+        if let Some(key) = inserted.last() {
+            state
+                .processed
+                .store(key + 1, std::sync::atomic::Ordering::Release);
+        }
+        state.waker.wake();
+    }
+}
+
+fn open_file(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+}
+
+/// Appends records to the file. If the write fails, the file is truncated back to its previous length,
+/// so that a partially written record doesn't invalidate records written after it.
+fn write_batch(file: &mut std::fs::File, bytes: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    if bytes.is_empty() {
+        return Ok(());
+    }
+
+    let len = file.metadata()?.len();
+    let result = file.write_all(bytes).and_then(|()| file.sync_data());
+    if result.is_err() {
+        if let Err(err) = file.set_len(len) {
+            log::warn!("could not truncate outbox file after failed write: {}", err);
+        }
+    }
+
+    result
+}
+
+/// Reads all valid records of the file and truncates the file after the last valid one.
+fn read_records(path: &std::path::Path, file: &mut std::fs::File) -> std::io::Result<Vec<Record>> {
+    use std::io::{Read, Seek};
+
+    let mut contents = vec![];
+    file.seek(std::io::SeekFrom::Start(0))?;
+    file.read_to_end(&mut contents)?;
+
+    let mut records = vec![];
+    let mut offset = 0;
+    while offset < contents.len() {
+        match Record::decode(&contents[offset..]) {
+            Some((record, len)) => {
+                records.push(record);
+                offset += len;
+            }
+            None => {
+                log::warn!(
+                    "outbox file {} contains an invalid record at offset {}, truncating",
+                    path.display(),
+                    offset
+                );
+                file.set_len(offset as u64)?;
+                file.sync_data()?;
+                break;
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// Rewrites the file with live publications only. Returns the rewritten file.
+fn compact_file(
+    path: &std::path::Path,
+    file: &mut std::fs::File,
+) -> std::io::Result<std::fs::File> {
+    use std::io::Write;
+
+    let records = live_records(read_records(path, file)?);
+
+    let mut bytes = bytes::BytesMut::new();
+    for record in &records {
+        record.encode(&mut bytes);
+    }
+
+    let temp = path.with_extension("tmp");
+    let mut compacted = std::fs::File::create(&temp)?;
+    compacted.write_all(&bytes)?;
+    compacted.sync_all()?;
+    std::fs::rename(&temp, path)?;
+
+    log::debug!(
+        "compacted outbox file {} to {} publications",
+        path.display(),
+        records.len()
+    );
+    open_file(path)
+}
+
+fn lock<T>(mutex: &std::sync::Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Returns insert records which don't have a matching remove record.
+fn live_records(records: Vec<Record>) -> Vec<Record> {
+    let removed: std::collections::HashSet<_> = records
+        .iter()
+        .filter(|record| record.publication.is_none())
+        .map(|record| record.key)
+        .collect();
+
+    records
+        .into_iter()
+        .filter(|record| record.publication.is_some() && !removed.contains(&record.key))
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+struct Record {
+    key: u64,
+    publication: Option<crate::proto::Publication>,
+}
+
+impl Record {
+    fn encode(&self, dst: &mut bytes::BytesMut) {
+        use bytes::BufMut;
+
+        let mut data = bytes::BytesMut::new();
+        let kind = match &self.publication {
+            Some(publication) => {
+                encode_publication(publication, &mut data);
+                RECORD_INSERT
+            }
+            None => RECORD_REMOVE,
+        };
+
+        let mut header = bytes::BytesMut::with_capacity(RECORD_HEADER_SIZE);
+        header.put_u32(RECORD_HINT);
+        header.put_u8(kind);
+        header.put_u64(self.key);
+        #[allow(clippy::cast_possible_truncation)]
+        header.put_u32(data.len() as u32);
+        header.put_u32(crc(&data));
+        let header_crc = crc(&header);
+        header.put_u32(header_crc);
+
+        dst.extend_from_slice(&header);
+        dst.extend_from_slice(&data);
+    }
+
+    /// Decodes a record from the beginning of `src`. Returns the record and its length,
+    /// or `None` if `src` doesn't start with a valid record.
+    fn decode(mut src: &[u8]) -> Option<(Self, usize)> {
+        use bytes::Buf;
+
+        if src.len() < RECORD_HEADER_SIZE {
+            return None;
+        }
+
+        let header_crc = crc(&src[..RECORD_HEADER_SIZE - 4]);
+
+        let hint = src.get_u32();
+        let kind = src.get_u8();
+        let key = src.get_u64();
+        let data_size = src.get_u32() as usize;
+        let data_crc = src.get_u32();
+        if hint != RECORD_HINT || src.get_u32() != header_crc {
+            return None;
+        }
+
+        if src.len() < data_size || crc(&src[..data_size]) != data_crc {
+            return None;
+        }
+        let data = &src[..data_size];
+
+        let publication = match kind {
+            RECORD_INSERT => Some(decode_publication(data)?),
+            RECORD_REMOVE => None,
+            _ => return None,
+        };
+
+        Some((Record { key, publication }, RECORD_HEADER_SIZE + data_size))
+    }
+}
+
+/// Encodes a publication as QoS, retain flag, topic name length, topic name and payload.
+fn encode_publication(publication: &crate::proto::Publication, dst: &mut bytes::BytesMut) {
+    use bytes::BufMut;
+
+    dst.put_u8(match publication.qos {
+        crate::proto::QoS::AtMostOnce => 0,
+        crate::proto::QoS::AtLeastOnce => 1,
+        crate::proto::QoS::ExactlyOnce => 2,
+    });
+    dst.put_u8(publication.retain.into());
+    #[allow(clippy::cast_possible_truncation)]
+    dst.put_u32(publication.topic_name.len() as u32);
+    dst.put_slice(publication.topic_name.as_bytes());
+    dst.put_slice(&publication.payload);
+}
+
+fn decode_publication(mut src: &[u8]) -> Option<crate::proto::Publication> {
+    use bytes::Buf;
+
+    if src.len() < 6 {
+        return None;
+    }
+
+    let qos = match src.get_u8() {
+        0 => crate::proto::QoS::AtMostOnce,
+        1 => crate::proto::QoS::AtLeastOnce,
+        2 => crate::proto::QoS::ExactlyOnce,
+        _ => return None,
+    };
+    let retain = src.get_u8() != 0;
+    let topic_name_len = src.get_u32() as usize;
+    if src.len() < topic_name_len {
+        return None;
+    }
+    let topic_name = std::str::from_utf8(&src[..topic_name_len]).ok()?.to_owned();
+    let payload = bytes::Bytes::copy_from_slice(&src[topic_name_len..]);
+
+    Some(crate::proto::Publication {
+        topic_name,
+        qos,
+        retain,
+        payload,
+    })
+}
+
+fn crc(bytes: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(bytes);
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileOutbox, Outbox, COMPACTION_THRESHOLD};
+
+    #[test]
+    fn file_outbox_persists_publications() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("outbox");
+
+        let mut outbox = FileOutbox::open(&path).unwrap();
+        assert_eq!(outbox.load().unwrap(), vec![]);
+
+        let key1 = outbox.insert(&publication("topic1")).unwrap();
+        let key2 = outbox.insert(&publication("topic2")).unwrap();
+        let key3 = outbox.insert(&publication("topic3")).unwrap();
+        outbox.remove(key3).unwrap();
+        drop(outbox);
+
+        let mut outbox = FileOutbox::open(&path).unwrap();
+        assert_eq!(
+            outbox.load().unwrap(),
+            vec![(key1, publication("topic1")), (key2, publication("topic2"))]
+        );
+
+        // keys of removed publications are not reused after restart
+        let key4 = outbox.insert(&publication("topic4")).unwrap();
+        assert!(key4 > key3);
+    }
+
+    #[test]
+    fn file_outbox_truncates_interrupted_write() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("outbox");
+
+        let mut outbox = FileOutbox::open(&path).unwrap();
+        let key1 = outbox.insert(&publication("topic1")).unwrap();
+        outbox.insert(&publication("topic2")).unwrap();
+        drop(outbox);
+
+        // chop off the end of the last record
+        let len = std::fs::metadata(&path).unwrap().len();
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - 3).unwrap();
+        drop(file);
+
+        let mut outbox = FileOutbox::open(&path).unwrap();
+        assert_eq!(outbox.load().unwrap(), vec![(key1, publication("topic1"))]);
+
+        let key3 = outbox.insert(&publication("topic3")).unwrap();
+        drop(outbox);
+
+        let mut outbox = FileOutbox::open(&path).unwrap();
+        assert_eq!(
+            outbox.load().unwrap(),
+            vec![(key1, publication("topic1")), (key3, publication("topic3"))]
+        );
+    }
+
+    #[test]
+    fn file_outbox_compacts_removed_publications() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("outbox");
+
+        let mut outbox = FileOutbox::open(&path).unwrap();
+        let kept = outbox.insert(&publication("kept")).unwrap();
+        for _ in 0..COMPACTION_THRESHOLD {
+            let key = outbox.insert(&publication("removed")).unwrap();
+            outbox.remove(key).unwrap();
+        }
+        drop(outbox);
+
+        let len = std::fs::metadata(&path).unwrap().len();
+        assert!(len < 100, "file is not compacted: {} bytes", len);
+
+        let mut outbox = FileOutbox::open(&path).unwrap();
+        assert_eq!(outbox.load().unwrap(), vec![(kept, publication("kept"))]);
+    }
+
+    #[tokio::test]
+    async fn file_outbox_flushes_publications_in_background() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("outbox");
+
+        let mut outbox = FileOutbox::open(&path).unwrap();
+        assert_eq!(outbox.load().unwrap(), vec![]);
+
+        let key1 = outbox.insert(&publication("topic1")).unwrap();
+        let key2 = outbox.insert(&publication("topic2")).unwrap();
+        futures_util::future::poll_fn(|cx| outbox.poll_flush(cx, key2))
+            .await
+            .unwrap();
+
+        // both publications are durable while the outbox is still open
+        let mut reopened = FileOutbox::open(&path).unwrap();
+        assert_eq!(
+            reopened.load().unwrap(),
+            vec![(key1, publication("topic1")), (key2, publication("topic2"))]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn file_outbox_reports_failed_writes() {
+        // every write to /dev/full fails with ENOSPC
+        let mut outbox = FileOutbox::open("/dev/full").unwrap();
+
+        let key1 = outbox.insert(&publication("topic1")).unwrap();
+        let key2 = outbox.insert(&publication("topic2")).unwrap();
+
+        for key in &[key1, key2] {
+            let err = futures_util::future::poll_fn(|cx| outbox.poll_flush(cx, *key))
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("No space left on device"));
+        }
+    }
+
+    fn publication(topic_name: &str) -> crate::proto::Publication {
+        crate::proto::Publication {
+            topic_name: topic_name.to_owned(),
+            qos: crate::proto::QoS::AtLeastOnce,
+            retain: false,
+            payload: bytes::Bytes::from_static(b"payload"),
+        }
+    }
+}
@@ -8,10 +8,7 @@ pub(super) struct State {
     publish_requests_waiting_to_be_sent: std::collections::VecDeque<PublishRequest>,
 
     /// Holds PUBLISH packets sent by us, waiting for a corresponding PUBACK or PUBREC
-    waiting_to_be_acked: std::collections::BTreeMap<
-        crate::proto::PacketIdentifier,
-        (futures_channel::oneshot::Sender<()>, crate::proto::Publish),
-    >,
+    waiting_to_be_acked: std::collections::BTreeMap<crate::proto::PacketIdentifier, SentPublish>,
 
     /// Holds the identifiers of PUBREC packets sent by us, waiting for a corresponding PUBREL,
     /// and the contents of the original PUBLISH packet for which we sent the PUBREC
@@ -19,10 +16,17 @@ pub(super) struct State {
         std::collections::BTreeMap<crate::proto::PacketIdentifier, crate::ReceivedPublication>,
 
     /// Holds PUBLISH packets sent by us, waiting for a corresponding PUBCOMP
-    waiting_to_be_completed: std::collections::BTreeMap<
-        crate::proto::PacketIdentifier,
-        (futures_channel::oneshot::Sender<()>, crate::proto::Publish),
-    >,
+    waiting_to_be_completed:
+        std::collections::BTreeMap<crate::proto::PacketIdentifier, SentPublish>,
+
+    /// Persists QoS 1 and QoS 2 publications until they are acknowledged by the server
+    outbox: Option<Box<dyn super::Outbox + Send>>,
+
+    /// Max number of AtLeastOnce and ExactlyOnce publications sent but not yet acknowledged by the server
+    max_inflight: usize,
+
+    /// Inflight and queued publication counters shared with publish handles
+    counters: std::sync::Arc<PublishCounters>,
 }
 
 impl State {
@@ -47,12 +51,17 @@ impl State {
         match packet.take() {
             Some(crate::proto::Packet::PubAck(crate::proto::PubAck { packet_identifier })) => {
                 match self.waiting_to_be_acked.remove(&packet_identifier) {
-                    Some((ack_sender, _)) => {
+                    Some(SentPublish {
+                        ack_sender,
+                        outbox_key,
+                        ..
+                    }) => {
                         packet_identifiers.discard(packet_identifier);
+                        self.remove_from_outbox(outbox_key);
 
-                        match ack_sender.send(()) {
+                        match ack_sender.send(Ok(())) {
 						Ok(()) => (),
-						Err(()) => log::debug!("could not send ack for publish request because ack receiver has been dropped"),
+						Err(_) => log::debug!("could not send ack for publish request because ack receiver has been dropped"),
 					}
                     }
                     None => log::warn!("ignoring PUBACK for a PUBLISH we never sent"),
@@ -61,12 +70,17 @@ impl State {
 
             Some(crate::proto::Packet::PubComp(crate::proto::PubComp { packet_identifier })) => {
                 match self.waiting_to_be_completed.remove(&packet_identifier) {
-                    Some((ack_sender, _)) => {
+                    Some(SentPublish {
+                        ack_sender,
+                        outbox_key,
+                        ..
+                    }) => {
                         packet_identifiers.discard(packet_identifier);
+                        self.remove_from_outbox(outbox_key);
 
-                        match ack_sender.send(()) {
+                        match ack_sender.send(Ok(())) {
 						Ok(()) => (),
-						Err(()) => log::debug!("could not send ack for publish request because ack receiver has been dropped"),
+						Err(_) => log::debug!("could not send ack for publish request because ack receiver has been dropped"),
 					}
                     }
                     None => log::warn!("ignoring PUBCOMP for a PUBREL we never sent"),
@@ -135,9 +149,9 @@ impl State {
 
             Some(crate::proto::Packet::PubRec(crate::proto::PubRec { packet_identifier })) => {
                 match self.waiting_to_be_acked.remove(&packet_identifier) {
-                    Some((ack_sender, packet)) => {
+                    Some(sent_publish) => {
                         self.waiting_to_be_completed
-                            .insert(packet_identifier, (ack_sender, packet));
+                            .insert(packet_identifier, sent_publish);
                     }
                     None => log::warn!("ignoring PUBREC for a PUBLISH we never sent"),
                 }
@@ -163,18 +177,41 @@ impl State {
             other => *packet = other,
         }
 
-        while let std::task::Poll::Ready(Some(publish_request)) =
-            std::pin::Pin::new(&mut self.publish_request_recv).poll_next(cx)
-        {
-            self.publish_requests_waiting_to_be_sent
-                .push_back(publish_request);
-        }
+        loop {
+            // Publish requests are pulled from the channel only when there are no queued ones,
+            // so that publish handles wait for capacity while the inflight window is full.
+            if self.publish_requests_waiting_to_be_sent.is_empty() {
+                let publish_request =
+                    std::pin::Pin::new(&mut self.publish_request_recv).poll_next(cx);
+                match publish_request {
+                    std::task::Poll::Ready(Some(publish_request)) => self.enqueue(publish_request),
+                    std::task::Poll::Ready(None) | std::task::Poll::Pending => break,
+                }
+            }
+
+            // Publications are sent in order, so an AtMostOnce publication queued after
+            // an AtLeastOnce or ExactlyOnce one waits for the window too.
+            if self.is_window_full() {
+                break;
+            }
+
+            // Publications stored in the outbox are sent only once they are durable.
+            if !self.poll_outbox_flush(cx) {
+                break;
+            }
+
+            let PublishRequest {
+                publication,
+                ack_sender,
+                outbox_key,
+                dup,
+            } = match self.publish_requests_waiting_to_be_sent.pop_front() {
+                Some(publish_request) => publish_request,
+                // Queued publications may have failed to be stored in the outbox,
+                // the next ones are pulled from the channel.
+                None => continue,
+            };
 
-        while let Some(PublishRequest {
-            publication,
-            ack_sender,
-        }) = self.publish_requests_waiting_to_be_sent.pop_front()
-        {
             match publication.qos {
                 crate::proto::QoS::AtMostOnce => {
                     packets_waiting_to_be_sent.push(crate::proto::Packet::Publish(
@@ -187,9 +224,9 @@ impl State {
                         },
                     ));
 
-                    match ack_sender.send(()) {
+                    match ack_sender.send(Ok(())) {
 						Ok(()) => (),
-						Err(()) => log::debug!("could not send ack for publish request because ack receiver has been dropped"),
+						Err(_) => log::debug!("could not send ack for publish request because ack receiver has been dropped"),
 					}
                 }
 
@@ -201,6 +238,8 @@ impl State {
                                 .push_front(PublishRequest {
                                     publication,
                                     ack_sender,
+                                    outbox_key,
+                                    dup,
                                 });
                             return Err(err);
                         }
@@ -210,7 +249,7 @@ impl State {
                         packet_identifier_dup_qos:
                             crate::proto::PacketIdentifierDupQoS::AtLeastOnce(
                                 packet_identifier,
-                                false,
+                                dup,
                             ),
                         retain: publication.retain,
                         topic_name: publication.topic_name.clone(),
@@ -219,9 +258,9 @@ impl State {
 
                     self.waiting_to_be_acked.insert(
                         packet_identifier,
-                        (
+                        SentPublish {
                             ack_sender,
-                            crate::proto::Publish {
+                            packet: crate::proto::Publish {
                                 packet_identifier_dup_qos:
                                     crate::proto::PacketIdentifierDupQoS::AtLeastOnce(
                                         packet_identifier,
@@ -231,7 +270,8 @@ impl State {
                                 topic_name: publication.topic_name,
                                 payload: publication.payload,
                             },
-                        ),
+                            outbox_key,
+                        },
                     );
 
                     packets_waiting_to_be_sent.push(packet);
@@ -245,6 +285,8 @@ impl State {
                                 .push_front(PublishRequest {
                                     publication,
                                     ack_sender,
+                                    outbox_key,
+                                    dup,
                                 });
                             return Err(err);
                         }
@@ -254,7 +296,7 @@ impl State {
                         packet_identifier_dup_qos:
                             crate::proto::PacketIdentifierDupQoS::ExactlyOnce(
                                 packet_identifier,
-                                false,
+                                dup,
                             ),
                         retain: publication.retain,
                         topic_name: publication.topic_name.clone(),
@@ -263,9 +305,9 @@ impl State {
 
                     self.waiting_to_be_acked.insert(
                         packet_identifier,
-                        (
+                        SentPublish {
                             ack_sender,
-                            crate::proto::Publish {
+                            packet: crate::proto::Publish {
                                 packet_identifier_dup_qos:
                                     crate::proto::PacketIdentifierDupQoS::ExactlyOnce(
                                         packet_identifier,
@@ -275,7 +317,8 @@ impl State {
                                 topic_name: publication.topic_name,
                                 payload: publication.payload,
                             },
-                        ),
+                            outbox_key,
+                        },
                     );
 
                     packets_waiting_to_be_sent.push(packet);
@@ -283,6 +326,8 @@ impl State {
             }
         }
 
+        self.update_counters();
+
         Ok((packets_waiting_to_be_sent, publication_received))
     }
 
@@ -304,7 +349,7 @@ impl State {
 
         self.waiting_to_be_acked
             .values()
-            .map(|(_, packet)| crate::proto::Packet::Publish(packet.clone()))
+            .map(|SentPublish { packet, .. }| crate::proto::Packet::Publish(packet.clone()))
             .chain(
                 self.waiting_to_be_released
                     .keys()
@@ -315,7 +360,9 @@ impl State {
             .chain(
                 self.waiting_to_be_completed
                     .values()
-                    .map(|(_, packet)| crate::proto::Packet::Publish(packet.clone())),
+                    .map(|SentPublish { packet, .. }| {
+                        crate::proto::Packet::Publish(packet.clone())
+                    }),
             )
     }
 
@@ -328,10 +375,12 @@ impl State {
             Ok(publish_request) => {
                 use futures_util::TryFutureExt;
 
-                self.publish_requests_waiting_to_be_sent
-                    .push_back(publish_request);
+                self.enqueue(publish_request);
+                self.update_counters();
                 futures_util::future::Either::Left(
-                    ack_receiver.map_err(|_| PublishError::ClientDoesNotExist),
+                    ack_receiver
+                        .map_err(|_| PublishError::ClientDoesNotExist)
+                        .and_then(futures_util::future::ready),
                 )
             }
 
@@ -340,7 +389,166 @@ impl State {
     }
 
     pub(super) fn publish_handle(&self) -> PublishHandle {
-        PublishHandle(self.publish_request_send.clone())
+        PublishHandle {
+            sender: self.publish_request_send.clone(),
+            counters: self.counters.clone(),
+        }
+    }
+
+    pub(super) fn set_max_inflight(&mut self, max_inflight: std::num::NonZeroUsize) {
+        self.max_inflight = max_inflight.get();
+    }
+
+    /// Sets the outbox and queues publications left in it by a previous instance of the client
+    /// ahead of any other publication. These publications are sent with the DUP flag set.
+    pub(super) fn set_outbox(
+        &mut self,
+        mut outbox: Box<dyn super::Outbox + Send>,
+    ) -> std::io::Result<()> {
+        let publications = outbox.load()?;
+        if !publications.is_empty() {
+            log::info!(
+                "replaying {} unacknowledged publications from outbox",
+                publications.len()
+            );
+        }
+
+        for (key, publication) in publications.into_iter().rev() {
+            // Nobody waits for the ack of a publication made by a previous instance of the client.
+            let (ack_sender, _) = futures_channel::oneshot::channel();
+            self.publish_requests_waiting_to_be_sent
+                .push_front(PublishRequest {
+                    publication,
+                    ack_sender,
+                    outbox_key: Some(key),
+                    dup: true,
+                });
+        }
+
+        self.outbox = Some(outbox);
+        self.update_counters();
+        Ok(())
+    }
+
+    /// Number of AtLeastOnce and ExactlyOnce publications sent but not yet acknowledged by the server
+    fn inflight(&self) -> usize {
+        self.waiting_to_be_acked.len() + self.waiting_to_be_completed.len()
+    }
+
+    fn is_window_full(&self) -> bool {
+        match self.publish_requests_waiting_to_be_sent.front() {
+            Some(publish_request)
+                if publish_request.publication.qos != crate::proto::QoS::AtMostOnce =>
+            {
+                self.inflight() >= self.max_inflight
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns false while the next publication to send is stored in the outbox but not durable yet.
+    ///
+    /// Publications which could not be written to the outbox are failed instead of being sent.
+    fn poll_outbox_flush(&mut self, cx: &mut std::task::Context<'_>) -> bool {
+        loop {
+            let (outbox, key) = match (
+                &mut self.outbox,
+                self.publish_requests_waiting_to_be_sent.front(),
+            ) {
+                (
+                    Some(outbox),
+                    Some(PublishRequest {
+                        outbox_key: Some(key),
+                        ..
+                    }),
+                ) => (outbox, *key),
+                _ => return true,
+            };
+
+            match outbox.poll_flush(cx, key) {
+                std::task::Poll::Ready(Ok(())) => return true,
+                std::task::Poll::Ready(Err(err)) => {
+                    if let Err(err) = outbox.remove(key) {
+                        log::warn!("could not remove failed publication from outbox: {}", err);
+                    }
+
+                    let PublishRequest {
+                        publication,
+                        ack_sender,
+                        ..
+                    } = self
+                        .publish_requests_waiting_to_be_sent
+                        .pop_front()
+                        .expect("publish request is at the front of the queue");
+
+                    // The publication is not sent, since it would be lost if the client restarted before it is acknowledged.
+                    log::warn!(
+                        "could not write publication to topic {:?} to outbox: {}",
+                        publication.topic_name,
+                        err
+                    );
+                    match ack_sender.send(Err(PublishError::Outbox(publication, err))) {
+                        Ok(()) => (),
+                        Err(_) => log::debug!("could not send outbox error for publish request because ack receiver has been dropped"),
+                    }
+                }
+                std::task::Poll::Pending => return false,
+            }
+        }
+    }
+
+    fn update_counters(&self) {
+        self.counters
+            .inflight
+            .store(self.inflight(), std::sync::atomic::Ordering::Relaxed);
+        self.counters.queued.store(
+            self.publish_requests_waiting_to_be_sent.len(),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+
+    fn enqueue(&mut self, mut publish_request: PublishRequest) {
+        if let Some(outbox) = &mut self.outbox {
+            if publish_request.publication.qos != crate::proto::QoS::AtMostOnce {
+                match outbox.insert(&publish_request.publication) {
+                    Ok(key) => publish_request.outbox_key = Some(key),
+                    Err(err) => {
+                        // The publication is not sent, since it would be lost if the client restarted before it is acknowledged.
+                        log::warn!(
+                            "could not store publication to topic {:?} in outbox: {}",
+                            publish_request.publication.topic_name,
+                            err
+                        );
+
+                        let PublishRequest {
+                            publication,
+                            ack_sender,
+                            ..
+                        } = publish_request;
+                        match ack_sender.send(Err(PublishError::Outbox(publication, err))) {
+                            Ok(()) => (),
+                            Err(_) => log::debug!("could not send outbox error for publish request because ack receiver has been dropped"),
+                        }
+
+                        return;
+                    }
+                }
+            }
+        }
+
+        self.publish_requests_waiting_to_be_sent
+            .push_back(publish_request);
+    }
+
+    fn remove_from_outbox(&mut self, outbox_key: Option<u64>) {
+        if let (Some(outbox), Some(key)) = (&mut self.outbox, outbox_key) {
+            if let Err(err) = outbox.remove(key) {
+                log::warn!(
+                    "could not remove acknowledged publication from outbox: {}",
+                    err
+                );
+            }
+        }
     }
 }
 
@@ -356,13 +564,27 @@ impl Default for State {
             waiting_to_be_acked: Default::default(),
             waiting_to_be_released: Default::default(),
             waiting_to_be_completed: Default::default(),
+
+            outbox: None,
+
+            max_inflight: usize::max_value(),
+            counters: Default::default(),
         }
     }
 }
 
 /// Used to publish messages to the server
 #[derive(Clone, Debug)]
-pub struct PublishHandle(futures_channel::mpsc::Sender<PublishRequest>);
+pub struct PublishHandle {
+    sender: futures_channel::mpsc::Sender<PublishRequest>,
+    counters: std::sync::Arc<PublishCounters>,
+}
+
+#[derive(Debug, Default)]
+struct PublishCounters {
+    inflight: std::sync::atomic::AtomicUsize,
+    queued: std::sync::atomic::AtomicUsize,
+}
 
 impl PublishHandle {
     /// Publish the given message to the server
@@ -375,14 +597,30 @@ impl PublishHandle {
         let (ack_sender, ack_receiver) = futures_channel::oneshot::channel();
 
         let publish_request = PublishRequest::new(publication, ack_sender)?;
-        self.0
+        self.sender
             .send(publish_request)
             .await
             .map_err(|_| PublishError::ClientDoesNotExist)?;
         ack_receiver
             .await
-            .map_err(|_| PublishError::ClientDoesNotExist)?;
-        Ok(())
+            .map_err(|_| PublishError::ClientDoesNotExist)?
+    }
+
+    /// Returns the number of AtLeastOnce and ExactlyOnce publications sent to the server
+    /// and not acknowledged yet.
+    pub fn inflight(&self) -> usize {
+        self.counters
+            .inflight
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Returns the number of publications accepted by the client but not sent to the server yet.
+    ///
+    /// Publications of `publish` calls waiting for the client to accept them are not counted.
+    pub fn queued(&self) -> usize {
+        self.counters
+            .queued
+            .load(std::sync::atomic::Ordering::Relaxed)
     }
 }
 
@@ -390,6 +628,7 @@ impl PublishHandle {
 pub enum PublishError {
     ClientDoesNotExist,
     EncodePacket(crate::proto::Publication, crate::proto::EncodeError),
+    Outbox(crate::proto::Publication, std::io::Error),
 }
 
 impl std::fmt::Display for PublishError {
@@ -401,6 +640,11 @@ impl std::fmt::Display for PublishError {
                 "cannot encode PUBLISH packet with topic {:?}: {}",
                 publication.topic_name, err
             ),
+            PublishError::Outbox(publication, err) => write!(
+                f,
+                "cannot store publication with topic {:?} in outbox: {}",
+                publication.topic_name, err
+            ),
         }
     }
 }
@@ -410,6 +654,7 @@ impl std::error::Error for PublishError {
         match self {
             PublishError::ClientDoesNotExist => None,
             PublishError::EncodePacket(_, err) => Some(err),
+            PublishError::Outbox(_, err) => Some(err),
         }
     }
 }
@@ -417,13 +662,27 @@ impl std::error::Error for PublishError {
 #[derive(Debug)]
 struct PublishRequest {
     publication: crate::proto::Publication,
-    ack_sender: futures_channel::oneshot::Sender<()>,
+    ack_sender: futures_channel::oneshot::Sender<Result<(), PublishError>>,
+
+    /// Key of the publication in the outbox, if it is stored there
+    outbox_key: Option<u64>,
+
+    /// Whether the publication may have been sent before, by a previous instance of the client
+    dup: bool,
+}
+
+/// A PUBLISH packet sent by us, which is ready to be sent again with the DUP flag set
+#[derive(Debug)]
+struct SentPublish {
+    ack_sender: futures_channel::oneshot::Sender<Result<(), PublishError>>,
+    packet: crate::proto::Publish,
+    outbox_key: Option<u64>,
 }
 
 impl PublishRequest {
     fn new(
         publication: crate::proto::Publication,
-        ack_sender: futures_channel::oneshot::Sender<()>,
+        ack_sender: futures_channel::oneshot::Sender<Result<(), PublishError>>,
     ) -> Result<PublishRequest, PublishError> {
         use crate::proto::PacketMeta;
 
@@ -450,6 +709,8 @@ impl PublishRequest {
             Ok(_) => Ok(PublishRequest {
                 publication,
                 ack_sender,
+                outbox_key: None,
+                dup: false,
             }),
             Err(err) => Err(PublishError::EncodePacket(publication, err)),
         }
@@ -0,0 +1,226 @@
+/// Decides how long a [`crate::Client`] waits before trying to connect to the server again
+/// after a connection attempt failed, and when it stops trying.
+///
+/// The client reconnects immediately after an established connection is broken,
+/// and only consults the policy once a connection attempt fails.
+pub trait ReconnectPolicy: std::fmt::Debug {
+    /// Returns how long to wait before the next connection attempt, or `None` to stop reconnecting.
+    ///
+    /// `attempt` is the number of consecutive failed connection attempts so far, starting at 1.
+    fn back_off(&mut self, attempt: u32) -> Option<std::time::Duration>;
+
+    /// Called when a connection to the server is established.
+    fn reset(&mut self) {}
+
+    /// Stops reconnecting after the given number of consecutive failed connection attempts.
+    fn with_max_attempts(self, max_attempts: u32) -> MaxAttempts<Self>
+    where
+        Self: Sized,
+    {
+        MaxAttempts {
+            inner: self,
+            max_attempts,
+        }
+    }
+}
+
+/// Retries immediately after the first failed connection attempt, then waits one second and doubles
+/// the back-off after every further failed attempt, up to this value.
+///
+/// This policy never gives up and does not add jitter.
+impl ReconnectPolicy for std::time::Duration {
+    fn back_off(&mut self, attempt: u32) -> Option<std::time::Duration> {
+        if attempt <= 1 {
+            return Some(std::time::Duration::from_secs(0));
+        }
+
+        Some(exponential(
+            std::time::Duration::from_secs(1),
+            *self,
+            attempt - 1,
+        ))
+    }
+}
+
+/// Exponential back-off with full jitter.
+///
+/// The back-off before the next attempt is chosen uniformly between zero and
+/// `initial * 2^(attempt - 1)`, capped at `max`. Spreading reconnects out like this prevents
+/// many clients that lost their connection at the same time from reconnecting in lockstep.
+#[derive(Clone, Debug)]
+pub struct ExponentialBackOff {
+    initial: std::time::Duration,
+    max: std::time::Duration,
+}
+
+impl ExponentialBackOff {
+    pub fn new(initial: std::time::Duration, max: std::time::Duration) -> Self {
+        ExponentialBackOff { initial, max }
+    }
+}
+
+impl ReconnectPolicy for ExponentialBackOff {
+    fn back_off(&mut self, attempt: u32) -> Option<std::time::Duration> {
+        let ceiling = exponential(self.initial, self.max, attempt);
+        Some(random_between(std::time::Duration::from_secs(0), ceiling))
+    }
+}
+
+/// Waits the same amount of time before every connection attempt.
+#[derive(Clone, Debug)]
+pub struct FixedBackOff(std::time::Duration);
+
+impl FixedBackOff {
+    pub fn new(back_off: std::time::Duration) -> Self {
+        FixedBackOff(back_off)
+    }
+}
+
+impl ReconnectPolicy for FixedBackOff {
+    fn back_off(&mut self, _attempt: u32) -> Option<std::time::Duration> {
+        Some(self.0)
+    }
+}
+
+/// Decorrelated jitter back-off.
+///
+/// The back-off before the next attempt is chosen uniformly between `base` and three times
+/// the previous back-off, capped at `max`.
+#[derive(Clone, Debug)]
+pub struct DecorrelatedJitterBackOff {
+    base: std::time::Duration,
+    max: std::time::Duration,
+    previous: std::time::Duration,
+}
+
+impl DecorrelatedJitterBackOff {
+    pub fn new(base: std::time::Duration, max: std::time::Duration) -> Self {
+        DecorrelatedJitterBackOff {
+            base,
+            max,
+            previous: base,
+        }
+    }
+}
+
+impl ReconnectPolicy for DecorrelatedJitterBackOff {
+    fn back_off(&mut self, _attempt: u32) -> Option<std::time::Duration> {
+        let ceiling = self.previous.checked_mul(3).unwrap_or(self.max);
+        let back_off = std::cmp::min(self.max, random_between(self.base, ceiling));
+        self.previous = back_off;
+        Some(back_off)
+    }
+
+    fn reset(&mut self) {
+        self.previous = self.base;
+    }
+}
+
+/// Wraps another policy and gives up after a number of consecutive failed connection attempts.
+///
+/// Created by [`ReconnectPolicy::with_max_attempts`].
+#[derive(Clone, Debug)]
+pub struct MaxAttempts<P> {
+    inner: P,
+    max_attempts: u32,
+}
+
+impl<P> ReconnectPolicy for MaxAttempts<P>
+where
+    P: ReconnectPolicy,
+{
+    fn back_off(&mut self, attempt: u32) -> Option<std::time::Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        self.inner.back_off(attempt)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+fn exponential(
+    initial: std::time::Duration,
+    max: std::time::Duration,
+    attempt: u32,
+) -> std::time::Duration {
+    2_u32
+        .checked_pow(attempt.saturating_sub(1))
+        .and_then(|factor| initial.checked_mul(factor))
+        .map_or(max, |back_off| std::cmp::min(max, back_off))
+}
+
+fn random_between(low: std::time::Duration, high: std::time::Duration) -> std::time::Duration {
+    use rand::Rng;
+
+    if high <= low {
+        return low;
+    }
+
+    rand::thread_rng().gen_range(low..=high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecorrelatedJitterBackOff, ExponentialBackOff, FixedBackOff, ReconnectPolicy};
+
+    #[test]
+    fn duration_retries_immediately_then_doubles_up_to_max() {
+        let mut policy = std::time::Duration::from_secs(10);
+
+        let back_offs: Vec<_> = (1..=7).map(|attempt| policy.back_off(attempt)).collect();
+        assert_eq!(
+            back_offs,
+            [0, 1, 2, 4, 8, 10, 10]
+                .iter()
+                .map(|secs| Some(std::time::Duration::from_secs(*secs)))
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(policy.back_off(u32::max_value()), Some(policy));
+    }
+
+    #[test]
+    fn exponential_back_off_is_jittered_within_ceiling() {
+        let mut policy = ExponentialBackOff::new(
+            std::time::Duration::from_millis(100),
+            std::time::Duration::from_secs(1),
+        );
+
+        for attempt in 1..=10 {
+            let ceiling = std::cmp::min(
+                std::time::Duration::from_millis(100 * 2_u64.pow(attempt - 1)),
+                std::time::Duration::from_secs(1),
+            );
+            let back_off = policy.back_off(attempt).unwrap();
+            assert!(back_off <= ceiling, "{:?} > {:?}", back_off, ceiling);
+        }
+    }
+
+    #[test]
+    fn decorrelated_jitter_back_off_stays_within_bounds() {
+        let base = std::time::Duration::from_millis(100);
+        let max = std::time::Duration::from_secs(2);
+        let mut policy = DecorrelatedJitterBackOff::new(base, max);
+
+        for attempt in 1..=100 {
+            let back_off = policy.back_off(attempt).unwrap();
+            assert!(base <= back_off && back_off <= max, "{:?}", back_off);
+        }
+
+        policy.reset();
+        assert!(policy.back_off(1).unwrap() <= base * 3);
+    }
+
+    #[test]
+    fn max_attempts_gives_up() {
+        let mut policy = FixedBackOff::new(std::time::Duration::from_secs(1)).with_max_attempts(3);
+
+        assert_eq!(policy.back_off(1), Some(std::time::Duration::from_secs(1)));
+        assert_eq!(policy.back_off(2), Some(std::time::Duration::from_secs(1)));
+        assert_eq!(policy.back_off(3), None);
+    }
+}
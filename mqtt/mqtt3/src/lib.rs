@@ -27,11 +27,17 @@ pub const PROTOCOL_LEVEL: u8 = 0x04;
 
 mod client;
 pub use client::{
-    Client, ConnectionError, Error, Event, IoSource, PublishError, PublishHandle,
-    ReceivedPublication, ShutdownError, ShutdownHandle, SubscriptionUpdateEvent,
-    UpdateSubscriptionError, UpdateSubscriptionHandle,
+    Client, ConnectionAttemptError, ConnectionError, DecorrelatedJitterBackOff, Error, Event,
+    ExponentialBackOff, FileOutbox, FixedBackOff, IoSource, MaxAttempts, Outbox, PublishError,
+    PublishHandle, ReceivedPublication, ReconnectPolicy, ShutdownError, ShutdownHandle,
+    SubscriptionUpdateEvent, UpdateSubscriptionError, UpdateSubscriptionHandle,
 };
 
 mod logging_framed;
 
+mod request;
+pub use request::{RequestClient, RequestError, RequestHandle};
+
 pub mod proto;
+
+pub mod recording;
@@ -6,6 +6,7 @@ where
     T: tokio::io::AsyncRead + tokio::io::AsyncWrite,
 {
     inner: tokio_util::codec::Framed<T, crate::proto::PacketCodec>,
+    recorder: Option<crate::recording::Recorder>,
 }
 
 impl<T> LoggingFramed<T>
@@ -15,8 +16,14 @@ where
     pub(crate) fn new(io: T) -> Self {
         LoggingFramed {
             inner: tokio_util::codec::Framed::new(io, Default::default()),
+            recorder: None,
         }
     }
+
+    pub(crate) fn with_recorder(mut self, recorder: crate::recording::Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
 }
 
 impl<T> futures_sink::Sink<Packet> for LoggingFramed<T>
@@ -34,6 +41,9 @@ where
 
     fn start_send(mut self: std::pin::Pin<&mut Self>, item: Packet) -> Result<(), Self::Error> {
         log::trace!(">>> {:?}", item);
+        if let Some(recorder) = &self.recorder {
+            recorder.record(crate::recording::Direction::ClientToServer, &item);
+        }
         std::pin::Pin::new(&mut self.inner).start_send(item)
     }
 
@@ -68,6 +78,9 @@ where
         let result = std::pin::Pin::new(&mut self.inner).poll_next(cx);
         if let std::task::Poll::Ready(Some(Ok(item))) = &result {
             log::trace!("<<< {:?}", item);
+            if let Some(recorder) = &self.recorder {
+                recorder.record(crate::recording::Direction::ServerToClient, item);
+            }
         }
         result
     }
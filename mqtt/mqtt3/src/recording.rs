@@ -0,0 +1,509 @@
+/*!
+ * Recording of timestamped MQTT packet streams, so that the traffic of a connection can be replayed later.
+ *
+ * A recording file starts with [`MAGIC`], followed by one record per packet. Each record consists of
+ * the time elapsed since the recording was started in microseconds (u64, big-endian), the [`Direction`] of the packet (u8),
+ * the length of the encoded packet (u32, big-endian) and the packet itself, encoded as it would be on the wire.
+ *
+ * Passwords in CONNECT packets are never recorded, so a replayed connection has to supply the password again
+ * to authenticate with a broker which requires one.
+ */
+
+/// The bytes every recording file starts with
+pub const MAGIC: &[u8; 8] = b"MQTTREC1";
+
+/// The direction a recorded packet was sent in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+/// A packet read from a recording
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedPacket {
+    /// The time elapsed between the start of the recording and the packet being sent or received
+    pub elapsed: std::time::Duration,
+    pub direction: Direction,
+    pub packet: crate::proto::Packet,
+}
+
+/// The default limit on the size of a recording file
+pub const DEFAULT_MAX_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Recorded packets are handed over to the file once this many bytes are buffered ...
+const FLUSH_SIZE: usize = 64 * 1024;
+
+/// ... or once this much time has passed since the previous hand-over.
+const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Writes the packets of a single connection to a recording file.
+///
+/// Packets are buffered in memory and written to the file on a blocking task of the tokio runtime,
+/// so that recording never blocks the connection being recorded. Once the file reaches its size limit,
+/// further packets are not recorded.
+///
+/// Clones of a `Recorder` write to the same file.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    inner: std::sync::Arc<RecorderInner>,
+}
+
+#[derive(Debug)]
+struct RecorderInner {
+    path: std::path::PathBuf,
+    start: std::time::Instant,
+    state: std::sync::Mutex<RecorderState>,
+    /// The file is only written by one task at a time, see `RecorderState::writing`.
+    file: std::sync::Mutex<Option<std::fs::File>>,
+}
+
+#[derive(Debug)]
+struct RecorderState {
+    /// Encoded packets which have not been handed over to the file yet
+    buf: bytes::BytesMut,
+    last_flush: std::time::Instant,
+    /// Whether a task writing `buf` to the file is running
+    writing: bool,
+    size: u64,
+    max_size: u64,
+    /// Whether the size limit has been reached, packets are not recorded anymore
+    full: bool,
+}
+
+impl Recorder {
+    /// Creates a new recording file at the given path, replacing any existing file.
+    ///
+    /// The file is limited to [`DEFAULT_MAX_SIZE`] bytes.
+    pub fn create(path: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        use std::io::Write;
+
+        let path = path.into();
+        let mut file = std::fs::File::create(&path)?;
+        file.write_all(MAGIC)?;
+
+        let now = std::time::Instant::now();
+        Ok(Recorder {
+            inner: std::sync::Arc::new(RecorderInner {
+                path,
+                start: now,
+                state: std::sync::Mutex::new(RecorderState {
+                    buf: Default::default(),
+                    last_flush: now,
+                    writing: false,
+                    size: MAGIC.len() as u64,
+                    max_size: DEFAULT_MAX_SIZE,
+                    full: false,
+                }),
+                file: std::sync::Mutex::new(Some(file)),
+            }),
+        })
+    }
+
+    /// Limits the size of the recording file.
+    pub fn with_max_size(self, max_size: u64) -> Self {
+        lock(&self.inner.state).max_size = max_size;
+        self
+    }
+
+    /// Appends a packet to the recording.
+    ///
+    /// Failures are logged rather than returned, so that a broken recording never breaks the connection being recorded.
+    pub fn record(&self, direction: Direction, packet: &crate::proto::Packet) {
+        let flush = {
+            let mut state = lock(&self.inner.state);
+            match state.record(self.inner.start, direction, packet) {
+                Ok(()) => state.should_flush(),
+                Err(err) => {
+                    log::warn!(
+                        "could not record packet to {}: {}",
+                        self.inner.path.display(),
+                        err
+                    );
+                    false
+                }
+            }
+        };
+
+        if flush {
+            self.flush();
+        }
+    }
+
+    /// Hands buffered packets over to a blocking task which writes them to the file.
+    /// Without a tokio runtime, packets are written on the calling thread.
+    fn flush(&self) {
+        let inner = self.inner.clone();
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(move || inner.write_buffered());
+            }
+            Err(_) => inner.write_buffered(),
+        }
+    }
+}
+
+impl RecorderInner {
+    /// Writes buffered packets to the file until there are no more.
+    fn write_buffered(&self) {
+        use std::io::Write;
+
+        loop {
+            let buf = {
+                let mut state = lock(&self.state);
+                if state.buf.is_empty() {
+                    state.writing = false;
+                    return;
+                }
+                state.last_flush = std::time::Instant::now();
+                state.buf.split()
+            };
+
+            if let Some(file) = &mut *lock(&self.file) {
+                if let Err(err) = file.write_all(&buf) {
+                    log::warn!("could not write recording {}: {}", self.path.display(), err);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for RecorderInner {
+    fn drop(&mut self) {
+        use std::io::Write;
+
+        // The last clone of the recorder is gone and no task writes to the file anymore,
+        // so the rest of the buffer is written by a new task.
+        let buf = std::mem::take(&mut lock(&self.state).buf);
+        let file = lock(&self.file).take();
+        let path = std::mem::take(&mut self.path);
+
+        if let (Some(mut file), false) = (file, buf.is_empty()) {
+            let mut write = move || {
+                if let Err(err) = file.write_all(&buf) {
+                    log::warn!("could not write recording {}: {}", path.display(), err);
+                }
+            };
+
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    runtime.spawn_blocking(write);
+                }
+                Err(_) => write(),
+            }
+        }
+    }
+}
+
+impl RecorderState {
+    fn record(
+        &mut self,
+        start: std::time::Instant,
+        direction: Direction,
+        packet: &crate::proto::Packet,
+    ) -> Result<(), RecordingError> {
+        use bytes::BufMut;
+        use std::convert::TryInto;
+        use tokio_util::codec::Encoder;
+
+        if self.full {
+            return Ok(());
+        }
+
+        let packet = match packet {
+            crate::proto::Packet::Connect(connect) if connect.password.is_some() => {
+                crate::proto::Packet::Connect(crate::proto::Connect {
+                    password: None,
+                    ..connect.clone()
+                })
+            }
+            packet => packet.clone(),
+        };
+
+        let mut encoded = bytes::BytesMut::new();
+        crate::proto::PacketCodec::default()
+            .encode(packet, &mut encoded)
+            .map_err(RecordingError::EncodePacket)?;
+
+        let elapsed: u64 = start
+            .elapsed()
+            .as_micros()
+            .try_into()
+            .unwrap_or(u64::max_value());
+        let direction: u8 = match direction {
+            Direction::ClientToServer => 0,
+            Direction::ServerToClient => 1,
+        };
+        let len: u32 = encoded
+            .len()
+            .try_into()
+            .expect("encoded packet length always fits in u32");
+
+        let record_len = (8 + 1 + 4 + encoded.len()) as u64;
+        if self.size + record_len > self.max_size {
+            // Packets are not recorded from now on, the error is reported only once.
+            self.full = true;
+            return Err(RecordingError::SizeLimitReached(self.max_size));
+        }
+
+        self.buf.put_u64(elapsed);
+        self.buf.put_u8(direction);
+        self.buf.put_u32(len);
+        self.buf.put_slice(&encoded);
+        self.size += record_len;
+
+        Ok(())
+    }
+
+    /// Whether buffered packets should be handed over to the file. Marks the hand-over as started.
+    fn should_flush(&mut self) -> bool {
+        if self.writing || self.buf.is_empty() {
+            return false;
+        }
+
+        if self.buf.len() >= FLUSH_SIZE || self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.writing = true;
+            return true;
+        }
+
+        false
+    }
+}
+
+fn lock<T>(mutex: &std::sync::Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Reads the packets of a recording file in the order they were recorded.
+#[derive(Debug)]
+pub struct Recording<R> {
+    reader: R,
+}
+
+impl Recording<std::io::BufReader<std::fs::File>> {
+    /// Opens the recording file at the given path.
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, RecordingError> {
+        let file = std::fs::File::open(path)?;
+        Recording::new(std::io::BufReader::new(file))
+    }
+}
+
+impl<R> Recording<R>
+where
+    R: std::io::Read,
+{
+    pub fn new(mut reader: R) -> Result<Self, RecordingError> {
+        let mut magic = [0_u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(RecordingError::InvalidHeader);
+        }
+
+        Ok(Recording { reader })
+    }
+
+    fn read_packet(&mut self) -> Result<Option<RecordedPacket>, RecordingError> {
+        use tokio_util::codec::Decoder;
+
+        let mut elapsed = [0_u8; 8];
+        match self.reader.read_exact(&mut elapsed) {
+            Ok(()) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let elapsed = std::time::Duration::from_micros(u64::from_be_bytes(elapsed));
+
+        let mut direction = [0_u8; 1];
+        self.reader.read_exact(&mut direction)?;
+        let direction = match direction[0] {
+            0 => Direction::ClientToServer,
+            1 => Direction::ServerToClient,
+            direction => return Err(RecordingError::InvalidDirection(direction)),
+        };
+
+        let mut len = [0_u8; 4];
+        self.reader.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+
+        let mut buf = bytes::BytesMut::new();
+        buf.resize(len, 0);
+        self.reader.read_exact(&mut buf)?;
+
+        let packet = crate::proto::PacketCodec::default()
+            .decode(&mut buf)
+            .map_err(RecordingError::DecodePacket)?
+            .ok_or(RecordingError::TruncatedPacket)?;
+
+        Ok(Some(RecordedPacket {
+            elapsed,
+            direction,
+            packet,
+        }))
+    }
+}
+
+impl<R> Iterator for Recording<R>
+where
+    R: std::io::Read,
+{
+    type Item = Result<RecordedPacket, RecordingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_packet().transpose()
+    }
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    DecodePacket(crate::proto::DecodeError),
+    EncodePacket(crate::proto::EncodeError),
+    InvalidDirection(u8),
+    InvalidHeader,
+    Io(std::io::Error),
+    SizeLimitReached(u64),
+    TruncatedPacket,
+}
+
+impl std::fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordingError::DecodePacket(err) => write!(f, "could not decode packet: {}", err),
+            RecordingError::EncodePacket(err) => write!(f, "could not encode packet: {}", err),
+            RecordingError::InvalidDirection(direction) => {
+                write!(f, "invalid packet direction {}", direction)
+            }
+            RecordingError::InvalidHeader => write!(f, "not a recording file"),
+            RecordingError::Io(err) => write!(f, "I/O error: {}", err),
+            RecordingError::SizeLimitReached(max_size) => write!(
+                f,
+                "recording reached its size limit of {} bytes, further packets are not recorded",
+                max_size
+            ),
+            RecordingError::TruncatedPacket => write!(f, "recorded packet is truncated"),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RecordingError::DecodePacket(err) => Some(err),
+            RecordingError::EncodePacket(err) => Some(err),
+            RecordingError::Io(err) => Some(err),
+            RecordingError::InvalidDirection(_)
+            | RecordingError::InvalidHeader
+            | RecordingError::SizeLimitReached(_)
+            | RecordingError::TruncatedPacket => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for RecordingError {
+    fn from(err: std::io::Error) -> Self {
+        RecordingError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Recorder, Recording, MAGIC};
+
+    #[test]
+    fn recording_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("connection.mqttrec");
+
+        let connect = crate::proto::Connect {
+            username: Some("user".to_owned()),
+            password: Some("secret".to_owned()),
+            will: None,
+            client_id: crate::proto::ClientId::IdWithCleanSession("client".to_owned()),
+            keep_alive: std::time::Duration::from_secs(30),
+            protocol_name: crate::PROTOCOL_NAME.to_owned(),
+            protocol_level: crate::PROTOCOL_LEVEL,
+        };
+        let connack = crate::proto::Packet::ConnAck(crate::proto::ConnAck {
+            session_present: false,
+            return_code: crate::proto::ConnectReturnCode::Accepted,
+        });
+
+        let recorder = Recorder::create(&path).unwrap();
+        recorder.record(
+            Direction::ClientToServer,
+            &crate::proto::Packet::Connect(connect.clone()),
+        );
+        recorder.clone().record(Direction::ServerToClient, &connack);
+        drop(recorder);
+
+        let packets: Vec<_> = Recording::open(&path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(packets.len(), 2);
+        assert!(packets[0].elapsed <= packets[1].elapsed);
+        assert_eq!(packets[0].direction, Direction::ClientToServer);
+        assert_eq!(
+            packets[0].packet,
+            crate::proto::Packet::Connect(crate::proto::Connect {
+                password: None,
+                ..connect
+            })
+        );
+        assert_eq!(packets[1].direction, Direction::ServerToClient);
+        assert_eq!(packets[1].packet, connack);
+    }
+
+    #[test]
+    fn recording_stops_at_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("connection.mqttrec");
+
+        let connack = crate::proto::Packet::ConnAck(crate::proto::ConnAck {
+            session_present: false,
+            return_code: crate::proto::ConnectReturnCode::Accepted,
+        });
+        let pingresp = crate::proto::Packet::PingResp(crate::proto::PingResp);
+
+        // room for the header and a single record of a 4 byte CONNACK
+        let recorder = Recorder::create(&path)
+            .unwrap()
+            .with_max_size(MAGIC.len() as u64 + 8 + 1 + 4 + 4);
+        recorder.record(Direction::ServerToClient, &connack);
+        recorder.record(Direction::ServerToClient, &pingresp);
+        recorder.record(Direction::ServerToClient, &pingresp);
+        drop(recorder);
+
+        let packets: Vec<_> = Recording::open(&path)
+            .unwrap()
+            .map(|packet| packet.unwrap().packet)
+            .collect();
+        assert_eq!(packets, vec![connack]);
+    }
+
+    #[tokio::test]
+    async fn recording_writes_in_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("connection.mqttrec");
+
+        let pingreq = crate::proto::Packet::PingReq(crate::proto::PingReq);
+
+        let recorder = Recorder::create(&path).unwrap();
+        for _ in 0..(super::FLUSH_SIZE / 2 + 1) {
+            recorder.record(Direction::ClientToServer, &pingreq);
+        }
+
+        // a full buffer is handed over to a blocking task without waiting for the recorder to be dropped
+        let mut packets = 0;
+        for _ in 0..100 {
+            packets = Recording::open(&path).unwrap().count();
+            if packets > 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(packets > 0);
+    }
+}
@@ -0,0 +1,313 @@
+/// A [`crate::Client`] wrapper that correlates requests with their responses.
+///
+/// Requests are made via the handle returned by [`RequestClient::request_handle`]. Every request is published to
+/// `{topic_name}/?rid={correlation id}&reply={reply topic}`, and the responder is expected to publish its response
+/// to `{reply topic}/?rid={correlation id}`. The client subscribes to `{reply topic}/#` and resolves the pending request
+/// with the matching correlation id. Responses are not surfaced as [`crate::Event`]s; all other events are passed through.
+///
+/// If the session is reset, requests wait until the reply topic has been subscribed to again before they are published.
+#[derive(Debug)]
+pub struct RequestClient<IoS>
+where
+    IoS: crate::IoSource,
+{
+    client: crate::Client<IoS>,
+    reply_topic: String,
+    reply_filter: String,
+    pending: std::sync::Arc<std::sync::Mutex<PendingRequests>>,
+    subscribed: tokio::sync::watch::Sender<bool>,
+    subscribed_recv: tokio::sync::watch::Receiver<bool>,
+    next_correlation_id: std::sync::Arc<std::sync::atomic::AtomicU64>,
+}
+
+type PendingRequests =
+    std::collections::HashMap<u64, futures_channel::oneshot::Sender<crate::ReceivedPublication>>;
+
+impl<IoS> RequestClient<IoS>
+where
+    IoS: crate::IoSource,
+{
+    /// Wraps the given client and subscribes it to responses published to `reply_topic`.
+    pub fn new(
+        mut client: crate::Client<IoS>,
+        reply_topic: impl Into<String>,
+    ) -> Result<Self, crate::UpdateSubscriptionError> {
+        let reply_topic = reply_topic.into();
+        let reply_filter = format!("{}/#", reply_topic);
+
+        client.subscribe(crate::proto::SubscribeTo {
+            topic_filter: reply_filter.clone(),
+            qos: crate::proto::QoS::AtLeastOnce,
+        })?;
+
+        let (subscribed, subscribed_recv) = tokio::sync::watch::channel(false);
+
+        Ok(RequestClient {
+            client,
+            reply_topic,
+            reply_filter,
+            pending: Default::default(),
+            subscribed,
+            subscribed_recv,
+            next_correlation_id: Default::default(),
+        })
+    }
+
+    /// Returns a handle that can be used to make requests
+    pub fn request_handle(&self) -> Result<RequestHandle, crate::PublishError> {
+        Ok(RequestHandle {
+            publish_handle: self.client.publish_handle()?,
+            reply_topic: self.reply_topic.clone(),
+            pending: self.pending.clone(),
+            subscribed: self.subscribed_recv.clone(),
+            next_correlation_id: self.next_correlation_id.clone(),
+        })
+    }
+
+    /// Returns the wrapped client
+    pub fn get_ref(&self) -> &crate::Client<IoS> {
+        &self.client
+    }
+
+    /// Returns the wrapped client
+    pub fn get_mut(&mut self) -> &mut crate::Client<IoS> {
+        &mut self.client
+    }
+
+    fn handle_response(&self, publication: crate::ReceivedPublication) {
+        let correlation_id = publication
+            .topic_name
+            .strip_prefix(&self.reply_topic)
+            .and_then(|properties| properties.strip_prefix("/?"))
+            .and_then(|properties| {
+                properties
+                    .split('&')
+                    .find_map(|property| property.strip_prefix("rid="))
+            })
+            .and_then(|correlation_id| correlation_id.parse().ok());
+
+        let correlation_id = match correlation_id {
+            Some(correlation_id) => correlation_id,
+            None => {
+                log::warn!(
+                    "discarding response on {} without a correlation id",
+                    publication.topic_name
+                );
+                return;
+            }
+        };
+
+        let response_sender = lock(&self.pending).remove(&correlation_id);
+        match response_sender {
+            Some(response_sender) => match response_sender.send(publication) {
+                Ok(()) => (),
+                Err(_) => log::debug!(
+                    "could not send response to request {} because the request has been dropped",
+                    correlation_id
+                ),
+            },
+            None => log::debug!(
+                "discarding response to request {} which is no longer pending",
+                correlation_id
+            ),
+        }
+    }
+
+    fn handle_subscription_updates(
+        &self,
+        updates: Vec<crate::SubscriptionUpdateEvent>,
+    ) -> Vec<crate::SubscriptionUpdateEvent> {
+        let mut skipped = vec![];
+
+        for update in updates {
+            match update {
+                crate::SubscriptionUpdateEvent::Subscribe(subscribe_to)
+                    if subscribe_to.topic_filter == self.reply_filter =>
+                {
+                    self.set_subscribed(true);
+                }
+
+                crate::SubscriptionUpdateEvent::RejectedByServer(subscribe_to)
+                    if subscribe_to.topic_filter == self.reply_filter =>
+                {
+                    log::warn!(
+                        "server rejected subscription to responses on {}",
+                        self.reply_filter
+                    );
+                    self.set_subscribed(false);
+                }
+
+                update => skipped.push(update),
+            }
+        }
+
+        skipped
+    }
+
+    fn set_subscribed(&self, subscribed: bool) {
+        // Sending cannot fail since self.subscribed_recv keeps the channel open.
+        let _: Result<_, _> = self.subscribed.send(subscribed);
+    }
+}
+
+impl<IoS> futures_core::Stream for RequestClient<IoS>
+where
+    crate::Client<IoS>: futures_core::Stream<Item = Result<crate::Event, crate::Error>> + Unpin,
+    IoS: crate::IoSource,
+{
+    type Item = Result<crate::Event, crate::Error>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        loop {
+            let event = match std::pin::Pin::new(&mut self.client).poll_next(cx) {
+                std::task::Poll::Ready(Some(Ok(event))) => event,
+                std::task::Poll::Ready(Some(Err(err))) => {
+                    return std::task::Poll::Ready(Some(Err(err)))
+                }
+                std::task::Poll::Ready(None) => {
+                    // Dropping the senders resolves pending requests with an error.
+                    lock(&self.pending).clear();
+                    return std::task::Poll::Ready(None);
+                }
+                std::task::Poll::Pending => return std::task::Poll::Pending,
+            };
+
+            match event {
+                crate::Event::Publication(publication)
+                    if publication
+                        .topic_name
+                        .strip_prefix(&self.reply_topic)
+                        .map_or(false, |rest| rest.starts_with('/')) =>
+                {
+                    self.handle_response(publication);
+                }
+
+                crate::Event::NewConnection { reset_session } => {
+                    if reset_session {
+                        self.set_subscribed(false);
+                    }
+
+                    return std::task::Poll::Ready(Some(Ok(crate::Event::NewConnection {
+                        reset_session,
+                    })));
+                }
+
+                crate::Event::SubscriptionUpdates(updates) => {
+                    let updates = self.handle_subscription_updates(updates);
+                    if !updates.is_empty() {
+                        return std::task::Poll::Ready(Some(Ok(
+                            crate::Event::SubscriptionUpdates(updates),
+                        )));
+                    }
+                }
+
+                event => return std::task::Poll::Ready(Some(Ok(event))),
+            }
+        }
+    }
+}
+
+/// Used to make requests via a [`RequestClient`]
+#[derive(Clone, Debug)]
+pub struct RequestHandle {
+    publish_handle: crate::PublishHandle,
+    reply_topic: String,
+    pending: std::sync::Arc<std::sync::Mutex<PendingRequests>>,
+    subscribed: tokio::sync::watch::Receiver<bool>,
+    next_correlation_id: std::sync::Arc<std::sync::atomic::AtomicU64>,
+}
+
+impl RequestHandle {
+    /// Publishes a request and waits for the matching response.
+    ///
+    /// The request fails with [`RequestError::Timeout`] if no response arrives within `timeout`,
+    /// including the time spent waiting for the reply topic to be subscribed to and for the request to be published.
+    pub async fn request(
+        &mut self,
+        request: crate::proto::Publication,
+        timeout: std::time::Duration,
+    ) -> Result<crate::ReceivedPublication, RequestError> {
+        let correlation_id = self
+            .next_correlation_id
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        let (response_sender, response_receiver) = futures_channel::oneshot::channel();
+        lock(&self.pending).insert(correlation_id, response_sender);
+
+        let request = crate::proto::Publication {
+            topic_name: format!(
+                "{}/?rid={}&reply={}",
+                request.topic_name, correlation_id, self.reply_topic
+            ),
+            ..request
+        };
+
+        let result = tokio::time::timeout(timeout, async {
+            loop {
+                let subscribed = *self.subscribed.borrow();
+                if subscribed {
+                    break;
+                }
+
+                self.subscribed
+                    .changed()
+                    .await
+                    .map_err(|_| RequestError::ClientDoesNotExist)?;
+            }
+
+            self.publish_handle
+                .publish(request)
+                .await
+                .map_err(RequestError::Publish)?;
+
+            response_receiver
+                .await
+                .map_err(|_| RequestError::ClientDoesNotExist)
+        })
+        .await;
+
+        lock(&self.pending).remove(&correlation_id);
+
+        match result {
+            Ok(result) => result,
+            Err(_) => Err(RequestError::Timeout(timeout)),
+        }
+    }
+}
+
+fn lock(pending: &std::sync::Mutex<PendingRequests>) -> std::sync::MutexGuard<'_, PendingRequests> {
+    pending
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[derive(Debug)]
+pub enum RequestError {
+    ClientDoesNotExist,
+    Publish(crate::PublishError),
+    Timeout(std::time::Duration),
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::ClientDoesNotExist => write!(f, "client does not exist"),
+            RequestError::Publish(err) => write!(f, "could not publish request: {}", err),
+            RequestError::Timeout(timeout) => {
+                write!(f, "no response received within {:?}", timeout)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RequestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RequestError::ClientDoesNotExist | RequestError::Timeout(_) => None,
+            RequestError::Publish(err) => Some(err),
+        }
+    }
+}
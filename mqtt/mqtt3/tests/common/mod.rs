@@ -120,6 +120,18 @@ pub(crate) struct TestConnection {
     done_send: Option<futures_channel::oneshot::Sender<()>>,
 }
 
+impl Drop for TestConnection {
+    fn drop(&mut self) {
+        // The client may drop a connection right after its last step, for example after a refused ConnAck,
+        // without reading or writing again.
+        if self.steps.is_empty() {
+            if let Some(done_send) = self.done_send.take() {
+                let _ = done_send.send(());
+            }
+        }
+    }
+}
+
 /// A single step in the connection between a client and a server
 #[derive(Debug)]
 pub(crate) enum TestConnectionStep<TReceives, TSends> {
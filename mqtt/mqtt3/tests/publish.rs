@@ -577,3 +577,363 @@ async fn should_reject_invalid_publications() {
 		result => panic!("expected client.publish() to fail with EncodePacket(StringTooLarge) but it returned {:?}", result),
 	}
 }
+
+#[tokio::test]
+async fn client_replays_outbox_after_restart() {
+    use mqtt3::Outbox;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("outbox");
+
+    // publication left unacknowledged by a previous instance of the client
+    let mut outbox = mqtt3::FileOutbox::open(&path).unwrap();
+    let key = outbox
+        .insert(&mqtt3::proto::Publication {
+            topic_name: "topic1".to_owned(),
+            qos: mqtt3::proto::QoS::AtLeastOnce,
+            retain: false,
+            payload: [0x01, 0x02, 0x03][..].into(),
+        })
+        .unwrap();
+    futures_util::future::poll_fn(|cx| outbox.poll_flush(cx, key))
+        .await
+        .unwrap();
+    drop(outbox);
+
+    let (io_source, done) = common::IoSource::new(vec![vec![
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Connect(
+            mqtt3::proto::Connect {
+                username: None,
+                password: None,
+                will: None,
+                client_id: mqtt3::proto::ClientId::ServerGenerated,
+                keep_alive: std::time::Duration::from_secs(4),
+                protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
+                protocol_level: mqtt3::PROTOCOL_LEVEL,
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
+            session_present: false,
+            return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+        })),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Publish(
+            mqtt3::proto::Publish {
+                packet_identifier_dup_qos: mqtt3::proto::PacketIdentifierDupQoS::AtLeastOnce(
+                    mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+                    true,
+                ),
+                retain: false,
+                topic_name: "topic1".to_owned(),
+                payload: [0x01, 0x02, 0x03][..].into(),
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PubAck(mqtt3::proto::PubAck {
+            packet_identifier: mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+        })),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(mqtt3::proto::PingReq)),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PingResp(mqtt3::proto::PingResp)),
+    ]]);
+
+    let client = mqtt3::Client::new(
+        None,
+        None,
+        None,
+        io_source,
+        std::time::Duration::from_secs(0),
+        std::time::Duration::from_secs(4),
+    )
+    .with_outbox(mqtt3::FileOutbox::open(&path).unwrap())
+    .unwrap();
+
+    common::verify_client_events(
+        client,
+        vec![mqtt3::Event::NewConnection {
+            reset_session: true,
+        }],
+    );
+
+    done.await
+        .expect("connection broken while there were still steps remaining on the server");
+
+    // acknowledged publication is removed from the outbox
+    let mut outbox = mqtt3::FileOutbox::open(&path).unwrap();
+    assert_eq!(outbox.load().unwrap(), vec![]);
+}
+
+#[tokio::test]
+async fn client_fails_publication_not_stored_in_outbox() {
+    #[derive(Debug)]
+    struct FailingOutbox;
+
+    impl mqtt3::Outbox for FailingOutbox {
+        fn load(&mut self) -> std::io::Result<Vec<(u64, mqtt3::proto::Publication)>> {
+            Ok(vec![])
+        }
+
+        fn insert(&mut self, _: &mqtt3::proto::Publication) -> std::io::Result<u64> {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full"))
+        }
+
+        fn remove(&mut self, _: u64) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let (io_source, done) = common::IoSource::new(vec![vec![
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Connect(
+            mqtt3::proto::Connect {
+                username: None,
+                password: None,
+                will: None,
+                client_id: mqtt3::proto::ClientId::ServerGenerated,
+                keep_alive: std::time::Duration::from_secs(4),
+                protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
+                protocol_level: mqtt3::PROTOCOL_LEVEL,
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
+            session_present: false,
+            return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+        })),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(mqtt3::proto::PingReq)),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PingResp(mqtt3::proto::PingResp)),
+    ]]);
+
+    let mut client = mqtt3::Client::new(
+        None,
+        None,
+        None,
+        io_source,
+        std::time::Duration::from_secs(0),
+        std::time::Duration::from_secs(4),
+    )
+    .with_outbox(FailingOutbox)
+    .unwrap();
+
+    let publish_future = client.publish(mqtt3::proto::Publication {
+        topic_name: "topic1".to_owned(),
+        qos: mqtt3::proto::QoS::AtLeastOnce,
+        retain: false,
+        payload: [0x01, 0x02, 0x03][..].into(),
+    });
+
+    common::verify_client_events(
+        client,
+        vec![mqtt3::Event::NewConnection {
+            reset_session: true,
+        }],
+    );
+
+    done.await
+        .expect("connection broken while there were still steps remaining on the server");
+
+    match publish_future.await {
+        Err(mqtt3::PublishError::Outbox(publication, _)) => {
+            assert_eq!(publication.topic_name, "topic1")
+        }
+        result => panic!(
+            "expected client.publish() to fail with Outbox but it returned {:?}",
+            result
+        ),
+    }
+}
+
+#[tokio::test]
+async fn client_fails_publication_not_written_to_outbox() {
+    /// Stores publications, but fails to write the first one.
+    #[derive(Debug)]
+    struct FailingOutbox(std::sync::Arc<std::sync::Mutex<Vec<u64>>>);
+
+    impl mqtt3::Outbox for FailingOutbox {
+        fn load(&mut self) -> std::io::Result<Vec<(u64, mqtt3::proto::Publication)>> {
+            Ok(vec![])
+        }
+
+        fn insert(&mut self, _: &mqtt3::proto::Publication) -> std::io::Result<u64> {
+            let mut live = self.0.lock().unwrap();
+            let key = live.len() as u64;
+            live.push(key);
+            Ok(key)
+        }
+
+        fn remove(&mut self, key: u64) -> std::io::Result<()> {
+            self.0.lock().unwrap().retain(|live| *live != key);
+            Ok(())
+        }
+
+        fn poll_flush(
+            &mut self,
+            _cx: &mut std::task::Context<'_>,
+            key: u64,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(if key == 0 {
+                Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full"))
+            } else {
+                Ok(())
+            })
+        }
+    }
+
+    let live: std::sync::Arc<std::sync::Mutex<Vec<u64>>> = Default::default();
+
+    let (io_source, done) = common::IoSource::new(vec![vec![
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Connect(
+            mqtt3::proto::Connect {
+                username: None,
+                password: None,
+                will: None,
+                client_id: mqtt3::proto::ClientId::ServerGenerated,
+                keep_alive: std::time::Duration::from_secs(4),
+                protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
+                protocol_level: mqtt3::PROTOCOL_LEVEL,
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
+            session_present: false,
+            return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+        })),
+        // only the publication written to the outbox is sent
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Publish(
+            mqtt3::proto::Publish {
+                packet_identifier_dup_qos: mqtt3::proto::PacketIdentifierDupQoS::AtLeastOnce(
+                    mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+                    false,
+                ),
+                retain: false,
+                topic_name: "topic2".to_owned(),
+                payload: [0x01, 0x02, 0x03][..].into(),
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PubAck(mqtt3::proto::PubAck {
+            packet_identifier: mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+        })),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(mqtt3::proto::PingReq)),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PingResp(mqtt3::proto::PingResp)),
+    ]]);
+
+    let mut client = mqtt3::Client::new(
+        None,
+        None,
+        None,
+        io_source,
+        std::time::Duration::from_secs(0),
+        std::time::Duration::from_secs(4),
+    )
+    .with_outbox(FailingOutbox(live.clone()))
+    .unwrap();
+
+    let publication = |topic_name: &str| mqtt3::proto::Publication {
+        topic_name: topic_name.to_owned(),
+        qos: mqtt3::proto::QoS::AtLeastOnce,
+        retain: false,
+        payload: [0x01, 0x02, 0x03][..].into(),
+    };
+    let publish_future = client.publish(publication("topic1"));
+    let publish_future2 = client.publish(publication("topic2"));
+
+    common::verify_client_events(
+        client,
+        vec![mqtt3::Event::NewConnection {
+            reset_session: true,
+        }],
+    );
+
+    done.await
+        .expect("connection broken while there were still steps remaining on the server");
+
+    match publish_future.await {
+        Err(mqtt3::PublishError::Outbox(publication, _)) => {
+            assert_eq!(publication.topic_name, "topic1")
+        }
+        result => panic!(
+            "expected client.publish() to fail with Outbox but it returned {:?}",
+            result
+        ),
+    }
+    publish_future2.await.unwrap();
+
+    // the failed publication is removed along with the acknowledged one
+    assert_eq!(*live.lock().unwrap(), vec![]);
+}
+
+#[tokio::test]
+async fn client_waits_for_inflight_window() {
+    let publication = |topic_name: &str| mqtt3::proto::Publication {
+        topic_name: topic_name.to_owned(),
+        qos: mqtt3::proto::QoS::AtLeastOnce,
+        retain: false,
+        payload: [0x01, 0x02, 0x03][..].into(),
+    };
+    let publish = |packet_identifier: u16, topic_name: &str| {
+        mqtt3::proto::Packet::Publish(mqtt3::proto::Publish {
+            packet_identifier_dup_qos: mqtt3::proto::PacketIdentifierDupQoS::AtLeastOnce(
+                mqtt3::proto::PacketIdentifier::new(packet_identifier).unwrap(),
+                false,
+            ),
+            retain: false,
+            topic_name: topic_name.to_owned(),
+            payload: [0x01, 0x02, 0x03][..].into(),
+        })
+    };
+    let puback = |packet_identifier: u16| {
+        mqtt3::proto::Packet::PubAck(mqtt3::proto::PubAck {
+            packet_identifier: mqtt3::proto::PacketIdentifier::new(packet_identifier).unwrap(),
+        })
+    };
+
+    // second publication is not sent until the first one is acked
+    let (io_source, done) = common::IoSource::new(vec![vec![
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Connect(
+            mqtt3::proto::Connect {
+                username: None,
+                password: None,
+                will: None,
+                client_id: mqtt3::proto::ClientId::ServerGenerated,
+                keep_alive: std::time::Duration::from_secs(4),
+                protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
+                protocol_level: mqtt3::PROTOCOL_LEVEL,
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
+            session_present: false,
+            return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+        })),
+        common::TestConnectionStep::Receives(publish(1, "topic1")),
+        common::TestConnectionStep::Sends(puback(1)),
+        common::TestConnectionStep::Receives(publish(2, "topic2")),
+        common::TestConnectionStep::Sends(puback(2)),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(mqtt3::proto::PingReq)),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PingResp(mqtt3::proto::PingResp)),
+    ]]);
+
+    let mut client = mqtt3::Client::new(
+        None,
+        None,
+        None,
+        io_source,
+        std::time::Duration::from_secs(0),
+        std::time::Duration::from_secs(4),
+    )
+    .with_max_inflight(std::num::NonZeroUsize::new(1).unwrap());
+
+    let handle = client.publish_handle().unwrap();
+    let publish1 = client.publish(publication("topic1"));
+    let publish2 = client.publish(publication("topic2"));
+    assert_eq!(handle.queued(), 2);
+    assert_eq!(handle.inflight(), 0);
+
+    common::verify_client_events(
+        client,
+        vec![mqtt3::Event::NewConnection {
+            reset_session: true,
+        }],
+    );
+
+    done.await
+        .expect("connection broken while there were still steps remaining on the server");
+
+    publish1.await.unwrap();
+    publish2.await.unwrap();
+    assert_eq!(handle.queued(), 0);
+    assert_eq!(handle.inflight(), 0);
+}
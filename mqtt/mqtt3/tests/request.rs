@@ -0,0 +1,110 @@
+mod common;
+
+#[tokio::test]
+async fn request_resolves_with_matching_response() {
+    let (io_source, done) = common::IoSource::new(vec![vec![
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Connect(
+            mqtt3::proto::Connect {
+                username: None,
+                password: None,
+                will: None,
+                client_id: mqtt3::proto::ClientId::ServerGenerated,
+                keep_alive: std::time::Duration::from_secs(4),
+                protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
+                protocol_level: mqtt3::PROTOCOL_LEVEL,
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(mqtt3::proto::ConnAck {
+            session_present: false,
+            return_code: mqtt3::proto::ConnectReturnCode::Accepted,
+        })),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Subscribe(
+            mqtt3::proto::Subscribe {
+                packet_identifier: mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+                subscribe_to: vec![mqtt3::proto::SubscribeTo {
+                    topic_filter: "replies/#".to_owned(),
+                    qos: mqtt3::proto::QoS::AtLeastOnce,
+                }],
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::SubAck(mqtt3::proto::SubAck {
+            packet_identifier: mqtt3::proto::PacketIdentifier::new(1).unwrap(),
+            qos: vec![mqtt3::proto::SubAckQos::Success(
+                mqtt3::proto::QoS::AtLeastOnce,
+            )],
+        })),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::Publish(
+            mqtt3::proto::Publish {
+                packet_identifier_dup_qos: mqtt3::proto::PacketIdentifierDupQoS::AtLeastOnce(
+                    mqtt3::proto::PacketIdentifier::new(2).unwrap(),
+                    false,
+                ),
+                retain: false,
+                topic_name: "requests/?rid=0&reply=replies".to_owned(),
+                payload: [0x01, 0x02, 0x03][..].into(),
+            },
+        )),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PubAck(mqtt3::proto::PubAck {
+            packet_identifier: mqtt3::proto::PacketIdentifier::new(2).unwrap(),
+        })),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::Publish(mqtt3::proto::Publish {
+            packet_identifier_dup_qos: mqtt3::proto::PacketIdentifierDupQoS::AtMostOnce,
+            retain: false,
+            topic_name: "replies/?rid=0".to_owned(),
+            payload: [0x04, 0x05, 0x06][..].into(),
+        })),
+        common::TestConnectionStep::Receives(mqtt3::proto::Packet::PingReq(mqtt3::proto::PingReq)),
+        common::TestConnectionStep::Sends(mqtt3::proto::Packet::PingResp(mqtt3::proto::PingResp)),
+    ]]);
+
+    let client = mqtt3::Client::new(
+        None,
+        None,
+        None,
+        io_source,
+        std::time::Duration::from_secs(0),
+        std::time::Duration::from_secs(4),
+    );
+    let client = mqtt3::RequestClient::new(client, "replies").unwrap();
+    let mut request_handle = client.request_handle().unwrap();
+
+    let mut expected = vec![mqtt3::Event::NewConnection {
+        reset_session: true,
+    }]
+    .into_iter();
+    tokio::spawn(async move {
+        use futures_util::StreamExt;
+
+        let mut client = client;
+        while let Some(event) = client.next().await {
+            let event = event.unwrap();
+            assert_eq!(expected.next(), Some(event));
+        }
+    });
+
+    let response = request_handle
+        .request(
+            mqtt3::proto::Publication {
+                topic_name: "requests".to_owned(),
+                qos: mqtt3::proto::QoS::AtLeastOnce,
+                retain: false,
+                payload: [0x01, 0x02, 0x03][..].into(),
+            },
+            std::time::Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        response,
+        mqtt3::ReceivedPublication {
+            topic_name: "replies/?rid=0".to_owned(),
+            dup: false,
+            qos: mqtt3::proto::QoS::AtMostOnce,
+            retain: false,
+            payload: [0x04, 0x05, 0x06][..].into(),
+        }
+    );
+
+    done.await
+        .expect("connection broken while there were still steps remaining on the server");
+}
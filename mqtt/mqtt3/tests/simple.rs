@@ -231,3 +231,75 @@ async fn client_id_can_connect_and_idle() {
     done.await
         .expect("connection broken while there were still steps remaining on the server");
 }
+
+#[tokio::test]
+async fn client_gives_up_after_max_attempts() {
+    use futures_util::StreamExt;
+    use mqtt3::ReconnectPolicy;
+
+    let refused_connection = || {
+        vec![
+            common::TestConnectionStep::Receives(mqtt3::proto::Packet::Connect(
+                mqtt3::proto::Connect {
+                    username: None,
+                    password: None,
+                    will: None,
+                    client_id: mqtt3::proto::ClientId::ServerGenerated,
+                    keep_alive: std::time::Duration::from_secs(4),
+                    protocol_name: mqtt3::PROTOCOL_NAME.to_string(),
+                    protocol_level: mqtt3::PROTOCOL_LEVEL,
+                },
+            )),
+            common::TestConnectionStep::Sends(mqtt3::proto::Packet::ConnAck(
+                mqtt3::proto::ConnAck {
+                    session_present: false,
+                    return_code: mqtt3::proto::ConnectReturnCode::Refused(
+                        mqtt3::proto::ConnectionRefusedReason::ServerUnavailable,
+                    ),
+                },
+            )),
+        ]
+    };
+    let (io_source, done) = common::IoSource::new(vec![refused_connection(), refused_connection()]);
+
+    let mut client = mqtt3::Client::new(
+        None,
+        None,
+        None,
+        io_source,
+        mqtt3::FixedBackOff::new(std::time::Duration::from_secs(0)).with_max_attempts(2),
+        std::time::Duration::from_secs(4),
+    );
+
+    assert_eq!(
+        client.next().await.unwrap().unwrap(),
+        mqtt3::Event::ConnectionAttemptFailed {
+            attempt: 1,
+            reason: mqtt3::ConnectionAttemptError::ConnectionRefused(
+                mqtt3::proto::ConnectionRefusedReason::ServerUnavailable
+            ),
+            retry_in: Some(std::time::Duration::from_secs(0)),
+        }
+    );
+    assert_eq!(
+        client.next().await.unwrap().unwrap(),
+        mqtt3::Event::ConnectionAttemptFailed {
+            attempt: 2,
+            reason: mqtt3::ConnectionAttemptError::ConnectionRefused(
+                mqtt3::proto::ConnectionRefusedReason::ServerUnavailable
+            ),
+            retry_in: None,
+        }
+    );
+    match client.next().await {
+        Some(Err(mqtt3::Error::ReconnectAttemptsExhausted(2))) => (),
+        event => panic!(
+            "expected client to give up after 2 attempts but it returned {:?}",
+            event
+        ),
+    }
+    assert!(client.next().await.is_none());
+
+    done.await
+        .expect("connection broken while there were still steps remaining on the server");
+}
@@ -19,23 +19,23 @@ use mqtt_broker::{
     auth::Authorizer,
     sidecar::{Sidecar, SidecarShutdownHandle},
     Broker, BrokerBuilder, BrokerHandle, BrokerReady, BrokerSnapshot, FilePersistor,
-    MakeMqttPacketProcessor, Message, Persist, Server, ServerCertificate, SystemEvent,
-    VersionedFileFormat,
+    MakeMqttPacketProcessor, MakeTranslationPacketProcessor, Message, Persist, Server,
+    ServerCertificate, SystemEvent, VersionedFileFormat,
 };
 use mqtt_edgehub::{
     auth::{
         EdgeHubAuthenticator, EdgeHubAuthorizer, LocalAuthenticator, LocalAuthorizer,
-        PolicyAuthorizer,
+        OfflineAuthCache, PolicyAuthorizer,
     },
     command::{
         AuthorizedIdentitiesCommand, BridgeUpdateCommand, CommandHandler, DisconnectCommand,
-        PolicyUpdateCommand,
+        PolicyUpdateCommand, RemotesUpdateCommand,
     },
     connection::MakeEdgeHubPacketProcessor,
     settings::Settings,
 };
 
-use super::{shutdown, Bootstrap};
+use super::{load_translation, recording, shutdown, Bootstrap};
 
 const DEVICE_ID_ENV: &str = "IOTEDGE_DEVICEID";
 const IOTHUB_HOSTNAME_ENV: &str = "IOTEDGE_IOTHUBHOSTNAME";
@@ -108,10 +108,16 @@ impl Bootstrap for EdgeHubBootstrap {
         broker: Broker<Self::Authorizer>,
     ) -> Result<BrokerSnapshot> {
         let broker_handle = broker.handle();
-        let sidecars = make_sidecars(&broker_handle, &config)?;
+
+        let offline_auth = config
+            .auth()
+            .offline()
+            .map(|offline| OfflineAuthCache::load(offline.path(), offline.grace_period()));
+
+        let sidecars = make_sidecars(&broker_handle, &config, offline_auth.clone())?;
 
         info!("starting server...");
-        let server = make_server(config, broker, self.broker_ready).await?;
+        let server = make_server(config, broker, self.broker_ready, offline_auth).await?;
 
         let shutdown_signal = shutdown_signal(&server);
         let server = tokio::spawn(server.serve(shutdown_signal));
@@ -170,21 +176,49 @@ async fn make_server<Z>(
     config: Settings,
     broker: Broker<Z>,
     broker_ready: BrokerReady,
-) -> Result<Server<Z, MakeEdgeHubPacketProcessor<MakeMqttPacketProcessor>>>
+    offline_auth: Option<OfflineAuthCache>,
+) -> Result<
+    Server<Z, MakeTranslationPacketProcessor<MakeEdgeHubPacketProcessor<MakeMqttPacketProcessor>>>,
+>
 where
     Z: Authorizer + Send + 'static,
 {
     let broker_handle = broker.handle();
 
-    let make_processor = MakeEdgeHubPacketProcessor::new_default(broker_handle.clone());
-    let mut server = Server::from_broker(broker).with_packet_processor(make_processor);
+    // custom topic translation is applied to packets before IoT Hub topic
+    // translation, so legacy topics can be mapped onto IoT Hub topics.
+    let translation = load_translation(config.broker())?;
 
-    // Add system transport to allow communication between edgehub components
+    let make_processor = MakeTranslationPacketProcessor::new(
+        translation,
+        MakeEdgeHubPacketProcessor::new_default(broker_handle.clone()),
+    );
+    let mut server = Server::from_broker(broker).with_packet_processor(make_processor)?;
+
+    if let Some(recording) = recording(config.broker())? {
+        server.with_recording(recording);
+    }
+
+    // Add system transport to allow communication between edgehub components.
+    // Custom topic translation is intended for external clients only,
+    // so edgehub components connect without it.
     let authenticator = LocalAuthenticator::new();
-    server.with_tcp(config.listener().system().addr(), authenticator, None)?;
+    let system_processor = MakeTranslationPacketProcessor::new(
+        None,
+        MakeEdgeHubPacketProcessor::new_default(broker_handle),
+    );
+    server.with_tcp_processor(
+        config.listener().system().addr(),
+        authenticator,
+        None,
+        system_processor,
+    )?;
 
     // Add regular MQTT over TCP transport
-    let authenticator = EdgeHubAuthenticator::new(config.auth().url());
+    let mut authenticator = EdgeHubAuthenticator::new(config.auth().url());
+    if let Some(offline_auth) = offline_auth {
+        authenticator = authenticator.with_offline_cache(offline_auth);
+    }
 
     if let Some(tcp) = config.listener().tcp() {
         let broker_ready = Some(broker_ready.signal());
@@ -219,6 +253,7 @@ where
 fn make_sidecars(
     broker_handle: &BrokerHandle,
     config: &Settings,
+    offline_auth: Option<OfflineAuthCache>,
 ) -> Result<Vec<Box<dyn Sidecar + Send>>> {
     let mut sidecars: Vec<Box<dyn Sidecar + Send>> = Vec::new();
 
@@ -236,9 +271,14 @@ fn make_sidecars(
 
     let mut command_handler = CommandHandler::new(system_address, &device_id);
     command_handler.add_command(DisconnectCommand::new(broker_handle));
-    command_handler.add_command(AuthorizedIdentitiesCommand::new(broker_handle));
+    let mut authorized_identities = AuthorizedIdentitiesCommand::new(broker_handle);
+    if let Some(offline_auth) = offline_auth {
+        authorized_identities = authorized_identities.with_offline_cache(offline_auth);
+    }
+    command_handler.add_command(authorized_identities);
     command_handler.add_command(PolicyUpdateCommand::new(broker_handle));
-    command_handler.add_command(BridgeUpdateCommand::new(bridge_controller_handle));
+    command_handler.add_command(BridgeUpdateCommand::new(bridge_controller_handle.clone()));
+    command_handler.add_command(RemotesUpdateCommand::new(bridge_controller_handle));
     sidecars.push(Box::new(command_handler));
 
     Ok(sidecars)
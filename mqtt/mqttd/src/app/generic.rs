@@ -11,12 +11,12 @@ use tracing::{error, info};
 
 use mqtt_broker::{
     auth::{authenticate_fn_ok, AllowAll, Authorizer},
-    AuthId, Broker, BrokerBuilder, BrokerSnapshot, FilePersistor, MakeMqttPacketProcessor, Persist,
-    Server, ServerCertificate, VersionedFileFormat,
+    AuthId, Broker, BrokerBuilder, BrokerSnapshot, FilePersistor, MakeMqttPacketProcessor,
+    MakeTranslationPacketProcessor, Persist, Server, ServerCertificate, VersionedFileFormat,
 };
 use mqtt_generic::settings::{CertificateConfig, Settings};
 
-use super::{shutdown, Bootstrap};
+use super::{load_translation, recording, shutdown, Bootstrap};
 
 #[derive(Default)]
 pub struct GenericBootstrap;
@@ -85,11 +85,18 @@ impl Bootstrap for GenericBootstrap {
 async fn make_server<Z>(
     config: Settings,
     broker: Broker<Z>,
-) -> Result<Server<Z, MakeMqttPacketProcessor>>
+) -> Result<Server<Z, MakeTranslationPacketProcessor<MakeMqttPacketProcessor>>>
 where
     Z: Authorizer + Send + 'static,
 {
-    let mut server = Server::from_broker(broker);
+    let translation = load_translation(config.broker())?;
+
+    let make_processor = MakeTranslationPacketProcessor::new_default(translation);
+    let mut server = Server::from_broker(broker).with_packet_processor(make_processor)?;
+
+    if let Some(recording) = recording(config.broker())? {
+        server.with_recording(recording);
+    }
 
     if let Some(tcp) = config.listener().tcp() {
         let authenticator = authenticate_fn_ok(|_| Some(AuthId::Anonymous));
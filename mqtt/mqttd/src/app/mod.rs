@@ -18,7 +18,7 @@ cfg_if! {
     }
 }
 
-use std::{path::Path, time::Duration};
+use std::{fs, path::Path, time::Duration};
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
@@ -26,7 +26,8 @@ use cfg_if::cfg_if;
 use tracing::{error, info};
 
 use mqtt_broker::{
-    auth::Authorizer, Broker, BrokerSnapshot, FilePersistor, Persist, VersionedFileFormat,
+    auth::Authorizer, settings::RecordingConfig, Broker, BrokerConfig, BrokerSnapshot,
+    FilePersistor, Persist, TopicTranslation, VersionedFileFormat,
 };
 
 /// Main entrypoint to the app.
@@ -127,3 +128,39 @@ pub trait Bootstrap {
         broker: Broker<Self::Authorizer>,
     ) -> Result<BrokerSnapshot>;
 }
+
+/// Loads custom topic translation rules if configured.
+fn load_translation(config: &BrokerConfig) -> Result<Option<TopicTranslation>> {
+    config
+        .translation()
+        .map(|translation| {
+            info!(
+                "loading topic translation rules from {}",
+                translation.rules_path().display()
+            );
+            TopicTranslation::from_file(translation.rules_path())
+        })
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// Returns client connections recording settings if configured,
+/// creating the recording directory.
+fn recording(config: &BrokerConfig) -> Result<Option<RecordingConfig>> {
+    config
+        .recording()
+        .map(|recording| {
+            info!(
+                "recording client connections to {}",
+                recording.directory().display()
+            );
+            fs::create_dir_all(recording.directory()).with_context(|| {
+                format!(
+                    "cannot create recording directory {}",
+                    recording.directory().display()
+                )
+            })?;
+            Ok(recording.clone())
+        })
+        .transpose()
+}
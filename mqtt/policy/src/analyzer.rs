@@ -0,0 +1,664 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashSet},
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{
+    core::{identity_match, GROUP_PREFIX},
+    PolicyDefinition, ResourceMatcher, Statement,
+};
+
+/// Policy analyzer. Looks for statements in a policy definition which are
+/// likely to be a mistake, even though the definition is valid.
+///
+/// Operations are compared as is. Identities are compared as is, except
+/// identity patterns (like `myhub.azure-devices.net/sensor-*`), which cover
+/// every identity they match. Variable values are known only at runtime, so
+/// groups and identities with variables are compared literally and a
+/// statement for a group member is never considered covered by a statement
+/// for the group.
+/// Resources are compared with `ResourceMatcher::includes`.
+///
+/// The analyzer reports:
+/// - shadowed statements - statements that never apply, since all their
+///   identities, operations and resources are covered by unconditional
+///   statements with higher priority and opposite effect.
+/// - redundant statements - statements that never apply, since all their
+///   identities, operations and resources are covered by unconditional
+///   statements with higher priority and the same effect, so the statement
+///   can be removed.
+/// - conflicting statements - allow and deny statements for the same identity
+///   and operation with overlapping resources.
+/// - unused variables - variables which are never substituted, so rules
+///   containing them never match. Checked only if the list of known
+///   variables is provided.
+///
+/// A statement covered partially by statements with the same effect and
+/// partially by statements with opposite effect is reported both as shadowed
+/// and as redundant, each finding listing its own covering statements.
+#[derive(Debug)]
+pub struct PolicyAnalyzer<M> {
+    matcher: M,
+    variables: Option<HashSet<String>>,
+}
+
+impl<M> PolicyAnalyzer<M>
+where
+    M: ResourceMatcher,
+{
+    pub fn new(matcher: M) -> Self {
+        Self {
+            matcher,
+            variables: None,
+        }
+    }
+
+    /// Specifies the list of variables (like `{{var_name}}`) the `Substituter`
+    /// supports.
+    pub fn with_variables<I, V>(mut self, variables: I) -> Self
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<String>,
+    {
+        self.variables = Some(variables.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Analyzes the policy definition and returns all findings ordered by
+    /// statement index.
+    pub fn analyze(&self, definition: &PolicyDefinition) -> Vec<Finding> {
+        let statements = definition.statements();
+        let mut findings = vec![];
+
+        for (index, statement) in statements.iter().enumerate() {
+            findings.extend(self.visit_variables(index, statement));
+            findings.extend(self.visit_overlaps(index, statement, &statements[..index]));
+        }
+
+        findings
+    }
+
+    fn visit_variables(&self, index: usize, statement: &Statement) -> Vec<Finding> {
+        let variables = match &self.variables {
+            Some(variables) => variables,
+            None => return vec![],
+        };
+
+        let unused = statement
+            .identities()
+            .iter()
+            .chain(statement.operations())
+            .chain(statement.resources())
+            .flat_map(|value| VAR_PATTERN.find_iter(value))
+            .map(|variable| variable.as_str())
+            .filter(|variable| !variables.contains(*variable))
+            .collect::<BTreeSet<_>>();
+
+        unused
+            .into_iter()
+            .map(|variable| Finding::UnusedVariable {
+                statement: index,
+                variable: variable.into(),
+            })
+            .collect()
+    }
+
+    fn visit_overlaps(
+        &self,
+        index: usize,
+        statement: &Statement,
+        previous: &[Statement],
+    ) -> Vec<Finding> {
+        let mut shadowed_by = BTreeSet::new();
+        let mut redundant_with = BTreeSet::new();
+        let mut uncovered = vec![];
+
+        for (identity, operation, resource) in rules(statement) {
+            // the first unconditional statement covering the rule decides
+            let covering = previous.iter().position(|other| {
+                other.conditions().is_empty()
+                    && rules(other).into_iter().any(|(i, o, r)| {
+                        covers_identity(&i, &identity)
+                            && o == operation
+                            && self.includes(r, resource)
+                    })
+            });
+
+            match covering {
+                Some(other) if previous[other].effect() == statement.effect() => {
+                    redundant_with.insert(other);
+                }
+                Some(other) => {
+                    shadowed_by.insert(other);
+                }
+                None => uncovered.push((identity, operation, resource)),
+            }
+        }
+
+        if uncovered.is_empty() {
+            let mut findings = vec![];
+            if !shadowed_by.is_empty() {
+                findings.push(Finding::Shadowed {
+                    statement: index,
+                    covered_by: shadowed_by.into_iter().collect(),
+                });
+            }
+            if !redundant_with.is_empty() {
+                findings.push(Finding::Redundant {
+                    statement: index,
+                    covered_by: redundant_with.into_iter().collect(),
+                });
+            }
+            return findings;
+        }
+
+        previous
+            .iter()
+            .enumerate()
+            .filter(|(_, other)| other.effect() != statement.effect())
+            .filter_map(|(other_index, other)| {
+                uncovered
+                    .iter()
+                    .find(|(identity, operation, resource)| {
+                        rules(other).into_iter().any(|(i, o, r)| {
+                            (covers_identity(&i, identity) || covers_identity(identity, &i))
+                                && o == *operation
+                                && (self.includes(r, resource) || self.includes(resource, r))
+                        })
+                    })
+                    .map(|(identity, operation, resource)| Finding::Conflict {
+                        statement: index,
+                        other: other_index,
+                        identity: identity.to_string(),
+                        operation: (*operation).into(),
+                        resource: (*resource).into(),
+                    })
+            })
+            .collect()
+    }
+
+    fn includes(&self, policy: &str, other: &str) -> bool {
+        policy == other || self.matcher.includes(policy, other)
+    }
+}
+
+/// Represents a potential problem with a policy statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Finding {
+    /// Statement never applies, since it is covered by unconditional
+    /// statements with higher priority and opposite effect.
+    Shadowed {
+        statement: usize,
+        covered_by: Vec<usize>,
+    },
+
+    /// Statement never applies, since it is covered by unconditional
+    /// statements with higher priority and the same effect.
+    Redundant {
+        statement: usize,
+        covered_by: Vec<usize>,
+    },
+
+    /// Statement partially overlaps with a statement with higher priority
+    /// and opposite effect.
+    Conflict {
+        statement: usize,
+        other: usize,
+        identity: String,
+        operation: String,
+        resource: String,
+    },
+
+    /// Statement contains a variable which is never substituted.
+    UnusedVariable { statement: usize, variable: String },
+}
+
+impl Finding {
+    /// Returns the index of the statement the finding is about.
+    pub fn statement(&self) -> usize {
+        match self {
+            Self::Shadowed { statement, .. }
+            | Self::Redundant { statement, .. }
+            | Self::Conflict { statement, .. }
+            | Self::UnusedVariable { statement, .. } => *statement,
+        }
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Shadowed {
+                statement,
+                covered_by,
+            } => write!(
+                f,
+                "statement #{} is shadowed by statements with opposite effect: {}",
+                statement,
+                join(covered_by)
+            ),
+            Self::Redundant {
+                statement,
+                covered_by,
+            } => write!(
+                f,
+                "statement #{} is redundant, it is covered by statements: {}",
+                statement,
+                join(covered_by)
+            ),
+            Self::Conflict {
+                statement,
+                other,
+                identity,
+                operation,
+                resource,
+            } => write!(
+                f,
+                "statement #{} conflicts with statement #{} on identity \"{}\", operation \"{}\", resource \"{}\"",
+                statement, other, identity, operation, resource
+            ),
+            Self::UnusedVariable {
+                statement,
+                variable,
+            } => write!(
+                f,
+                "statement #{} contains variable {} which is never substituted",
+                statement, variable
+            ),
+        }
+    }
+}
+
+/// Checks whether a rule identity applies to every request the other
+/// rule identity applies to.
+///
+/// Literal identities and groups never contain wildcards, so only identity
+/// patterns are matched against the other identity.
+fn covers_identity(identity: &str, other: &str) -> bool {
+    if identity == other {
+        return true;
+    }
+    if is_group(identity) || is_group(other) {
+        return false;
+    }
+
+    // `?` in a pattern matches a single character, so it can not cover a `*`
+    // of the other pattern.
+    let other_is_pattern = other.contains(|c| c == '*' || c == '?');
+    identity_match(identity, other) && !(other_is_pattern && identity.contains('?'))
+}
+
+fn is_group(identity: &str) -> bool {
+    identity.starts_with(GROUP_PREFIX)
+}
+
+/// Returns all combinations of rule identity, operation and resource defined
+/// by the statement.
+fn rules(statement: &Statement) -> Vec<(Cow<'_, str>, &str, &str)> {
+    let resources = if statement.resources().is_empty() {
+        vec![""]
+    } else {
+        statement.resources().iter().map(String::as_str).collect()
+    };
+
+    let mut rules = vec![];
+    for identity in statement.rule_identities() {
+        for operation in statement.operations() {
+            for resource in &resources {
+                rules.push((identity.clone(), operation.as_str(), *resource));
+            }
+        }
+    }
+    rules
+}
+
+fn join(statements: &[usize]) -> String {
+    statements
+        .iter()
+        .map(|statement| format!("#{}", statement))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+lazy_static! {
+    static ref VAR_PATTERN: Regex =
+        Regex::new(r#"\{\{[^\{\}]+\}\}"#).expect("failed to create a Regex from pattern");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DefaultResourceMatcher, PolicyDefinition, Request, ResourceMatcher};
+
+    use super::*;
+
+    fn analyze(json: &str) -> Vec<Finding> {
+        let definition = PolicyDefinition::from_json(json).unwrap();
+        PolicyAnalyzer::new(DefaultResourceMatcher)
+            .with_variables(vec!["{{var_actor}}"])
+            .analyze(&definition)
+    }
+
+    #[test]
+    fn valid_policy_has_no_findings() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "deny",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["resource_1"]
+                },
+                {
+                    "effect": "allow",
+                    "identities": ["{{var_actor}}"],
+                    "operations": ["read"],
+                    "resources": ["resource_1"]
+                }
+            ]
+        }"#;
+
+        assert_eq!(analyze(json), vec![]);
+    }
+
+    #[test]
+    fn shadowed_and_redundant_statements() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identities": ["actor_a", "actor_b"],
+                    "operations": ["write"],
+                    "resources": ["resource_1", "resource_2"]
+                },
+                {
+                    "effect": "deny",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["resource_1"]
+                },
+                {
+                    "effect": "allow",
+                    "identities": ["actor_b"],
+                    "operations": ["write"],
+                    "resources": ["resource_2"]
+                }
+            ]
+        }"#;
+
+        assert_eq!(
+            analyze(json),
+            vec![
+                Finding::Shadowed {
+                    statement: 1,
+                    covered_by: vec![0]
+                },
+                Finding::Redundant {
+                    statement: 2,
+                    covered_by: vec![0]
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn conditional_statement_does_not_shadow() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["resource_1"],
+                    "conditions": [
+                        { "operator": "timeOfDay", "start": "08:00", "end": "18:00" }
+                    ]
+                },
+                {
+                    "effect": "deny",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["resource_1"]
+                }
+            ]
+        }"#;
+
+        assert_eq!(
+            analyze(json),
+            vec![Finding::Conflict {
+                statement: 1,
+                other: 0,
+                identity: "actor_a".into(),
+                operation: "write".into(),
+                resource: "resource_1".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn conflicting_statements_with_overlapping_resources() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "deny",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["floor1/secret"]
+                },
+                {
+                    "effect": "allow",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["floor1/"]
+                },
+                {
+                    "effect": "deny",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["floor1/public"]
+                }
+            ]
+        }"#;
+
+        let definition = PolicyDefinition::from_json(json).unwrap();
+        let findings = PolicyAnalyzer::new(PrefixMatcher).analyze(&definition);
+
+        assert_eq!(
+            findings,
+            vec![
+                Finding::Conflict {
+                    statement: 1,
+                    other: 0,
+                    identity: "actor_a".into(),
+                    operation: "write".into(),
+                    resource: "floor1/".into()
+                },
+                Finding::Shadowed {
+                    statement: 2,
+                    covered_by: vec![1]
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn statement_covered_by_both_effects_is_shadowed_and_redundant() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "deny",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["resource_1"]
+                },
+                {
+                    "effect": "allow",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["resource_2"]
+                },
+                {
+                    "effect": "allow",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["resource_1", "resource_2"]
+                }
+            ]
+        }"#;
+
+        assert_eq!(
+            analyze(json),
+            vec![
+                Finding::Shadowed {
+                    statement: 2,
+                    covered_by: vec![0]
+                },
+                Finding::Redundant {
+                    statement: 2,
+                    covered_by: vec![1]
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn identity_pattern_covers_matching_identities() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "deny",
+                    "identityPatterns": ["hub/sensor-*"],
+                    "operations": ["write"],
+                    "resources": ["resource_1"]
+                },
+                {
+                    "effect": "allow",
+                    "identities": ["hub/sensor-1"],
+                    "identityPatterns": ["hub/sensor-2*"],
+                    "operations": ["write"],
+                    "resources": ["resource_1"]
+                },
+                {
+                    "effect": "allow",
+                    "identityPatterns": ["hub/sensor-?"],
+                    "operations": ["read"],
+                    "resources": ["resource_1"]
+                },
+                {
+                    "effect": "allow",
+                    "identityPatterns": ["hub/sensor-*"],
+                    "operations": ["read"],
+                    "resources": ["resource_1"]
+                },
+                {
+                    "effect": "allow",
+                    "identities": ["hub/sensor-1/module_a"],
+                    "operations": ["write"],
+                    "resources": ["resource_1"]
+                }
+            ]
+        }"#;
+
+        assert_eq!(
+            analyze(json),
+            vec![Finding::Shadowed {
+                statement: 1,
+                covered_by: vec![0]
+            }]
+        );
+    }
+
+    #[test]
+    fn group_and_variable_identities_are_compared_literally() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "groups": {
+                "sensors": ["hub/sensor-1"]
+            },
+            "statements": [
+                {
+                    "effect": "deny",
+                    "identities": ["{{var_actor}}"],
+                    "groups": ["sensors"],
+                    "operations": ["write"],
+                    "resources": ["resource_1"]
+                },
+                {
+                    "effect": "allow",
+                    "identities": ["hub/sensor-1"],
+                    "operations": ["write"],
+                    "resources": ["resource_1"]
+                }
+            ]
+        }"#;
+
+        assert_eq!(analyze(json), vec![]);
+    }
+
+    #[test]
+    fn unused_variables() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identities": ["{{var_actor}}", "{{unknown_actor}}"],
+                    "operations": ["write"],
+                    "resources": ["{{unknown_resource}}/{{var_actor}}"]
+                }
+            ]
+        }"#;
+
+        assert_eq!(
+            analyze(json),
+            vec![
+                Finding::UnusedVariable {
+                    statement: 0,
+                    variable: "{{unknown_actor}}".into()
+                },
+                Finding::UnusedVariable {
+                    statement: 0,
+                    variable: "{{unknown_resource}}".into()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn finding_display() {
+        let finding = Finding::Shadowed {
+            statement: 3,
+            covered_by: vec![0, 1],
+        };
+
+        assert_eq!(
+            finding.to_string(),
+            "statement #3 is shadowed by statements with opposite effect: #0, #1"
+        );
+    }
+
+    /// `PrefixMatcher` matches resources that start with policy resource.
+    #[derive(Debug)]
+    struct PrefixMatcher;
+
+    impl ResourceMatcher for PrefixMatcher {
+        type Context = ();
+
+        fn do_match(&self, _: &Request<Self::Context>, input: &str, policy: &str) -> bool {
+            input.starts_with(policy)
+        }
+
+        fn includes(&self, policy: &str, other: &str) -> bool {
+            other.starts_with(policy)
+        }
+    }
+}
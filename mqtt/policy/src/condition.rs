@@ -0,0 +1,450 @@
+use std::{
+    convert::TryFrom,
+    fmt::{Display, Formatter, Result as FmtResult},
+    net::IpAddr,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+
+/// Attribute key for the current time of day. If `Substituter` does not
+/// provide a value for it, the system clock (UTC) is used.
+pub const TIME_OF_DAY_KEY: &str = "env:time_of_day";
+
+const SECS_PER_DAY: u32 = 24 * 60 * 60;
+
+/// Represents a condition in a policy statement.
+///
+/// Statement applies to the request only if all its conditions are satisfied.
+/// Conditions with a list of values are satisfied if any of the values matches.
+/// Condition with an attribute not provided by `Substituter` is never satisfied.
+///
+/// # Example:
+/// ```json
+/// "conditions": [
+///     { "operator": "ipAddress", "key": "mqtt:peer_addr", "values": ["172.18.0.0/16"] },
+///     { "operator": "numericEquals", "key": "mqtt:qos", "value": 0 },
+///     { "operator": "timeOfDay", "start": "22:00", "end": "06:00" }
+/// ]
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "operator", rename_all = "camelCase")]
+pub enum Condition {
+    IpAddress { key: String, values: Vec<Cidr> },
+    NumericEquals { key: String, value: f64 },
+    NumericLessThan { key: String, value: f64 },
+    NumericLessThanEquals { key: String, value: f64 },
+    NumericGreaterThan { key: String, value: f64 },
+    NumericGreaterThanEquals { key: String, value: f64 },
+    StringEquals { key: String, values: Vec<String> },
+    StringLike { key: String, values: Vec<String> },
+    TimeOfDay { start: TimeOfDay, end: TimeOfDay },
+}
+
+impl Condition {
+    /// Returns a key of request attribute the condition is evaluated against.
+    pub fn key(&self) -> &str {
+        match self {
+            Self::IpAddress { key, .. }
+            | Self::NumericEquals { key, .. }
+            | Self::NumericLessThan { key, .. }
+            | Self::NumericLessThanEquals { key, .. }
+            | Self::NumericGreaterThan { key, .. }
+            | Self::NumericGreaterThanEquals { key, .. }
+            | Self::StringEquals { key, .. }
+            | Self::StringLike { key, .. } => key,
+            Self::TimeOfDay { .. } => TIME_OF_DAY_KEY,
+        }
+    }
+
+    /// Returns the name of the condition operator as it appears in policy definition.
+    pub fn operator(&self) -> &'static str {
+        match self {
+            Self::IpAddress { .. } => "ipAddress",
+            Self::NumericEquals { .. } => "numericEquals",
+            Self::NumericLessThan { .. } => "numericLessThan",
+            Self::NumericLessThanEquals { .. } => "numericLessThanEquals",
+            Self::NumericGreaterThan { .. } => "numericGreaterThan",
+            Self::NumericGreaterThanEquals { .. } => "numericGreaterThanEquals",
+            Self::StringEquals { .. } => "stringEquals",
+            Self::StringLike { .. } => "stringLike",
+            Self::TimeOfDay { .. } => "timeOfDay",
+        }
+    }
+
+    /// Checks the condition against a request attribute value.
+    pub(crate) fn evaluate(&self, value: Option<Value>) -> bool {
+        match (self, value) {
+            (Self::IpAddress { values, .. }, Some(Value::Address(addr))) => {
+                values.iter().any(|cidr| cidr.contains(addr))
+            }
+            (
+                Self::NumericEquals {
+                    value: expected, ..
+                },
+                Some(Value::Number(actual)),
+            ) => (actual - expected).abs() < f64::EPSILON,
+            (
+                Self::NumericLessThan {
+                    value: expected, ..
+                },
+                Some(Value::Number(actual)),
+            ) => actual < *expected,
+            (
+                Self::NumericLessThanEquals {
+                    value: expected, ..
+                },
+                Some(Value::Number(actual)),
+            ) => actual <= *expected,
+            (
+                Self::NumericGreaterThan {
+                    value: expected, ..
+                },
+                Some(Value::Number(actual)),
+            ) => actual > *expected,
+            (
+                Self::NumericGreaterThanEquals {
+                    value: expected, ..
+                },
+                Some(Value::Number(actual)),
+            ) => actual >= *expected,
+            (Self::StringEquals { values, .. }, Some(value)) => {
+                let value = value.to_string();
+                values.iter().any(|expected| *expected == value)
+            }
+            (Self::StringLike { values, .. }, Some(value)) => {
+                let value = value.to_string();
+                values.iter().any(|pattern| glob_match(pattern, &value))
+            }
+            (Self::TimeOfDay { start, end }, Some(Value::TimeOfDay(time))) => {
+                is_within(*start, *end, time)
+            }
+            (Self::TimeOfDay { start, end }, None) => is_within(*start, *end, TimeOfDay::now()),
+            _ => false,
+        }
+    }
+}
+
+/// Represents a value of request attribute to evaluate conditions against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Address(IpAddr),
+    TimeOfDay(TimeOfDay),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Number(value) => write!(f, "{}", value),
+            Self::String(value) => write!(f, "{}", value),
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Address(value) => write!(f, "{}", value),
+            Self::TimeOfDay(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// An IP network in CIDR notation (`172.18.0.0/16`) or a single IP address.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.network, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                prefix_eq(&network.octets(), &addr.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                prefix_eq(&network.octets(), &addr.octets(), self.prefix_len)
+            }
+            (IpAddr::V4(network), IpAddr::V6(addr)) => addr.to_ipv4().map_or(false, |addr| {
+                prefix_eq(&network.octets(), &addr.octets(), self.prefix_len)
+            }),
+            (IpAddr::V6(_), IpAddr::V4(_)) => false,
+        }
+    }
+}
+
+fn prefix_eq(network: &[u8], addr: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = usize::from(prefix_len / 8);
+    if network[..full_bytes] != addr[..full_bytes] {
+        return false;
+    }
+
+    let rest = prefix_len % 8;
+    if rest == 0 {
+        return true;
+    }
+
+    let mask = 0xff_u8 << (8 - rest);
+    network[full_bytes] & mask == addr[full_bytes] & mask
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (network, prefix_len) = match s.split_once('/') {
+            Some((network, prefix_len)) => (network, Some(prefix_len)),
+            None => (s, None),
+        };
+
+        let network: IpAddr = network
+            .parse()
+            .map_err(|_| format!("invalid IP address: {}", s))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("invalid network prefix length: {}", s))?,
+            None => max_len,
+        };
+
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Time of day in UTC with minute precision, in `HH:MM` format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(u32);
+
+impl TimeOfDay {
+    pub fn new(hours: u32, minutes: u32) -> Option<Self> {
+        if hours < 24 && minutes < 60 {
+            Some(Self(hours * 60 * 60 + minutes * 60))
+        } else {
+            None
+        }
+    }
+
+    pub fn from_secs(secs: u64) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self((secs % u64::from(SECS_PER_DAY)) as u32)
+    }
+
+    fn now() -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        Self::from_secs(secs)
+    }
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{:02}:{:02}", self.0 / 3600, self.0 % 3600 / 60)
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_once(':')
+            .and_then(|(hours, minutes)| {
+                let hours = hours.parse().ok()?;
+                let minutes = minutes.parse().ok()?;
+                Self::new(hours, minutes)
+            })
+            .ok_or_else(|| format!("invalid time of day, expected HH:MM: {}", s))
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Checks that time is within a window. Window wraps around midnight if
+/// `end` is earlier than `start`, and covers the whole day if they are equal.
+fn is_within(start: TimeOfDay, end: TimeOfDay, time: TimeOfDay) -> bool {
+    match start.cmp(&end) {
+        std::cmp::Ordering::Less => start <= time && time < end,
+        std::cmp::Ordering::Greater => start <= time || time < end,
+        std::cmp::Ordering::Equal => true,
+    }
+}
+
+/// Matches a value against a pattern with `*` (any sequence of characters)
+/// and `?` (any single character) wildcards.
+pub(crate) fn glob_match(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let value = value.chars().collect::<Vec<_>>();
+
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    v = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::*;
+
+    fn time(s: &str) -> TimeOfDay {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_contains_address() {
+        let cidr: Cidr = "172.18.0.0/16".parse().unwrap();
+
+        assert!(cidr.contains("172.18.3.4".parse().unwrap()));
+        assert!(!cidr.contains("172.19.0.1".parse().unwrap()));
+        assert!(cidr.contains("::ffff:172.18.0.1".parse().unwrap()));
+
+        let cidr: Cidr = "10.0.0.0/9".parse().unwrap();
+        assert!(cidr.contains("10.127.255.255".parse().unwrap()));
+        assert!(!cidr.contains("10.128.0.0".parse().unwrap()));
+
+        let cidr: Cidr = "10.1.2.3".parse().unwrap();
+        assert!(cidr.contains("10.1.2.3".parse().unwrap()));
+        assert!(!cidr.contains("10.1.2.4".parse().unwrap()));
+
+        let cidr: Cidr = "fd00::/8".parse().unwrap();
+        assert!(cidr.contains("fd12::1".parse().unwrap()));
+        assert!(!cidr.contains("10.1.2.4".parse().unwrap()));
+    }
+
+    #[test]
+    fn cidr_rejects_invalid_values() {
+        assert_matches!("172.18.0.0/33".parse::<Cidr>(), Err(_));
+        assert_matches!("172.18.0/16".parse::<Cidr>(), Err(_));
+        assert_matches!("localhost".parse::<Cidr>(), Err(_));
+    }
+
+    #[test]
+    fn time_window_wraps_around_midnight() {
+        assert!(is_within(time("08:00"), time("18:00"), time("08:00")));
+        assert!(!is_within(time("08:00"), time("18:00"), time("18:00")));
+        assert!(is_within(time("22:00"), time("06:00"), time("23:30")));
+        assert!(is_within(time("22:00"), time("06:00"), time("05:59")));
+        assert!(!is_within(time("22:00"), time("06:00"), time("12:00")));
+        assert!(is_within(time("00:00"), time("00:00"), time("12:00")));
+    }
+
+    #[test]
+    fn glob_matches_wildcards() {
+        assert!(glob_match("sensor-*", "sensor-42"));
+        assert!(glob_match("*-42", "sensor-42"));
+        assert!(glob_match("s?nsor*", "sensor"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+
+        assert!(!glob_match("sensor-?", "sensor-42"));
+        assert!(!glob_match("a*b*c", "aXXbYY"));
+    }
+
+    #[test]
+    fn numeric_conditions() {
+        let condition = Condition::NumericLessThanEquals {
+            key: "mqtt:qos".into(),
+            value: 1.0,
+        };
+
+        assert!(condition.evaluate(Some(Value::Number(0.0))));
+        assert!(condition.evaluate(Some(Value::Number(1.0))));
+        assert!(!condition.evaluate(Some(Value::Number(2.0))));
+        assert!(!condition.evaluate(Some(Value::String("0".into()))));
+        assert!(!condition.evaluate(None));
+    }
+
+    #[test]
+    fn string_conditions_compare_any_value_as_string() {
+        let condition = Condition::StringEquals {
+            key: "mqtt:retain".into(),
+            values: vec!["false".into()],
+        };
+
+        assert!(condition.evaluate(Some(Value::Bool(false))));
+        assert!(!condition.evaluate(Some(Value::Bool(true))));
+    }
+
+    #[test]
+    fn deserialize_conditions() {
+        let json = r#"[
+            { "operator": "ipAddress", "key": "mqtt:peer_addr", "values": ["172.18.0.0/16"] },
+            { "operator": "numericGreaterThan", "key": "mqtt:payload_size", "value": 1024 },
+            { "operator": "timeOfDay", "start": "22:00", "end": "06:00" }
+        ]"#;
+
+        let conditions: Vec<Condition> = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            conditions,
+            vec![
+                Condition::IpAddress {
+                    key: "mqtt:peer_addr".into(),
+                    values: vec!["172.18.0.0/16".parse().unwrap()]
+                },
+                Condition::NumericGreaterThan {
+                    key: "mqtt:payload_size".into(),
+                    value: 1024.0
+                },
+                Condition::TimeOfDay {
+                    start: time("22:00"),
+                    end: time("06:00")
+                },
+            ]
+        );
+        assert_eq!(conditions[2].key(), TIME_OF_DAY_KEY);
+    }
+
+    #[test]
+    fn deserialize_invalid_condition_fails() {
+        let json = r#"{ "operator": "ipAddress", "key": "mqtt:peer_addr", "values": ["nope"] }"#;
+        assert_matches!(serde_json::from_str::<Condition>(json), Err(_));
+
+        let json = r#"{ "operator": "timeOfDay", "start": "25:00", "end": "06:00" }"#;
+        assert_matches!(serde_json::from_str::<Condition>(json), Err(_));
+    }
+}
@@ -1,13 +1,15 @@
-use std::error::Error as StdError;
+use std::{borrow::Cow, collections::BTreeMap, error::Error as StdError};
 
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::Deserialize;
 
 use crate::{
-    core::{Identities, Operations, Resources},
-    Decision, DefaultResourceMatcher, DefaultSubstituter, DefaultValidator, Error, Policy,
-    PolicyValidator, ResourceMatcher, Result, Substituter,
+    core::{
+        group_key, Identities, IdentityResolver, Operations, Resources, VariableIndex, GROUP_PREFIX,
+    },
+    Condition, Decision, DefaultResourceMatcher, DefaultSubstituter, DefaultValidator, Error,
+    Policy, PolicyValidator, ResourceMatcher, Result, Substituter,
 };
 
 /// A policy builder, responsible for parsing policy definition
@@ -134,23 +136,67 @@ where
             .validate(&definition)
             .map_err(|e| Error::Validation(e.into()))?;
 
+        check_identities(&definition)?;
+
         let mut static_rules = Identities::new();
         let mut variable_rules = Identities::new();
+        let mut descriptions = Vec::with_capacity(definition.statements.len());
 
-        for statement in definition.statements {
-            process_statement(&statement, &mut static_rules, &mut variable_rules);
+        for statement in &definition.statements {
+            process_statement(statement, &mut static_rules, &mut variable_rules);
+            descriptions.push(statement.description.clone());
         }
 
+        let resolver = IdentityResolver::new(
+            &definition.groups,
+            definition
+                .statements
+                .iter()
+                .flat_map(|statement| &statement.identity_patterns),
+        );
+        let variable_index = VariableIndex::new(&variable_rules.0);
+
         Ok(Policy {
             default_decision,
             resource_matcher: matcher,
             substituter,
             static_rules: static_rules.0,
             variable_rules: variable_rules.0,
+            descriptions,
+            resolver,
+            variable_index,
         })
     }
 }
 
+/// Checks that every statement identity has only one meaning. Literal identities
+/// must not look like identity patterns or group references, and patterns and
+/// groups must not contain variables.
+fn check_identities(definition: &PolicyDefinition) -> Result<()> {
+    for statement in &definition.statements {
+        for identity in &statement.identities {
+            if is_pattern(identity) || identity.starts_with(GROUP_PREFIX) {
+                return Err(Error::AmbiguousIdentity(identity.clone()));
+            }
+        }
+        for pattern in &statement.identity_patterns {
+            if pattern.starts_with(GROUP_PREFIX) || is_variable_rule(pattern) {
+                return Err(Error::AmbiguousIdentity(pattern.clone()));
+            }
+        }
+        for group in &statement.groups {
+            if is_variable_rule(group) {
+                return Err(Error::AmbiguousIdentity(group.clone()));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_pattern(identity: &str) -> bool {
+    identity.contains(|c| c == '*' || c == '?')
+}
+
 fn process_statement(
     statement: &Statement,
     static_rules: &mut Identities,
@@ -165,7 +211,8 @@ fn process_statement(
 fn process_identities(statement: &Statement) -> (Identities, Identities) {
     let mut static_ids = Identities::new();
     let mut variable_ids = Identities::new();
-    for identity in &statement.identities {
+    for identity in statement.rule_identities() {
+        let identity = identity.as_ref();
         let (static_ops, variable_ops) = process_operations(statement);
 
         if is_variable_rule(identity) {
@@ -245,10 +292,20 @@ enum Source {
 }
 
 /// Represents a deserialized policy definition.
+///
+/// Besides statements it may declare named identity groups, which
+/// statements refer to by name in their `groups` field:
+/// ```json
+/// "groups": {
+///     "sensors": ["myhub.azure-devices.net/sensor_a", "myhub.azure-devices.net/sensor_b"]
+/// }
+/// ```
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PolicyDefinition {
     pub(super) statements: Vec<Statement>,
+    #[serde(default)]
+    pub(super) groups: BTreeMap<String, Vec<String>>,
 }
 
 impl PolicyDefinition {
@@ -262,6 +319,10 @@ impl PolicyDefinition {
     pub fn statements(&self) -> &Vec<Statement> {
         &self.statements
     }
+
+    pub fn groups(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.groups
+    }
 }
 
 /// Represents a statement in a policy definition.
@@ -273,10 +334,17 @@ pub struct Statement {
     #[serde(default)]
     pub(super) description: String,
     pub(super) effect: Effect,
+    #[serde(default)]
     pub(super) identities: Vec<String>,
+    #[serde(default)]
+    pub(super) identity_patterns: Vec<String>,
+    #[serde(default)]
+    pub(super) groups: Vec<String>,
     pub(super) operations: Vec<String>,
     #[serde(default)]
     pub(super) resources: Vec<String>,
+    #[serde(default)]
+    pub(super) conditions: Vec<Condition>,
 }
 
 impl Statement {
@@ -296,6 +364,24 @@ impl Statement {
         &self.identities
     }
 
+    pub fn identity_patterns(&self) -> &Vec<String> {
+        &self.identity_patterns
+    }
+
+    pub fn groups(&self) -> &Vec<String> {
+        &self.groups
+    }
+
+    /// Returns identities of all rules defined by the statement: literal
+    /// identities, identity patterns and groups.
+    pub(crate) fn rule_identities(&self) -> impl Iterator<Item = Cow<'_, str>> {
+        self.identities
+            .iter()
+            .chain(&self.identity_patterns)
+            .map(|identity| Cow::Borrowed(identity.as_str()))
+            .chain(self.groups.iter().map(|group| Cow::Owned(group_key(group))))
+    }
+
     pub fn operations(&self) -> &Vec<String> {
         &self.operations
     }
@@ -303,6 +389,10 @@ impl Statement {
     pub fn resources(&self) -> &Vec<String> {
         &self.resources
     }
+
+    pub fn conditions(&self) -> &Vec<Condition> {
+        &self.conditions
+    }
 }
 
 /// Represents an effect on a statement.
@@ -1,13 +1,21 @@
 use std::{
     cmp::Ordering,
-    collections::{btree_map::Entry, BTreeMap},
+    collections::{btree_map::Entry, BTreeMap, HashMap},
+    fmt::{Display, Formatter, Result as FmtResult},
 };
 
+use serde::Deserialize;
+
 use crate::errors::Result;
-use crate::{substituter::Substituter, Error, ResourceMatcher};
+use crate::{substituter::Substituter, Condition, Error, ResourceMatcher};
 
 mod builder;
+mod resolver;
+mod variables;
 pub use builder::{Effect, PolicyBuilder, PolicyDefinition, Statement};
+use resolver::IdentityResolver;
+pub(crate) use resolver::{group_key, identity_match, GROUP_PREFIX};
+use variables::VariableIndex;
 
 /// Policy engine. Represents a read-only set of rules and can
 /// evaluate `Request` based on those rules.
@@ -16,7 +24,11 @@ pub use builder::{Effect, PolicyBuilder, PolicyDefinition, Statement};
 /// - static rules
 /// - variable rules - any rule that contains variables ("{{..}}").
 /// Static rules are organized in a data structure with fast querying time.
-/// Variable rules are evaluated on every request.
+/// Variable rules are evaluated on every request from precompiled templates.
+///
+/// Static rules may apply to identity groups and identity patterns
+/// (like `myhub.azure-devices.net/sensor-*`). A request identity is
+/// resolved to all groups and patterns it matches using an index.
 #[derive(Debug)]
 pub struct Policy<R, S> {
     default_decision: Decision,
@@ -24,6 +36,9 @@ pub struct Policy<R, S> {
     substituter: S,
     static_rules: BTreeMap<String, Operations>,
     variable_rules: BTreeMap<String, Operations>,
+    descriptions: Vec<String>,
+    resolver: IdentityResolver,
+    variable_index: VariableIndex,
 }
 
 impl<R, S, RC> Policy<R, S>
@@ -35,112 +50,149 @@ where
     ///
     /// If no rules match the `&Request` - the default `Decision` is returned.
     pub fn evaluate(&self, request: &Request<RC>) -> Result<Decision> {
-        match self.eval_static_rules(request) {
-            // static rules undefined. Need to check variable rules.
-            Ok(None) => match self.eval_variable_rules(request) {
-                // variable rules undefined as well. Return default decision.
-                Ok(None) => Ok(self.default_decision),
-                // variable rules defined. Return the decision.
-                Ok(Some(effect)) => Ok(effect.into()),
-                Err(e) => Err(e),
+        Ok(match self.find_effect(request)? {
+            Some((effect, _)) => effect.into(),
+            None => self.default_decision,
+        })
+    }
+
+    /// Evaluates the provided `&Request` the same way as `evaluate` does
+    /// and explains which statement produced the `Decision`.
+    pub fn explain(&self, request: &Request<RC>) -> Result<Explanation> {
+        Ok(match self.find_effect(request)? {
+            Some((effect, rule)) => Explanation {
+                decision: effect.into(),
+                source: DecisionSource::Statement {
+                    index: effect.order,
+                    description: self
+                        .descriptions
+                        .get(effect.order)
+                        .cloned()
+                        .unwrap_or_default(),
+                    rule,
+                },
             },
-            // static rules are defined. Evaluate variable rules and compare priority.
-            Ok(Some(static_effect)) => {
-                match self.eval_variable_rules(request) {
-                    // variable rules undefined. Proceed with static rule decision.
-                    Ok(None) => Ok(static_effect.into()),
-                    // variable rules defined. Compare priority and return.
-                    Ok(Some(variable_effect)) => {
-                        // compare order.
-                        Ok(if variable_effect > static_effect {
-                            static_effect
-                        } else {
-                            variable_effect
-                        }
-                        .into())
-                    }
-                    Err(e) => Err(e),
+            None => Explanation {
+                decision: self.default_decision,
+                source: DecisionSource::Default,
+            },
+        })
+    }
+
+    /// Replaces identity groups populated at runtime (e.g. from identity tags).
+    /// Groups declared in the policy definition are kept, members of both
+    /// declared and updated groups with the same name belong to the group.
+    pub fn update_groups(&mut self, groups: &HashMap<String, Vec<String>>) {
+        self.resolver.update_groups(groups);
+    }
+
+    fn find_effect(&self, request: &Request<RC>) -> Result<Option<(EffectOrd, RuleKind)>> {
+        let static_effect = self.eval_static_rules(request)?;
+        let variable_effect = self.eval_variable_rules(request)?;
+
+        Ok(match (static_effect, variable_effect) {
+            // both static and variable rules are defined. Compare priority.
+            (Some(static_effect), Some(variable_effect)) => {
+                if variable_effect > static_effect {
+                    Some((static_effect, RuleKind::Static))
+                } else {
+                    Some((variable_effect, RuleKind::Variable))
                 }
             }
-
-            Err(e) => Err(e),
-        }
+            (Some(static_effect), None) => Some((static_effect, RuleKind::Static)),
+            (None, Some(variable_effect)) => Some((variable_effect, RuleKind::Variable)),
+            // neither static nor variable rules are defined.
+            (None, None) => None,
+        })
     }
 
     #[allow(clippy::unnecessary_wraps)]
     fn eval_static_rules(&self, request: &Request<RC>) -> Result<Option<EffectOrd>> {
-        // lookup an identity
-        match self.static_rules.get(&request.identity) {
+        // we need to go through all identities the request identity resolves to
+        // (itself, groups and patterns) and find an effect with highest priority (smallest order).
+        let mut result: Option<EffectOrd> = None;
+        for identity in self.resolver.resolve(&request.identity) {
+            // lookup an identity
+            let operations = match self.static_rules.get(identity) {
+                Some(operations) => operations,
+                None => continue,
+            };
             // identity exists. Look up operations.
-            Some(operations) => match operations.0.get(&request.operation) {
+            if let Some(resources) = operations.0.get(&request.operation) {
                 // operation exists.
-                Some(resources) => {
-                    // iterate over and match resources.
-                    // we need to go through all resources and find one with highest priority (smallest order).
-                    let mut result: Option<EffectOrd> = None;
-                    for (resource, effect) in &resources.0 {
-                        // check the order first
-                        if effect.order < result.map_or(usize::MAX, |e| e.order)
-                             // only then check that matches
-                            && self.resource_matcher.do_match( // only then check that matches
-                                request,
-                                &request.resource,
-                                resource,
-                            )
-                        {
-                            result = Some(*effect);
+                // iterate over and match resources.
+                for (resource, effects) in &resources.0 {
+                    let order = result.map_or(usize::MAX, |e| e.order);
+                    // check the order first
+                    if effects.order() < order
+                         // only then check that matches
+                        && self.resource_matcher.do_match(
+                            request,
+                            &request.resource,
+                            resource,
+                        )
+                    {
+                        // and finally check conditions
+                        if let Some(effect) = effects.find(order, |conditions| {
+                            self.check_conditions(request, conditions)
+                        }) {
+                            result = Some(effect);
                         }
                     }
-                    Ok(result)
                 }
-                None => Ok(None),
-            },
-            None => Ok(None),
+            }
         }
+        Ok(result)
     }
 
     fn eval_variable_rules(&self, request: &Request<RC>) -> Result<Option<EffectOrd>> {
-        for (identity, operations) in &self.variable_rules {
-            // process identity variables.
-            let identity = self.substituter.visit_identity(identity, request)?;
-            // check if it does match after processing variables.
-            if identity == request.identity {
-                // lookup operation.
-                return match operations.0.get(&request.operation) {
-                    // operation exists.
-                    Some(resources) => {
-                        // iterate over and match resources.
-                        // we need to go through all resources and find one with highest priority (smallest order).
-                        let mut result: Option<EffectOrd> = None;
-                        for (resource, effect) in &resources.0 {
-                            let resource = self.substituter.visit_resource(resource, request)?;
-                            // check the order first
-                            if effect.order < result.map_or(usize::MAX, |e| e.order)
-                                // only then check that matches
-                                && self.resource_matcher.do_match(
-                                    request,
-                                    &request.resource,
-                                    &resource,
-                                )
-                            {
-                                result = Some(*effect);
-                            }
-                        }
-                        // continue to look for other identity variable rules
-                        // if no resources matched the current one.
-                        if result == None {
-                            continue;
-                        }
-                        Ok(result)
+        // variable rules with identity matching the request identity after processing
+        // variables, and resources resolved for the request operation.
+        let rules = self.variable_index.resolve(request, &self.substituter)?;
+
+        for (identity, resolved) in rules.iter() {
+            // lookup operation.
+            let resources = match self.variable_rules[identity].0.get(&request.operation) {
+                Some(resources) => resources,
+                None => continue,
+            };
+            // iterate over and match resources.
+            // we need to go through all resources and find one with highest priority (smallest order).
+            let mut result: Option<EffectOrd> = None;
+            for (effects, resource) in resources.0.values().zip(resolved) {
+                let order = result.map_or(usize::MAX, |e| e.order);
+                // check the order first
+                if effects.order() < order
+                    // only then check that matches
+                    && self.resource_matcher.do_match(
+                        request,
+                        &request.resource,
+                        resource,
+                    )
+                {
+                    // and finally check conditions
+                    if let Some(effect) = effects.find(order, |conditions| {
+                        self.check_conditions(request, conditions)
+                    }) {
+                        result = Some(effect);
                     }
-                    // continue to look for other identity variable rules
-                    // if no operation found for the current one.
-                    None => continue,
-                };
+                }
+            }
+            // continue to look for other identity variable rules
+            // if no resources matched the current one.
+            if result.is_some() {
+                return Ok(result);
             }
         }
         Ok(None)
     }
+
+    fn check_conditions(&self, request: &Request<RC>, conditions: &[Condition]) -> bool {
+        conditions.iter().all(|condition| {
+            let value = self.substituter.visit_attribute(condition.key(), request);
+            condition.evaluate(value)
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -204,7 +256,7 @@ impl From<BTreeMap<String, Resources>> for Operations {
 }
 
 #[derive(Debug, Clone)]
-struct Resources(BTreeMap<String, EffectOrd>);
+struct Resources(BTreeMap<String, Effects>);
 
 impl Resources {
     pub fn new() -> Self {
@@ -213,27 +265,88 @@ impl Resources {
 
     pub fn merge(&mut self, collection: Resources) {
         for (key, value) in collection.0 {
-            self.insert(&key, value);
+            for effect in value.0 {
+                self.insert(&key, effect);
+            }
         }
     }
 
-    fn insert(&mut self, resource: &str, effect: EffectOrd) {
+    fn insert(&mut self, resource: &str, effect: ConditionalEffect) {
         let entry = self.0.entry(resource.to_string());
         match entry {
             Entry::Vacant(item) => {
-                item.insert(effect);
+                item.insert(Effects(vec![effect]));
             }
-            Entry::Occupied(mut item) => item.get_mut().merge(effect),
+            Entry::Occupied(mut item) => item.get_mut().insert(effect),
         }
     }
 }
 
-impl From<BTreeMap<String, EffectOrd>> for Resources {
-    fn from(map: BTreeMap<String, EffectOrd>) -> Self {
+impl From<BTreeMap<String, Effects>> for Resources {
+    fn from(map: BTreeMap<String, Effects>) -> Self {
         Resources(map)
     }
 }
 
+/// Effects of all statements for the same identity, operation and resource
+/// sorted by priority.
+///
+/// Only effects up to the first unconditional one are kept, since it
+/// overrides all effects with lower priority.
+#[derive(Debug, Clone)]
+struct Effects(Vec<ConditionalEffect>);
+
+impl Effects {
+    fn insert(&mut self, effect: ConditionalEffect) {
+        if self.0.iter().any(|e| e.effect.order == effect.effect.order) {
+            return;
+        }
+
+        let index = self
+            .0
+            .partition_point(|e| e.effect.order < effect.effect.order);
+        self.0.insert(index, effect);
+
+        if let Some(index) = self.0.iter().position(|e| e.conditions.is_empty()) {
+            self.0.truncate(index + 1);
+        }
+    }
+
+    /// Returns the order of the effect with highest priority.
+    fn order(&self) -> usize {
+        self.0.first().map_or(usize::MAX, |e| e.effect.order)
+    }
+
+    /// Returns the effect with highest priority which is higher than a given
+    /// `order` and which conditions are satisfied.
+    fn find<F>(&self, order: usize, mut satisfied: F) -> Option<EffectOrd>
+    where
+        F: FnMut(&[Condition]) -> bool,
+    {
+        self.0
+            .iter()
+            .take_while(|e| e.effect.order < order)
+            .find(|e| e.conditions.is_empty() || satisfied(&e.conditions))
+            .map(|e| e.effect)
+    }
+}
+
+/// Statement effect which applies only if statement conditions are satisfied.
+#[derive(Debug, Clone)]
+struct ConditionalEffect {
+    effect: EffectOrd,
+    conditions: Vec<Condition>,
+}
+
+impl From<&Statement> for ConditionalEffect {
+    fn from(statement: &Statement) -> Self {
+        Self {
+            effect: statement.into(),
+            conditions: statement.conditions().clone(),
+        }
+    }
+}
+
 /// Represents a request that needs to be `evaluate`d by `Policy` engine.
 #[derive(Debug)]
 pub struct Request<RC> {
@@ -294,12 +407,92 @@ impl<RC> Request<RC> {
 }
 
 /// Represents a decision on the `Request` to the `Policy` engine.
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
 pub enum Decision {
     Allowed,
     Denied,
 }
 
+impl Display for Decision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Allowed => write!(f, "allowed"),
+            Self::Denied => write!(f, "denied"),
+        }
+    }
+}
+
+/// Represents a `Decision` along with the source it was produced from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    decision: Decision,
+    source: DecisionSource,
+}
+
+impl Explanation {
+    pub fn decision(&self) -> Decision {
+        self.decision
+    }
+
+    pub fn source(&self) -> &DecisionSource {
+        &self.source
+    }
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match &self.source {
+            DecisionSource::Statement {
+                index,
+                description,
+                rule,
+            } => {
+                write!(f, "{} by statement #{}", self.decision, index)?;
+                if !description.is_empty() {
+                    write!(f, " \"{}\"", description)?;
+                }
+                write!(f, " ({} rule)", rule)
+            }
+            DecisionSource::Default => write!(f, "{} by default decision", self.decision),
+        }
+    }
+}
+
+/// Describes what produced a `Decision`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecisionSource {
+    /// A policy statement with a given index in the policy definition
+    /// matched the request.
+    Statement {
+        index: usize,
+        description: String,
+        rule: RuleKind,
+    },
+
+    /// No statements matched the request and the default decision was used.
+    Default,
+}
+
+/// Kind of rule a statement was matched by.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RuleKind {
+    /// A rule without variables.
+    Static,
+
+    /// A rule containing variables in identity, operation or resource.
+    Variable,
+}
+
+impl Display for RuleKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Static => write!(f, "static"),
+            Self::Variable => write!(f, "variable"),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 struct EffectOrd {
     order: usize,
@@ -310,15 +503,6 @@ impl EffectOrd {
     pub fn new(effect: Effect, order: usize) -> Self {
         Self { order, effect }
     }
-
-    /// Merges two `EffectOrd` by replacing with the one with higher priority.
-    ///
-    /// Lower the order value => higher the effect priority.
-    pub fn merge(&mut self, item: EffectOrd) {
-        if self.order > item.order {
-            *self = item;
-        }
-    }
 }
 
 impl PartialOrd for EffectOrd {
@@ -348,7 +532,7 @@ impl From<&Statement> for EffectOrd {
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
-    use crate::{DefaultResourceMatcher, DefaultSubstituter};
+    use crate::{DefaultResourceMatcher, DefaultSubstituter, Value, TIME_OF_DAY_KEY};
     use matches::assert_matches;
 
     /// Helper method to build a policy.
@@ -799,8 +983,383 @@ pub(crate) mod tests {
         assert_matches!(policy.evaluate(&request), Ok(Decision::Allowed));
     }
 
-    /// `TestSubstituter` replaces any value with the corresponding identity
-    /// from the request, thus making the variable rule to always match the request.
+    #[test]
+    fn evaluate_conditional_rule_falls_back_to_next_statement() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identities": [
+                        "actor_a"
+                    ],
+                    "operations": [
+                        "write"
+                    ],
+                    "resources": [
+                        "telemetry"
+                    ],
+                    "conditions": [
+                        { "operator": "numericEquals", "key": "qos", "value": 0 }
+                    ]
+                },
+                {
+                    "effect": "deny",
+                    "identities": [
+                        "actor_a"
+                    ],
+                    "operations": [
+                        "write"
+                    ],
+                    "resources": [
+                        "telemetry"
+                    ]
+                }
+            ]
+        }"#;
+
+        let policy = PolicyBuilder::from_json(json)
+            .with_default_decision(Decision::Allowed)
+            .with_substituter(AttributeSubstituter(vec![("qos", Value::Number(0.0))]))
+            .build()
+            .expect("Unable to build policy from json.");
+
+        let request = Request::new("actor_a", "write", "telemetry").unwrap();
+        assert_matches!(policy.evaluate(&request), Ok(Decision::Allowed));
+
+        let policy = PolicyBuilder::from_json(json)
+            .with_default_decision(Decision::Allowed)
+            .with_substituter(AttributeSubstituter(vec![("qos", Value::Number(1.0))]))
+            .build()
+            .expect("Unable to build policy from json.");
+
+        assert_matches!(policy.evaluate(&request), Ok(Decision::Denied));
+    }
+
+    #[test]
+    fn evaluate_conditional_rule_with_unknown_attribute_does_not_apply() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identities": [
+                        "actor_a"
+                    ],
+                    "operations": [
+                        "connect"
+                    ],
+                    "conditions": [
+                        { "operator": "ipAddress", "key": "peer_addr", "values": ["172.18.0.0/16"] }
+                    ]
+                }
+            ]
+        }"#;
+
+        let policy = build_policy(json);
+
+        let request = Request::new("actor_a", "connect", "").unwrap();
+        assert_matches!(policy.evaluate(&request), Ok(Decision::Denied));
+
+        let policy = PolicyBuilder::from_json(json)
+            .with_default_decision(Decision::Denied)
+            .with_substituter(AttributeSubstituter(vec![(
+                "peer_addr",
+                Value::Address("172.18.0.5".parse().unwrap()),
+            )]))
+            .build()
+            .expect("Unable to build policy from json.");
+
+        assert_matches!(policy.evaluate(&request), Ok(Decision::Allowed));
+    }
+
+    #[test]
+    fn evaluate_conditional_variable_rule() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "deny",
+                    "identities": [
+                        "actor_a"
+                    ],
+                    "operations": [
+                        "write"
+                    ],
+                    "resources": [
+                        "{{var}}/telemetry"
+                    ],
+                    "conditions": [
+                        { "operator": "timeOfDay", "start": "08:00", "end": "18:00" }
+                    ]
+                },
+                {
+                    "effect": "allow",
+                    "identities": [
+                        "actor_a"
+                    ],
+                    "operations": [
+                        "write"
+                    ],
+                    "resources": [
+                        "{{var}}/telemetry"
+                    ]
+                }
+            ]
+        }"#;
+
+        let request = Request::new("actor_a", "write", "{{var}}/telemetry").unwrap();
+
+        let policy = PolicyBuilder::from_json(json)
+            .with_default_decision(Decision::Denied)
+            .with_substituter(AttributeSubstituter(vec![(
+                TIME_OF_DAY_KEY,
+                Value::TimeOfDay("12:00".parse().unwrap()),
+            )]))
+            .build()
+            .expect("Unable to build policy from json.");
+
+        assert_matches!(policy.evaluate(&request), Ok(Decision::Denied));
+
+        let policy = PolicyBuilder::from_json(json)
+            .with_default_decision(Decision::Denied)
+            .with_substituter(AttributeSubstituter(vec![(
+                TIME_OF_DAY_KEY,
+                Value::TimeOfDay("20:00".parse().unwrap()),
+            )]))
+            .build()
+            .expect("Unable to build policy from json.");
+
+        assert_matches!(policy.evaluate(&request), Ok(Decision::Allowed));
+    }
+
+    #[test]
+    fn unconditional_effect_overrides_following_effects() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["telemetry"],
+                    "conditions": [
+                        { "operator": "stringEquals", "key": "retain", "values": ["false"] }
+                    ]
+                },
+                {
+                    "effect": "deny",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["telemetry"]
+                },
+                {
+                    "effect": "allow",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["telemetry"],
+                    "conditions": [
+                        { "operator": "stringEquals", "key": "retain", "values": ["true"] }
+                    ]
+                }
+            ]
+        }"#;
+
+        let policy = build_policy(json);
+
+        assert_eq!(
+            2,
+            policy.static_rules["actor_a"].0["write"].0["telemetry"]
+                .0
+                .len()
+        );
+    }
+
+    #[test]
+    fn evaluate_identity_groups_and_patterns() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "groups": {
+                "sensors": ["hub/device_1"]
+            },
+            "statements": [
+                {
+                    "effect": "deny",
+                    "identities": ["hub/sensor-2"],
+                    "operations": ["write"],
+                    "resources": ["telemetry"]
+                },
+                {
+                    "effect": "allow",
+                    "groups": ["sensors"],
+                    "identityPatterns": ["hub/sensor-*"],
+                    "operations": ["write"],
+                    "resources": ["telemetry"]
+                }
+            ]
+        }"#;
+
+        let policy = build_policy(json);
+
+        let request = Request::new("hub/device_1", "write", "telemetry").unwrap();
+        assert_matches!(policy.evaluate(&request), Ok(Decision::Allowed));
+
+        let request = Request::new("hub/sensor-1", "write", "telemetry").unwrap();
+        assert_matches!(policy.evaluate(&request), Ok(Decision::Allowed));
+
+        // explicit deny for an identity has higher priority than pattern.
+        let request = Request::new("hub/sensor-2", "write", "telemetry").unwrap();
+        assert_matches!(policy.evaluate(&request), Ok(Decision::Denied));
+
+        let request = Request::new("hub/device_2", "write", "telemetry").unwrap();
+        assert_matches!(policy.evaluate(&request), Ok(Decision::Denied));
+
+        // pattern for devices does not apply to their modules.
+        let request = Request::new("hub/sensor-1/moduleX", "write", "telemetry").unwrap();
+        assert_matches!(policy.evaluate(&request), Ok(Decision::Denied));
+
+        // identity named after a group is not a member of the group.
+        let request = Request::new("group:sensors", "write", "telemetry").unwrap();
+        assert_matches!(policy.evaluate(&request), Ok(Decision::Denied));
+    }
+
+    #[test]
+    fn ambiguous_identities_are_rejected() {
+        for identity in &["hub/sensor-*", "hub/sensor-?", "group:sensors"] {
+            let json = format!(
+                r#"{{
+                    "schemaVersion": "2020-10-30",
+                    "statements": [
+                        {{
+                            "effect": "allow",
+                            "identities": ["{}"],
+                            "operations": ["write"],
+                            "resources": ["telemetry"]
+                        }}
+                    ]
+                }}"#,
+                identity
+            );
+
+            let result = PolicyBuilder::from_json(json).build();
+
+            assert_matches!(result, Err(Error::AmbiguousIdentity(i)) if i == *identity);
+        }
+    }
+
+    #[test]
+    fn ambiguous_patterns_and_groups_are_rejected() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identityPatterns": ["{{iot:identity}}*"],
+                    "operations": ["write"],
+                    "resources": ["telemetry"]
+                }
+            ]
+        }"#;
+        assert_matches!(
+            PolicyBuilder::from_json(json).build(),
+            Err(Error::AmbiguousIdentity(_))
+        );
+
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identityPatterns": ["group:sensors"],
+                    "operations": ["write"],
+                    "resources": ["telemetry"]
+                }
+            ]
+        }"#;
+        assert_matches!(
+            PolicyBuilder::from_json(json).build(),
+            Err(Error::AmbiguousIdentity(_))
+        );
+    }
+
+    #[test]
+    fn explain_decision() {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "description": "Allow actor_a to write",
+                    "effect": "allow",
+                    "identities": ["actor_a"],
+                    "operations": ["write"],
+                    "resources": ["resource_1"]
+                },
+                {
+                    "effect": "deny",
+                    "identities": ["{{var_actor}}"],
+                    "operations": ["read"],
+                    "resources": ["resource_1"]
+                }
+            ]
+        }"#;
+
+        let policy = PolicyBuilder::from_json(json)
+            .with_substituter(TestIdentitySubstituter)
+            .with_default_decision(Decision::Denied)
+            .build()
+            .expect("Unable to build policy from json.");
+
+        let request = Request::new("actor_a", "write", "resource_1").unwrap();
+        let explanation = policy.explain(&request).unwrap();
+        assert_eq!(explanation.decision(), Decision::Allowed);
+        assert_eq!(
+            explanation.source(),
+            &DecisionSource::Statement {
+                index: 0,
+                description: "Allow actor_a to write".into(),
+                rule: RuleKind::Static,
+            }
+        );
+        assert_eq!(
+            explanation.to_string(),
+            "allowed by statement #0 \"Allow actor_a to write\" (static rule)"
+        );
+
+        let request = Request::new("actor_b", "read", "resource_1").unwrap();
+        let explanation = policy.explain(&request).unwrap();
+        assert_eq!(explanation.decision(), Decision::Denied);
+        assert_eq!(
+            explanation.source(),
+            &DecisionSource::Statement {
+                index: 1,
+                description: String::new(),
+                rule: RuleKind::Variable,
+            }
+        );
+
+        let request = Request::new("actor_b", "write", "resource_2").unwrap();
+        let explanation = policy.explain(&request).unwrap();
+        assert_eq!(explanation.decision(), Decision::Denied);
+        assert_eq!(explanation.source(), &DecisionSource::Default);
+        assert_eq!(explanation.to_string(), "denied by default decision");
+    }
+
+    /// Rules tree with a single unconditional effect is compared to the effect.
+    impl PartialEq<EffectOrd> for Effects {
+        fn eq(&self, other: &EffectOrd) -> bool {
+            matches!(self.0.as_slice(), [effect] if effect.conditions.is_empty() && effect.effect == *other)
+        }
+    }
+
+    impl PartialEq<Effects> for EffectOrd {
+        fn eq(&self, other: &Effects) -> bool {
+            other == self
+        }
+    }
+
+    /// `TestIdentitySubstituter` replaces any identity variable with the
+    /// corresponding identity from the request, thus making the variable rule
+    /// with a single variable identity to always match the request.
     #[derive(Debug)]
     struct TestIdentitySubstituter;
 
@@ -816,6 +1375,30 @@ pub(crate) mod tests {
         }
     }
 
+    /// `AttributeSubstituter` does not replace variables and resolves
+    /// attributes from a predefined list.
+    #[derive(Debug)]
+    struct AttributeSubstituter(Vec<(&'static str, Value)>);
+
+    impl Substituter for AttributeSubstituter {
+        type Context = ();
+
+        fn visit_identity(&self, value: &str, _context: &Request<Self::Context>) -> Result<String> {
+            Ok(value.into())
+        }
+
+        fn visit_resource(&self, value: &str, _context: &Request<Self::Context>) -> Result<String> {
+            Ok(value.into())
+        }
+
+        fn visit_attribute(&self, key: &str, _context: &Request<Self::Context>) -> Option<Value> {
+            self.0
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, value)| value.clone())
+        }
+    }
+
     /// `StartWithMatcher` matches resources that start with requested value. For
     /// example, if a policy defines a resource "hello/world", then request for "hello/"
     /// will match.
@@ -832,6 +1415,8 @@ pub(crate) mod tests {
 
     #[cfg(feature = "proptest")]
     mod proptests {
+        use std::collections::BTreeMap;
+
         use crate::{Decision, Effect, PolicyBuilder, PolicyDefinition, Request, Statement};
         use proptest::{collection::vec, prelude::*};
 
@@ -879,7 +1464,8 @@ pub(crate) mod tests {
                 statements in vec(arb_statement(), 1..5)
             ) -> PolicyDefinition {
                 PolicyDefinition {
-                    statements
+                    statements,
+                    groups: BTreeMap::new(),
                 }
             }
         }
@@ -897,8 +1483,11 @@ pub(crate) mod tests {
                     description,
                     effect,
                     identities,
+                    identity_patterns: vec![],
+                    groups: vec![],
                     operations,
                     resources,
+                    conditions: vec![],
                 }
             }
         }
@@ -912,7 +1501,7 @@ pub(crate) mod tests {
         }
 
         pub fn arb_identity() -> impl Strategy<Value = String> {
-            "(\\PC+)|(\\{\\{\\PC+\\}\\})"
+            "([^*?\\p{C}]+)|(\\{\\{[^*?\\p{C}]+\\}\\})"
         }
 
         pub fn arb_operation() -> impl Strategy<Value = String> {
@@ -0,0 +1,209 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::condition::glob_match;
+
+/// Prefix of a rule identity which refers to a named identity group.
+///
+/// Statements refer to groups with a separate `groups` field, and literal
+/// identities with this prefix are rejected, so a group rule can not be
+/// confused with a rule for an identity.
+pub(crate) const GROUP_PREFIX: &str = "group:";
+
+/// Resolves a request identity to all identities of static rules which
+/// apply to it:
+/// - the identity itself.
+/// - groups the identity is a member of.
+/// - identity patterns (like `myhub.azure-devices.net/sensor-*`) matching it.
+///
+/// Group membership is indexed by member identity and identity patterns are
+/// indexed by the literal prefix before the first wildcard, so resolution
+/// does not iterate over all groups or patterns in the policy.
+#[derive(Debug, Default)]
+pub(super) struct IdentityResolver {
+    declared: BTreeMap<String, Vec<String>>,
+    groups: HashMap<String, BTreeSet<String>>,
+    patterns: BTreeMap<String, Vec<String>>,
+}
+
+impl IdentityResolver {
+    /// Creates a resolver for groups declared in policy definition and
+    /// identity patterns found in statements.
+    pub fn new<'a>(
+        declared: &BTreeMap<String, Vec<String>>,
+        patterns: impl IntoIterator<Item = &'a String>,
+    ) -> Self {
+        let mut index: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for pattern in patterns {
+            let prefix = pattern
+                .find(|c| c == '*' || c == '?')
+                .map_or(pattern.as_str(), |index| &pattern[..index]);
+            let entry = index.entry(prefix.to_string()).or_default();
+            if !entry.contains(pattern) {
+                entry.push(pattern.clone());
+            }
+        }
+
+        Self {
+            declared: declared.clone(),
+            groups: index_groups(declared),
+            patterns: index,
+        }
+    }
+
+    /// Replaces groups populated at runtime. Declared groups are kept.
+    pub fn update_groups(&mut self, updated: &HashMap<String, Vec<String>>) {
+        self.groups = index_groups(self.declared.iter().chain(updated));
+    }
+
+    /// Returns all identities of static rules which apply to the request identity.
+    pub fn resolve<'a>(&'a self, identity: &'a str) -> impl Iterator<Item = &'a str> {
+        // rules keyed by group prefix belong to groups only.
+        let literal = Some(identity).filter(|identity| !identity.starts_with(GROUP_PREFIX));
+
+        let groups = self
+            .groups
+            .get(identity)
+            .into_iter()
+            .flatten()
+            .map(String::as_str);
+
+        let patterns = identity
+            .char_indices()
+            .map(|(index, _)| index)
+            .chain(Some(identity.len()))
+            .filter_map(move |index| self.patterns.get(&identity[..index]))
+            .flatten()
+            .filter(move |pattern| identity_match(pattern, identity))
+            .map(String::as_str);
+
+        literal.into_iter().chain(groups).chain(patterns)
+    }
+}
+
+/// Indexes group names by member identity.
+fn index_groups<'a>(
+    groups: impl IntoIterator<Item = (&'a String, &'a Vec<String>)>,
+) -> HashMap<String, BTreeSet<String>> {
+    let mut index: HashMap<String, BTreeSet<String>> = HashMap::new();
+    for (group, members) in groups {
+        for member in members {
+            index
+                .entry(member.clone())
+                .or_default()
+                .insert(group_key(group));
+        }
+    }
+    index
+}
+
+/// Returns the rule identity for a named identity group.
+pub(crate) fn group_key(group: &str) -> String {
+    format!("{}{}", GROUP_PREFIX, group)
+}
+
+/// Matches an identity against an identity pattern. Wildcards `*` and `?`
+/// match within a single `/`-separated segment, so `hub/sensor-*` matches
+/// device `hub/sensor-1`, but not its module `hub/sensor-1/module_a`.
+pub(crate) fn identity_match(pattern: &str, identity: &str) -> bool {
+    pattern.split('/').count() == identity.split('/').count()
+        && pattern
+            .split('/')
+            .zip(identity.split('/'))
+            .all(|(pattern, segment)| glob_match(pattern, segment))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::{identity_match, IdentityResolver};
+
+    #[test]
+    fn resolves_identity_groups_and_patterns() {
+        let mut declared = BTreeMap::new();
+        declared.insert("sensors".to_string(), vec!["hub/device_1".to_string()]);
+
+        let patterns = vec![
+            "hub/device_*".to_string(),
+            "hub/sensor-*".to_string(),
+            "*".to_string(),
+        ];
+        let resolver = IdentityResolver::new(&declared, &patterns);
+
+        assert_eq!(
+            resolver.resolve("hub/device_1").collect::<Vec<_>>(),
+            vec!["hub/device_1", "group:sensors", "hub/device_*"]
+        );
+        assert_eq!(
+            resolver.resolve("hub/device_2").collect::<Vec<_>>(),
+            vec!["hub/device_2", "hub/device_*"]
+        );
+        assert_eq!(
+            resolver.resolve("device_3").collect::<Vec<_>>(),
+            vec!["device_3", "*"]
+        );
+    }
+
+    #[test]
+    fn updated_groups_extend_declared_groups() {
+        let mut declared = BTreeMap::new();
+        declared.insert("sensors".to_string(), vec!["hub/device_1".to_string()]);
+        let mut resolver = IdentityResolver::new(&declared, &[]);
+
+        let mut updated = HashMap::new();
+        updated.insert("sensors".to_string(), vec!["hub/device_2".to_string()]);
+        updated.insert("monitors".to_string(), vec!["hub/device_1".to_string()]);
+        resolver.update_groups(&updated);
+
+        assert_eq!(
+            resolver.resolve("hub/device_1").collect::<Vec<_>>(),
+            vec!["hub/device_1", "group:monitors", "group:sensors"]
+        );
+        assert_eq!(
+            resolver.resolve("hub/device_2").collect::<Vec<_>>(),
+            vec!["hub/device_2", "group:sensors"]
+        );
+
+        resolver.update_groups(&HashMap::new());
+
+        assert_eq!(
+            resolver.resolve("hub/device_1").collect::<Vec<_>>(),
+            vec!["hub/device_1", "group:sensors"]
+        );
+        assert_eq!(
+            resolver.resolve("hub/device_2").collect::<Vec<_>>(),
+            vec!["hub/device_2"]
+        );
+    }
+
+    #[test]
+    fn pattern_does_not_match_module_identity() {
+        let patterns = vec!["hub/sensor-*".to_string(), "hub/sensor-?".to_string()];
+        let resolver = IdentityResolver::new(&BTreeMap::new(), &patterns);
+
+        assert_eq!(
+            resolver.resolve("hub/sensor-1/moduleX").collect::<Vec<_>>(),
+            vec!["hub/sensor-1/moduleX"]
+        );
+    }
+
+    #[test]
+    fn group_identity_does_not_resolve_to_group_rules() {
+        let mut declared = BTreeMap::new();
+        declared.insert("sensors".to_string(), vec!["hub/device_1".to_string()]);
+        let resolver = IdentityResolver::new(&declared, &[]);
+
+        assert_eq!(resolver.resolve("group:sensors").count(), 0);
+    }
+
+    #[test]
+    fn wildcards_match_within_segment() {
+        assert!(identity_match("hub/sensor-*", "hub/sensor-1"));
+        assert!(identity_match("*/sensor-?", "hub/sensor-1"));
+        assert!(identity_match("hub/*/module_a", "hub/sensor-1/module_a"));
+
+        assert!(!identity_match("hub/sensor-*", "hub/sensor-1/moduleX"));
+        assert!(!identity_match("hub/sensor-?", "hub/sensor-/"));
+        assert!(!identity_match("*", "hub/sensor-1"));
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use lazy_static::lazy_static;
+use linked_hash_map::LinkedHashMap;
+use regex::Regex;
+
+use crate::{errors::Result, Request, Substituter};
+
+use super::Operations;
+
+/// Max number of resolved rule sets kept in `VariableIndex` cache.
+const CACHE_CAPACITY: usize = 4096;
+
+/// Variable rules compiled into templates.
+///
+/// Every identity and resource of variable rules is split into literal and
+/// variable segments once, when the policy is built. On evaluation only
+/// distinct variables are substituted, and templates are rendered with their
+/// values.
+///
+/// Variable rules resolved for a request are cached by request identity,
+/// operation and the substituter cache key (see `Substituter::cache_key`),
+/// so subsequent requests with the same key neither substitute variables
+/// nor render templates. When the cache is full, the least recently used
+/// rules are evicted.
+#[derive(Debug)]
+pub(super) struct VariableIndex {
+    identities: Vec<IdentityTemplate>,
+    identity_variables: Vec<String>,
+    resource_variables: Vec<String>,
+    cache: Mutex<LinkedHashMap<CacheKey, Arc<ResolvedRules>>>,
+    capacity: usize,
+}
+
+/// Variable rules which identity matches the request identity in the order
+/// of evaluation, along with resources resolved for the request operation.
+pub(super) type ResolvedRules = Vec<(String, Vec<String>)>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    identity: String,
+    operation: String,
+    substitution: String,
+}
+
+#[derive(Debug)]
+struct IdentityTemplate {
+    identity: String,
+    template: Template,
+    operations: BTreeMap<String, Vec<Template>>,
+}
+
+impl VariableIndex {
+    pub fn new(variable_rules: &BTreeMap<String, Operations>) -> Self {
+        Self::with_capacity(variable_rules, CACHE_CAPACITY)
+    }
+
+    fn with_capacity(variable_rules: &BTreeMap<String, Operations>, capacity: usize) -> Self {
+        let mut identity_variables = BTreeSet::new();
+        let mut resource_variables = BTreeSet::new();
+
+        let identities = variable_rules
+            .iter()
+            .map(|(identity, operations)| {
+                let template = Template::parse(identity);
+                identity_variables.extend(template.variables().map(String::from));
+
+                let operations = operations
+                    .0
+                    .iter()
+                    .map(|(operation, resources)| {
+                        let templates = resources
+                            .0
+                            .keys()
+                            .map(|resource| Template::parse(resource))
+                            .collect::<Vec<_>>();
+                        for template in &templates {
+                            resource_variables.extend(template.variables().map(String::from));
+                        }
+                        (operation.clone(), templates)
+                    })
+                    .collect();
+
+                IdentityTemplate {
+                    identity: identity.clone(),
+                    template,
+                    operations,
+                }
+            })
+            .collect();
+
+        Self {
+            identities,
+            identity_variables: identity_variables.into_iter().collect(),
+            resource_variables: resource_variables.into_iter().collect(),
+            cache: Mutex::new(LinkedHashMap::new()),
+            capacity,
+        }
+    }
+
+    /// Returns variable rules which apply to the request.
+    pub fn resolve<S, RC>(
+        &self,
+        request: &Request<RC>,
+        substituter: &S,
+    ) -> Result<Arc<ResolvedRules>>
+    where
+        S: Substituter<Context = RC>,
+    {
+        let key = substituter.cache_key(request).map(|substitution| CacheKey {
+            identity: request.identity.clone(),
+            operation: request.operation.clone(),
+            substitution,
+        });
+
+        if let Some(rules) = key
+            .as_ref()
+            .and_then(|key| self.lock().get_refresh(key).cloned())
+        {
+            return Ok(rules);
+        }
+
+        let mut identity_values = HashMap::with_capacity(self.identity_variables.len());
+        for variable in &self.identity_variables {
+            let value = substituter.visit_identity(variable, request)?;
+            identity_values.insert(variable.as_str(), value);
+        }
+
+        let mut resource_values = HashMap::with_capacity(self.resource_variables.len());
+        for variable in &self.resource_variables {
+            let value = substituter.visit_resource(variable, request)?;
+            resource_values.insert(variable.as_str(), value);
+        }
+
+        let rules = self
+            .identities
+            .iter()
+            .filter(|identity| identity.template.render(&identity_values) == request.identity)
+            .filter_map(|identity| {
+                identity
+                    .operations
+                    .get(&request.operation)
+                    .map(|resources| {
+                        let resources = resources
+                            .iter()
+                            .map(|resource| resource.render(&resource_values))
+                            .collect();
+                        (identity.identity.clone(), resources)
+                    })
+            })
+            .collect::<Vec<_>>();
+        let rules = Arc::new(rules);
+
+        if let Some(key) = key {
+            let mut cache = self.lock();
+            cache.insert(key, rules.clone());
+            while cache.len() > self.capacity {
+                cache.pop_front();
+            }
+        }
+
+        Ok(rules)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LinkedHashMap<CacheKey, Arc<ResolvedRules>>> {
+        self.cache
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// A string split into literal and variable (`{{var_name}}`) segments.
+#[derive(Debug, Clone, PartialEq)]
+struct Template(Vec<Segment>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Variable(String),
+}
+
+impl Template {
+    fn parse(value: &str) -> Self {
+        let mut segments = vec![];
+        let mut position = 0;
+        for variable in VAR_PATTERN.find_iter(value) {
+            if variable.start() > position {
+                segments.push(Segment::Literal(
+                    value[position..variable.start()].to_string(),
+                ));
+            }
+            segments.push(Segment::Variable(variable.as_str().to_string()));
+            position = variable.end();
+        }
+        if position < value.len() {
+            segments.push(Segment::Literal(value[position..].to_string()));
+        }
+        Self(segments)
+    }
+
+    fn variables(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(|segment| match segment {
+            Segment::Variable(variable) => Some(variable.as_str()),
+            Segment::Literal(_) => None,
+        })
+    }
+
+    /// Renders the template replacing variables with given values.
+    /// Variables without a value are kept as is.
+    fn render(&self, values: &HashMap<&str, String>) -> String {
+        let mut result = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(literal) => result.push_str(literal),
+                Segment::Variable(variable) => match values.get(variable.as_str()) {
+                    Some(value) => result.push_str(value),
+                    None => result.push_str(variable),
+                },
+            }
+        }
+        result
+    }
+}
+
+lazy_static! {
+    static ref VAR_PATTERN: Regex =
+        Regex::new(r#"\{\{[^\{\}]+\}\}"#).expect("failed to create a Regex from pattern");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{errors::Result, Decision, PolicyBuilder, Request, Substituter};
+
+    use super::{Segment, Template, VariableIndex};
+
+    #[test]
+    fn template_parse_and_render() {
+        let template = Template::parse("devices/{{identity}}/{{module}}-suffix");
+
+        assert_eq!(
+            template,
+            Template(vec![
+                Segment::Literal("devices/".into()),
+                Segment::Variable("{{identity}}".into()),
+                Segment::Literal("/".into()),
+                Segment::Variable("{{module}}".into()),
+                Segment::Literal("-suffix".into()),
+            ])
+        );
+        assert_eq!(
+            template.variables().collect::<Vec<_>>(),
+            vec!["{{identity}}", "{{module}}"]
+        );
+
+        let mut values = HashMap::new();
+        values.insert("{{identity}}", "device_1".to_string());
+        assert_eq!(
+            template.render(&values),
+            "devices/device_1/{{module}}-suffix"
+        );
+    }
+
+    #[test]
+    fn template_without_variables() {
+        let template = Template::parse("devices/#");
+
+        assert_eq!(
+            template,
+            Template(vec![Segment::Literal("devices/#".into())])
+        );
+        assert_eq!(template.render(&HashMap::new()), "devices/#");
+    }
+
+    #[test]
+    fn cached_rules_are_resolved_without_substitution() {
+        let substituter = CountingSubstituter::new(true);
+        let index = index(4);
+
+        let rules = index.resolve(&request("actor_a"), &substituter).unwrap();
+        assert_eq!(
+            *rules,
+            vec![("{{var}}".to_string(), vec!["actor_a/events".to_string()])]
+        );
+        assert_eq!(substituter.visits(), 2);
+
+        let cached = index.resolve(&request("actor_a"), &substituter).unwrap();
+        assert_eq!(cached, rules);
+        assert_eq!(substituter.visits(), 2);
+
+        index.resolve(&request("actor_b"), &substituter).unwrap();
+        assert_eq!(substituter.visits(), 4);
+    }
+
+    #[test]
+    fn least_recently_used_rules_are_evicted() {
+        let substituter = CountingSubstituter::new(true);
+        let index = index(2);
+
+        index.resolve(&request("actor_a"), &substituter).unwrap();
+        index.resolve(&request("actor_b"), &substituter).unwrap();
+        index.resolve(&request("actor_a"), &substituter).unwrap();
+        assert_eq!(substituter.visits(), 4);
+
+        // actor_b is evicted as the least recently used one
+        index.resolve(&request("actor_c"), &substituter).unwrap();
+        index.resolve(&request("actor_a"), &substituter).unwrap();
+        assert_eq!(substituter.visits(), 6);
+
+        index.resolve(&request("actor_b"), &substituter).unwrap();
+        assert_eq!(substituter.visits(), 8);
+    }
+
+    #[test]
+    fn rules_are_not_cached_without_cache_key() {
+        let substituter = CountingSubstituter::new(false);
+        let index = index(4);
+
+        index.resolve(&request("actor_a"), &substituter).unwrap();
+        index.resolve(&request("actor_a"), &substituter).unwrap();
+        assert_eq!(substituter.visits(), 4);
+    }
+
+    fn index(capacity: usize) -> VariableIndex {
+        let json = r#"{
+            "schemaVersion": "2020-10-30",
+            "statements": [
+                {
+                    "effect": "allow",
+                    "identities": [
+                        "{{var}}"
+                    ],
+                    "operations": [
+                        "write"
+                    ],
+                    "resources": [
+                        "{{var}}/events"
+                    ]
+                }
+            ]
+        }"#;
+
+        let policy = PolicyBuilder::from_json(json)
+            .with_default_decision(Decision::Denied)
+            .with_substituter(CountingSubstituter::new(true))
+            .build()
+            .expect("Unable to build policy from json.");
+
+        VariableIndex::with_capacity(&policy.variable_rules, capacity)
+    }
+
+    fn request(identity: &str) -> Request<()> {
+        Request::new(identity, "write", "resource").unwrap()
+    }
+
+    /// `CountingSubstituter` replaces any variable with the request identity
+    /// and counts substituted variables.
+    #[derive(Debug)]
+    struct CountingSubstituter {
+        cacheable: bool,
+        visits: AtomicUsize,
+    }
+
+    impl CountingSubstituter {
+        fn new(cacheable: bool) -> Self {
+            Self {
+                cacheable,
+                visits: AtomicUsize::new(0),
+            }
+        }
+
+        fn visits(&self) -> usize {
+            self.visits.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Substituter for CountingSubstituter {
+        type Context = ();
+
+        fn visit_identity(&self, _value: &str, context: &Request<Self::Context>) -> Result<String> {
+            self.visits.fetch_add(1, Ordering::SeqCst);
+            Ok(context.identity.clone())
+        }
+
+        fn visit_resource(&self, _value: &str, context: &Request<Self::Context>) -> Result<String> {
+            self.visits.fetch_add(1, Ordering::SeqCst);
+            Ok(context.identity.clone())
+        }
+
+        fn cache_key(&self, _context: &Request<Self::Context>) -> Option<String> {
+            if self.cacheable {
+                Some(String::new())
+            } else {
+                None
+            }
+        }
+    }
+}
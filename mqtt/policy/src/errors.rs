@@ -13,6 +13,9 @@ pub enum Error {
     #[error("An error occurred validating policy definition: {0}")]
     Validation(#[source] Box<dyn std::error::Error>),
 
+    #[error("Statement identity \"{0}\" is ambiguous: identity patterns must be listed in \"identityPatterns\" and groups in \"groups\", without variables.")]
+    AmbiguousIdentity(String),
+
     #[error("An error occurred constructing the request: {0}.")]
     BadRequest(String),
 }
@@ -10,13 +10,17 @@
     clippy::missing_errors_doc
 )]
 
+mod analyzer;
+mod condition;
 mod core;
 mod errors;
 mod matcher;
 mod substituter;
 mod validator;
 
-pub use crate::core::{Decision, Effect, Policy, Request};
+pub use crate::analyzer::{Finding, PolicyAnalyzer};
+pub use crate::condition::{Cidr, Condition, TimeOfDay, Value, TIME_OF_DAY_KEY};
+pub use crate::core::{Decision, DecisionSource, Effect, Explanation, Policy, Request, RuleKind};
 pub use crate::core::{PolicyBuilder, PolicyDefinition, Statement};
 pub use crate::errors::{Error, Result};
 pub use crate::matcher::{DefaultResourceMatcher, ResourceMatcher};
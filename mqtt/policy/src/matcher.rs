@@ -8,6 +8,15 @@ pub trait ResourceMatcher {
     /// This method is being called by `Policy` when it tries to match a `Request` to
     /// a resource in the policy rules.
     fn do_match(&self, context: &Request<Self::Context>, input: &str, policy: &str) -> bool;
+
+    /// This method is being called by `PolicyAnalyzer` to check whether every
+    /// request resource matched by `other` policy resource is also matched by
+    /// `policy` resource.
+    ///
+    /// Default implementation uses equality check.
+    fn includes(&self, policy: &str, other: &str) -> bool {
+        policy == other
+    }
 }
 
 /// Default matcher uses equality check for resource matching.
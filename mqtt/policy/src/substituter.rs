@@ -1,23 +1,51 @@
-use crate::{Error, Request};
+use crate::{Error, Request, Value};
 
 /// Trait to extend `Policy` variable rules resolution.
+///
+/// Variable rules are split into literal and variable segments when the
+/// policy is built, so `visit_identity` and `visit_resource` are called with
+/// a single variable (like `{{var_name}}`) rather than with the whole
+/// identity or resource, once per distinct variable. The returned value
+/// replaces the variable in every identity or resource containing it.
 pub trait Substituter {
     /// The type of the context associated with the request.
     type Context;
 
-    /// This method is called by `Policy` on every `Request` for every variable identity rule.
+    /// This method is called by `Policy` for every distinct variable of
+    /// variable identity rules, unless variable rules for the `Request`
+    /// are cached.
     fn visit_identity(
         &self,
         value: &str,
         context: &Request<Self::Context>,
     ) -> Result<String, Error>;
 
-    /// This method is called by `Policy` on every `Request` for every variable resource rule.
+    /// This method is called by `Policy` for every distinct variable of
+    /// variable resource rules, unless variable rules for the `Request`
+    /// are cached.
     fn visit_resource(
         &self,
         value: &str,
         context: &Request<Self::Context>,
     ) -> Result<String, Error>;
+
+    /// This method is called by `Policy` on every `Request` to build a key
+    /// variable rules resolved for the request are cached by, along with
+    /// request identity and operation. Requests with the same identity,
+    /// operation and key must get the same values of all variables.
+    ///
+    /// Variable rules are not cached if `None` is returned.
+    fn cache_key(&self, _context: &Request<Self::Context>) -> Option<String> {
+        None
+    }
+
+    /// This method is called by `Policy` to resolve a request attribute
+    /// referenced by a statement condition.
+    ///
+    /// Conditions on attributes which are not resolved are never satisfied.
+    fn visit_attribute(&self, _key: &str, _context: &Request<Self::Context>) -> Option<Value> {
+        None
+    }
 }
 
 #[derive(Debug)]
@@ -40,4 +68,9 @@ impl Substituter for DefaultSubstituter {
     ) -> Result<String, Error> {
         Ok(value.to_string())
     }
+
+    fn cache_key(&self, _context: &Request<Self::Context>) -> Option<String> {
+        // variables are never replaced
+        Some(String::new())
+    }
 }
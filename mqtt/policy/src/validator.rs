@@ -37,7 +37,10 @@ impl PolicyValidator for DefaultValidator {
 
 fn visit_statement(statement: &Statement) -> Vec<String> {
     let mut result = vec![];
-    if statement.identities().is_empty() {
+    if statement.identities().is_empty()
+        && statement.identity_patterns().is_empty()
+        && statement.groups().is_empty()
+    {
         result.push("Identities list must not be empty".into());
     }
     if statement.operations().is_empty() {
@@ -393,6 +393,11 @@ fn process_event(
         Event::Disconnected(_) => {
             info!("received disconnect");
         }
+        Event::ConnectionAttemptFailed {
+            attempt, reason, ..
+        } => {
+            info!("connection attempt {} failed: {}", attempt, reason);
+        }
     };
 
     Ok(())